@@ -1,13 +1,34 @@
 use clap::Args;
 use common::{
-    Error, instanceservice::ServiceInstance, instancesystem::SystemInstance, instancetenant::TenantInstance,
-    jukebox::JukeBox,
+    Error, catalog::CatalogPackage, instanceservice::ServiceInstance, instancesystem::SystemInstance,
+    instancetenant::TenantInstance, jukebox::JukeBox, tenantprofile::TenantProfile, vynilbackup::VynilBackup,
+    vynildefaults::VynilDefaults, vynilpolicy::VynilPolicy,
 };
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
 use kube::CustomResourceExt;
 
 #[derive(Args, Debug)]
 pub struct Parameters {}
 
+/// Keep the content of the instance `spec.options` and `status.effectiveOptions` maps
+fn free_form_options(crd: &mut CustomResourceDefinition) {
+    if let Some(ref mut schema) = crd.spec.versions[0].schema
+        && let Some(ref mut api) = schema.open_api_v3_schema
+        && let Some(ref mut props) = api.properties
+    {
+        for (field, key) in [("spec", "options"), ("status", "effectiveOptions")] {
+            props.entry(field.into()).and_modify(|field| {
+                if let Some(ref mut props) = field.properties {
+                    props.entry(key.into()).and_modify(|options| {
+                        options.x_kubernetes_preserve_unknown_fields = Some(true);
+                        options.additional_properties = None;
+                    });
+                }
+            });
+        }
+    }
+}
+
 pub async fn run(_args: &Parameters) -> std::result::Result<(), Error> {
     println!("---");
     let mut crd = JukeBox::crd();
@@ -15,25 +36,6 @@ pub async fn run(_args: &Parameters) -> std::result::Result<(), Error> {
         && let Some(ref mut api) = schema.open_api_v3_schema
         && let Some(ref mut props) = api.properties
     {
-        props.entry("status".into()).and_modify(|status| {
-                    if let Some(ref mut props) = status.properties {
-                        props.entry("packages".into()).and_modify(|pspec| {
-                            if let Some(ref mut pprops) = pspec.items {
-                                match pprops {
-                                    k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaPropsOrArray::Schema(sc) => {
-                                        if let Some(ref mut pr) = sc.properties {
-                                            pr.entry("options".into()).and_modify(|spec| {
-                                                spec.x_kubernetes_preserve_unknown_fields = Some(true);
-                                                spec.additional_properties = None;
-                                            });
-                                        }
-                                    },k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaPropsOrArray::Schemas(_x) => {}
-
-                                }
-                            }
-                        });
-                    }
-                });
         props.entry("spec".into()).and_modify(|spec| {
             if let Some(ref mut props) = spec.properties {
                 // schemars génère anyOf:[{oneOf:[...]}, {enum:[null],nullable:true}] pour Option<Enum>
@@ -54,50 +56,104 @@ pub async fn run(_args: &Parameters) -> std::result::Result<(), Error> {
     print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
     println!("---");
     let mut crd = TenantInstance::crd();
+    free_form_options(&mut crd);
+    print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
+    println!("---");
+    let mut crd = ServiceInstance::crd();
+    free_form_options(&mut crd);
+    print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
+    println!("---");
+    let mut crd = SystemInstance::crd();
+    free_form_options(&mut crd);
+    print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
+    println!("---");
+    let mut crd = TenantProfile::crd();
     if let Some(ref mut schema) = crd.spec.versions[0].schema
         && let Some(ref mut api) = schema.open_api_v3_schema
         && let Some(ref mut props) = api.properties
     {
         props.entry("spec".into()).and_modify(|spec| {
             if let Some(ref mut props) = spec.properties {
-                props.entry("options".into()).and_modify(|spec| {
-                    spec.x_kubernetes_preserve_unknown_fields = Some(true);
-                    spec.additional_properties = None;
-                    //print!("{:?}", spec.additional_properties);
+                props.entry("instances".into()).and_modify(|instances| {
+                    if let Some(k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaPropsOrArray::Schema(ref mut sc)) = instances.items
+                        && let Some(ref mut pr) = sc.properties
+                    {
+                        pr.entry("options".into()).and_modify(|spec| {
+                            spec.x_kubernetes_preserve_unknown_fields = Some(true);
+                            spec.additional_properties = None;
+                        });
+                    }
                 });
             }
         });
     }
     print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
     println!("---");
-    let mut crd = ServiceInstance::crd();
+    let mut crd = VynilPolicy::crd();
     if let Some(ref mut schema) = crd.spec.versions[0].schema
         && let Some(ref mut api) = schema.open_api_v3_schema
         && let Some(ref mut props) = api.properties
     {
         props.entry("spec".into()).and_modify(|spec| {
             if let Some(ref mut props) = spec.properties {
-                props.entry("options".into()).and_modify(|spec| {
-                    spec.x_kubernetes_preserve_unknown_fields = Some(true);
-                    spec.additional_properties = None;
-                    //print!("{:?}", spec.additional_properties);
+                props.entry("options".into()).and_modify(|options| {
+                    if let Some(k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaPropsOrArray::Schema(ref mut sc)) = options.items
+                        && let Some(ref mut pr) = sc.properties
+                    {
+                        pr.entry("schema".into()).and_modify(|spec| {
+                            spec.x_kubernetes_preserve_unknown_fields = Some(true);
+                            spec.additional_properties = None;
+                        });
+                    }
                 });
             }
         });
     }
     print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
     println!("---");
-    let mut crd = SystemInstance::crd();
+    let mut crd = VynilDefaults::crd();
+    if let Some(ref mut schema) = crd.spec.versions[0].schema
+        && let Some(ref mut api) = schema.open_api_v3_schema
+        && let Some(ref mut props) = api.properties
+    {
+        props.entry("spec".into()).and_modify(|spec| {
+            if let Some(ref mut props) = spec.properties {
+                props.entry("options".into()).and_modify(|options| {
+                    options.x_kubernetes_preserve_unknown_fields = Some(true);
+                    options.additional_properties = None;
+                });
+                for layer in ["categories", "packages"] {
+                    props.entry(layer.into()).and_modify(|layer| {
+                        if let Some(k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaPropsOrBool::Schema(ref mut sc)) = layer.additional_properties {
+                            sc.x_kubernetes_preserve_unknown_fields = Some(true);
+                            sc.additional_properties = None;
+                        }
+                    });
+                }
+            }
+        });
+    }
+    print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
+    println!("---");
+    let crd = VynilBackup::crd();
+    print!("{}", common::yamlhandler::yaml_serialize_to_string(&crd).unwrap());
+    println!("---");
+    let mut crd = CatalogPackage::crd();
     if let Some(ref mut schema) = crd.spec.versions[0].schema
         && let Some(ref mut api) = schema.open_api_v3_schema
         && let Some(ref mut props) = api.properties
     {
         props.entry("spec".into()).and_modify(|spec| {
             if let Some(ref mut props) = spec.properties {
-                props.entry("options".into()).and_modify(|spec| {
-                    spec.x_kubernetes_preserve_unknown_fields = Some(true);
-                    spec.additional_properties = None;
-                    //print!("{:?}", spec.additional_properties);
+                props.entry("versions".into()).and_modify(|versions| {
+                    if let Some(k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaPropsOrArray::Schema(ref mut sc)) = versions.items
+                        && let Some(ref mut pr) = sc.properties
+                    {
+                        pr.entry("options".into()).and_modify(|spec| {
+                            spec.x_kubernetes_preserve_unknown_fields = Some(true);
+                            spec.additional_properties = None;
+                        });
+                    }
                 });
             }
         });
@@ -0,0 +1,27 @@
+pub mod drill;
+pub mod rotate_key;
+
+/// Kind of the instances whose actions are shared between the tenant and service sub-commands
+#[derive(Clone, Copy, Debug)]
+pub enum Kind {
+    Tenant,
+    Service,
+}
+
+impl Kind {
+    /// Agent script directory of the kind
+    pub fn scripts(&self) -> &'static str {
+        match self {
+            Kind::Tenant => "tenant",
+            Kind::Service => "service",
+        }
+    }
+
+    /// Kubernetes kind of the instances
+    pub fn instance_kind(&self) -> &'static str {
+        match self {
+            Kind::Tenant => "TenantInstance",
+            Kind::Service => "ServiceInstance",
+        }
+    }
+}
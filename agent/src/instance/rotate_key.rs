@@ -0,0 +1,45 @@
+use super::Kind;
+use clap::Args;
+use common::{Result, rhaihandler::Script};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Exit code of a failed rotation, the same for every kind
+pub const EXIT_CODE: i32 = 10;
+
+#[derive(Args, Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    /// Namespace of the backup secret
+    #[arg(short = 'n', long = "namespace", env = "NAMESPACE", value_name = "NAMESPACE")]
+    namespace: String,
+    /// Instance whose backup secret to rotate
+    #[arg(short = 'i', long = "instance", env = "INSTANCE", value_name = "INSTANCE")]
+    instance: Option<String>,
+    /// Backup secret to rotate (default: the one of the instance, or backup-settings)
+    #[arg(long = "secret-name", env = "BACKUP_SECRET", value_name = "BACKUP_SECRET")]
+    secret_name: Option<String>,
+    /// New repository password (generated when not set)
+    #[arg(
+        long = "new-password",
+        env = "NEW_RESTIC_PASSWORD",
+        value_name = "NEW_RESTIC_PASSWORD"
+    )]
+    new_password: Option<String>,
+    /// Agent script directory
+    #[arg(
+        short = 's',
+        long = "script-dir",
+        env = "SCRIPT_DIRECTORY",
+        value_name = "SCRIPT_DIRECTORY",
+        default_value = "./agent/scripts"
+    )]
+    script_dir: PathBuf,
+}
+
+pub async fn run(args: &Parameters, kind: Kind) -> Result<()> {
+    let mut rhai = Script::new(vec![format!("{}/lib", args.script_dir.display())]);
+    rhai.set_dynamic("args", &serde_json::to_value(args).unwrap());
+    rhai.ctx.set_value("kind", kind.instance_kind().to_string());
+    let _ = rhai.eval("import(\"backup_keys\") as keys;\n        keys::run(args, kind);")?;
+    Ok(())
+}
@@ -657,7 +657,10 @@ impl<'a> HelperWalker<'a> {
             && !segs.is_empty()
             && let PathSeg::Named(first) = &segs[0]
             && first == "tenant"
-            && self.pkg.metadata.usage == common::vynilpackage::VynilPackageType::System
+            && matches!(
+                self.pkg.metadata.usage,
+                common::vynilpackage::VynilPackageType::System | common::vynilpackage::VynilPackageType::Bundle
+            )
             && let Some(level) = self.config.resolve_level(
                 "hbs/wrong-package-type",
                 &self.file,
@@ -805,6 +808,8 @@ mod tests {
             images: None,
             resources: None,
             value_script: None,
+            members: None,
+            migrations: None,
         }
     }
 
@@ -1234,6 +1239,8 @@ mod tests {
             images: None,
             resources: None,
             value_script: None,
+            members: None,
+            migrations: None,
         }
     }
 
@@ -1263,6 +1270,8 @@ mod tests {
             images: Some(images),
             resources: None,
             value_script: None,
+            members: None,
+            migrations: None,
         }
     }
 
@@ -1300,6 +1309,8 @@ mod tests {
             images: None,
             resources: Some(resources),
             value_script: None,
+            members: None,
+            migrations: None,
         }
     }
 
@@ -1322,6 +1333,8 @@ mod tests {
             images: None,
             resources: None,
             value_script: None,
+            members: None,
+            migrations: None,
         }
     }
 
@@ -1355,6 +1368,8 @@ mod tests {
             images: None,
             resources: None,
             value_script: None,
+            members: None,
+            migrations: None,
         }
     }
 
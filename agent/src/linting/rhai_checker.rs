@@ -70,7 +70,7 @@ impl<'a> RhaiChecker<'a> {
             let type_subdir = match pkg.metadata.usage {
                 VynilPackageType::Tenant => "tenant",
                 VynilPackageType::Service => "service",
-                VynilPackageType::System => "system",
+                VynilPackageType::System | VynilPackageType::Bundle => "system",
             };
             resolver_paths.push(dir.join(type_subdir));
         }
@@ -830,7 +830,7 @@ fn first_property_in_dot_chain(expr: &Expr) -> Option<&str> {
 fn check_wrong_package_type(ast: &AST, file: &Path, pkg: &VynilPackageSource) -> Vec<LintFinding> {
     let mut findings = Vec::new();
 
-    if pkg.metadata.usage != VynilPackageType::System {
+    if !matches!(pkg.metadata.usage, VynilPackageType::System | VynilPackageType::Bundle) {
         return findings;
     }
 
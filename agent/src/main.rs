@@ -1,7 +1,9 @@
 mod boxes;
 mod crdgen;
+mod instance;
 mod linting;
 mod package;
+mod platform;
 mod run;
 mod service;
 mod system;
@@ -37,6 +39,8 @@ pub enum Commands {
     Template(template::Parameters),
     /// box sub-command
     Box(boxes::Parameters),
+    /// Platform export and rebuild sub-command
+    Platform(platform::Parameters),
     /// Version sub-command
     Version(version::Parameters),
 }
@@ -73,5 +77,6 @@ async fn main() {
         Commands::Service(args) => service::run(args).await,
         Commands::Tenant(args) => tenant::run(args).await,
         Commands::Box(args) => boxes::run(args).await,
+        Commands::Platform(args) => platform::run(args).await,
     }
 }
@@ -1,7 +1,9 @@
 use clap::{Args, ValueEnum};
 use common::{
     Result,
-    vynilpackage::{VynilPackageRequirement, VynilPackageSource, VynilPackageType, read_package_yaml},
+    vynilpackage::{
+        VynilOptionRule, VynilPackageRequirement, VynilPackageSource, VynilPackageType, read_package_yaml,
+    },
 };
 use std::{collections::HashSet, path::PathBuf};
 
@@ -64,6 +66,7 @@ pub struct Parameters {
 fn expected_dirs(pkg_type: &VynilPackageType) -> &[&str] {
     match pkg_type {
         VynilPackageType::System => &["systems", "crds", "scripts", "handlebars"],
+        VynilPackageType::Bundle => &["systems", "scripts", "handlebars"],
         VynilPackageType::Service => &[
             "vitals",
             "scalables",
@@ -237,6 +240,26 @@ pub async fn run(args: &Parameters) -> Result<()> {
         }
     }
 
+    for migration in package.migrations.clone().unwrap_or_default() {
+        for rule in migration.rules {
+            if let VynilOptionRule::Transform { path, script } = rule {
+                let virtual_path =
+                    PathBuf::from(format!("package.yaml#migrations({}).{}", migration.from, path));
+                let findings = rhai_checker.check_file(&virtual_path, &script);
+                for finding in findings {
+                    collector.add(finding);
+                }
+            }
+        }
+    }
+    // The options migration hook sits next to package.yaml, out of the scanned directories
+    if let Ok(source) = std::fs::read_to_string(args.package_dir.join("options_migrate.rhai")) {
+        let findings = rhai_checker.check_file(&PathBuf::from("options_migrate.rhai"), &source);
+        for finding in findings {
+            collector.add(finding);
+        }
+    }
+
     collector.extend(rhai_checker.finalize());
 
     collector.prefix_files(&args.package_dir);
@@ -285,6 +308,64 @@ fn check_manifest_fields(
     }
     check_options(package, manifest_path, collector);
     check_prerelease_versions(package, manifest_path, collector);
+    check_bundle_members(package, manifest_path, collector);
+}
+
+fn check_bundle_members(
+    package: &VynilPackageSource,
+    manifest_path: &std::path::Path,
+    collector: &mut crate::linting::LintResultCollector,
+) {
+    let manifest = PathBuf::from("package.yaml");
+    let members = package.members.clone().unwrap_or_default();
+    if package.metadata.usage != VynilPackageType::Bundle {
+        if !members.is_empty() {
+            collector.add(crate::linting::LintFinding {
+                rule: "package/invalid-manifest".to_string(),
+                level: crate::linting::LintLevel::Warn,
+                file: manifest,
+                line: find_line_with_key(manifest_path, "members"),
+                message: "members are ignored for non-bundle packages".to_string(),
+            });
+        }
+        return;
+    }
+    if members.is_empty() {
+        collector.add(crate::linting::LintFinding {
+            rule: "package/invalid-manifest".to_string(),
+            level: crate::linting::LintLevel::Error,
+            file: manifest.clone(),
+            line: None,
+            message: "A bundle package must list its members".to_string(),
+        });
+    }
+    let mut seen = HashSet::new();
+    for member in &members {
+        if member.usage == VynilPackageType::Bundle {
+            collector.add(crate::linting::LintFinding {
+                rule: "package/invalid-manifest".to_string(),
+                level: crate::linting::LintLevel::Error,
+                file: manifest.clone(),
+                line: find_line_with_key(manifest_path, "members"),
+                message: format!("Bundle member {} cannot be of type bundle", member.name),
+            });
+        }
+        let key = format!(
+            "{:?}/{}/{}",
+            member.usage,
+            member.namespace.clone().unwrap_or_default(),
+            member.name
+        );
+        if !seen.insert(key) {
+            collector.add(crate::linting::LintFinding {
+                rule: "package/invalid-manifest".to_string(),
+                level: crate::linting::LintLevel::Error,
+                file: manifest.clone(),
+                line: find_line_with_key(manifest_path, "members"),
+                message: format!("Duplicate bundle member {}", member.name),
+            });
+        }
+    }
 }
 
 fn is_prerelease(version: &str) -> bool {
@@ -574,6 +655,8 @@ mod tests {
             images: None,
             resources: None,
             value_script: None,
+            members: None,
+            migrations: None,
         }
     }
 
@@ -612,6 +695,52 @@ mod tests {
         assert!(collector.has_errors());
     }
 
+    fn make_member(name: &str) -> common::vynilpackage::VynilBundleMember {
+        common::vynilpackage::VynilBundleMember {
+            name: name.to_string(),
+            usage: VynilPackageType::System,
+            namespace: None,
+            jukebox: None,
+            category: "core".to_string(),
+            package: name.to_string(),
+            options: None,
+            wait: None,
+        }
+    }
+
+    #[test]
+    fn check_bundle_members_bundle_without_members_is_error() {
+        let mut package = make_valid_package();
+        package.metadata.usage = VynilPackageType::Bundle;
+        let mut collector = crate::linting::LintResultCollector::new();
+        check_bundle_members(&package, std::path::Path::new(""), &mut collector);
+        assert!(collector.has_errors());
+    }
+
+    #[test]
+    fn check_bundle_members_valid_bundle_passes() {
+        let mut package = make_valid_package();
+        package.metadata.usage = VynilPackageType::Bundle;
+        package.members = Some(vec![make_member("cert-manager"), make_member("traefik")]);
+        let mut collector = crate::linting::LintResultCollector::new();
+        check_bundle_members(&package, std::path::Path::new(""), &mut collector);
+        assert!(!collector.has_errors());
+    }
+
+    #[test]
+    fn check_bundle_members_duplicate_and_nested_bundle_are_errors() {
+        let mut package = make_valid_package();
+        package.metadata.usage = VynilPackageType::Bundle;
+        let mut nested = make_member("other");
+        nested.usage = VynilPackageType::Bundle;
+        package.members = Some(vec![make_member("traefik"), make_member("traefik"), nested]);
+        let mut collector = crate::linting::LintResultCollector::new();
+        check_bundle_members(&package, std::path::Path::new(""), &mut collector);
+        let text = collector.to_text(crate::linting::LintLevel::Info);
+        assert!(text.contains("Duplicate bundle member traefik"));
+        assert!(text.contains("cannot be of type bundle"));
+    }
+
     #[test]
     fn check_options_scalar_reports_key_name() {
         let mut package = make_valid_package();
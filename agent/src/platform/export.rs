@@ -0,0 +1,136 @@
+use clap::Args;
+use common::{
+    Error, Result, catalog,
+    context::get_client_async,
+    dependents::{self, InstalledInstance},
+    instanceservice::ServiceInstance,
+    instancesystem::SystemInstance,
+    instancetenant::TenantInstance,
+    jukebox::JukeBox,
+    platform,
+    vynilpackage::VynilPackageType,
+};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{
+    Client, Resource, ResourceExt,
+    api::{Api, DynamicObject, ListParams},
+    discovery::ApiResource,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+#[derive(Args, Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    /// Directory to write the export to
+    #[arg(short = 'o', long = "output", env = "OUTPUT", value_name = "OUTPUT")]
+    output: PathBuf,
+}
+
+/// Lists the objects of a kind, sanitized
+async fn list_sanitized<K: Resource<DynamicType = ()>>(client: Client) -> Result<Vec<Value>> {
+    let ar = ApiResource::erase::<K>(&());
+    let api: Api<DynamicObject> = Api::all_with(client, &ar);
+    let mut res = Vec::new();
+    for obj in api.list(&ListParams::default()).await.map_err(Error::KubeError)? {
+        if obj.metadata.deletion_timestamp.is_some() {
+            continue;
+        }
+        let mut value = serde_json::to_value(obj)?;
+        value["apiVersion"] = ar.api_version.clone().into();
+        value["kind"] = ar.kind.clone().into();
+        res.push(platform::sanitize(&value));
+    }
+    Ok(res)
+}
+
+fn key(kind: &str, namespace: &str, name: &str) -> String {
+    format!("{kind}/{namespace}/{name}")
+}
+
+fn write(args: &Parameters, file: &str, objects: &[Value]) -> Result<()> {
+    let path = args.output.join(file);
+    std::fs::write(&path, platform::to_yaml_documents(objects)?).map_err(Error::Stdio)?;
+    tracing::info!("Wrote {} objects to {}", objects.len(), path.display());
+    Ok(())
+}
+
+pub async fn run(args: &Parameters) -> Result<()> {
+    let client = get_client_async().await;
+    std::fs::create_dir_all(&args.output).map_err(Error::Stdio)?;
+
+    write(
+        args,
+        "00-jukeboxes.yaml",
+        &list_sanitized::<JukeBox>(client.clone()).await?,
+    )?;
+
+    let installed: Vec<InstalledInstance> = dependents::list_installed(client.clone())
+        .await?
+        .into_iter()
+        .filter(|i| !i.deleting)
+        .collect();
+    let label_key = std::env::var("TENANT_LABEL").unwrap_or_else(|_| "vynil.solidite.fr/tenant".to_string());
+    let namespaces = Api::<Namespace>::all(client.clone())
+        .list_metadata(&ListParams::default())
+        .await
+        .map_err(Error::KubeError)?;
+    let labels: BTreeMap<String, BTreeMap<String, String>> = namespaces
+        .items
+        .into_iter()
+        .map(|n| (n.name_any(), n.metadata.labels.unwrap_or_default()))
+        .collect();
+    let used: BTreeSet<&String> = installed
+        .iter()
+        .filter(|i| i.usage != VynilPackageType::System)
+        .map(|i| &i.namespace)
+        .collect();
+    let tenant_namespaces: Vec<Value> = labels
+        .iter()
+        .filter(|(name, l)| used.contains(name) || l.contains_key(&label_key))
+        .map(|(name, l)| platform::sanitize_namespace(name, l))
+        .collect();
+    write(args, "01-namespaces.yaml", &tenant_namespaces)?;
+
+    let mut objects: BTreeMap<String, Value> = BTreeMap::new();
+    for obj in list_sanitized::<SystemInstance>(client.clone())
+        .await?
+        .into_iter()
+        .chain(list_sanitized::<ServiceInstance>(client.clone()).await?)
+        .chain(list_sanitized::<TenantInstance>(client.clone()).await?)
+    {
+        let meta = &obj["metadata"];
+        objects.insert(
+            key(
+                obj["kind"].as_str().unwrap_or_default(),
+                meta["namespace"].as_str().unwrap_or_default(),
+                meta["name"].as_str().unwrap_or_default(),
+            ),
+            obj,
+        );
+    }
+    let catalog = catalog::load_all(client).await?;
+    let tiers = platform::install_tiers(
+        &installed,
+        |ns| {
+            labels
+                .get(ns)
+                .and_then(|l| l.get(&label_key))
+                .cloned()
+                .unwrap_or_else(|| ns.to_string())
+        },
+        |i| dependents::package_requirements(&catalog, i),
+    );
+    for (tier, indexes) in tiers.iter().enumerate() {
+        let tier_objects: Vec<Value> = indexes
+            .iter()
+            .map(|&i| &installed[i])
+            .filter_map(|i| objects.get(&key(i.kind, &i.namespace, &i.name)).cloned())
+            .collect();
+        write(args, &format!("{:02}-tier-{tier}.yaml", tier + 2), &tier_objects)?;
+    }
+    Ok(())
+}
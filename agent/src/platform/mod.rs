@@ -0,0 +1,32 @@
+mod export;
+mod rebuild;
+use clap::{Parser, Subcommand};
+use std::process;
+
+#[derive(Parser, Debug)]
+pub struct Parameters {
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Export the JukeBoxes, the instances and the tenant namespaces to a directory
+    Export(export::Parameters),
+    /// Apply an export tier by tier, waiting for each tier to be Ready
+    Rebuild(rebuild::Parameters),
+}
+
+pub async fn run(cmd: &Parameters) {
+    common::context::init_k8s();
+    match &cmd.command {
+        Commands::Export(args) => export::run(args).await.unwrap_or_else(|e| {
+            tracing::error!("Exporting the platform failed with: {e:}");
+            process::exit(1)
+        }),
+        Commands::Rebuild(args) => rebuild::run(args).await.unwrap_or_else(|e| {
+            tracing::error!("Rebuilding the platform failed with: {e:}");
+            process::exit(1)
+        }),
+    }
+}
@@ -0,0 +1,125 @@
+use clap::Args;
+use common::{
+    Error, Result,
+    context::{get_client_async, get_client_name},
+    instanceservice::ServiceInstance,
+    instancesystem::SystemInstance,
+    instancetenant::TenantInstance,
+    jukebox::JukeBox,
+    platform,
+};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{
+    Client,
+    api::{Api, DynamicObject, Patch, PatchParams},
+    discovery::ApiResource,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{path::PathBuf, time::Duration};
+
+#[derive(Args, Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    /// Directory written by `platform export`
+    #[arg(short = 'd', long = "directory", env = "DIRECTORY", value_name = "DIRECTORY")]
+    directory: PathBuf,
+    /// Seconds to wait for each tier to be Ready
+    #[arg(
+        long = "timeout",
+        env = "TIMEOUT",
+        value_name = "TIMEOUT",
+        default_value_t = 1800
+    )]
+    timeout: u64,
+}
+
+/// Api of an exported object
+fn api_for(client: Client, obj: &Value) -> Result<Api<DynamicObject>> {
+    let kind = obj["kind"].as_str().unwrap_or_default();
+    let (ar, namespaced) = match kind {
+        "JukeBox" => (ApiResource::erase::<JukeBox>(&()), false),
+        "Namespace" => (ApiResource::erase::<Namespace>(&()), false),
+        "SystemInstance" => (ApiResource::erase::<SystemInstance>(&()), true),
+        "ServiceInstance" => (ApiResource::erase::<ServiceInstance>(&()), true),
+        "TenantInstance" => (ApiResource::erase::<TenantInstance>(&()), true),
+        _ => return Err(Error::Other(format!("unsupported kind {kind}"))),
+    };
+    Ok(match obj["metadata"]["namespace"].as_str() {
+        Some(ns) if namespaced => Api::namespaced_with(client, ns, &ar),
+        _ => Api::all_with(client, &ar),
+    })
+}
+
+fn is_ready(obj: &DynamicObject) -> bool {
+    obj.data["status"]["conditions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|c| c["type"] == "Ready" && c["status"] == "True")
+}
+
+pub async fn run(args: &Parameters) -> Result<()> {
+    let client = get_client_async().await;
+    let params = PatchParams::apply(&get_client_name());
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&args.directory)
+        .map_err(Error::Stdio)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "yaml"))
+        .collect();
+    files.sort();
+
+    for file in files {
+        let content = std::fs::read_to_string(&file).map_err(Error::Stdio)?;
+        let objects = platform::from_yaml_documents(&content)?;
+        tracing::info!("Applying {} objects from {}", objects.len(), file.display());
+        let mut pending = Vec::new();
+        for obj in objects {
+            let api = api_for(client.clone(), &obj)?;
+            let name = obj["metadata"]["name"].as_str().unwrap_or_default().to_string();
+            api.patch(&name, &params, &Patch::Apply(&obj))
+                .await
+                .map_err(Error::KubeError)?;
+            if obj["kind"] != "Namespace" {
+                let label = match obj["metadata"]["namespace"].as_str() {
+                    Some(ns) => format!("{} {ns}/{name}", obj["kind"].as_str().unwrap_or_default()),
+                    None => format!("{} {name}", obj["kind"].as_str().unwrap_or_default()),
+                };
+                pending.push((label, api, name));
+            }
+        }
+        let deadline = std::time::Instant::now() + Duration::from_secs(args.timeout);
+        while !pending.is_empty() {
+            let mut waiting = Vec::new();
+            for (label, api, name) in pending {
+                if !api
+                    .get_opt(&name)
+                    .await
+                    .map_err(Error::KubeError)?
+                    .is_some_and(|o| is_ready(&o))
+                {
+                    waiting.push((label, api, name));
+                }
+            }
+            pending = waiting;
+            if pending.is_empty() {
+                break;
+            }
+            if std::time::Instant::now() >= deadline {
+                let labels: Vec<&str> = pending.iter().map(|(l, _, _)| l.as_str()).collect();
+                return Err(Error::Other(format!(
+                    "{}: not Ready after {}s: {}",
+                    file.display(),
+                    args.timeout,
+                    labels.join(", ")
+                )));
+            }
+            tracing::info!(
+                "Waiting for {} objects of {} to be Ready",
+                pending.len(),
+                file.display()
+            );
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,9 @@
 mod backup;
 mod delete;
 mod install;
-//mod reconfigure;
+mod reconfigure;
 mod restore;
+use crate::instance::{Kind, drill, rotate_key};
 use clap::{Parser, Subcommand};
 use std::process;
 
@@ -22,8 +23,12 @@ pub enum Commands {
     Backup(backup::Parameters),
     // Restore an instance
     Restore(restore::Parameters),
-    // Reconfigure an instance
-    //    Reconfigure(reconfigure::Parameters),
+    /// Reconfigure an instance after an options change
+    Reconfigure(reconfigure::Parameters),
+    /// Restore the latest backup of an instance in a sandbox namespace
+    Drill(drill::Parameters),
+    /// Rotate the key of the backup repositories using a backup secret
+    RotateKey(rotate_key::Parameters),
 }
 
 pub async fn run(cmd: &Parameters) {
@@ -45,9 +50,17 @@ pub async fn run(cmd: &Parameters) {
             tracing::error!("Restore of a package failed with: {e:}");
             process::exit(5)
         }),
-        /*Commands::Reconfigure(args) => reconfigure::run(args).await.unwrap_or_else(|e| {
-            tracing::error!("Reconfiguring of a package failed with: {e:}");
+        Commands::Reconfigure(args) => reconfigure::run(args).await.unwrap_or_else(|e| {
+            tracing::error!("Reconfiguring a package failed with: {e:}");
             process::exit(6)
-        }),*/
+        }),
+        Commands::Drill(args) => drill::run(args, Kind::Service).await.unwrap_or_else(|e| {
+            tracing::error!("Restore drill of a package failed with: {e:}");
+            process::exit(drill::EXIT_CODE)
+        }),
+        Commands::RotateKey(args) => rotate_key::run(args, Kind::Service).await.unwrap_or_else(|e| {
+            tracing::error!("Rotating the backup key failed with: {e:}");
+            process::exit(rotate_key::EXIT_CODE)
+        }),
     }
 }
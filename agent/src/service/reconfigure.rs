@@ -1,6 +1,6 @@
-use common::{context::set_service, instanceservice::ServiceInstance, rhaihandler::Script, Result};
-use serde::{Deserialize, Serialize};
 use clap::Args;
+use common::{Result, context::set_service, instanceservice::ServiceInstance, rhaihandler::Script};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[derive(Args, Debug, Serialize, Deserialize)]
@@ -0,0 +1,50 @@
+use clap::Args;
+use common::{Result, rhaihandler::Script};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    /// Instance namespace to export
+    #[arg(short = 'n', long = "namespace", env = "NAMESPACE", value_name = "NAMESPACE")]
+    namespace: String,
+    /// Instance name to export
+    #[arg(short = 'i', long = "instance", env = "INSTANCE", value_name = "INSTANCE")]
+    instance: String,
+    /// Export file to write
+    #[arg(short = 'o', long = "output", env = "OUTPUT", value_name = "OUTPUT")]
+    output: PathBuf,
+    /// Export the latest backup instead of taking a new one
+    #[arg(long = "no-backup")]
+    no_backup: bool,
+    /// Seconds to wait for the backup
+    #[arg(
+        long = "timeout",
+        env = "TIMEOUT",
+        value_name = "TIMEOUT",
+        default_value_t = 3600
+    )]
+    timeout: i64,
+    /// Agent script directory
+    #[arg(
+        short = 's',
+        long = "script-dir",
+        env = "SCRIPT_DIRECTORY",
+        value_name = "SCRIPT_DIRECTORY",
+        default_value = "./agent/scripts"
+    )]
+    script_dir: PathBuf,
+}
+
+pub async fn run(args: &Parameters) -> Result<()> {
+    let mut rhai = Script::new(vec![
+        format!("{}/tenant", args.script_dir.display()),
+        format!("{}/lib", args.script_dir.display()),
+    ]);
+    rhai.set_dynamic("args", &serde_json::to_value(args).unwrap());
+    let _ = rhai.eval(
+        "import(\"export\") as exporter;\n\
+        exporter::run(args);",
+    )?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use clap::Args;
+use common::{Result, rhaihandler::Script};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    /// Export file to import
+    #[arg(short = 'f', long = "file", env = "FILE", value_name = "FILE")]
+    file: PathBuf,
+    /// Namespace of the new instance (default to the exported one)
+    #[arg(short = 'n', long = "namespace", env = "NAMESPACE", value_name = "NAMESPACE")]
+    namespace: Option<String>,
+    /// Name of the new instance (default to the exported one)
+    #[arg(short = 'i', long = "instance", env = "INSTANCE", value_name = "INSTANCE")]
+    name: Option<String>,
+    /// JukeBox to install from (default to the exported one)
+    #[arg(short = 'j', long = "jukebox", env = "JUKEBOX", value_name = "JUKEBOX")]
+    jukebox: Option<String>,
+    /// Secret holding the settings of the backup repository (default to the exported one)
+    #[arg(long = "secret-name", env = "SECRET_NAME", value_name = "SECRET_NAME")]
+    secret_name: Option<String>,
+    /// Agent script directory
+    #[arg(
+        short = 's',
+        long = "script-dir",
+        env = "SCRIPT_DIRECTORY",
+        value_name = "SCRIPT_DIRECTORY",
+        default_value = "./agent/scripts"
+    )]
+    script_dir: PathBuf,
+}
+
+pub async fn run(args: &Parameters) -> Result<()> {
+    let mut rhai = Script::new(vec![
+        format!("{}/tenant", args.script_dir.display()),
+        format!("{}/lib", args.script_dir.display()),
+    ]);
+    rhai.set_dynamic("args", &serde_json::to_value(args).unwrap());
+    let _ = rhai.eval(
+        "import(\"import\") as importer;\n\
+        importer::run(args);",
+    )?;
+    Ok(())
+}
@@ -1,6 +1,6 @@
-use common::{context::set_tenant, instancetenant::TenantInstance, rhaihandler::Script, Result};
-use serde::{Deserialize, Serialize};
 use clap::Args;
+use common::{Result, context::set_tenant, instancetenant::TenantInstance, rhaihandler::Script};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[derive(Args, Debug, Serialize, Deserialize)]
@@ -78,10 +78,10 @@ pub struct Parameters {
 
 pub async fn run(args: &Parameters) -> Result<()> {
     let mut rhai = Script::new(vec![
-        format!("{}/scripts", args.package_dir),
-        format!("{}", args.config_dir),
-        format!("{}/tenant", args.script_dir),
-        format!("{}/lib", args.script_dir),
+        format!("{}/scripts", args.package_dir.display()),
+        format!("{}", args.config_dir.display()),
+        format!("{}/tenant", args.script_dir.display()),
+        format!("{}/lib", args.script_dir.display()),
     ]);
     let context = TenantInstance::get(args.namespace.clone(), args.instance.clone()).await?;
     set_tenant(context.clone());
@@ -1,9 +1,10 @@
 use super::{
     result::TestResultCollector,
-    vyniltest::{VynilTest, VynilTestSetRef, appslug},
+    vyniltest::{VynilTest, VynilTestSetRef},
     vyniltestset::{VynilAssert, VynilAssertResult, VynilAssertSelector, VynilTestSet, VynilTestSetMocks},
 };
 use common::{
+    appslug,
     handlebarshandler::HandleBars,
     httpmock::HttpMockItem,
     rhaihandler::{Dynamic, Map, Script},
@@ -337,7 +338,7 @@ impl TestHandler {
         // so Rhai scripts can find it via get_{service,system,tenant}_instance().
         let instance_kind = match &package.metadata.usage {
             VynilPackageType::Service => "ServiceInstance",
-            VynilPackageType::System => "SystemInstance",
+            VynilPackageType::System | VynilPackageType::Bundle => "SystemInstance",
             VynilPackageType::Tenant => "TenantInstance",
         };
         let already_mocked = k8s_mocks.iter().any(|m| {
@@ -494,7 +495,7 @@ impl TestHandler {
         // Build resolver path
         let type_dir = match self.package.metadata.usage {
             VynilPackageType::Tenant => "tenant",
-            VynilPackageType::System => "system",
+            VynilPackageType::System | VynilPackageType::Bundle => "system",
             VynilPackageType::Service => "service",
         };
         let resolver_path = vec![
@@ -569,7 +570,7 @@ impl TestHandler {
         //rhai.set_dynamic("instance", &instance_json);
         let fun_name = match self.package.metadata.usage {
             VynilPackageType::Tenant => "get_tenant_instance",
-            VynilPackageType::System => "get_system_instance",
+            VynilPackageType::System | VynilPackageType::Bundle => "get_system_instance",
             VynilPackageType::Service => "get_service_instance",
         };
 
@@ -5,19 +5,6 @@ use common::rhaihandler::Dynamic;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-pub fn appslug(pkg: &str, inst: &str) -> String {
-    if pkg == inst {
-        inst.to_string()
-    } else {
-        let slug = format!("{inst}-{pkg}");
-        if slug.chars().count() > 28 {
-            slug.chars().take(28).collect()
-        } else {
-            slug
-        }
-    }
-}
-
 /// Vynil Test
 #[allow(non_snake_case)]
 #[derive(Deserialize, Serialize, Clone, Debug)]
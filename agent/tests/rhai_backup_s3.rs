@@ -0,0 +1,109 @@
+use common::rhaihandler::Script;
+
+fn make_tenant_script() -> Script {
+    let base = env!("CARGO_MANIFEST_DIR");
+    Script::new_mock(
+        vec![format!("{base}/scripts/lib"), format!("{base}/scripts/tenant")],
+        vec![],
+        vec![],
+        Default::default(),
+    )
+}
+
+fn context(s3_list: &[&str]) -> String {
+    let list = s3_list
+        .iter()
+        .map(|s| format!("\"{s}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("#{{ namespace: \"test-ns\", s3_concurrency: 4, s3_list: [{list}] }}")
+}
+
+// The bucket is opened from the COSI BucketInfo of the BucketAccess credentials secret
+const MOCK_ENV: &str = r#"
+    fn get_env(name) {
+        if name == "media_prefix" { "uploads/" }
+        else if name == "media_bucket_info" {
+            `{"spec": {"bucketName": "team-media", "secretS3": {"endpoint": "https://s3.local", "region": "us-east-1", "accessKeyID": "id", "accessSecretKey": "key"}}}`
+        }
+        else { "" }
+    }
+    fn s3_bucket(settings) {
+        if settings.bucket != "team-media" || settings.endpoint != "https://s3.local" || settings.secret_key != "key" {
+            throw `unexpected bucket settings ${settings}`;
+        }
+        settings
+    }
+"#;
+
+// ─── backup_prepare_s3 ──────────────────────────────────────────────────────
+
+#[test]
+fn backup_s3_empty_list_completes() {
+    let mut rhai = make_tenant_script();
+    let ctx = context(&[]);
+    let result = rhai.eval(&format!(r#"import "backup_prepare_s3" as bk; bk::run({ctx});"#));
+    assert!(result.is_ok(), "Expected success, got: {:?}", result.err());
+}
+
+#[test]
+fn backup_s3_mirrors_the_prefix() {
+    let mut rhai = make_tenant_script();
+    rhai.add_code(MOCK_ENV);
+    rhai.add_code(
+        r#"
+        fn download(prefix, dir, opts) {
+            if prefix != "uploads/" || dir != "/backup/s3_media" || opts.concurrency != 4 {
+                throw `unexpected download(${prefix}, ${dir}, ${opts})`;
+            }
+            #{objects: 2, bytes: 10, deleted: 0}
+        }
+        "#,
+    );
+    let ctx = context(&["media"]);
+    let result = rhai.eval(&format!(r#"import "backup_prepare_s3" as bk; bk::run({ctx});"#));
+    assert!(result.is_ok(), "Expected success, got: {:?}", result.err());
+}
+
+#[test]
+fn backup_s3_download_failure_throws() {
+    let mut rhai = make_tenant_script();
+    rhai.add_code(MOCK_ENV);
+    rhai.add_code(r#"fn download(prefix, dir, opts) { throw "size mismatch for uploads/logo.png"; }"#);
+    let ctx = context(&["media"]);
+    let result = rhai.eval(&format!(r#"import "backup_prepare_s3" as bk; bk::run({ctx});"#));
+    assert!(result.is_err(), "Expected error when the mirror fails");
+}
+
+// ─── restore_s3 ─────────────────────────────────────────────────────────────
+
+#[test]
+fn restore_s3_without_mirror_is_skipped() {
+    // No /backup/s3_media directory: the bucket is left untouched.
+    let mut rhai = make_tenant_script();
+    rhai.add_code(MOCK_ENV);
+    rhai.add_code(r#"fn upload(dir, prefix, opts) { throw "upload should not be called"; }"#);
+    let ctx = context(&["media"]);
+    let result = rhai.eval(&format!(r#"import "restore_s3" as rs; rs::run({ctx});"#));
+    assert!(result.is_ok(), "Expected success, got: {:?}", result.err());
+}
+
+#[test]
+fn restore_s3_uploads_the_mirror() {
+    let mut rhai = make_tenant_script();
+    rhai.add_code(MOCK_ENV);
+    rhai.add_code(
+        r#"
+        fn is_dir(path) { path == "/backup/s3_media" }
+        fn upload(dir, prefix, opts) {
+            if dir != "/backup/s3_media" || prefix != "uploads/" || !opts.delete_extra {
+                throw `unexpected upload(${dir}, ${prefix}, ${opts})`;
+            }
+            #{objects: 2, bytes: 10, deleted: 1}
+        }
+        "#,
+    );
+    let ctx = context(&["media"]);
+    let result = rhai.eval(&format!(r#"import "restore_s3" as rs; rs::run({ctx});"#));
+    assert!(result.is_ok(), "Expected success, got: {:?}", result.err());
+}
@@ -590,6 +590,40 @@ fn install_from_dir_empty_directory_no_objects() {
     assert_eq!(created_objs.len(), 0);
 }
 
+#[test]
+fn install_from_dir_keeps_unchanged_objects() {
+    // Verify install_obj() keeps the previous entry of an unchanged object and applies a changed one
+    let (mut rhai, _created) = make_lib_script_with_k8s(vec![]);
+
+    let result = rhai
+        .eval(
+            r#"
+        import "install_from_dir" as install;
+
+        let obj = #{
+            apiVersion: "v1",
+            kind: "ConfigMap",
+            metadata: #{ name: "test-config", namespace: "test-ns" },
+            data: #{ key: "value" }
+        };
+        let first = install::install_obj(obj, true, "test-ns", false);
+        let previous = [first[0]];
+        previous[0].uid = "kept";
+
+        let same = install::install_obj(obj, true, "test-ns", false, previous);
+        obj.data.key = "changed";
+        let changed = install::install_obj(obj, true, "test-ns", false, previous);
+
+        [same[0].uid, changed[0].hash != first[0].hash && changed[0].uid != "kept"]
+    "#,
+        )
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    assert_eq!(res[0].clone().into_string().unwrap(), "kept");
+    assert!(res[1].as_bool().unwrap());
+}
+
 #[test]
 fn install_from_dir_respects_namespace_parameter() {
     // Verify force_ns=true forces namespace on all objects regardless of YAML
@@ -1206,6 +1240,153 @@ fn backup_context_reduce_handles_empty_list() {
     );
 }
 
+#[test]
+fn backup_context_policy_merges_spec_over_secret_and_maintenance_window() {
+    // Verify policy() takes the instance retention over the RESTIC_MAX_* of the secret,
+    // and defaults the schedule to the maintenance window
+    let secret = dynamic_from_json(serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": { "name": "backup-settings", "namespace": "test-ns" },
+        "data": { "RESTIC_MAX_DAYLY": "MTQ=", "RESTIC_MAX_WEEKLY": "Mg==" }
+    }));
+    let (mut rhai, _) = make_lib_script_with_k8s(vec![secret]);
+    let result = rhai
+        .eval(
+            r#"
+        import "backup_context" as ctx;
+
+        let instance = #{
+            metadata: #{ namespace: "test-ns", name: "wiki" },
+            spec: #{ backup: #{ retention: #{ hourly: 24, weekly: 4 } } }
+        };
+        let context = #{
+            namespace: #{ maintenance_start_hour: "1", maintenance_start_minut: "30" }
+        };
+        let policy = ctx::policy(instance, context);
+
+        policy.enabled == true &&
+        policy.schedule == "30 1 * * *" &&
+        policy.secretName == "backup-settings" &&
+        policy.retention.hourly == 24 &&
+        policy.retention.daily == 14 &&
+        policy.retention.weekly == 4 &&
+        policy.retention.monthly == 12
+    "#,
+        )
+        .unwrap();
+
+    assert!(
+        result.as_bool().unwrap(),
+        "backup_context policy must merge the instance spec over the secret settings"
+    );
+}
+
+#[test]
+fn backup_context_repository_passes_insecure_tls_to_the_repository_options() {
+    let (mut rhai, _) = make_lib_script_with_k8s(vec![]);
+
+    let result = rhai
+        .eval(
+            r#"
+        import "backup_context" as ctx;
+        let settings = #{
+            BASE_REPO_URL: "https://minio.local/backups/",
+            RESTIC_PASSWORD: "secret",
+            INSECURE_TLS: "true",
+            AWS_ACCESS_KEY_ID: "key",
+        };
+        let secure = ctx::repository(#{}, #{ BASE_REPO_URL: "https://minio.local/backups" }, "ns/app");
+        let context = ctx::repository(#{}, settings, "/ns/app/");
+        [context.s3_url, context.repo_options, secure.repo_options]
+    "#,
+        )
+        .unwrap();
+
+    let result = serde_json::to_value(&result).unwrap();
+    assert_eq!(result[0], "s3:https://minio.local/backups/ns/app");
+    assert_eq!(result[1]["insecure_tls"], "true");
+    assert_eq!(result[1]["access_key_id"], "key");
+    assert!(result[2].get("insecure_tls").is_none());
+}
+
+#[test]
+fn backup_context_clone_secret_copies_the_source_repository_secret() {
+    let k8s_mocks = vec![dynamic_from_json(serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": { "name": "backup-settings", "namespace": "prod" },
+        "data": { "RESTIC_PASSWORD": "c2VjcmV0" }
+    }))];
+    let (mut rhai, created) = make_lib_script_with_k8s(k8s_mocks);
+
+    let result = rhai
+        .eval(
+            r#"
+        import "backup_context" as ctx;
+        let instance = #{ metadata: #{ namespace: "staging", name: "wiki" } };
+        let context = #{ instance: #{ appslug: "wiki" } };
+        ctx::clone_secret(instance, context, "prod", "backup-settings")
+    "#,
+        )
+        .unwrap();
+
+    assert_eq!(result.into_string().unwrap(), "wiki-init-from");
+    let created_objs = created.lock().unwrap();
+    assert_eq!(created_objs.len(), 1);
+    let secret = serde_json::to_value(&created_objs[0]).unwrap();
+    assert_eq!(secret["metadata"]["namespace"], "staging");
+    assert_eq!(
+        secret["metadata"]["labels"]["vynil.solidite.fr/init-from"],
+        "prod.backup-settings"
+    );
+    assert_eq!(secret["data"]["RESTIC_PASSWORD"], "c2VjcmV0");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backup_context_prune_records_deletes_the_records_of_forgotten_snapshots() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("data.txt"), "some data").unwrap();
+    let (mut rhai, _) = make_lib_script_with_k8s(vec![]);
+
+    let result = rhai
+        .eval(&format!(
+            r#"
+        import "backup_context" as ctx;
+        let repo = backup_repo_open("{repo}", "secret", #{{ create: true }});
+        let first = backup_repo_snapshot(repo, "{source}", "", "wiki");
+        let second = backup_repo_snapshot(repo, "{source}", "", "wiki");
+        let api = k8s_resource("VynilBackup", "prod");
+        for snap in [first, second] {{
+            api.create(#{{
+                apiVersion: "vynil.solidite.fr/v1",
+                kind: "VynilBackup",
+                metadata: #{{ name: `wiki-${{snap.id}}`, namespace: "prod" }},
+                spec: #{{
+                    instance: #{{ kind: "TenantInstance", name: "wiki" }},
+                    secretName: "backup-settings",
+                    subPath: "prod/wiki",
+                    snapshot: snap.id,
+                }}
+            }});
+        }}
+        backup_repo_forget(repo, #{{ last: 1 }});
+        let context = #{{ namespace: "prod", instance: "wiki", secret_name: "backup-settings", sub_path: "prod/wiki" }};
+        ctx::prune_records(context, "TenantInstance", repo) == [`wiki-${{first.id}}`]
+    "#,
+            repo = dir.path().join("repo").display(),
+            source = source.display(),
+        ))
+        .unwrap();
+
+    assert!(
+        result.as_bool().unwrap(),
+        "backup_context prune_records must delete the record of the forgotten snapshot only"
+    );
+}
+
 // ===== resolv_service.rhai tests =====
 
 #[test]
@@ -4250,3 +4431,547 @@ fn clean_metadata_removes_all_helm_sh_annotations() {
         "clean_metadata doit supprimer TOUTES les annotations contenant 'helm.sh', y compris resource-policy"
     );
 }
+
+// ===== value_from.rhai tests =====
+
+#[test]
+fn value_from_resolves_secret_and_configmap_keys() {
+    // Verify resolve() replaces the valueFrom references anywhere in the options tree
+    let k8s_mocks = vec![
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "smtp", "namespace": "test-ns" },
+            "data": { "password": "c2VjcmV0" }
+        })),
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "dns", "namespace": "test-ns" },
+            "data": { "domain": "example.com" }
+        })),
+    ];
+    let (mut rhai, _created) = make_lib_script_with_k8s(k8s_mocks);
+
+    let result = rhai
+        .eval(
+            r#"
+        import "value_from" as value_from;
+
+        let options = #{
+            smtp: #{ password: #{ valueFrom: #{ secretKeyRef: #{ name: "smtp", key: "password" } } } },
+            domains: [#{ valueFrom: #{ configMapKeyRef: #{ name: "dns", key: "domain" } } }],
+            missing: #{ valueFrom: #{ secretKeyRef: #{ name: "absent", key: "key", optional: true } } },
+            replicas: 2
+        };
+        let values = value_from::resolve(options, "test-ns");
+        [values.smtp.password, values.domains[0], values.missing == (), values.replicas]
+    "#,
+        )
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    assert_eq!(res[0].clone().into_string().unwrap(), "secret");
+    assert_eq!(res[1].clone().into_string().unwrap(), "example.com");
+    assert!(res[2].as_bool().unwrap());
+    assert_eq!(res[3].as_int().unwrap(), 2);
+}
+
+#[test]
+fn value_from_fails_on_a_missing_required_key() {
+    let (mut rhai, _created) = make_lib_script_with_k8s(vec![]);
+
+    let result = rhai.eval(
+        r#"
+        import "value_from" as value_from;
+        value_from::resolve(#{ password: #{ valueFrom: #{ secretKeyRef: #{ name: "absent", key: "key" } } } }, "test-ns")
+    "#,
+    );
+
+    assert!(result.is_err());
+}
+
+// ===== drill_sandbox.rhai tests =====
+
+#[test]
+fn drill_sandbox_namespace_name_is_a_valid_namespace() {
+    let mut rhai = make_lib_script();
+
+    let result = rhai
+        .eval(
+            r#"
+        import "drill_sandbox" as sandbox;
+        [
+            sandbox::namespace_name(#{ metadata: #{ namespace: "team", name: "wiki" } }),
+            sandbox::namespace_name(#{ metadata: #{ namespace: "a-very-long-namespace-name-for-the-team", name: "wiki-instance-ab-cd" } }),
+        ]
+    "#,
+        )
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    assert_eq!(res[0].clone().into_string().unwrap(), "drill-team-wiki");
+    let long = res[1].clone().into_string().unwrap();
+    assert_eq!(
+        long,
+        "drill-a-very-long-namespace-name-for-the-team-wiki-instance-ab"
+    );
+    assert!(long.len() <= 63 && !long.ends_with('-'));
+}
+
+#[test]
+fn drill_sandbox_restores_the_latest_successful_backup() {
+    let backup = |name: &str, started: &str, outcome: &str, snapshot: Option<&str>| {
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "vynil.solidite.fr/v1",
+            "kind": "VynilBackup",
+            "metadata": { "name": name, "namespace": "team", "labels": { "vynil.solidite.fr/instance": "wiki" } },
+            "spec": {
+                "instance": { "kind": "TenantInstance", "name": "wiki" },
+                "secretName": "backup-settings",
+                "subPath": "team/wiki",
+                "version": "1.2.0",
+                "snapshot": snapshot,
+                "startedAt": started,
+                "outcome": outcome
+            }
+        }))
+    };
+    let k8s_mocks = vec![
+        backup("wiki-1", "2026-10-01T02:00:00+00:00", "Succeeded", Some("aaaa")),
+        backup("wiki-3", "2026-10-03T02:00:00+00:00", "Failed", None),
+        backup("wiki-2", "2026-10-02T02:00:00+00:00", "Succeeded", Some("bbbb")),
+    ];
+    let (mut rhai, _created) = make_lib_script_with_k8s(k8s_mocks);
+
+    let result = rhai
+        .eval(
+            r#"
+        import "drill_sandbox" as sandbox;
+        let instance = #{ metadata: #{ namespace: "team", name: "wiki" }, spec: #{ backup: #{ drill: #{ schedule: "@weekly" } } } };
+        let record = sandbox::latest_backup(instance, "TenantInstance");
+        let spec = sandbox::sandbox_spec(instance, #{ snapshot: record.spec.snapshot });
+        [record.metadata.name, spec.backup.enabled, spec.initFrom.snapshot]
+    "#,
+        )
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    assert_eq!(res[0].clone().into_string().unwrap(), "wiki-2");
+    assert!(!res[1].as_bool().unwrap());
+    assert_eq!(res[2].clone().into_string().unwrap(), "bbbb");
+}
+
+#[test]
+fn drill_sandbox_joins_the_tenant_copies_the_references_and_moves_the_hosts() {
+    let k8s_mocks = vec![
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": { "name": "team", "labels": {
+                "kubernetes.io/metadata.name": "team",
+                "vynil.solidite.fr/tenant": "acme"
+            } }
+        })),
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "backup-settings", "namespace": "team" },
+            "data": { "RESTIC_PASSWORD": "c2VjcmV0" }
+        })),
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "wiki-smtp", "namespace": "team" },
+            "type": "Opaque",
+            "data": { "password": "cGFzcw==" }
+        })),
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "cluster-dns", "namespace": "team" },
+            "data": { "domain": "apps.example.com" }
+        })),
+    ];
+    let (mut rhai, created) = make_lib_script_with_k8s(k8s_mocks);
+
+    let result = rhai
+        .eval(
+            r#"
+        import "drill_sandbox" as sandbox;
+        let instance = #{
+            metadata: #{ namespace: "team", name: "wiki" },
+            spec: #{ options: #{
+                domain: #{ valueFrom: #{ configMapKeyRef: #{ name: "cluster-dns", key: "domain" } } },
+                ingress: #{ host: "wiki.example.com", hosts: ["*.wiki.example.com"], className: "nginx" },
+                smtp: #{ password: #{ valueFrom: #{ secretKeyRef: #{ name: "wiki-smtp", key: "password" } } } },
+            } },
+        };
+        let namespace = sandbox::sandbox_namespace(instance, "drill-team-wiki", "team.wiki");
+        sandbox::seed(instance, "drill-team-wiki", "backup-settings");
+        let spec = sandbox::sandbox_spec(instance, #{ snapshot: "latest" });
+        [
+            namespace.metadata.labels["vynil.solidite.fr/tenant"],
+            namespace.metadata.labels["vynil.solidite.fr/drill"],
+            "kubernetes.io/metadata.name" in namespace.metadata.labels,
+            spec.options.domain,
+            spec.options.ingress.host,
+            spec.options.ingress.hosts[0],
+            spec.options.ingress.className,
+            spec.options.smtp.password.valueFrom.secretKeyRef.name,
+        ]
+    "#,
+        )
+        .unwrap();
+
+    let res: Vec<String> = result
+        .into_array()
+        .unwrap()
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect();
+    assert_eq!(
+        res,
+        [
+            "acme",
+            "team.wiki",
+            "false",
+            "apps-drill.example.com",
+            "wiki-drill.example.com",
+            "*.wiki-drill.example.com",
+            "nginx",
+            "wiki-smtp",
+        ]
+    );
+    let created_objs = created.lock().unwrap();
+    let mut copies: Vec<String> = created_objs
+        .iter()
+        .map(|o| {
+            let o = o.clone().cast::<rhai::Map>();
+            let meta = o["metadata"].clone().cast::<rhai::Map>();
+            format!("{} {}/{}", o["kind"], meta["namespace"], meta["name"])
+        })
+        .collect();
+    copies.sort();
+    assert_eq!(
+        copies,
+        [
+            "ConfigMap drill-team-wiki/cluster-dns",
+            "Secret drill-team-wiki/backup-settings",
+            "Secret drill-team-wiki/wiki-smtp",
+        ]
+    );
+}
+
+// ===== instance export tests =====
+
+#[test]
+fn instance_export_archive_describes_the_instance_and_its_backup() {
+    let mut rhai = make_lib_script();
+
+    let result = rhai
+        .eval(
+            r#"
+        let instance = #{
+            metadata: #{ namespace: "prod", name: "wiki" },
+            spec: #{
+                jukebox: "home-alpha", category: "apps", "package": "wiki",
+                options: #{
+                    domain: "wiki.example.com",
+                    smtp: #{ password: #{ valueFrom: #{ secretKeyRef: #{ name: "wiki-smtp", key: "password" } } } },
+                },
+                initFrom: #{ snapshot: "old" },
+            },
+            status: #{
+                tag: "1.2.0",
+                effectiveOptions: #{ domain: "wiki.example.com", smtp: #{ password: "s3cr3t" } },
+                services: [#{ key: "wiki" }],
+            },
+        };
+        let record = #{
+            metadata: #{ name: "wiki-20261018020000" },
+            spec: #{ secretName: "backup-settings", subPath: "prod/wiki", snapshot: "4bd2e7f1", version: "1.2.0", startedAt: "2026-10-18T02:00:00Z" },
+        };
+        let archive = instance_export_archive(instance, "TenantInstance", record);
+        [
+            archive.kind,
+            archive["package"].tag,
+            "initFrom" in archive.spec,
+            archive.references[0].name,
+            archive.spec.options.smtp.password.valueFrom.secretKeyRef.name,
+            archive.backup.snapshot,
+            archive.services.len(),
+        ]
+    "#,
+        )
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    assert_eq!(res[0].clone().into_string().unwrap(), "InstanceExport");
+    assert_eq!(res[1].clone().into_string().unwrap(), "1.2.0");
+    assert!(!res[2].as_bool().unwrap());
+    assert_eq!(res[3].clone().into_string().unwrap(), "wiki-smtp");
+    assert_eq!(res[4].clone().into_string().unwrap(), "wiki-smtp");
+    assert_eq!(res[5].clone().into_string().unwrap(), "4bd2e7f1");
+    assert_eq!(res[6].as_int().unwrap(), 1);
+}
+
+#[test]
+fn instance_export_import_restores_the_backup() {
+    let mut rhai = make_lib_script();
+
+    let result = rhai
+        .eval(
+            r#"
+        let archive = #{
+            kind: "InstanceExport",
+            source: #{ kind: "TenantInstance", namespace: "prod", name: "wiki" },
+            spec: #{ jukebox: "home-alpha", category: "apps", "package": "wiki", options: #{ domain: "wiki.example.com" } },
+            references: [],
+            backup: #{ secretName: "backup-settings", subPath: "prod/wiki", snapshot: "4bd2e7f1", version: "1.2.0" },
+            services: [],
+        };
+        let obj = instance_export_from_archive(archive, #{ namespace: "staging", jukebox: "home-beta" });
+        [
+            obj.kind,
+            obj.metadata.namespace,
+            obj.metadata.name,
+            obj.spec.jukebox,
+            obj.spec.options.domain,
+            obj.spec.initFrom.secretName,
+            obj.spec.initFrom.subPath,
+            obj.spec.initFrom.version,
+        ]
+    "#,
+        )
+        .unwrap();
+
+    let res: Vec<String> = result
+        .into_array()
+        .unwrap()
+        .into_iter()
+        .map(|v| v.into_string().unwrap())
+        .collect();
+    assert_eq!(
+        res,
+        [
+            "TenantInstance",
+            "staging",
+            "wiki",
+            "home-beta",
+            "wiki.example.com",
+            "backup-settings",
+            "prod/wiki",
+            "1.2.0"
+        ]
+    );
+}
+
+// ===== backup_keys.rhai tests =====
+
+#[test]
+fn backup_keys_lists_the_instances_using_the_secret() {
+    let tenant = |name: &str, package: &str, secret: Option<&str>| {
+        let mut spec = serde_json::json!({ "category": "apps", "package": package });
+        if let Some(secret) = secret {
+            spec["backup"] = serde_json::json!({ "secretName": secret });
+        }
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "vynil.solidite.fr/v1",
+            "kind": "TenantInstance",
+            "metadata": { "name": name, "namespace": "prod" },
+            "spec": spec
+        }))
+    };
+    let k8s_mocks = vec![
+        tenant("wiki", "wiki", None),
+        tenant("docs", "wiki", Some("backup-settings")),
+        tenant("chat", "chat", Some("other-settings")),
+        k8s_object("Deployment", "prod", "wiki"),
+    ];
+    let (mut rhai, _created) = make_lib_script_with_k8s(k8s_mocks);
+
+    let result = rhai
+        .eval(
+            r#"
+        import "backup_keys" as keys;
+        let secret = #{ data: #{ RESTIC_PASSWORD: base64_encode("s3cr3t") } };
+        let found = keys::instances("prod", "backup-settings");
+        [
+            keys::settings(secret).RESTIC_PASSWORD,
+            found.len(),
+            found[0].sub_path,
+            found[1].sub_path,
+            keys::instances("prod", "other-settings")[0].name,
+        ]
+    "#,
+        )
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    assert_eq!(res[0].clone().into_string().unwrap(), "s3cr3t");
+    assert_eq!(res[1].as_int().unwrap(), 2);
+    assert_eq!(res[2].clone().into_string().unwrap(), "prod/wiki");
+    assert_eq!(res[3].clone().into_string().unwrap(), "prod/docs-wiki");
+    assert_eq!(res[4].clone().into_string().unwrap(), "chat");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backup_keys_rotation_resumes_after_a_failed_repository() {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    let dir = tempfile::tempdir().unwrap();
+    let tenant = |name: &str, package: &str| {
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "vynil.solidite.fr/v1",
+            "kind": "TenantInstance",
+            "metadata": { "name": name, "namespace": "prod" },
+            "spec": { "category": "apps", "package": package }
+        }))
+    };
+    let k8s_mocks = vec![
+        tenant("wiki", "wiki"),
+        tenant("docs", "chat"),
+        dynamic_from_json(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "backup-settings", "namespace": "prod" },
+            "data": {
+                "BASE_REPO_URL": STANDARD.encode(dir.path().display().to_string()),
+                "RESTIC_PASSWORD": STANDARD.encode("old-pass"),
+            }
+        })),
+    ];
+    let (mut rhai, _created) = make_lib_script_with_k8s(k8s_mocks);
+
+    let result = rhai
+        .eval(&format!(
+            r#"
+        import "backup_keys" as keys;
+        backup_repo_open("{base}/prod/wiki", "old-pass", #{{ create: true }});
+        // the repository of docs does not open with the password of the secret
+        backup_repo_open("{base}/prod/docs-chat", "other-pass", #{{ create: true }});
+        let first = "";
+        try {{
+            keys::rotate("prod", "backup-settings", "new-pass");
+        }} catch (e) {{
+            first = e;
+        }}
+        let failed = keys::settings(k8s_resource("Secret", "prod").get("backup-settings"));
+        let failed_phase = get_tenant_instance("prod", "docs").status.keyRotation.phase;
+        backup_repo_add_key(backup_repo_open("{base}/prod/docs-chat", "other-pass"), "old-pass");
+        keys::rotate("prod", "backup-settings", ());
+        let done = keys::settings(k8s_resource("Secret", "prod").get("backup-settings"));
+        [
+            first.contains("docs"),
+            failed.RESTIC_PASSWORD,
+            failed.RESTIC_NEW_PASSWORD,
+            "RESTIC_OLD_PASSWORD" in failed,
+            failed_phase,
+            done.RESTIC_PASSWORD,
+            "RESTIC_NEW_PASSWORD" in done,
+            "RESTIC_OLD_PASSWORD" in done,
+            backup_repo_keys(backup_repo_open("{base}/prod/wiki", "new-pass")).len(),
+            backup_repo_keys(backup_repo_open("{base}/prod/docs-chat", "new-pass")).len(),
+            get_tenant_instance("prod", "wiki").status.keyRotation.phase,
+            get_tenant_instance("prod", "docs").status.keyRotation.phase,
+        ]
+    "#,
+            base = dir.path().display(),
+        ))
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    assert!(res[0].as_bool().unwrap(), "the first run must fail on docs");
+    assert_eq!(res[1].clone().into_string().unwrap(), "old-pass");
+    assert_eq!(res[2].clone().into_string().unwrap(), "new-pass");
+    assert!(!res[3].as_bool().unwrap());
+    assert_eq!(res[4].clone().into_string().unwrap(), "Failed");
+    assert_eq!(res[5].clone().into_string().unwrap(), "new-pass");
+    assert!(!res[6].as_bool().unwrap());
+    assert!(!res[7].as_bool().unwrap());
+    // the old key is removed, docs keeps the key it was created with
+    assert_eq!(res[8].as_int().unwrap(), 1);
+    assert_eq!(res[9].as_int().unwrap(), 2);
+    assert_eq!(res[10].clone().into_string().unwrap(), "Rotated");
+    assert_eq!(res[11].clone().into_string().unwrap(), "Rotated");
+}
+
+// ===== bundle.rhai tests =====
+
+#[test]
+fn bundle_get_objects_returns_one_instance_per_member() {
+    let mut rhai = make_lib_script();
+
+    let result = rhai
+        .eval(
+            r#"
+        import "bundle" as bundle;
+        let instance = #{ metadata: #{ name: "stack", namespace: "vynil-system" } };
+        let context = #{
+            config_dir: "/nonexistent",
+            package_dir: "/nonexistent",
+            domain: "example.com",
+            instance: #{
+                "package": #{
+                    jukebox: "home",
+                    members: [
+                        #{ name: "cnpg", "type": "system", category: "dbo", "package": "cnpg" },
+                        #{
+                            name: "pg", "type": "service", category: "dbo", "package": "pg",
+                            namespace: "db", jukebox: "other", wait: false,
+                            options: #{ host: "pg.{{domain}}", sizes: ["{{domain}}"], replicas: 2 },
+                        },
+                        #{ name: "wiki", "type": "tenant", category: "apps", "package": "wiki" },
+                    ],
+                },
+            },
+        };
+        let objects = bundle::get_objects(instance, context);
+        [
+            objects.len(),
+            objects.map(|o| o.kind),
+            objects.map(|o| o.metadata.namespace),
+            objects.map(|o| o.spec.jukebox),
+            objects.map(|o| o.wait),
+            objects[1].spec.options.host,
+            objects[1].spec.options.sizes[0],
+            objects[1].spec.options.replicas,
+            objects[0].spec.options.len(),
+            objects[2].metadata.labels["vynil.solidite.fr/bundle"],
+            objects[2].metadata.labels["vynil.solidite.fr/bundle-namespace"],
+        ]
+    "#,
+        )
+        .unwrap();
+
+    let res = result.into_array().unwrap();
+    let strings = |v: &Dynamic| -> Vec<String> {
+        v.clone()
+            .into_array()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.into_string().unwrap())
+            .collect()
+    };
+    assert_eq!(res[0].as_int().unwrap(), 3);
+    assert_eq!(
+        strings(&res[1]),
+        ["SystemInstance", "ServiceInstance", "TenantInstance"]
+    );
+    assert_eq!(strings(&res[2]), ["vynil-system", "db", "vynil-system"]);
+    assert_eq!(strings(&res[3]), ["home", "other", "home"]);
+    let waits: Vec<bool> = res[4]
+        .clone()
+        .into_array()
+        .unwrap()
+        .into_iter()
+        .map(|w| w.as_bool().unwrap())
+        .collect();
+    assert_eq!(waits, [true, false, true]);
+    assert_eq!(res[5].clone().into_string().unwrap(), "pg.example.com");
+    assert_eq!(res[6].clone().into_string().unwrap(), "example.com");
+    assert_eq!(res[7].as_int().unwrap(), 2);
+    assert_eq!(res[8].as_int().unwrap(), 0);
+    assert_eq!(res[9].clone().into_string().unwrap(), "stack");
+    assert_eq!(res[10].clone().into_string().unwrap(), "vynil-system");
+}
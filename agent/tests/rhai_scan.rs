@@ -249,7 +249,7 @@ fn scan_rhai_without_filter_runs_without_error() {
 
 #[test]
 fn partial_filter_no_match_does_not_full_scan() {
-    // When the filter matches no package in box.status.packages the script must NOT fall
+    // When the filter matches no package in box.packages the script must NOT fall
     // back to scanning the full image list — it must warn and call set_status_packages_merge
     // with an empty list.
     // Fails on current code: falls back to `list` → calls new_registry (which throws here).
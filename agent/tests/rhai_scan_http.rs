@@ -80,13 +80,13 @@ async fn scan_http_source_finds_package() {
         result.err()
     );
 
-    let pkg_count = script.eval("box.status.packages.len()").unwrap();
+    let pkg_count = script.eval("box.packages.len()").unwrap();
     assert_eq!(
         pkg_count.as_int().unwrap(),
         1,
         "expected 1 package found via Http source"
     );
-    let pkg_tag = script.eval(r#"box.status.packages[0].tag"#).unwrap();
+    let pkg_tag = script.eval(r#"box.packages[0].tag"#).unwrap();
     assert_eq!(pkg_tag.into_string().unwrap(), "1.0.0", "expected tag 1.0.0");
 }
 
@@ -113,7 +113,7 @@ async fn scan_http_source_filter_skips_other_categories() {
         result.err()
     );
 
-    let pkg_count = script.eval("box.status.packages.len()").unwrap();
+    let pkg_count = script.eval("box.packages.len()").unwrap();
     assert_eq!(
         pkg_count.as_int().unwrap(),
         1,
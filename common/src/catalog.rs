@@ -0,0 +1,191 @@
+use crate::{Error, Result, context::get_client_name, jukebox::JukeBox, vynilpackage::VynilPackage};
+use kube::{
+    Client, CustomResource, Resource, ResourceExt,
+    api::{Api, DeleteParams, ListParams, Patch, PatchParams},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Label holding the JukeBox name on the catalog entries
+pub const JUKEBOX_LABEL: &str = "vynil.solidite.fr/jukebox";
+
+/// Catalog entry: every version of one package of a JukeBox
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    kind = "VynilPackage",
+    root = "CatalogPackage",
+    shortname = "vpkg",
+    group = "vynil.solidite.fr",
+    version = "v1"
+)]
+#[kube(
+    doc = "Custom resource holding the versions of a package found by a JukeBox scan",
+    printcolumn = r#"{"name":"juke", "type":"string", "description":"JukeBox",  "jsonPath":".spec.jukebox"}"#,
+    printcolumn = r#"{"name":"cat",  "type":"string", "description":"Category", "jsonPath":".spec.category"}"#,
+    printcolumn = r#"{"name":"pkg",  "type":"string", "description":"Package",  "jsonPath":".spec.name"}"#
+)]
+pub struct CatalogPackageSpec {
+    /// The JukeBox that found this package
+    pub jukebox: String,
+    /// The category name
+    pub category: String,
+    /// The package name
+    pub name: String,
+    /// Upgrade waypoints of the package
+    pub versions: Vec<VynilPackage>,
+}
+
+/// Catalog summary kept in the JukeBox status
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, Default, JsonSchema)]
+pub struct CatalogSummary {
+    /// Number of packages
+    pub packages: u32,
+    /// Number of package versions
+    pub versions: u32,
+}
+
+/// Name of the catalog entry of a package
+pub fn entry_name(jukebox: &str, category: &str, name: &str) -> String {
+    format!("{jukebox}.{category}.{name}")
+        .to_lowercase()
+        .replace('_', "-")
+}
+
+/// Group the versions by category/name
+pub fn group(packages: Vec<VynilPackage>) -> BTreeMap<(String, String), Vec<VynilPackage>> {
+    let mut ret: BTreeMap<(String, String), Vec<VynilPackage>> = BTreeMap::new();
+    for p in packages {
+        ret.entry((p.metadata.category.clone(), p.metadata.name.clone()))
+            .or_default()
+            .push(p);
+    }
+    ret
+}
+
+/// List the catalog entries, of a single JukeBox or of all of them
+pub async fn list(client: Client, jukebox: Option<&str>) -> Result<Vec<CatalogPackage>> {
+    let api = Api::<CatalogPackage>::all(client);
+    let lp = match jukebox {
+        Some(j) => ListParams::default().labels(&format!("{JUKEBOX_LABEL}={j}")),
+        None => ListParams::default(),
+    };
+    Ok(api.list(&lp).await.map_err(Error::KubeError)?.items)
+}
+
+/// Load the packages of a JukeBox
+pub async fn load(client: Client, jukebox: &str) -> Result<Vec<VynilPackage>> {
+    Ok(list(client, Some(jukebox))
+        .await?
+        .into_iter()
+        .flat_map(|e| e.spec.versions)
+        .collect())
+}
+
+/// Load the packages of every JukeBox, keyed by JukeBox name
+pub async fn load_all(client: Client) -> Result<BTreeMap<String, Vec<VynilPackage>>> {
+    let mut ret: BTreeMap<String, Vec<VynilPackage>> = BTreeMap::new();
+    for e in list(client, None).await? {
+        ret.entry(e.spec.jukebox).or_default().extend(e.spec.versions);
+    }
+    Ok(ret)
+}
+
+/// Replace the catalog of a JukeBox, removing the entries that are not in `packages`
+pub async fn store(client: Client, jukebox: &JukeBox, packages: Vec<VynilPackage>) -> Result<CatalogSummary> {
+    let api = Api::<CatalogPackage>::all(client.clone());
+    let juke = jukebox.name_any();
+    let owner = jukebox
+        .controller_owner_ref(&())
+        .ok_or(Error::Other("JukeBox without uid".to_string()))?;
+    let params = PatchParams::apply(&get_client_name()).force();
+    let mut summary = CatalogSummary::default();
+    let mut written = BTreeSet::new();
+    for ((category, name), versions) in group(packages) {
+        let entry = entry_name(&juke, &category, &name);
+        let mut labels = serde_json::Map::new();
+        labels.insert(JUKEBOX_LABEL.to_string(), juke.clone().into());
+        summary.packages += 1;
+        summary.versions += versions.len() as u32;
+        api.patch(
+            &entry,
+            &params,
+            &Patch::Apply(json!({
+                "apiVersion": "vynil.solidite.fr/v1",
+                "kind": "VynilPackage",
+                "metadata": {
+                    "name": entry,
+                    "labels": labels,
+                    "ownerReferences": [owner],
+                },
+                "spec": {
+                    "jukebox": juke,
+                    "category": category,
+                    "name": name,
+                    "versions": versions,
+                },
+            })),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+        written.insert(entry);
+    }
+    for e in list(client, Some(&juke)).await? {
+        if written.contains(&e.name_any()) {
+            continue;
+        }
+        match api.delete(&e.name_any(), &DeleteParams::default()).await {
+            Ok(_) => {}
+            Err(kube::Error::Api(err)) if err.code == 404 => {}
+            Err(err) => return Err(Error::KubeError(err)),
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vynilpackage::{VynilPackageMeta, VynilPackageType};
+
+    fn make_pkg(category: &str, name: &str, tag: &str) -> VynilPackage {
+        VynilPackage {
+            registry: String::new(),
+            image: String::new(),
+            tag: tag.to_string(),
+            metadata: VynilPackageMeta {
+                name: name.to_string(),
+                category: category.to_string(),
+                description: String::new(),
+                app_version: None,
+                usage: VynilPackageType::default(),
+                features: vec![],
+                backup_affinity: None,
+            },
+            requirements: vec![],
+            recommandations: None,
+            options: None,
+            value_script: None,
+            migrations: None,
+            options_migrate: None,
+        }
+    }
+
+    #[test]
+    fn group_keeps_every_waypoint() {
+        let grouped = group(vec![
+            make_pkg("db", "pg", "1.0.0"),
+            make_pkg("db", "pg", "2.0.0"),
+            make_pkg("db", "mysql", "1.0.0"),
+        ]);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[&("db".to_string(), "pg".to_string())].len(), 2);
+    }
+
+    #[test]
+    fn entry_name_is_a_valid_object_name() {
+        assert_eq!(entry_name("home", "db", "pg"), "home.db.pg");
+        assert_eq!(entry_name("Home", "my_cat", "pkg"), "home.my-cat.pkg");
+    }
+}
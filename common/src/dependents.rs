@@ -0,0 +1,350 @@
+use crate::{
+    Error, Result, catalog,
+    instanceservice::ServiceInstance,
+    instancesystem::SystemInstance,
+    instancetenant::TenantInstance,
+    vynilpackage::{VynilPackage, VynilPackageRequirement, VynilPackageType},
+};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{
+    Client, ResourceExt,
+    api::{Api, ListParams},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Minimal view of an installed instance, whatever its kind
+#[derive(Clone, Debug)]
+pub struct InstalledInstance {
+    pub kind: &'static str,
+    pub namespace: String,
+    pub name: String,
+    pub jukebox: String,
+    pub category: String,
+    pub package: String,
+    pub usage: VynilPackageType,
+    /// Keys of the services published by this instance
+    pub services: Vec<String>,
+    pub deleting: bool,
+}
+
+impl InstalledInstance {
+    pub fn is(&self, kind: &str, namespace: &str, name: &str) -> bool {
+        self.kind == kind && self.namespace == namespace && self.name == name
+    }
+}
+
+/// An instance that would break if another one was deleted
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dependent {
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    /// The requirement the other instance fulfills
+    pub requirement: String,
+}
+
+impl std::fmt::Display for Dependent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}/{} ({})", self.kind, self.namespace, self.name, self.requirement)
+    }
+}
+
+/// Returns the instance kind matching a plural resource name
+pub fn kind_from_plural(plural: &str) -> Option<&'static str> {
+    match plural {
+        "tenantinstances" => Some("TenantInstance"),
+        "serviceinstances" => Some("ServiceInstance"),
+        "systeminstances" => Some("SystemInstance"),
+        _ => None,
+    }
+}
+
+/// Lists the namespaces sharing the tenant of `namespace` (through the TENANT_LABEL label)
+pub async fn tenant_namespaces(client: Client, namespace: &str) -> Result<Vec<String>> {
+    let ns_api: Api<Namespace> = Api::all(client);
+    let ns_meta = ns_api.get_metadata(namespace).await.map_err(Error::KubeError)?;
+    let label_key = std::env::var("TENANT_LABEL").unwrap_or_else(|_| "vynil.solidite.fr/tenant".to_string());
+    if let Some(labels) = ns_meta.metadata.labels
+        && let Some(tenant_name) = labels.get(&label_key)
+    {
+        let lp = ListParams::default().labels(format!("{}=={}", label_key, tenant_name).as_str());
+        let nss = ns_api.list_metadata(&lp).await.map_err(Error::KubeError)?;
+        return Ok(nss.items.into_iter().filter_map(|n| n.metadata.name).collect());
+    }
+    Ok(vec![namespace.to_string()])
+}
+
+/// Name of the tenant of `namespace`: its TENANT_LABEL label, or the namespace itself
+pub async fn tenant_name(client: Client, namespace: &str) -> Result<String> {
+    let ns_api: Api<Namespace> = Api::all(client);
+    let ns_meta = ns_api.get_metadata(namespace).await.map_err(Error::KubeError)?;
+    let label_key = std::env::var("TENANT_LABEL").unwrap_or_else(|_| "vynil.solidite.fr/tenant".to_string());
+    Ok(ns_meta
+        .metadata
+        .labels
+        .and_then(|mut labels| labels.remove(&label_key))
+        .unwrap_or_else(|| namespace.to_string()))
+}
+
+/// Lists every vynil instance of the cluster
+pub async fn list_installed(client: Client) -> Result<Vec<InstalledInstance>> {
+    let lp = ListParams::default();
+    let mut res = Vec::new();
+    let api: Api<TenantInstance> = Api::all(client.clone());
+    for i in api.list(&lp).await.map_err(Error::KubeError)? {
+        res.push(InstalledInstance {
+            kind: "TenantInstance",
+            namespace: i.namespace().unwrap_or_default(),
+            name: i.name_any(),
+            services: i.get_services().into_iter().map(|s| s.key).collect(),
+            deleting: i.metadata.deletion_timestamp.is_some(),
+            jukebox: i.spec.jukebox,
+            category: i.spec.category,
+            package: i.spec.package,
+            usage: VynilPackageType::Tenant,
+        });
+    }
+    let api: Api<ServiceInstance> = Api::all(client.clone());
+    for i in api.list(&lp).await.map_err(Error::KubeError)? {
+        res.push(InstalledInstance {
+            kind: "ServiceInstance",
+            namespace: i.namespace().unwrap_or_default(),
+            name: i.name_any(),
+            services: i.get_services().into_iter().map(|s| s.key).collect(),
+            deleting: i.metadata.deletion_timestamp.is_some(),
+            jukebox: i.spec.jukebox,
+            category: i.spec.category,
+            package: i.spec.package,
+            usage: VynilPackageType::Service,
+        });
+    }
+    let api: Api<SystemInstance> = Api::all(client);
+    for i in api.list(&lp).await.map_err(Error::KubeError)? {
+        res.push(InstalledInstance {
+            kind: "SystemInstance",
+            namespace: i.namespace().unwrap_or_default(),
+            name: i.name_any(),
+            services: Vec::new(),
+            deleting: i.metadata.deletion_timestamp.is_some(),
+            jukebox: i.spec.jukebox,
+            category: i.spec.category,
+            package: i.spec.package,
+            usage: VynilPackageType::System,
+        });
+    }
+    Ok(res)
+}
+
+/// Returns the requirements of the package an instance is using, from a jukebox catalog
+pub fn package_requirements(
+    catalog: &BTreeMap<String, Vec<VynilPackage>>,
+    inst: &InstalledInstance,
+) -> Vec<VynilPackageRequirement> {
+    catalog
+        .get(&inst.jukebox)
+        .and_then(|packages| {
+            packages.iter().find(|p| {
+                p.metadata.category == inst.category
+                    && p.metadata.name == inst.package
+                    && (p.metadata.usage == inst.usage
+                        || (inst.usage == VynilPackageType::System
+                            && p.metadata.usage == VynilPackageType::Bundle))
+            })
+        })
+        .map(|p| p.requirements.clone())
+        .unwrap_or_default()
+}
+
+/// Computes the instances whose requirements are only fulfilled by `target`.
+///
+/// `tenant_namespaces` are the namespaces of the tenant `target` belongs to; TenantPackage and
+/// TenantService requirements only count within them. A requirement also fulfilled by another
+/// (non-deleting) instance doesn't make a dependent.
+pub fn find_dependents<F>(
+    target: &InstalledInstance,
+    tenant_namespaces: &[String],
+    installed: &[InstalledInstance],
+    requirements_of: F,
+) -> Vec<Dependent>
+where
+    F: Fn(&InstalledInstance) -> Vec<VynilPackageRequirement>,
+{
+    let others: Vec<&InstalledInstance> = installed
+        .iter()
+        .filter(|i| !i.deleting && !i.is(target.kind, &target.namespace, &target.name))
+        .collect();
+    let in_tenant = |i: &InstalledInstance| tenant_namespaces.contains(&i.namespace);
+    let is_package = |i: &InstalledInstance, usage: VynilPackageType, category: &str, name: &str| {
+        i.usage == usage && i.category == category && i.package == name
+    };
+    let mut res = Vec::new();
+    for &dep in &others {
+        let found = requirements_of(dep).into_iter().find_map(|req| match &req {
+            VynilPackageRequirement::SystemPackage { category, name }
+                if is_package(target, VynilPackageType::System, category, name)
+                    && !others
+                        .iter()
+                        .any(|&o| is_package(o, VynilPackageType::System, category, name)) =>
+            {
+                Some(format!("system package {category}/{name}"))
+            }
+            VynilPackageRequirement::TenantPackage { category, name }
+                if dep.usage == VynilPackageType::Tenant
+                    && in_tenant(dep)
+                    && is_package(target, VynilPackageType::Tenant, category, name)
+                    && !others
+                        .iter()
+                        .any(|&o| in_tenant(o) && is_package(o, VynilPackageType::Tenant, category, name)) =>
+            {
+                Some(format!("tenant package {category}/{name}"))
+            }
+            VynilPackageRequirement::SystemService(svc)
+                if target.usage == VynilPackageType::Service
+                    && target.services.contains(svc)
+                    && !others
+                        .iter()
+                        .any(|&o| o.usage == VynilPackageType::Service && o.services.contains(svc)) =>
+            {
+                Some(format!("system service {svc}"))
+            }
+            VynilPackageRequirement::TenantService(svc)
+                if dep.usage == VynilPackageType::Tenant
+                    && in_tenant(dep)
+                    && target.usage == VynilPackageType::Tenant
+                    && target.services.contains(svc)
+                    && !others.iter().any(|&o| {
+                        o.usage == VynilPackageType::Tenant && in_tenant(o) && o.services.contains(svc)
+                    }) =>
+            {
+                Some(format!("tenant service {svc}"))
+            }
+            _ => None,
+        });
+        if let Some(requirement) = found {
+            res.push(Dependent {
+                kind: dep.kind.to_string(),
+                namespace: dep.namespace.clone(),
+                name: dep.name.clone(),
+                requirement,
+            });
+        }
+    }
+    res
+}
+
+/// Computes the dependents of an instance, reading the packages catalog of the JukeBoxes
+pub async fn get_dependents(client: Client, kind: &str, namespace: &str, name: &str) -> Result<Vec<Dependent>> {
+    let installed = list_installed(client.clone()).await?;
+    let Some(target) = installed.iter().find(|i| i.is(kind, namespace, name)).cloned() else {
+        return Err(Error::Other(format!("{kind} {namespace}/{name} not found")));
+    };
+    let tenant_nss = if target.usage == VynilPackageType::Tenant {
+        tenant_namespaces(client.clone(), namespace).await?
+    } else {
+        vec![namespace.to_string()]
+    };
+    let catalog: BTreeMap<String, Vec<VynilPackage>> = catalog::load_all(client).await?;
+    Ok(find_dependents(&target, &tenant_nss, &installed, |i| {
+        package_requirements(&catalog, i)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inst(kind: &'static str, ns: &str, name: &str, usage: VynilPackageType, pkg: &str) -> InstalledInstance {
+        InstalledInstance {
+            kind,
+            namespace: ns.to_string(),
+            name: name.to_string(),
+            jukebox: "jb".to_string(),
+            category: "cat".to_string(),
+            package: pkg.to_string(),
+            usage,
+            services: vec![],
+            deleting: false,
+        }
+    }
+
+    fn requires(reqs: Vec<(&str, VynilPackageRequirement)>) -> impl Fn(&InstalledInstance) -> Vec<VynilPackageRequirement> {
+        move |i| {
+            reqs.iter()
+                .filter(|(n, _)| *n == i.name)
+                .map(|(_, r)| r.clone())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_system_service_dependent() {
+        let mut pg = inst("ServiceInstance", "db", "pg", VynilPackageType::Service, "postgresql");
+        pg.services = vec!["pg".to_string()];
+        let app = inst("TenantInstance", "t1", "app", VynilPackageType::Tenant, "app");
+        let installed = vec![pg.clone(), app];
+        let deps = find_dependents(
+            &pg,
+            &["db".to_string()],
+            &installed,
+            requires(vec![("app", VynilPackageRequirement::SystemService("pg".to_string()))]),
+        );
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].to_string(), "TenantInstance t1/app (system service pg)");
+    }
+
+    #[test]
+    fn test_alternative_provider_is_not_a_dependent() {
+        let mut pg = inst("ServiceInstance", "db", "pg", VynilPackageType::Service, "postgresql");
+        pg.services = vec!["pg".to_string()];
+        let mut pg2 = inst("ServiceInstance", "db2", "pg", VynilPackageType::Service, "postgresql");
+        pg2.services = vec!["pg".to_string()];
+        let app = inst("TenantInstance", "t1", "app", VynilPackageType::Tenant, "app");
+        let installed = vec![pg.clone(), pg2, app];
+        let deps = find_dependents(
+            &pg,
+            &["db".to_string()],
+            &installed,
+            requires(vec![("app", VynilPackageRequirement::SystemService("pg".to_string()))]),
+        );
+        assert!(deps.is_empty());
+    }
+
+    #[test]
+    fn test_tenant_package_scoped_to_tenant() {
+        let redis = inst("TenantInstance", "t1", "redis", VynilPackageType::Tenant, "redis");
+        let app = inst("TenantInstance", "t1-apps", "app", VynilPackageType::Tenant, "app");
+        let other = inst("TenantInstance", "t2", "other", VynilPackageType::Tenant, "app");
+        let installed = vec![redis.clone(), app, other];
+        let req = VynilPackageRequirement::TenantPackage {
+            category: "cat".to_string(),
+            name: "redis".to_string(),
+        };
+        let deps = find_dependents(
+            &redis,
+            &["t1".to_string(), "t1-apps".to_string()],
+            &installed,
+            requires(vec![("app", req.clone()), ("other", req)]),
+        );
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].name, "app");
+    }
+
+    #[test]
+    fn test_deleting_instances_are_ignored() {
+        let cm = inst("SystemInstance", "vynil-system", "cert-manager", VynilPackageType::System, "cert-manager");
+        let mut app = inst("SystemInstance", "vynil-system", "issuer", VynilPackageType::System, "issuer");
+        app.deleting = true;
+        let installed = vec![cm.clone(), app];
+        let deps = find_dependents(
+            &cm,
+            &["vynil-system".to_string()],
+            &installed,
+            requires(vec![("issuer", VynilPackageRequirement::SystemPackage {
+                category: "cat".to_string(),
+                name: "cert-manager".to_string(),
+            })]),
+        );
+        assert!(deps.is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{Children, Error, Result};
+use kube::{
+    Client,
+    api::{Api, DynamicObject},
+    core::GroupVersionKind,
+    discovery::{ApiResource, Scope},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Health of a monitored child
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ChildHealth {
+    /// kind of k8s object
+    pub kind: String,
+    /// Name of the object
+    pub name: String,
+    /// Namespace is only used for namespaced object
+    pub namespace: Option<String>,
+    /// Whether the object is healthy
+    pub healthy: bool,
+    /// Why the object is not healthy
+    pub message: Option<String>,
+}
+
+/// Health of the monitored children of an instance
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct HealthSummary {
+    /// Number of healthy children
+    pub healthy: u32,
+    /// Number of unhealthy children
+    pub unhealthy: u32,
+    /// Health of each monitored child
+    pub children: Vec<ChildHealth>,
+}
+
+impl HealthSummary {
+    pub fn is_healthy(&self) -> bool {
+        self.unhealthy == 0
+    }
+
+    /// First unhealthy children, for the condition message
+    pub fn message(&self) -> String {
+        if self.is_healthy() {
+            return format!("{} children healthy", self.healthy);
+        }
+        let details: Vec<String> = self
+            .children
+            .iter()
+            .filter(|c| !c.healthy)
+            .take(3)
+            .map(|c| format!("{}/{}: {}", c.kind, c.name, c.message.clone().unwrap_or_default()))
+            .collect();
+        format!(
+            "{} of {} children unhealthy: {}",
+            self.unhealthy,
+            self.healthy + self.unhealthy,
+            details.join(", ")
+        )
+    }
+}
+
+fn int(obj: &Value, pointer: &str) -> i64 {
+    obj.pointer(pointer).and_then(|v| v.as_i64()).unwrap_or(0)
+}
+
+fn condition<'a>(obj: &'a Value, cond: &str) -> Option<&'a Value> {
+    obj.pointer("/status/conditions")
+        .and_then(|c| c.as_array())
+        .and_then(|c| {
+            c.iter()
+                .find(|c| c.get("type").and_then(|t| t.as_str()) == Some(cond))
+        })
+}
+
+fn is_true(cond: &Value) -> bool {
+    cond.get("status").and_then(|s| s.as_str()) == Some("True")
+}
+
+fn message(cond: &Value) -> Option<String> {
+    cond.get("message")
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string())
+}
+
+fn replicas(kind: &str, ready: i64, wanted: i64) -> (bool, Option<String>) {
+    if ready < wanted {
+        (false, Some(format!("{ready}/{wanted} {kind} ready")))
+    } else {
+        (true, None)
+    }
+}
+
+/// Evaluate the health of a child from its current state
+pub fn evaluate(kind: &str, obj: &Value) -> (bool, Option<String>) {
+    match kind {
+        "Deployment" => {
+            if let Some(progressing) = condition(obj, "Progressing")
+                && !is_true(progressing)
+            {
+                return (false, message(progressing));
+            }
+            let wanted = obj
+                .pointer("/spec/replicas")
+                .and_then(|v| v.as_i64())
+                .unwrap_or(1);
+            replicas("replicas", int(obj, "/status/availableReplicas"), wanted)
+        }
+        "StatefulSet" => {
+            let wanted = obj
+                .pointer("/spec/replicas")
+                .and_then(|v| v.as_i64())
+                .unwrap_or(1);
+            replicas("replicas", int(obj, "/status/readyReplicas"), wanted)
+        }
+        "DaemonSet" => replicas(
+            "pods",
+            int(obj, "/status/numberReady"),
+            int(obj, "/status/desiredNumberScheduled"),
+        ),
+        "PersistentVolumeClaim" => match obj.pointer("/status/phase").and_then(|p| p.as_str()) {
+            Some("Bound") => (true, None),
+            phase => (false, Some(format!("Phase is {}", phase.unwrap_or("unknown")))),
+        },
+        "Job" => match condition(obj, "Failed") {
+            Some(failed) if is_true(failed) => (false, message(failed)),
+            _ => (true, None),
+        },
+        // Custom resources only report a health when they have a Ready condition
+        _ => match condition(obj, "Ready") {
+            Some(ready) if !is_true(ready) => (false, message(ready).or(Some("Not ready".to_string()))),
+            _ => (true, None),
+        },
+    }
+}
+
+/// Default apiVersion of the kinds usually recorded without one
+fn default_api_version(kind: &str) -> Option<&'static str> {
+    match kind {
+        "Deployment" | "StatefulSet" | "DaemonSet" => Some("apps/v1"),
+        "Job" => Some("batch/v1"),
+        "PersistentVolumeClaim" => Some("v1"),
+        _ => None,
+    }
+}
+
+async fn resource(
+    client: Client,
+    child: &Children,
+    cache: &mut BTreeMap<String, (ApiResource, Scope)>,
+) -> Result<Option<(ApiResource, Scope)>> {
+    let Some(api_version) = child
+        .api_version
+        .clone()
+        .or(default_api_version(&child.kind).map(|v| v.to_string()))
+    else {
+        return Ok(None);
+    };
+    let key = format!("{api_version}/{}", child.kind);
+    if let Some(res) = cache.get(&key) {
+        return Ok(Some(res.clone()));
+    }
+    let (group, version) = api_version.split_once('/').unwrap_or(("", api_version.as_str()));
+    let gvk = GroupVersionKind::gvk(group, version, &child.kind);
+    let (ar, caps) = kube::discovery::pinned_kind(&client, &gvk)
+        .await
+        .map_err(Error::KubeError)?;
+    cache.insert(key, (ar.clone(), caps.scope.clone()));
+    Ok(Some((ar, caps.scope)))
+}
+
+/// Api to reach a child, `None` when its kind cannot be resolved
+async fn api(
+    client: Client,
+    namespace: &str,
+    child: &Children,
+    cache: &mut BTreeMap<String, (ApiResource, Scope)>,
+) -> Result<Option<Api<DynamicObject>>> {
+    let Some((ar, scope)) = resource(client.clone(), child, cache).await? else {
+        return Ok(None);
+    };
+    Ok(Some(match scope {
+        Scope::Cluster => Api::all_with(client, &ar),
+        Scope::Namespaced => {
+            Api::namespaced_with(client, child.namespace.as_deref().unwrap_or(namespace), &ar)
+        }
+    }))
+}
+
+/// Check the health of the children of an instance
+pub async fn check(client: Client, namespace: &str, children: Vec<Children>) -> Result<HealthSummary> {
+    let mut cache = BTreeMap::new();
+    let mut summary = HealthSummary::default();
+    for child in children {
+        let Some(api) = api(client.clone(), namespace, &child, &mut cache).await? else {
+            continue;
+        };
+        let (healthy, message) = match api.get_opt(&child.name).await.map_err(Error::KubeError)? {
+            Some(obj) => evaluate(
+                &child.kind,
+                &serde_json::to_value(obj).map_err(Error::SerializationError)?,
+            ),
+            None => (false, Some("Not found".to_string())),
+        };
+        if healthy {
+            summary.healthy += 1;
+        } else {
+            summary.unhealthy += 1;
+        }
+        summary.children.push(ChildHealth {
+            kind: child.kind,
+            name: child.name,
+            namespace: child.namespace,
+            healthy,
+            message,
+        });
+    }
+    Ok(summary)
+}
+
+/// Kinds expected to vanish on their own (finished Jobs with a TTL, Pods)
+fn is_transient(kind: &str) -> bool {
+    matches!(kind, "Job" | "Pod")
+}
+
+/// List the children of an instance that no longer exist
+pub async fn missing(client: Client, namespace: &str, children: Vec<Children>) -> Result<Vec<Children>> {
+    let mut cache = BTreeMap::new();
+    let mut missing = Vec::new();
+    for child in children.into_iter().filter(|c| !is_transient(&c.kind)) {
+        let Some(api) = api(client.clone(), namespace, &child, &mut cache).await? else {
+            continue;
+        };
+        if api
+            .get_metadata_opt(&child.name)
+            .await
+            .map_err(Error::KubeError)?
+            .is_none()
+        {
+            missing.push(child);
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn deployment_health_follows_available_replicas() {
+        let obj = json!({"spec": {"replicas": 2}, "status": {"availableReplicas": 1}});
+        assert_eq!(
+            evaluate("Deployment", &obj),
+            (false, Some("1/2 replicas ready".to_string()))
+        );
+        let obj = json!({"spec": {"replicas": 2}, "status": {"availableReplicas": 2}});
+        assert_eq!(evaluate("Deployment", &obj), (true, None));
+    }
+
+    #[test]
+    fn custom_resources_use_their_ready_condition() {
+        let obj =
+            json!({"status": {"conditions": [{"type": "Ready", "status": "False", "message": "waiting"}]}});
+        assert_eq!(evaluate("Cluster", &obj), (false, Some("waiting".to_string())));
+        assert_eq!(evaluate("Cluster", &json!({})), (true, None));
+    }
+
+    #[test]
+    fn pvc_must_be_bound() {
+        assert_eq!(
+            evaluate("PersistentVolumeClaim", &json!({"status": {"phase": "Bound"}})),
+            (true, None)
+        );
+        assert!(!evaluate("PersistentVolumeClaim", &json!({"status": {"phase": "Pending"}})).0);
+    }
+}
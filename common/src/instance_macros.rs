@@ -1,7 +1,8 @@
 /// Generates the common `ApplicationCondition` constructors shared by all three instance types.
 /// Call this at module scope (not inside an `impl` block) in any instance module.
 /// Requires: local `ApplicationCondition`, `ConditionsStatus`, `ConditionsType` in scope,
-/// with at least: Ready, Installed, AgentStarted, TofuInstalled, RhaiApplied variants.
+/// with at least: Ready, Reconciling, Stalled, Installed, AgentStarted, Healthy, TofuInstalled, RhaiApplied
+/// variants.
 #[macro_export]
 macro_rules! impl_condition_common {
     () => {
@@ -13,15 +14,113 @@ macro_rules! impl_condition_common {
                 condition_type: ConditionsType,
                 generation: i64,
             ) -> ApplicationCondition {
+                let reason = match status {
+                    ConditionsStatus::True => "Succeeded".to_string(),
+                    ConditionsStatus::False => format!("{condition_type:?}Failed"),
+                };
                 ApplicationCondition {
                     last_transition_time: Some(::chrono::Utc::now()),
                     status,
                     condition_type,
                     message: message.to_string(),
+                    reason: Some(reason),
                     generation,
+                    observed_generation: Some(generation),
                 }
             }
 
+            #[must_use]
+            pub fn with_reason(mut self, reason: &str) -> ApplicationCondition {
+                self.reason = Some(reason.to_string());
+                self
+            }
+
+            /// Replace the kstatus summary conditions (`Reconciling`, `Stalled`) with the ones
+            /// computed from the detail conditions. Both are only present when `True`.
+            #[must_use]
+            pub fn summarize(
+                conditions: Vec<ApplicationCondition>,
+                generation: i64,
+            ) -> Vec<ApplicationCondition> {
+                let is_summary = |c: &ApplicationCondition| {
+                    c.condition_type == ConditionsType::Reconciling
+                        || c.condition_type == ConditionsType::Stalled
+                };
+                let previous: Vec<ApplicationCondition> = conditions
+                    .iter()
+                    .filter(|c| is_summary(c))
+                    .cloned()
+                    .collect();
+                let mut ret: Vec<ApplicationCondition> =
+                    conditions.into_iter().filter(|c| !is_summary(c)).collect();
+                let ready = ret
+                    .iter()
+                    .find(|c| c.condition_type == ConditionsType::Ready && c.status == ConditionsStatus::True)
+                    .cloned();
+                // Failures older than the last successful install are stale, but the health
+                // monitoring keeps `Healthy` current
+                let failed = ret
+                    .iter()
+                    .rev()
+                    .find(|c| {
+                        c.condition_type != ConditionsType::Ready
+                            && c.status == ConditionsStatus::False
+                            && (c.condition_type == ConditionsType::Healthy
+                                || ready
+                                    .as_ref()
+                                    .is_none_or(|r| c.last_transition_time >= r.last_transition_time))
+                    })
+                    .cloned();
+                let summary = if let Some(failed) = failed {
+                    Some(ApplicationCondition {
+                        condition_type: ConditionsType::Stalled,
+                        status: ConditionsStatus::True,
+                        reason: failed
+                            .reason
+                            .clone()
+                            .or_else(|| Some(format!("{:?}Failed", failed.condition_type))),
+                        ..failed
+                    })
+                } else if ready.as_ref().is_none_or(|r| r.generation < generation)
+                    || ret
+                        .iter()
+                        .any(|c| c.condition_type == ConditionsType::AgentStarted)
+                {
+                    let stage = ret
+                        .iter()
+                        .rev()
+                        .find(|c| c.condition_type != ConditionsType::Ready)
+                        .map_or_else(
+                            || "Pending".to_string(),
+                            |c| format!("{:?}", c.condition_type),
+                        );
+                    Some(
+                        ApplicationCondition::new(
+                            &format!("{stage} for generation {generation}"),
+                            ConditionsStatus::True,
+                            ConditionsType::Reconciling,
+                            generation,
+                        )
+                        .with_reason("Progressing"),
+                    )
+                } else {
+                    None
+                };
+                if let Some(mut summary) = summary {
+                    summary.generation = generation;
+                    summary.observed_generation = Some(generation);
+                    // Only a change of reason is a transition
+                    if let Some(prev) = previous
+                        .iter()
+                        .find(|p| p.condition_type == summary.condition_type && p.reason == summary.reason)
+                    {
+                        summary.last_transition_time = prev.last_transition_time;
+                    }
+                    ret.push(summary);
+                }
+                ret
+            }
+
             pub fn ready_ok(generation: i64) -> ApplicationCondition {
                 ApplicationCondition::new(
                     "Installed succesfully",
@@ -29,6 +128,7 @@ macro_rules! impl_condition_common {
                     ConditionsType::Ready,
                     generation,
                 )
+                .with_reason("Installed")
             }
 
             pub fn ready_ko(generation: i64) -> ApplicationCondition {
@@ -38,6 +138,7 @@ macro_rules! impl_condition_common {
                     ConditionsType::Ready,
                     generation,
                 )
+                .with_reason("NotInstalled")
             }
 
             pub fn installed_ko(message: &str, generation: i64) -> ApplicationCondition {
@@ -65,6 +166,7 @@ macro_rules! impl_condition_common {
                     ConditionsType::AgentStarted,
                     generation,
                 )
+                .with_reason("AgentStarted")
             }
 
             pub fn missing_package(cat: &str, name: &str, generation: i64) -> ApplicationCondition {
@@ -74,6 +176,7 @@ macro_rules! impl_condition_common {
                     ConditionsType::AgentStarted,
                     generation,
                 )
+                .with_reason("PackageMissing")
             }
 
             pub fn missing_box(name: &str, generation: i64) -> ApplicationCondition {
@@ -83,6 +186,7 @@ macro_rules! impl_condition_common {
                     ConditionsType::AgentStarted,
                     generation,
                 )
+                .with_reason("JukeBoxMissing")
             }
 
             pub fn missing_requirement(error: &str, generation: i64) -> ApplicationCondition {
@@ -92,6 +196,56 @@ macro_rules! impl_condition_common {
                     ConditionsType::AgentStarted,
                     generation,
                 )
+                .with_reason("RequirementMissing")
+            }
+
+            pub fn deletion_blocked(message: &str, generation: i64) -> ApplicationCondition {
+                ApplicationCondition::new(
+                    message,
+                    ConditionsStatus::False,
+                    ConditionsType::Deletable,
+                    generation,
+                )
+                .with_reason("DeletionBlocked")
+            }
+
+            pub fn policy_ko(message: &str, generation: i64) -> ApplicationCondition {
+                ApplicationCondition::new(
+                    message,
+                    ConditionsStatus::False,
+                    ConditionsType::PolicyCompliant,
+                    generation,
+                )
+                .with_reason("PolicyViolation")
+            }
+
+            pub fn policy_ok(generation: i64) -> ApplicationCondition {
+                ApplicationCondition::new(
+                    "Compliant with the policies",
+                    ConditionsStatus::True,
+                    ConditionsType::PolicyCompliant,
+                    generation,
+                )
+            }
+
+            pub fn healthy_ko(message: &str, generation: i64) -> ApplicationCondition {
+                ApplicationCondition::new(
+                    message,
+                    ConditionsStatus::False,
+                    ConditionsType::Healthy,
+                    generation,
+                )
+                .with_reason("Unhealthy")
+            }
+
+            pub fn healthy_ok(message: &str, generation: i64) -> ApplicationCondition {
+                ApplicationCondition::new(
+                    message,
+                    ConditionsStatus::True,
+                    ConditionsType::Healthy,
+                    generation,
+                )
+                .with_reason("Healthy")
             }
 
             pub fn tofu_ko(message: &str, generation: i64) -> ApplicationCondition {
@@ -246,6 +400,7 @@ macro_rules! impl_condition_children {
                     ConditionsType::InitFrom,
                     generation,
                 )
+                .with_reason("InitVersionNotFound")
             }
 
             pub fn schedule_ko(message: &str, generation: i64) -> ApplicationCondition {
@@ -288,12 +443,12 @@ macro_rules! impl_condition_crds {
 }
 
 /// Generates the `impl $type` block with methods common to all three instance types:
-/// CRUD helpers, status patching, event sending, tfstate/rhaistate, agent/missing-* setters,
-/// and their rhai wrappers.
+/// CRUD helpers, status patching, event sending, tfstate/rhaistate (through the state store),
+/// children inventory, agent/missing-* setters, and their rhai wrappers.
 ///
 /// Required at call site (call-site imports used by the macro expansion):
 /// - `use kube::{Resource, ResourceExt, api::{Api, ListParams, ObjectList, Patch, PatchParams}, ...}`
-/// - local `ConditionsType`, `ApplicationCondition`
+/// - local `ConditionsType`, `ConditionsStatus`, `ApplicationCondition`
 #[macro_export]
 macro_rules! impl_instance_common {
     ($type:ty, $kind_str:literal) => {
@@ -315,38 +470,190 @@ macro_rules! impl_instance_common {
                 api.list(&lp).await.map_err($crate::Error::KubeError)
             }
 
-            pub fn get_options_digest(&mut self) -> String {
-                if let Some(ref opt) = self.spec.options {
-                    sha256::digest(serde_json::to_string(opt).unwrap())
+            /// Digest of the effective options, including the resourceVersion of the objects of their `valueFrom`
+            pub fn get_options_digest(&mut self) -> $crate::Result<String> {
+                let effective = self.status.as_ref().and_then(|s| s.effective_options.clone());
+                // The backup policy (tenant and service instances) is part of the digest so changing
+                // it reschedules the backups
+                let backup = serde_json::to_value(&self.spec)
+                    .ok()
+                    .and_then(|s| s.get("backup").filter(|b| !b.is_null()).map(|b| b.to_string()))
+                    .unwrap_or_default();
+                let Some(ref opt) = effective.or_else(|| self.spec.options.clone()) else {
+                    return Ok(sha256::digest(backup));
+                };
+                let options = format!("{}{backup}", serde_json::to_string(opt).unwrap());
+                let refs = $crate::valuefrom::references(&serde_json::Value::Object(opt.clone()));
+                if refs.is_empty() {
+                    return Ok(sha256::digest(options));
+                }
+                let namespace = self.namespace().unwrap_or_default();
+                let versions = ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current().block_on(async move {
+                        $crate::valuefrom::versions(
+                            $crate::context::get_client_async().await,
+                            &namespace,
+                            &refs,
+                        )
+                        .await
+                    })
+                })?;
+                Ok(sha256::digest(format!("{options}{}", versions.join(","))))
+            }
+
+            pub fn rhai_get_options_digest(&mut self) -> $crate::RhaiRes<String> {
+                self.get_options_digest().map_err($crate::rhai_err)
+            }
+
+            fn get_state(&self, key: &str) -> $crate::Result<Option<String>> {
+                let Some(status) = self.status.clone() else {
+                    return Ok(None);
+                };
+                if let Some(stored) = status.state.filter(|s| s.has(key)) {
+                    let namespace = self.namespace().unwrap_or_default();
+                    return ::tokio::task::block_in_place(|| {
+                        ::tokio::runtime::Handle::current().block_on(async move {
+                            $crate::statestore::read(
+                                $crate::context::get_client_async().await,
+                                &namespace,
+                                &stored,
+                                key,
+                            )
+                            .await
+                        })
+                    });
+                }
+                let inline = if key == $crate::statestore::TFSTATE {
+                    status.tfstate
                 } else {
-                    sha256::digest("")
+                    status.rhaistate
+                };
+                match inline {
+                    Some(encoded) => Ok(Some($crate::tools::base64_gz_decode(encoded)?)),
+                    None => Ok(None),
                 }
             }
 
+            async fn store_state(
+                &self,
+                client: ::kube::Client,
+                key: &str,
+                value: &str,
+            ) -> $crate::Result<$crate::statestore::StoredState> {
+                let config = $crate::statestore::StateConfig::from_env()?;
+                let namespace = self.namespace().unwrap_or_default();
+                let name = self.name_any();
+                let previous = self.status.as_ref().and_then(|s| s.state.clone());
+                $crate::statestore::write(
+                    client,
+                    &config,
+                    &self.state_owner(&namespace, &name)?,
+                    previous.as_ref(),
+                    key,
+                    value,
+                )
+                .await
+            }
+
+            fn state_owner<'a>(
+                &self,
+                namespace: &'a str,
+                name: &'a str,
+            ) -> $crate::Result<$crate::statestore::StateOwner<'a>> {
+                let owner = self
+                    .controller_owner_ref(&())
+                    .ok_or($crate::Error::Other(format!("{} {} without uid", $kind_str, name)))?;
+                Ok($crate::statestore::StateOwner {
+                    kind: $kind_str,
+                    namespace,
+                    name,
+                    owner,
+                })
+            }
+
+            /// Locks the states of the instance until `unlock_state`, around a whole tofu run
+            pub async fn lock_state(&self) -> $crate::Result<()> {
+                let config = $crate::statestore::StateConfig::from_env()?;
+                let namespace = self.namespace().unwrap_or_default();
+                let name = self.name_any();
+                $crate::statestore::lock(&config, &self.state_owner(&namespace, &name)?).await
+            }
+
+            pub async fn unlock_state(&self) -> $crate::Result<()> {
+                let config = $crate::statestore::StateConfig::from_env()?;
+                let namespace = self.namespace().unwrap_or_default();
+                let name = self.name_any();
+                $crate::statestore::unlock(&config, &self.state_owner(&namespace, &name)?).await
+            }
+
             pub fn get_tfstate(&self) -> $crate::Result<Option<String>> {
-                if let Some(status) = self.status.clone() {
-                    if let Some(tf) = status.tfstate {
-                        let decoded = $crate::tools::base64_gz_decode(tf)?;
-                        Ok(Some(decoded))
-                    } else {
-                        Ok(None)
-                    }
-                } else {
-                    Ok(None)
-                }
+                self.get_state($crate::statestore::TFSTATE)
             }
 
             pub fn get_rhaistate(&self) -> $crate::Result<Option<String>> {
-                if let Some(status) = self.status.clone() {
-                    if let Some(tf) = status.rhaistate {
-                        let decoded = $crate::tools::base64_gz_decode(tf)?;
-                        Ok(Some(decoded))
-                    } else {
-                        Ok(None)
-                    }
-                } else {
-                    Ok(None)
+                self.get_state($crate::statestore::RHAISTATE)
+            }
+
+            pub async fn purge_state(&self) -> $crate::Result<()> {
+                match self.status.as_ref().and_then(|s| s.state.as_ref()) {
+                    Some(stored) => $crate::statestore::purge(stored).await,
+                    None => Ok(()),
+                }
+            }
+
+            pub fn get_children(
+                &self,
+                list: &str,
+            ) -> $crate::Result<Vec<$crate::inventory::InventoryItem>> {
+                let status = serde_json::to_value(&self.status).map_err($crate::Error::SerializationError)?;
+                if !$crate::inventory::recorded(&status, list) {
+                    return Ok($crate::inventory::inline(&status, list));
                 }
+                let namespace = self.namespace().unwrap_or_default();
+                ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current().block_on(async move {
+                        $crate::inventory::children(
+                            $crate::context::get_client_async().await,
+                            &namespace,
+                            &status,
+                            list,
+                        )
+                        .await
+                    })
+                })
+            }
+
+            async fn store_children(
+                &self,
+                client: ::kube::Client,
+                list: &str,
+                items: &[$crate::inventory::InventoryItem],
+                conditions: Vec<ApplicationCondition>,
+            ) -> $crate::Result<serde_json::Value> {
+                let name = self.name_any();
+                let owner = self
+                    .controller_owner_ref(&())
+                    .ok_or($crate::Error::Other(format!("{} {} without uid", $kind_str, name)))?;
+                let mut patch = $crate::inventory::write(
+                    client,
+                    $kind_str,
+                    &self.namespace().unwrap_or_default(),
+                    &name,
+                    owner,
+                    list,
+                    items,
+                )
+                .await?;
+                patch["conditions"] = serde_json::json!(conditions);
+                Ok(patch)
+            }
+
+            pub fn is_ready(&self) -> bool {
+                self.status.as_ref().is_some_and(|status| {
+                    status.conditions.iter().any(|c| {
+                        c.condition_type == ConditionsType::Ready && c.status == ConditionsStatus::True
+                    })
+                })
             }
 
             fn have_condition(&self, cond: &ApplicationCondition) -> bool {
@@ -380,13 +687,20 @@ macro_rules! impl_instance_common {
             async fn patch_status(
                 &mut self,
                 client: ::kube::Client,
-                patch: serde_json::Value,
+                mut patch: serde_json::Value,
             ) -> $crate::Result<Self> {
                 let api = ::kube::api::Api::<Self>::namespaced(
                     client.clone(),
                     &self.namespace().unwrap(),
                 );
                 let name = self.metadata.name.clone().unwrap();
+                if let Some(conditions) = patch.get("conditions").cloned() {
+                    let generation = self.metadata.generation.unwrap_or(1);
+                    let conditions: Vec<ApplicationCondition> =
+                        serde_json::from_value(conditions).map_err($crate::Error::SerializationError)?;
+                    patch["conditions"] =
+                        serde_json::json!(ApplicationCondition::summarize(conditions, generation));
+                }
                 let new_status: ::kube::api::Patch<serde_json::Value> =
                     ::kube::api::Patch::Merge(serde_json::json!({
                         "apiVersion": "vynil.solidite.fr/v1",
@@ -433,7 +747,9 @@ macro_rules! impl_instance_common {
             pub async fn set_tfstate(&mut self, tfstate: String) -> $crate::Result<Self> {
                 let client = $crate::context::get_client_async().await;
                 let generation = self.metadata.generation.unwrap_or(1);
-                let encoded = $crate::tools::encode_base64_gz(tfstate)?;
+                let stored = self
+                    .store_state(client.clone(), $crate::statestore::TFSTATE, &tfstate)
+                    .await?;
                 let mut conditions: Vec<ApplicationCondition> =
                     self.get_conditions_excluding(vec![ConditionsType::TofuInstalled]);
                 conditions.push(ApplicationCondition::tofu_ok(generation));
@@ -442,7 +758,8 @@ macro_rules! impl_instance_common {
                         client.clone(),
                         serde_json::json!({
                             "conditions": conditions,
-                            "tfstate": encoded
+                            "tfstate": null,
+                            "state": stored
                         }),
                     )
                     .await?;
@@ -464,7 +781,9 @@ macro_rules! impl_instance_common {
             ) -> $crate::Result<Self> {
                 let client = $crate::context::get_client_async().await;
                 let generation = self.metadata.generation.unwrap_or(1);
-                let encoded = $crate::tools::encode_base64_gz(tfstate)?;
+                let stored = self
+                    .store_state(client.clone(), $crate::statestore::TFSTATE, &tfstate)
+                    .await?;
                 let mut conditions: Vec<ApplicationCondition> = self.get_conditions_excluding(vec![
                     ConditionsType::AgentStarted,
                     ConditionsType::TofuInstalled,
@@ -484,7 +803,8 @@ macro_rules! impl_instance_common {
                         client.clone(),
                         serde_json::json!({
                             "conditions": conditions,
-                            "tfstate": encoded
+                            "tfstate": null,
+                            "state": stored
                         }),
                     )
                     .await?;
@@ -504,7 +824,9 @@ macro_rules! impl_instance_common {
             pub async fn set_rhaistate(&mut self, rhaistate: String) -> $crate::Result<Self> {
                 let client = $crate::context::get_client_async().await;
                 let generation = self.metadata.generation.unwrap_or(1);
-                let encoded = $crate::tools::encode_base64_gz(rhaistate)?;
+                let stored = self
+                    .store_state(client.clone(), $crate::statestore::RHAISTATE, &rhaistate)
+                    .await?;
                 let mut conditions: Vec<ApplicationCondition> =
                     self.get_conditions_excluding(vec![ConditionsType::RhaiApplied]);
                 conditions.push(ApplicationCondition::rhai_ok(generation));
@@ -513,7 +835,8 @@ macro_rules! impl_instance_common {
                         client.clone(),
                         serde_json::json!({
                             "conditions": conditions,
-                            "rhaistate": encoded
+                            "rhaistate": null,
+                            "state": stored
                         }),
                     )
                     .await?;
@@ -535,7 +858,9 @@ macro_rules! impl_instance_common {
             ) -> $crate::Result<Self> {
                 let client = $crate::context::get_client_async().await;
                 let generation = self.metadata.generation.unwrap_or(1);
-                let encoded = $crate::tools::encode_base64_gz(rhaistate)?;
+                let stored = self
+                    .store_state(client.clone(), $crate::statestore::RHAISTATE, &rhaistate)
+                    .await?;
                 let mut conditions: Vec<ApplicationCondition> = self.get_conditions_excluding(vec![
                     ConditionsType::AgentStarted,
                     ConditionsType::RhaiApplied,
@@ -555,7 +880,8 @@ macro_rules! impl_instance_common {
                         client.clone(),
                         serde_json::json!({
                             "conditions": conditions,
-                            "rhaistate": encoded
+                            "rhaistate": null,
+                            "state": stored
                         }),
                     )
                     .await?;
@@ -693,6 +1019,300 @@ macro_rules! impl_instance_common {
                 }
             }
 
+            pub async fn set_deletion_blocked(
+                &mut self,
+                dependents: Vec<String>,
+            ) -> $crate::Result<Self> {
+                let client = $crate::context::get_client_async().await;
+                let generation = self.metadata.generation.unwrap_or(1);
+                let message = format!("Required by: {}", dependents.join(", "));
+                let cond = ApplicationCondition::deletion_blocked(&message, generation);
+                if !self.have_condition(&cond) {
+                    let mut conditions: Vec<ApplicationCondition> =
+                        self.get_conditions_excluding(vec![ConditionsType::Deletable]);
+                    conditions.push(cond);
+                    let result = self
+                        .patch_status(
+                            client.clone(),
+                            serde_json::json!({ "conditions": conditions }),
+                        )
+                        .await?;
+                    let mut note = message;
+                    note.truncate(1023);
+                    self.send_event(client, ::kube::runtime::events::Event {
+                        type_: ::kube::runtime::events::EventType::Warning,
+                        reason: "DeletionBlocked".to_string(),
+                        note: Some(note),
+                        action: "Delete".to_string(),
+                        secondary: None,
+                    })
+                    .await?;
+                    Ok(result)
+                } else {
+                    Ok(self.clone())
+                }
+            }
+
+            pub async fn set_policy_violations(
+                &mut self,
+                violations: Vec<String>,
+            ) -> $crate::Result<Self> {
+                let client = $crate::context::get_client_async().await;
+                let generation = self.metadata.generation.unwrap_or(1);
+                let message = format!("Policy violations: {}", violations.join("; "));
+                let cond = ApplicationCondition::policy_ko(&message, generation);
+                if !self.have_condition(&cond) {
+                    let mut conditions: Vec<ApplicationCondition> =
+                        self.get_conditions_excluding(vec![ConditionsType::PolicyCompliant]);
+                    conditions.push(cond);
+                    let result = self
+                        .patch_status(
+                            client.clone(),
+                            serde_json::json!({ "conditions": conditions }),
+                        )
+                        .await?;
+                    let mut note = message;
+                    note.truncate(1023);
+                    self.send_event(client, ::kube::runtime::events::Event {
+                        type_: ::kube::runtime::events::EventType::Warning,
+                        reason: "PolicyViolation".to_string(),
+                        note: Some(note),
+                        action: "AgentStart".to_string(),
+                        secondary: None,
+                    })
+                    .await?;
+                    Ok(result)
+                } else {
+                    Ok(self.clone())
+                }
+            }
+
+            /// Clear a previous policy violation (no-op when none was recorded)
+            pub async fn set_policy_compliant(&mut self) -> $crate::Result<Self> {
+                let violated = self.status.as_ref().is_some_and(|s| {
+                    s.conditions.iter().any(|c| {
+                        c.condition_type == ConditionsType::PolicyCompliant
+                            && c.status == ConditionsStatus::False
+                    })
+                });
+                if violated {
+                    let client = $crate::context::get_client_async().await;
+                    let generation = self.metadata.generation.unwrap_or(1);
+                    let mut conditions: Vec<ApplicationCondition> =
+                        self.get_conditions_excluding(vec![ConditionsType::PolicyCompliant]);
+                    conditions.push(ApplicationCondition::policy_ok(generation));
+                    self.patch_status(client, serde_json::json!({ "conditions": conditions }))
+                        .await
+                } else {
+                    Ok(self.clone())
+                }
+            }
+
+            /// Record the generation the operator has reconciled; only called from its reconcile path
+            pub async fn set_observed_generation(&mut self) -> $crate::Result<Self> {
+                let generation = self.metadata.generation.unwrap_or(1);
+                if self.status.as_ref().and_then(|s| s.observed_generation) == Some(generation) {
+                    return Ok(self.clone());
+                }
+                let client = $crate::context::get_client_async().await;
+                let mut patch = serde_json::json!({ "observedGeneration": generation });
+                if self.status.is_none() {
+                    // A status always carries its conditions
+                    patch["conditions"] = serde_json::json!([]);
+                }
+                self.patch_status(client, patch).await
+            }
+
+            /// Write options migrated from the `from` version back to the spec, recording it in an annotation
+            pub async fn set_migrated_options(
+                &mut self,
+                options: serde_json::Map<String, serde_json::Value>,
+                from: String,
+                to: String,
+            ) -> $crate::Result<Self> {
+                let client = $crate::context::get_client_async().await;
+                let api = ::kube::api::Api::<Self>::namespaced(client.clone(), &self.namespace().unwrap());
+                let migration = format!("{from} -> {to}");
+                let annotation = if self.metadata.annotations.is_some() {
+                    serde_json::json!({
+                        "op": "add",
+                        "path": "/metadata/annotations/vynil.solidite.fr~1options-migrated",
+                        "value": migration
+                    })
+                } else {
+                    serde_json::json!({
+                        "op": "add",
+                        "path": "/metadata/annotations",
+                        "value": {"vynil.solidite.fr/options-migrated": migration}
+                    })
+                };
+                let patch = ::kube::api::Patch::Json::<()>(
+                    serde_json::from_value(serde_json::json!([
+                        {"op": "add", "path": "/spec/options", "value": options},
+                        annotation
+                    ]))
+                    .map_err($crate::Error::SerializationError)?,
+                );
+                let updated = api
+                    .patch(&self.name_any(), &::kube::api::PatchParams::default(), &patch)
+                    .await
+                    .map_err($crate::Error::KubeError)?;
+                self.send_event(client, ::kube::runtime::events::Event {
+                    type_: ::kube::runtime::events::EventType::Normal,
+                    reason: "OptionsMigrated".to_string(),
+                    note: Some(format!("Migrated the options from version {from} to {to}")),
+                    action: "Migrate".to_string(),
+                    secondary: None,
+                })
+                .await?;
+                Ok(updated)
+            }
+
+            /// Record the options merged over the VynilDefaults (no-op when unchanged)
+            pub async fn set_effective_options(
+                &mut self,
+                options: Option<serde_json::Map<String, serde_json::Value>>,
+            ) -> $crate::Result<Self> {
+                let previous = self.status.as_ref().and_then(|s| s.effective_options.clone());
+                if previous == options {
+                    return Ok(self.clone());
+                }
+                let client = $crate::context::get_client_async().await;
+                let patch = $crate::vynildefaults::merge_patch(previous.as_ref(), options.as_ref());
+                self.patch_status(client, serde_json::json!({ "effectiveOptions": patch }))
+                    .await
+            }
+
+            /// Children recorded in the given lists, from the inventory or the status
+            async fn listed_children(
+                &self,
+                client: ::kube::Client,
+                lists: &[&str],
+            ) -> $crate::Result<Vec<$crate::Children>> {
+                let namespace = self.namespace().unwrap_or_default();
+                let status_json = serde_json::to_value(&self.status).map_err($crate::Error::SerializationError)?;
+                let mut children = Vec::new();
+                for list in lists {
+                    let items = if $crate::inventory::recorded(&status_json, list) {
+                        $crate::inventory::children(client.clone(), &namespace, &status_json, list).await?
+                    } else {
+                        $crate::inventory::inline(&status_json, list)
+                    };
+                    children.extend(items.into_iter().map(|i| i.child));
+                }
+                Ok(children)
+            }
+
+            /// Looks for deleted children of the given lists, returns whether the install should run again
+            pub async fn self_heal(&mut self, lists: &[&str]) -> $crate::Result<bool> {
+                let Some(status) = self.status.as_ref() else {
+                    return Ok(false);
+                };
+                // The agent is expected to add and remove children while it runs
+                if status.tag.is_none()
+                    || status
+                        .conditions
+                        .iter()
+                        .any(|c| c.condition_type == ConditionsType::AgentStarted)
+                {
+                    return Ok(false);
+                }
+                let client = $crate::context::get_client_async().await;
+                let children = self.listed_children(client.clone(), lists).await?;
+                let missing =
+                    $crate::health::missing(client.clone(), &self.namespace().unwrap_or_default(), children)
+                        .await?;
+                if missing.is_empty() {
+                    return Ok(false);
+                }
+                let mut note = format!(
+                    "Re-applying the package, deleted children: {}",
+                    missing
+                        .iter()
+                        .map(|c| format!("{}/{}", c.kind, c.name))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
+                note.truncate(1023);
+                self.send_event(client, ::kube::runtime::events::Event {
+                    type_: ::kube::runtime::events::EventType::Warning,
+                    reason: "SelfHeal".to_string(),
+                    note: Some(note),
+                    action: "Reinstall".to_string(),
+                    secondary: None,
+                })
+                .await?;
+                Ok(true)
+            }
+
+            /// Check the health of the children of `lists` once installed, recording the `Healthy`
+            /// condition and the per-child summary. Returns `None` when the instance is not monitored.
+            pub async fn check_health(&mut self, lists: &[&str]) -> $crate::Result<Option<bool>> {
+                let Some(status) = self.status.clone() else {
+                    return Ok(None);
+                };
+                // Nothing to monitor before the first install nor while the agent is running
+                if status.tag.is_none()
+                    || status
+                        .conditions
+                        .iter()
+                        .any(|c| c.condition_type == ConditionsType::AgentStarted)
+                {
+                    return Ok(None);
+                }
+                let client = $crate::context::get_client_async().await;
+                let generation = self.metadata.generation.unwrap_or(1);
+                let namespace = self.namespace().unwrap_or_default();
+                let children = self.listed_children(client.clone(), lists).await?;
+                let health = $crate::health::check(client.clone(), &namespace, children).await?;
+                let healthy = health.is_healthy();
+                let message = health.message();
+                let mut cond = if healthy {
+                    ApplicationCondition::healthy_ok(&message, generation)
+                } else {
+                    ApplicationCondition::healthy_ko(&message, generation)
+                };
+                let previous = status
+                    .conditions
+                    .iter()
+                    .find(|c| c.condition_type == ConditionsType::Healthy)
+                    .cloned();
+                let transition = previous.as_ref().is_none_or(|p| p.status != cond.status);
+                if let Some(prev) = &previous
+                    && !transition
+                {
+                    if prev.message == cond.message && status.health.as_ref() == Some(&health) {
+                        return Ok(Some(healthy));
+                    }
+                    cond.last_transition_time = prev.last_transition_time;
+                }
+                let mut conditions: Vec<ApplicationCondition> =
+                    self.get_conditions_excluding(vec![ConditionsType::Healthy]);
+                conditions.push(cond);
+                self.patch_status(
+                    client.clone(),
+                    serde_json::json!({ "conditions": conditions, "health": health }),
+                )
+                .await?;
+                if transition && (!healthy || previous.is_some()) {
+                    let mut note = message;
+                    note.truncate(1023);
+                    self.send_event(client, ::kube::runtime::events::Event {
+                        type_: if healthy {
+                            ::kube::runtime::events::EventType::Normal
+                        } else {
+                            ::kube::runtime::events::EventType::Warning
+                        },
+                        reason: if healthy { "Healthy" } else { "Unhealthy" }.to_string(),
+                        note: Some(note),
+                        action: "Monitor".to_string(),
+                        secondary: None,
+                    })
+                    .await?;
+                }
+                Ok(Some(healthy))
+            }
+
             // ── Rhai wrappers ─────────────────────────────────────────────────────────
 
             pub fn rhai_get(namespace: String, name: String) -> $crate::RhaiRes<Self> {
@@ -733,6 +1353,13 @@ macro_rules! impl_instance_common {
                     .map_err(|e| $crate::rhai_err($crate::Error::SerializationError(e)))
             }
 
+            pub fn rhai_get_children(&mut self, list: String) -> $crate::RhaiRes<::rhai::Dynamic> {
+                let items = self.get_children(&list).map_err($crate::rhai_err)?;
+                let v = serde_json::to_string(&items)
+                    .map_err(|e| $crate::rhai_err($crate::Error::SerializationError(e)))?;
+                serde_json::from_str(&v).map_err(|e| $crate::rhai_err($crate::Error::SerializationError(e)))
+            }
+
             pub fn rhai_get_tfstate(&mut self) -> $crate::RhaiRes<String> {
                 self.get_tfstate()
                     .map_err($crate::rhai_err)
@@ -745,6 +1372,20 @@ macro_rules! impl_instance_common {
                     .map(|r| r.unwrap_or_default())
             }
 
+            pub fn rhai_lock_state(&mut self) -> $crate::RhaiRes<()> {
+                ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current().block_on(async move { self.lock_state().await })
+                })
+                .map_err($crate::rhai_err)
+            }
+
+            pub fn rhai_unlock_state(&mut self) -> $crate::RhaiRes<()> {
+                ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current().block_on(async move { self.unlock_state().await })
+                })
+                .map_err($crate::rhai_err)
+            }
+
             pub fn rhai_set_status_ready(&mut self, tag: String) -> $crate::RhaiRes<Self> {
                 ::tokio::task::block_in_place(|| {
                     ::tokio::runtime::Handle::current()
@@ -866,7 +1507,7 @@ macro_rules! impl_instance_befores {
                         serde_json::json!({
                             "conditions": conditions,
                             "tag": tag,
-                            "digest": self.clone().get_options_digest()
+                            "digest": self.clone().get_options_digest()?
                         }),
                     )
                     .await?;
@@ -914,9 +1555,203 @@ macro_rules! impl_instance_befores {
                 Ok(result)
             }
 
+            pub async fn set_tofu_digest(&mut self, digest: String) -> $crate::Result<Self> {
+                let client = $crate::context::get_client_async().await;
+                self.patch_status(client, serde_json::json!({ "tofuDigest": digest }))
+                    .await
+            }
+
+            pub async fn set_backup_policy(
+                &mut self,
+                policy: Option<$crate::vynilbackup::BackupPolicy>,
+            ) -> $crate::Result<Self> {
+                if self.status.as_ref().and_then(|s| s.backup_policy.as_ref()) == policy.as_ref() {
+                    return Ok(self.clone());
+                }
+                let client = $crate::context::get_client_async().await;
+                self.patch_status(client, serde_json::json!({ "backupPolicy": policy }))
+                    .await
+            }
+
+            pub async fn set_upgrade_backup(
+                &mut self,
+                upgrade: Option<$crate::vynilbackup::UpgradeBackup>,
+            ) -> $crate::Result<Self> {
+                use $crate::vynilbackup::UpgradePhase;
+                let previous = self.status.as_ref().and_then(|s| s.upgrade_backup.clone());
+                if previous == upgrade {
+                    return Ok(self.clone());
+                }
+                let client = $crate::context::get_client_async().await;
+                let result = self
+                    .patch_status(client.clone(), serde_json::json!({ "upgradeBackup": upgrade }))
+                    .await?;
+                if let Some(up) = upgrade.filter(|u| previous.is_none_or(|p| p.phase != u.phase)) {
+                    let (type_, reason, note) = match up.phase {
+                        UpgradePhase::BackingUp => (
+                            ::kube::runtime::events::EventType::Normal,
+                            "UpgradeBackupStarted",
+                            format!("Backing up before upgrading from {} to {}", up.from, up.to),
+                        ),
+                        UpgradePhase::BackupFailed => (
+                            ::kube::runtime::events::EventType::Warning,
+                            "UpgradeBackupFailed",
+                            format!("Upgrade to {} blocked, the backup failed", up.to),
+                        ),
+                        UpgradePhase::BackedUp => (
+                            ::kube::runtime::events::EventType::Normal,
+                            "UpgradeBackupSucceed",
+                            format!("Backed up as snapshot {}", up.snapshot.as_deref().unwrap_or("?")),
+                        ),
+                        UpgradePhase::UpgradeFailed => (
+                            ::kube::runtime::events::EventType::Warning,
+                            "UpgradeFailed",
+                            format!(
+                                "Upgrade to {} failed, annotate with {}=true to restore {}",
+                                up.to,
+                                $crate::vynilbackup::ROLLBACK_ANNOTATION,
+                                up.from
+                            ),
+                        ),
+                        UpgradePhase::RollingBack => (
+                            ::kube::runtime::events::EventType::Warning,
+                            "UpgradeRollingBack",
+                            format!("Reinstalling {} and restoring its backup", up.from),
+                        ),
+                        UpgradePhase::RolledBack => (
+                            ::kube::runtime::events::EventType::Normal,
+                            "UpgradeRolledBack",
+                            format!("Restored {}", up.from),
+                        ),
+                    };
+                    self.send_event(client, ::kube::runtime::events::Event {
+                        type_,
+                        reason: reason.to_string(),
+                        note: Some(note),
+                        action: "Upgrade".to_string(),
+                        secondary: None,
+                    })
+                    .await?;
+                }
+                Ok(result)
+            }
+
+            pub async fn set_last_drill(
+                &mut self,
+                drill: $crate::vynilbackup::DrillResult,
+            ) -> $crate::Result<Self> {
+                use $crate::vynilbackup::BackupOutcome;
+                let client = $crate::context::get_client_async().await;
+                let result = self
+                    .patch_status(client.clone(), serde_json::json!({ "lastDrill": drill }))
+                    .await?;
+                let (type_, reason, note) = match drill.outcome {
+                    BackupOutcome::Succeeded => (
+                        ::kube::runtime::events::EventType::Normal,
+                        "RestoreDrillSucceed",
+                        format!(
+                            "Restored snapshot {} in {}",
+                            drill.snapshot.as_deref().unwrap_or("latest"),
+                            drill.namespace
+                        ),
+                    ),
+                    BackupOutcome::Failed => (
+                        ::kube::runtime::events::EventType::Warning,
+                        "RestoreDrillFailed",
+                        drill.message.unwrap_or_default(),
+                    ),
+                };
+                self.send_event(client, ::kube::runtime::events::Event {
+                    type_,
+                    reason: reason.to_string(),
+                    note: Some(note),
+                    action: "RestoreDrill".to_string(),
+                    secondary: None,
+                })
+                .await?;
+                Ok(result)
+            }
+
+            pub async fn set_key_rotation(
+                &mut self,
+                rotation: $crate::vynilbackup::KeyRotation,
+            ) -> $crate::Result<Self> {
+                use $crate::vynilbackup::KeyRotationPhase;
+                let previous = self.status.as_ref().and_then(|s| s.key_rotation.clone());
+                if previous.as_ref() == Some(&rotation) {
+                    return Ok(self.clone());
+                }
+                let client = $crate::context::get_client_async().await;
+                let result = self
+                    .patch_status(client.clone(), serde_json::json!({ "keyRotation": rotation }))
+                    .await?;
+                if previous.is_none_or(|p| p.phase != rotation.phase) {
+                    let (type_, reason, note) = match rotation.phase {
+                        KeyRotationPhase::Started => (
+                            ::kube::runtime::events::EventType::Normal,
+                            "KeyRotationStarted",
+                            format!("Rotating the key of the backup secret {}", rotation.secret_name),
+                        ),
+                        KeyRotationPhase::KeyAdded => (
+                            ::kube::runtime::events::EventType::Normal,
+                            "KeyRotationKeyAdded",
+                            format!("Added the new key of the backup secret {}", rotation.secret_name),
+                        ),
+                        KeyRotationPhase::Rotated => (
+                            ::kube::runtime::events::EventType::Normal,
+                            "KeyRotationSucceed",
+                            format!("Rotated the key of the backup secret {}", rotation.secret_name),
+                        ),
+                        KeyRotationPhase::Failed => (
+                            ::kube::runtime::events::EventType::Warning,
+                            "KeyRotationFailed",
+                            rotation.message.clone().unwrap_or_default(),
+                        ),
+                    };
+                    self.send_event(client, ::kube::runtime::events::Event {
+                        type_,
+                        reason: reason.to_string(),
+                        note: Some(note),
+                        action: "RotateKey".to_string(),
+                        secondary: None,
+                    })
+                    .await?;
+                }
+                Ok(result)
+            }
+
+            pub async fn set_invalid_backup_policy(&mut self, reason: String) -> $crate::Result<Self> {
+                let client = $crate::context::get_client_async().await;
+                let generation = self.metadata.generation.unwrap_or(1);
+                let message = format!("Invalid backup policy: {reason}");
+                let cond = ApplicationCondition::schedule_ko(&message, generation);
+                if !self.have_condition(&cond) {
+                    let mut conditions: Vec<ApplicationCondition> =
+                        self.get_conditions_excluding(vec![ConditionsType::ScheduleBackup]);
+                    conditions.push(cond);
+                    let result = self
+                        .patch_status(
+                            client.clone(),
+                            serde_json::json!({ "conditions": conditions }),
+                        )
+                        .await?;
+                    self.send_event(client, ::kube::runtime::events::Event {
+                        type_: ::kube::runtime::events::EventType::Warning,
+                        reason: "InvalidBackupPolicy".to_string(),
+                        note: Some(message),
+                        action: "ScheduleBackup".to_string(),
+                        secondary: None,
+                    })
+                    .await?;
+                    Ok(result)
+                } else {
+                    Ok(self.clone())
+                }
+            }
+
             pub async fn set_status_befores(
                 &mut self,
-                befores: Vec<$crate::Children>,
+                befores: Vec<$crate::inventory::InventoryItem>,
             ) -> $crate::Result<Self> {
                 let count = befores.len();
                 let client = $crate::context::get_client_async().await;
@@ -924,12 +1759,8 @@ macro_rules! impl_instance_befores {
                 let mut conditions: Vec<ApplicationCondition> =
                     self.get_conditions_excluding(vec![ConditionsType::BeforeApplied]);
                 conditions.push(ApplicationCondition::before_ok(generation));
-                let result = self
-                    .patch_status(
-                        client.clone(),
-                        serde_json::json!({ "conditions": conditions, "befores": befores }),
-                    )
-                    .await?;
+                let patch = self.store_children(client.clone(), "befores", &befores, conditions).await?;
+                let result = self.patch_status(client.clone(), patch).await?;
                 self.send_event(client, ::kube::runtime::events::Event {
                     type_: ::kube::runtime::events::EventType::Normal,
                     reason: "BeforeApplySucceed".to_string(),
@@ -982,7 +1813,7 @@ macro_rules! impl_instance_befores {
 
             pub async fn set_status_vitals(
                 &mut self,
-                vitals: Vec<$crate::Children>,
+                vitals: Vec<$crate::inventory::InventoryItem>,
             ) -> $crate::Result<Self> {
                 let count = vitals.len();
                 let client = $crate::context::get_client_async().await;
@@ -990,12 +1821,8 @@ macro_rules! impl_instance_befores {
                 let mut conditions: Vec<ApplicationCondition> =
                     self.get_conditions_excluding(vec![ConditionsType::VitalApplied]);
                 conditions.push(ApplicationCondition::vital_ok(generation));
-                let result = self
-                    .patch_status(
-                        client.clone(),
-                        serde_json::json!({ "conditions": conditions, "vitals": vitals }),
-                    )
-                    .await?;
+                let patch = self.store_children(client.clone(), "vitals", &vitals, conditions).await?;
+                let result = self.patch_status(client.clone(), patch).await?;
                 self.send_event(client, ::kube::runtime::events::Event {
                     type_: ::kube::runtime::events::EventType::Normal,
                     reason: "VitalApplySucceed".to_string(),
@@ -1048,7 +1875,7 @@ macro_rules! impl_instance_befores {
 
             pub async fn set_status_scalables(
                 &mut self,
-                scalables: Vec<$crate::Children>,
+                scalables: Vec<$crate::inventory::InventoryItem>,
             ) -> $crate::Result<Self> {
                 let count = scalables.len();
                 let client = $crate::context::get_client_async().await;
@@ -1056,12 +1883,8 @@ macro_rules! impl_instance_befores {
                 let mut conditions: Vec<ApplicationCondition> =
                     self.get_conditions_excluding(vec![ConditionsType::ScalableApplied]);
                 conditions.push(ApplicationCondition::scalable_ok(generation));
-                let result = self
-                    .patch_status(
-                        client.clone(),
-                        serde_json::json!({ "conditions": conditions, "scalables": scalables }),
-                    )
-                    .await?;
+                let patch = self.store_children(client.clone(), "scalables", &scalables, conditions).await?;
+                let result = self.patch_status(client.clone(), patch).await?;
                 self.send_event(client, ::kube::runtime::events::Event {
                     type_: ::kube::runtime::events::EventType::Normal,
                     reason: "ScalableApplySucceed".to_string(),
@@ -1114,7 +1937,7 @@ macro_rules! impl_instance_befores {
 
             pub async fn set_status_others(
                 &mut self,
-                others: Vec<$crate::Children>,
+                others: Vec<$crate::inventory::InventoryItem>,
             ) -> $crate::Result<Self> {
                 let count = others.len();
                 let client = $crate::context::get_client_async().await;
@@ -1122,12 +1945,8 @@ macro_rules! impl_instance_befores {
                 let mut conditions: Vec<ApplicationCondition> =
                     self.get_conditions_excluding(vec![ConditionsType::OtherApplied]);
                 conditions.push(ApplicationCondition::other_ok(generation));
-                let result = self
-                    .patch_status(
-                        client.clone(),
-                        serde_json::json!({ "conditions": conditions, "others": others }),
-                    )
-                    .await?;
+                let patch = self.store_children(client.clone(), "others", &others, conditions).await?;
+                let result = self.patch_status(client.clone(), patch).await?;
                 self.send_event(client, ::kube::runtime::events::Event {
                     type_: ::kube::runtime::events::EventType::Normal,
                     reason: "OtherApplySucceed".to_string(),
@@ -1180,7 +1999,7 @@ macro_rules! impl_instance_befores {
 
             pub async fn set_status_posts(
                 &mut self,
-                posts: Vec<$crate::Children>,
+                posts: Vec<$crate::inventory::InventoryItem>,
             ) -> $crate::Result<Self> {
                 let count = posts.len();
                 let client = $crate::context::get_client_async().await;
@@ -1188,12 +2007,8 @@ macro_rules! impl_instance_befores {
                 let mut conditions: Vec<ApplicationCondition> =
                     self.get_conditions_excluding(vec![ConditionsType::PostApplied]);
                 conditions.push(ApplicationCondition::post_ok(generation));
-                let result = self
-                    .patch_status(
-                        client.clone(),
-                        serde_json::json!({ "conditions": conditions, "posts": posts }),
-                    )
-                    .await?;
+                let patch = self.store_children(client.clone(), "posts", &posts, conditions).await?;
+                let result = self.patch_status(client.clone(), patch).await?;
                 self.send_event(client, ::kube::runtime::events::Event {
                     type_: ::kube::runtime::events::EventType::Normal,
                     reason: "PostApplySucceed".to_string(),
@@ -1321,6 +2136,14 @@ macro_rules! impl_instance_befores {
 
             // ── Rhai wrappers ─────────────────────────────────────────────────────────
 
+            pub fn rhai_set_tofu_digest(&mut self, digest: String) -> $crate::RhaiRes<Self> {
+                ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current()
+                        .block_on(async move { self.set_tofu_digest(digest).await })
+                })
+                .map_err($crate::rhai_err)
+            }
+
             pub fn rhai_set_services(&mut self, services: ::rhai::Dynamic) -> $crate::RhaiRes<Self> {
                 ::tokio::task::block_in_place(|| {
                     ::tokio::runtime::Handle::current().block_on(async move {
@@ -1472,6 +2295,39 @@ macro_rules! impl_instance_befores {
                 .map_err($crate::rhai_err)
             }
 
+            pub fn rhai_set_backup_policy(&mut self, policy: ::rhai::Dynamic) -> $crate::RhaiRes<Self> {
+                ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current().block_on(async move {
+                        let v = serde_json::to_string(&policy).map_err($crate::Error::SerializationError)?;
+                        let policy = serde_json::from_str(&v).map_err($crate::Error::SerializationError)?;
+                        self.set_backup_policy(policy).await
+                    })
+                })
+                .map_err($crate::rhai_err)
+            }
+
+            pub fn rhai_set_last_drill(&mut self, drill: ::rhai::Dynamic) -> $crate::RhaiRes<Self> {
+                ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current().block_on(async move {
+                        let v = serde_json::to_string(&drill).map_err($crate::Error::SerializationError)?;
+                        let drill = serde_json::from_str(&v).map_err($crate::Error::SerializationError)?;
+                        self.set_last_drill(drill).await
+                    })
+                })
+                .map_err($crate::rhai_err)
+            }
+
+            pub fn rhai_set_key_rotation(&mut self, rotation: ::rhai::Dynamic) -> $crate::RhaiRes<Self> {
+                ::tokio::task::block_in_place(|| {
+                    ::tokio::runtime::Handle::current().block_on(async move {
+                        let v = serde_json::to_string(&rotation).map_err($crate::Error::SerializationError)?;
+                        let rotation = serde_json::from_str(&v).map_err($crate::Error::SerializationError)?;
+                        self.set_key_rotation(rotation).await
+                    })
+                })
+                .map_err($crate::rhai_err)
+            }
+
             pub async fn set_missing_init_version(
                 &mut self,
                 version: String,
@@ -1515,6 +2371,39 @@ macro_rules! impl_instance_befores {
                 })
                 .map_err($crate::rhai_err)
             }
+
+            /// Write the `initFrom` resolved from `initFrom.instanceRef` and the options copied from
+            /// the source instance to the spec
+            pub async fn set_init_from_instance(
+                &mut self,
+                init_from: serde_json::Value,
+                options: serde_json::Map<String, serde_json::Value>,
+                source: String,
+            ) -> $crate::Result<Self> {
+                let client = $crate::context::get_client_async().await;
+                let api = ::kube::api::Api::<Self>::namespaced(client.clone(), &self.namespace().unwrap());
+                let snapshot = init_from["snapshot"].as_str().unwrap_or_default().to_string();
+                let patch = ::kube::api::Patch::Json::<()>(
+                    serde_json::from_value(serde_json::json!([
+                        {"op": "add", "path": "/spec/initFrom", "value": init_from},
+                        {"op": "add", "path": "/spec/options", "value": options}
+                    ]))
+                    .map_err($crate::Error::SerializationError)?,
+                );
+                let updated = api
+                    .patch(&self.name_any(), &::kube::api::PatchParams::default(), &patch)
+                    .await
+                    .map_err($crate::Error::KubeError)?;
+                self.send_event(client, ::kube::runtime::events::Event {
+                    type_: ::kube::runtime::events::EventType::Normal,
+                    reason: "InitFromResolved".to_string(),
+                    note: Some(format!("Cloning snapshot {snapshot} of {source}")),
+                    action: "InitFrom".to_string(),
+                    secondary: None,
+                })
+                .await?;
+                Ok(updated)
+            }
         }
     };
 }
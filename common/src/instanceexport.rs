@@ -0,0 +1,105 @@
+//! Portable export of an instance (`InstanceExport`), restorable on another cluster.
+//!
+//! The format and the backup Jobs live in the `vynil-export` crate, shared with kubectl-vynil;
+//! this module registers them in rhai.
+use crate::{Error, RhaiRes, context::get_client_async, rhai_err};
+use chrono::Utc;
+use rhai::{Dynamic, Engine, serde::to_dynamic};
+use std::time::Duration;
+pub use vynil_export::{
+    BackupRecord, archive, fresh_backup, from_archive, job_created_at, job_outcome, latest_backup,
+    list_backups, missing_references, start_backup_job,
+};
+
+fn rhai_archive(instance: Dynamic, kind: &str, record: Dynamic) -> RhaiRes<Dynamic> {
+    let instance = serde_json::to_value(&instance).map_err(|e| rhai_err(Error::SerializationError(e)))?;
+    let record = serde_json::to_value(&record).map_err(|e| rhai_err(Error::SerializationError(e)))?;
+    to_dynamic(archive(kind, &instance, &record, &Utc::now().to_rfc3339()))
+}
+
+fn rhai_from_archive(archive: Dynamic, args: Dynamic) -> RhaiRes<Dynamic> {
+    let archive = serde_json::to_value(&archive).map_err(|e| rhai_err(Error::SerializationError(e)))?;
+    let args = serde_json::to_value(&args).map_err(|e| rhai_err(Error::SerializationError(e)))?;
+    to_dynamic(
+        from_archive(
+            &archive,
+            args["namespace"].as_str(),
+            args["name"].as_str(),
+            args["jukebox"].as_str(),
+            args["secret_name"].as_str(),
+        )
+        .map_err(|e| rhai_err(e.into()))?,
+    )
+}
+
+fn rhai_fresh_backup(instance: Dynamic, kind: &str, timeout: i64) -> RhaiRes<Dynamic> {
+    let instance = serde_json::to_value(&instance).map_err(|e| rhai_err(Error::SerializationError(e)))?;
+    let namespace = instance["metadata"]["namespace"].as_str().unwrap_or_default();
+    let name = instance["metadata"]["name"].as_str().unwrap_or_default();
+    let package = instance["spec"]["package"].as_str().unwrap_or_default();
+    let record = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async move {
+            let client = get_client_async().await;
+            let timeout = Duration::from_secs(timeout.max(0) as u64);
+            fresh_backup(client, kind, namespace, name, package, timeout).await
+        })
+    })
+    .map_err(|e| rhai_err(e.into()))?;
+    to_dynamic(record)
+}
+
+fn rhai_missing_references(archive: Dynamic, namespace: &str) -> RhaiRes<Dynamic> {
+    let archive = serde_json::to_value(&archive).map_err(|e| rhai_err(Error::SerializationError(e)))?;
+    let missing = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async move {
+            let client = get_client_async().await;
+            missing_references(client, namespace, &archive).await
+        })
+    })
+    .map_err(|e| rhai_err(e.into()))?;
+    to_dynamic(missing)
+}
+
+pub fn instance_export_rhai_register(engine: &mut Engine) {
+    engine
+        .register_fn("instance_export_archive", rhai_archive)
+        .register_fn("instance_export_from_archive", rhai_from_archive)
+        .register_fn("instance_fresh_backup", rhai_fresh_backup)
+        .register_fn("instance_export_missing_references", rhai_missing_references);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vynilbackup::VynilBackup;
+    use serde_json::json;
+
+    #[test]
+    fn latest_backup_picks_typed_records() {
+        let records: Vec<VynilBackup> = ["2026-10-17T02:00:00Z", "2026-10-18T02:00:00Z"]
+            .iter()
+            .enumerate()
+            .map(|(i, started)| {
+                serde_json::from_value(json!({
+                    "apiVersion": "vynil.solidite.fr/v1",
+                    "kind": "VynilBackup",
+                    "metadata": {"name": format!("wiki-{i}")},
+                    "spec": {
+                        "instance": {"kind": "TenantInstance", "name": "wiki"},
+                        "package": "apps/wiki",
+                        "version": "1.2.0",
+                        "secretName": "backup-settings",
+                        "subPath": "prod/wiki",
+                        "snapshot": format!("snap-{i}"),
+                        "startedAt": started,
+                        "outcome": "Succeeded"
+                    }
+                }))
+                .unwrap()
+            })
+            .collect();
+        let picked = latest_backup(&records, "TenantInstance", None).unwrap();
+        assert_eq!(picked.metadata.name.as_deref(), Some("wiki-1"));
+        assert!(latest_backup(&records, "ServiceInstance", None).is_none());
+    }
+}
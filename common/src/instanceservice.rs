@@ -16,10 +16,14 @@ pub struct InitFrom {
     pub secret_name: Option<String>,
     /// Path within the bucket containing the backup to use for recovery. Default to "<namespace-name>/<app-slug>"
     pub sub_path: Option<String>,
-    /// Snapshot id for restoration
-    pub snapshot: String,
+    /// Snapshot id for restoration (required unless `backup` is set)
+    pub snapshot: Option<String>,
     /// Version du package à utiliser pour la restauration
     pub version: Option<String>,
+    /// Name of a VynilBackup of the namespace to restore, providing the fields above
+    pub backup: Option<String>,
+    /// Instance to clone, the operator fills the fields above from one of its backups
+    pub instance_ref: Option<crate::vynilbackup::InstanceRef>,
 }
 
 /// Describe a source of vynil packages jukebox
@@ -39,8 +43,9 @@ pub struct InitFrom {
     printcolumn = r#"{"name":"pkg",         "type":"string", "description":"Package",           "jsonPath":".spec.package"}"#,
     printcolumn = r#"{"name":"tag",         "type":"string", "description":"Version",           "jsonPath":".status.tag"}"#,
     printcolumn = r#"{"name":"last_updated","type":"date",   "description":"Last update date",  "format":"date-time", "jsonPath":".status.conditions[?(@.type == 'Ready')].lastTransitionTime"}"#,
-    printcolumn = r#"{"name":"stage",       "type":"string", "description":"Stage",             "jsonPath":".status.conditions[-1:].type"}"#,
-    printcolumn = r#"{"name":"errors",      "type":"string", "description":"Errors",            "jsonPath":".status.conditions[?(@.status == 'False')].message"}"#
+    printcolumn = r#"{"name":"ready",       "type":"string", "description":"Ready",             "jsonPath":".status.conditions[?(@.type == 'Ready')].status"}"#,
+    printcolumn = r#"{"name":"reconciling", "type":"string", "description":"Reconciling stage", "jsonPath":".status.conditions[?(@.type == 'Reconciling')].message"}"#,
+    printcolumn = r#"{"name":"errors",      "type":"string", "description":"Errors",            "jsonPath":".status.conditions[?(@.type == 'Stalled')].message"}"#
 )]
 #[serde(rename_all = "camelCase")]
 pub struct ServiceInstanceSpec {
@@ -52,14 +57,22 @@ pub struct ServiceInstanceSpec {
     pub package: String,
     /// Init from a previous backup
     pub init_from: Option<InitFrom>,
+    /// Backup schedule and retention
+    pub backup: Option<crate::vynilbackup::BackupPolicy>,
     /// Parameters
     pub options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Install missing package dependencies automatically (default to the JukeBox setting)
+    pub auto_install_dependencies: Option<bool>,
+    /// Re-apply the package when one of its children is deleted (default to true)
+    pub self_heal: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
 pub enum ConditionsType {
     #[default]
     Ready,
+    Reconciling,
+    Stalled,
     Installed,
     Backuped,
     Restored,
@@ -74,6 +87,9 @@ pub enum ConditionsType {
     OtherApplied,
     RhaiApplied,
     PostApplied,
+    Deletable,
+    PolicyCompliant,
+    Healthy,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
@@ -96,8 +112,12 @@ pub struct ApplicationCondition {
     pub condition_type: ConditionsType,
     /// Status ("True" or "False") describe if the condition is enbled
     pub status: ConditionsStatus,
+    /// Machine-readable reason of the last transition (CamelCase)
+    pub reason: Option<String>,
     /// Generation for that status
     pub generation: i64,
+    /// Generation of the instance this condition was computed for
+    pub observed_generation: Option<i64>,
 }
 
 impl_condition_common!();
@@ -109,23 +129,50 @@ impl_condition_crds!();
 pub struct ServiceInstanceStatus {
     /// ServiceInstance Conditions
     pub conditions: Vec<ApplicationCondition>,
+    /// Last generation of the instance observed by the controller
+    #[serde(rename = "observedGeneration")]
+    pub observed_generation: Option<i64>,
     /// Current tag
     pub tag: Option<String>,
     /// Options digests
     pub digest: Option<String>,
-    /// Current terraform status (gzip+base64)
+    /// Options merged over the VynilDefaults selecting the namespace
+    #[serde(rename = "effectiveOptions")]
+    pub effective_options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Backup policy in use, set when the backups are scheduled
+    #[serde(rename = "backupPolicy")]
+    pub backup_policy: Option<crate::vynilbackup::BackupPolicy>,
+    /// Backup taken before the running upgrade
+    #[serde(rename = "upgradeBackup")]
+    pub upgrade_backup: Option<crate::vynilbackup::UpgradeBackup>,
+    /// Result of the last restore drill
+    #[serde(rename = "lastDrill")]
+    pub last_drill: Option<crate::vynilbackup::DrillResult>,
+    /// Rotation of the backup repository key
+    #[serde(rename = "keyRotation")]
+    pub key_rotation: Option<crate::vynilbackup::KeyRotation>,
+    /// Legacy inline terraform status (gzip+base64), moved to the state store on the next write
     pub tfstate: Option<String>,
-    /// Current rhai status (gzip+base64) (for custom package information)
+    /// Legacy inline rhai status (gzip+base64), moved to the state store on the next write
     pub rhaistate: Option<String>,
-    /// List of before children
+    /// Location of the terraform and rhai states in the state store
+    pub state: Option<crate::statestore::StoredState>,
+    /// Digest of the tofu inputs last applied
+    #[serde(rename = "tofuDigest")]
+    pub tofu_digest: Option<String>,
+    /// Inventory holding the children lists
+    pub inventory: Option<crate::inventory::InventoryRef>,
+    /// Health of the monitored children
+    pub health: Option<crate::health::HealthSummary>,
+    /// Legacy list of before children, moved to the inventory on the next apply
     pub befores: Option<Vec<crate::Children>>,
-    /// List of vital children
+    /// Legacy list of vital children, moved to the inventory on the next apply
     pub vitals: Option<Vec<crate::Children>>,
-    /// List of scalable children
+    /// Legacy list of scalable children, moved to the inventory on the next apply
     pub scalables: Option<Vec<crate::Children>>,
-    /// List of other children
+    /// Legacy list of other children, moved to the inventory on the next apply
     pub others: Option<Vec<crate::Children>>,
-    /// List of post children
+    /// Legacy list of post children, moved to the inventory on the next apply
     pub posts: Option<Vec<crate::Children>>,
     /// List of crds children
     pub crds: Option<Vec<String>>,
@@ -142,6 +189,16 @@ impl ServiceInstance {
             if status.tfstate.is_some() {
                 return true;
             }
+            if status.state.as_ref().is_some_and(|s| !s.keys.is_empty()) {
+                return true;
+            }
+            if status
+                .inventory
+                .as_ref()
+                .is_some_and(|i| i.counts.values().any(|c| *c > 0))
+            {
+                return true;
+            }
             if let Some(child) = status.befores.clone()
                 && !child.is_empty()
             {
@@ -222,9 +279,12 @@ pub fn service_rhai_register(engine: &mut Engine) {
         .register_fn("get_service_instance", ServiceInstance::rhai_get)
         .register_fn("list_service_instance", ServiceInstance::rhai_list)
         .register_fn("list_services_names", ServiceInstance::rhai_list_services_names)
-        .register_fn("options_digest", ServiceInstance::get_options_digest)
+        .register_fn("options_digest", ServiceInstance::rhai_get_options_digest)
         .register_fn("get_tfstate", ServiceInstance::rhai_get_tfstate)
         .register_fn("get_rhaistate", ServiceInstance::rhai_get_rhaistate)
+        .register_fn("lock_state", ServiceInstance::rhai_lock_state)
+        .register_fn("unlock_state", ServiceInstance::rhai_unlock_state)
+        .register_fn("get_children", ServiceInstance::rhai_get_children)
         .register_fn("set_services", ServiceInstance::rhai_set_services)
         .register_fn("get_services", ServiceInstance::rhai_get_services)
         .register_fn("set_agent_started", ServiceInstance::rhai_set_agent_started)
@@ -270,6 +330,7 @@ pub fn service_rhai_register(engine: &mut Engine) {
             ServiceInstance::rhai_set_status_post_failed,
         )
         .register_fn("set_tfstate", ServiceInstance::rhai_set_tfstate)
+        .register_fn("set_tofu_digest", ServiceInstance::rhai_set_tofu_digest)
         .register_fn(
             "set_status_tofu_failed",
             ServiceInstance::rhai_set_status_tofu_failed,
@@ -283,6 +344,9 @@ pub fn service_rhai_register(engine: &mut Engine) {
             "set_status_schedule_backup_failed",
             ServiceInstance::rhai_set_status_schedule_backup_failed,
         )
+        .register_fn("set_backup_policy", ServiceInstance::rhai_set_backup_policy)
+        .register_fn("set_last_drill", ServiceInstance::rhai_set_last_drill)
+        .register_fn("set_key_rotation", ServiceInstance::rhai_set_key_rotation)
         .register_fn(
             "set_status_init_failed",
             ServiceInstance::rhai_set_status_init_failed,
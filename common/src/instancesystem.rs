@@ -24,8 +24,9 @@ use serde::{Deserialize, Serialize};
     printcolumn = r#"{"name":"pkg",         "type":"string", "description":"Package",           "jsonPath":".spec.package"}"#,
     printcolumn = r#"{"name":"tag",         "type":"string", "description":"Version",           "jsonPath":".status.tag"}"#,
     printcolumn = r#"{"name":"last_updated","type":"date",   "description":"Last update date",  "format":"date-time", "jsonPath":".status.conditions[?(@.type == 'Ready')].lastTransitionTime"}"#,
-    printcolumn = r#"{"name":"stage",       "type":"string", "description":"Stage",             "jsonPath":".status.conditions[-1:].type"}"#,
-    printcolumn = r#"{"name":"errors",      "type":"string", "description":"Errors",            "jsonPath":".status.conditions[?(@.status == 'False')].message"}"#
+    printcolumn = r#"{"name":"ready",       "type":"string", "description":"Ready",             "jsonPath":".status.conditions[?(@.type == 'Ready')].status"}"#,
+    printcolumn = r#"{"name":"reconciling", "type":"string", "description":"Reconciling stage", "jsonPath":".status.conditions[?(@.type == 'Reconciling')].message"}"#,
+    printcolumn = r#"{"name":"errors",      "type":"string", "description":"Errors",            "jsonPath":".status.conditions[?(@.type == 'Stalled')].message"}"#
 )]
 pub struct SystemInstanceSpec {
     /// The jukebox source name
@@ -36,18 +37,30 @@ pub struct SystemInstanceSpec {
     pub package: String,
     /// Parameters
     pub options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Install missing package dependencies automatically (default to the JukeBox setting)
+    #[serde(rename = "autoInstallDependencies")]
+    pub auto_install_dependencies: Option<bool>,
+    /// Re-apply the package when one of its children is deleted (default to true)
+    #[serde(rename = "selfHeal")]
+    pub self_heal: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
 pub enum ConditionsType {
     #[default]
     Ready,
+    Reconciling,
+    Stalled,
     Installed,
     AgentStarted,
     CrdApplied,
     TofuInstalled,
     SystemApplied,
     RhaiApplied,
+    Deletable,
+    PolicyCompliant,
+    MembersApplied,
+    Healthy,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
@@ -70,8 +83,12 @@ pub struct ApplicationCondition {
     pub condition_type: ConditionsType,
     /// Status ("True" or "False") describe if the condition is enbled
     pub status: ConditionsStatus,
+    /// Machine-readable reason of the last transition (CamelCase)
+    pub reason: Option<String>,
     /// Generation for that status
     pub generation: i64,
+    /// Generation of the instance this condition was computed for
+    pub observed_generation: Option<i64>,
 }
 
 impl_condition_common!();
@@ -95,6 +112,24 @@ impl ApplicationCondition {
             generation,
         )
     }
+
+    pub fn members_ko(message: &str, generation: i64) -> ApplicationCondition {
+        ApplicationCondition::new(
+            message,
+            ConditionsStatus::False,
+            ConditionsType::MembersApplied,
+            generation,
+        )
+    }
+
+    pub fn members_ok(generation: i64) -> ApplicationCondition {
+        ApplicationCondition::new(
+            "Bundle members applied succesfully",
+            ConditionsStatus::True,
+            ConditionsType::MembersApplied,
+            generation,
+        )
+    }
 }
 
 /// The status object of `SystemInstance`
@@ -102,18 +137,32 @@ impl ApplicationCondition {
 pub struct SystemInstanceStatus {
     /// SystemInstance Conditions
     pub conditions: Vec<ApplicationCondition>,
+    /// Last generation of the instance observed by the controller
+    #[serde(rename = "observedGeneration")]
+    pub observed_generation: Option<i64>,
     /// Current tag
     pub tag: Option<String>,
     /// Options digests
     pub digest: Option<String>,
-    /// Current terraform status (gzip+base64)
+    /// Options merged over the VynilDefaults selecting the namespace
+    #[serde(rename = "effectiveOptions")]
+    pub effective_options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Legacy inline terraform status (gzip+base64), moved to the state store on the next write
     pub tfstate: Option<String>,
-    /// Current rhai status (gzip+base64) (for custom package information)
+    /// Legacy inline rhai status (gzip+base64), moved to the state store on the next write
     pub rhaistate: Option<String>,
-    /// List of system children
+    /// Location of the terraform and rhai states in the state store
+    pub state: Option<crate::statestore::StoredState>,
+    /// Inventory holding the children lists
+    pub inventory: Option<crate::inventory::InventoryRef>,
+    /// Health of the monitored children
+    pub health: Option<crate::health::HealthSummary>,
+    /// Legacy list of system children, moved to the inventory on the next apply
     pub systems: Option<Vec<crate::Children>>,
     /// List of crds children
     pub crds: Option<Vec<String>>,
+    /// List of bundle members instances
+    pub members: Option<Vec<crate::Children>>,
 }
 
 impl SystemInstance {
@@ -125,6 +174,16 @@ impl SystemInstance {
             if status.tfstate.is_some() {
                 return true;
             }
+            if status.state.as_ref().is_some_and(|s| !s.keys.is_empty()) {
+                return true;
+            }
+            if status
+                .inventory
+                .as_ref()
+                .is_some_and(|i| i.counts.values().any(|c| *c > 0))
+            {
+                return true;
+            }
             if let Some(child) = status.systems.clone()
                 && !child.is_empty()
             {
@@ -135,6 +194,11 @@ impl SystemInstance {
             {
                 return true;
             }
+            if let Some(child) = status.members.clone()
+                && !child.is_empty()
+            {
+                return true;
+            }
         }
         false
     }
@@ -155,7 +219,7 @@ impl SystemInstance {
                 serde_json::json!({
                     "conditions": conditions,
                     "tag": tag,
-                    "digest": self.clone().get_options_digest()
+                    "digest": self.clone().get_options_digest()?
                 }),
             )
             .await?;
@@ -170,19 +234,18 @@ impl SystemInstance {
         Ok(result)
     }
 
-    pub async fn set_status_systems(&mut self, systems: Vec<crate::Children>) -> crate::Result<Self> {
+    pub async fn set_status_systems(
+        &mut self,
+        systems: Vec<crate::inventory::InventoryItem>,
+    ) -> crate::Result<Self> {
         let count = systems.len();
         let client = crate::context::get_client_async().await;
         let generation = self.metadata.generation.unwrap_or(1);
         let mut conditions: Vec<ApplicationCondition> =
             self.get_conditions_excluding(vec![ConditionsType::SystemApplied]);
         conditions.push(ApplicationCondition::system_ok(generation));
-        let result = self
-            .patch_status(
-                client.clone(),
-                serde_json::json!({ "conditions": conditions, "systems": systems }),
-            )
-            .await?;
+        let patch = self.store_children(client.clone(), "systems", &systems, conditions).await?;
+        let result = self.patch_status(client.clone(), patch).await?;
         self.send_event(client, Event {
             type_: EventType::Normal,
             reason: "SystemApplySucceed".to_string(),
@@ -227,6 +290,82 @@ impl SystemInstance {
         Ok(result)
     }
 
+    pub async fn set_status_members(&mut self, members: Vec<crate::Children>) -> crate::Result<Self> {
+        let count = members.len();
+        let client = crate::context::get_client_async().await;
+        let generation = self.metadata.generation.unwrap_or(1);
+        let mut conditions: Vec<ApplicationCondition> =
+            self.get_conditions_excluding(vec![ConditionsType::MembersApplied]);
+        conditions.push(ApplicationCondition::members_ok(generation));
+        let result = self
+            .patch_status(
+                client.clone(),
+                serde_json::json!({ "conditions": conditions, "members": members }),
+            )
+            .await?;
+        self.send_event(client, Event {
+            type_: EventType::Normal,
+            reason: "MembersApplySucceed".to_string(),
+            note: Some(format!("Applied {} bundle members", count)),
+            action: "MembersApply".to_string(),
+            secondary: None,
+        })
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn set_status_members_failed(&mut self, reason: String) -> crate::Result<Self> {
+        let client = crate::context::get_client_async().await;
+        let generation: i64 = self.metadata.generation.unwrap_or(1);
+        let mut conditions: Vec<ApplicationCondition> = self.get_conditions_excluding(vec![
+            ConditionsType::AgentStarted,
+            ConditionsType::MembersApplied,
+            ConditionsType::Installed,
+        ]);
+        conditions.push(ApplicationCondition::members_ko(&reason, generation));
+        conditions.push(ApplicationCondition::installed_ko(&reason, generation));
+        if !conditions
+            .clone()
+            .into_iter()
+            .any(|c| c.condition_type == ConditionsType::Ready)
+        {
+            conditions.push(ApplicationCondition::ready_ko(generation));
+        }
+        let result = self
+            .patch_status(client.clone(), serde_json::json!({ "conditions": conditions }))
+            .await?;
+        let mut note = reason;
+        note.truncate(1023);
+        self.send_event(client, Event {
+            type_: EventType::Warning,
+            reason: "MembersApplyFailed".to_string(),
+            note: Some(note),
+            action: "MembersApply".to_string(),
+            secondary: None,
+        })
+        .await?;
+        Ok(result)
+    }
+
+    pub fn rhai_set_status_members(&mut self, list: rhai::Dynamic) -> crate::RhaiRes<Self> {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async move {
+                let v = serde_json::to_string(&list).map_err(crate::Error::SerializationError)?;
+                let lst = serde_json::from_str(&v).map_err(crate::Error::SerializationError)?;
+                self.set_status_members(lst).await
+            })
+        })
+        .map_err(crate::rhai_err)
+    }
+
+    pub fn rhai_set_status_members_failed(&mut self, reason: String) -> crate::RhaiRes<Self> {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current()
+                .block_on(async move { self.set_status_members_failed(reason).await })
+        })
+        .map_err(crate::rhai_err)
+    }
+
     pub fn rhai_set_status_systems(&mut self, list: rhai::Dynamic) -> crate::RhaiRes<Self> {
         tokio::task::block_in_place(|| {
             tokio::runtime::Handle::current().block_on(async move {
@@ -255,9 +394,12 @@ pub fn system_rhai_register(engine: &mut Engine) {
         .register_type_with_name::<SystemInstance>("SystemInstance")
         .register_fn("get_system_instance", SystemInstance::rhai_get)
         .register_fn("list_system_instance", SystemInstance::rhai_list)
-        .register_fn("options_digest", SystemInstance::get_options_digest)
+        .register_fn("options_digest", SystemInstance::rhai_get_options_digest)
         .register_fn("get_tfstate", SystemInstance::rhai_get_tfstate)
         .register_fn("get_rhaistate", SystemInstance::rhai_get_rhaistate)
+        .register_fn("lock_state", SystemInstance::rhai_lock_state)
+        .register_fn("unlock_state", SystemInstance::rhai_unlock_state)
+        .register_fn("get_children", SystemInstance::rhai_get_children)
         .register_fn("set_agent_started", SystemInstance::rhai_set_agent_started)
         .register_fn("set_missing_box", SystemInstance::rhai_set_missing_box)
         .register_fn("set_missing_package", SystemInstance::rhai_set_missing_package)
@@ -276,6 +418,11 @@ pub fn system_rhai_register(engine: &mut Engine) {
             "set_status_system_failed",
             SystemInstance::rhai_set_status_system_failed,
         )
+        .register_fn("set_status_members", SystemInstance::rhai_set_status_members)
+        .register_fn(
+            "set_status_members_failed",
+            SystemInstance::rhai_set_status_members_failed,
+        )
         .register_fn("set_tfstate", SystemInstance::rhai_set_tfstate)
         .register_fn(
             "set_status_tofu_failed",
@@ -1,6 +1,5 @@
 use crate::{Error, Published, Result, RhaiRes, context::get_client_async, rhai_err};
 use chrono::{DateTime, Utc};
-use k8s_openapi::api::core::v1::Namespace;
 use kube::{
     CustomResource, Resource, ResourceExt,
     api::{Api, ListParams},
@@ -17,10 +16,14 @@ pub struct InitFrom {
     pub secret_name: Option<String>,
     /// Path within the bucket containing the backup to use for recovery. Default to "<namespace-name>/<app-slug>"
     pub sub_path: Option<String>,
-    /// Snapshot id for restoration
-    pub snapshot: String,
+    /// Snapshot id for restoration (required unless `backup` is set)
+    pub snapshot: Option<String>,
     /// Version du package à utiliser pour la restauration
     pub version: Option<String>,
+    /// Name of a VynilBackup of the namespace to restore, providing the fields above
+    pub backup: Option<String>,
+    /// Instance to clone, the operator fills the fields above from one of its backups
+    pub instance_ref: Option<crate::vynilbackup::InstanceRef>,
 }
 
 /// Describe a source of vynil packages jukebox
@@ -40,8 +43,9 @@ pub struct InitFrom {
     printcolumn = r#"{"name":"pkg",         "type":"string", "description":"Package",           "jsonPath":".spec.package"}"#,
     printcolumn = r#"{"name":"tag",         "type":"string", "description":"Version",           "jsonPath":".status.tag"}"#,
     printcolumn = r#"{"name":"last_updated","type":"date",   "description":"Last update date",  "format":"date-time", "jsonPath":".status.conditions[?(@.type == 'Ready')].lastTransitionTime"}"#,
-    printcolumn = r#"{"name":"stage",       "type":"string", "description":"Stage",             "jsonPath":".status.conditions[-1:].type"}"#,
-    printcolumn = r#"{"name":"errors",      "type":"string", "description":"Errors",            "jsonPath":".status.conditions[?(@.status == 'False')].message"}"#
+    printcolumn = r#"{"name":"ready",       "type":"string", "description":"Ready",             "jsonPath":".status.conditions[?(@.type == 'Ready')].status"}"#,
+    printcolumn = r#"{"name":"reconciling", "type":"string", "description":"Reconciling stage", "jsonPath":".status.conditions[?(@.type == 'Reconciling')].message"}"#,
+    printcolumn = r#"{"name":"errors",      "type":"string", "description":"Errors",            "jsonPath":".status.conditions[?(@.type == 'Stalled')].message"}"#
 )]
 #[serde(rename_all = "camelCase")]
 pub struct TenantInstanceSpec {
@@ -53,14 +57,22 @@ pub struct TenantInstanceSpec {
     pub package: String,
     /// Init from a previous backup
     pub init_from: Option<InitFrom>,
+    /// Backup schedule and retention
+    pub backup: Option<crate::vynilbackup::BackupPolicy>,
     /// Parameters
     pub options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Install missing package dependencies automatically (default to the JukeBox setting)
+    pub auto_install_dependencies: Option<bool>,
+    /// Re-apply the package when one of its children is deleted (default to true)
+    pub self_heal: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
 pub enum ConditionsType {
     #[default]
     Ready,
+    Reconciling,
+    Stalled,
     Installed,
     Backuped,
     Restored,
@@ -74,6 +86,9 @@ pub enum ConditionsType {
     OtherApplied,
     RhaiApplied,
     PostApplied,
+    Deletable,
+    PolicyCompliant,
+    Healthy,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
@@ -96,8 +111,12 @@ pub struct ApplicationCondition {
     pub condition_type: ConditionsType,
     /// Status ("True" or "False") describe if the condition is enbled
     pub status: ConditionsStatus,
+    /// Machine-readable reason of the last transition (CamelCase)
+    pub reason: Option<String>,
     /// Generation for that status
     pub generation: i64,
+    /// Generation of the instance this condition was computed for
+    pub observed_generation: Option<i64>,
 }
 
 impl_condition_common!();
@@ -108,23 +127,50 @@ impl_condition_children!();
 pub struct TenantInstanceStatus {
     /// TenantInstance Conditions
     pub conditions: Vec<ApplicationCondition>,
+    /// Last generation of the instance observed by the controller
+    #[serde(rename = "observedGeneration")]
+    pub observed_generation: Option<i64>,
     /// Current tag
     pub tag: Option<String>,
     /// Options digests
     pub digest: Option<String>,
-    /// Current terraform status (gzip+base64)
+    /// Options merged over the VynilDefaults selecting the namespace
+    #[serde(rename = "effectiveOptions")]
+    pub effective_options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Backup policy in use, set when the backups are scheduled
+    #[serde(rename = "backupPolicy")]
+    pub backup_policy: Option<crate::vynilbackup::BackupPolicy>,
+    /// Backup taken before the running upgrade
+    #[serde(rename = "upgradeBackup")]
+    pub upgrade_backup: Option<crate::vynilbackup::UpgradeBackup>,
+    /// Result of the last restore drill
+    #[serde(rename = "lastDrill")]
+    pub last_drill: Option<crate::vynilbackup::DrillResult>,
+    /// Rotation of the backup repository key
+    #[serde(rename = "keyRotation")]
+    pub key_rotation: Option<crate::vynilbackup::KeyRotation>,
+    /// Legacy inline terraform status (gzip+base64), moved to the state store on the next write
     pub tfstate: Option<String>,
-    /// Current rhai status (gzip+base64) (for custom package information)
+    /// Legacy inline rhai status (gzip+base64), moved to the state store on the next write
     pub rhaistate: Option<String>,
-    /// List of before children
+    /// Location of the terraform and rhai states in the state store
+    pub state: Option<crate::statestore::StoredState>,
+    /// Digest of the tofu inputs last applied
+    #[serde(rename = "tofuDigest")]
+    pub tofu_digest: Option<String>,
+    /// Inventory holding the children lists
+    pub inventory: Option<crate::inventory::InventoryRef>,
+    /// Health of the monitored children
+    pub health: Option<crate::health::HealthSummary>,
+    /// Legacy list of before children, moved to the inventory on the next apply
     pub befores: Option<Vec<crate::Children>>,
-    /// List of vital children
+    /// Legacy list of vital children, moved to the inventory on the next apply
     pub vitals: Option<Vec<crate::Children>>,
-    /// List of scalable children
+    /// Legacy list of scalable children, moved to the inventory on the next apply
     pub scalables: Option<Vec<crate::Children>>,
-    /// List of other children
+    /// Legacy list of other children, moved to the inventory on the next apply
     pub others: Option<Vec<crate::Children>>,
-    /// List of post children
+    /// Legacy list of post children, moved to the inventory on the next apply
     pub posts: Option<Vec<crate::Children>>,
     /// List of the services
     pub services: Option<Vec<Published>>,
@@ -139,6 +185,16 @@ impl TenantInstance {
             if status.tfstate.is_some() {
                 return true;
             }
+            if status.state.as_ref().is_some_and(|s| !s.keys.is_empty()) {
+                return true;
+            }
+            if status
+                .inventory
+                .as_ref()
+                .is_some_and(|i| i.counts.values().any(|c| *c > 0))
+            {
+                return true;
+            }
             if let Some(child) = status.befores.clone()
                 && !child.is_empty()
             {
@@ -170,42 +226,12 @@ impl TenantInstance {
 
     pub async fn get_tenant_name(&self) -> Result<String> {
         let my_ns = self.metadata.namespace.clone().unwrap();
-        let ns_api: Api<Namespace> = Api::all(get_client_async().await);
-        let my_ns_meta = ns_api.get_metadata(&my_ns).await.map_err(Error::KubeError)?;
-        let label_key =
-            std::env::var("TENANT_LABEL").unwrap_or_else(|_| "vynil.solidite.fr/tenant".to_string());
-        if let Some(labels) = my_ns_meta.metadata.labels.clone() {
-            if labels.clone().keys().any(|k| k == &label_key) {
-                Ok(labels[&label_key].clone())
-            } else {
-                Ok(my_ns)
-            }
-        } else {
-            Ok(my_ns)
-        }
+        crate::dependents::tenant_name(get_client_async().await, &my_ns).await
     }
 
     pub async fn get_tenant_namespaces(&self) -> Result<Vec<String>> {
         let my_ns = self.metadata.namespace.clone().unwrap();
-        let ns_api: Api<Namespace> = Api::all(get_client_async().await);
-        let my_ns_meta = ns_api.get_metadata(&my_ns).await.map_err(Error::KubeError)?;
-        let label_key =
-            std::env::var("TENANT_LABEL").unwrap_or_else(|_| "vynil.solidite.fr/tenant".to_string());
-        let res = vec![my_ns];
-        if let Some(labels) = my_ns_meta.metadata.labels.clone()
-            && labels.clone().keys().any(|k| k == &label_key)
-        {
-            let tenant_name = &labels[&label_key];
-            let mut lp = ListParams::default();
-            lp = lp.labels(format!("{}=={}", label_key, tenant_name).as_str());
-            let my_nss = ns_api.list_metadata(&lp).await.map_err(Error::KubeError)?;
-            return Ok(my_nss
-                .items
-                .into_iter()
-                .map(|n| n.metadata.name.unwrap())
-                .collect());
-        }
-        Ok(res)
+        crate::dependents::tenant_namespaces(get_client_async().await, &my_ns).await
     }
 
     pub async fn get_tenant_services_names(&self) -> Result<Vec<String>> {
@@ -287,6 +313,31 @@ snapshot: "abc123"
         assert_eq!(init_from.version, None);
     }
 
+    #[test]
+    fn test_init_from_backup_reference() {
+        let yaml = r#"
+backup: "wiki-20261001020000"
+"#;
+        let init_from: InitFrom = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(init_from.backup.as_deref(), Some("wiki-20261001020000"));
+        assert_eq!(init_from.snapshot, None);
+    }
+
+    #[test]
+    fn test_init_from_instance_reference() {
+        let yaml = r#"
+instanceRef:
+  namespace: prod
+  name: wiki
+"#;
+        let init_from: InitFrom = serde_yaml::from_str(yaml).unwrap();
+        let source = init_from.instance_ref.unwrap();
+        assert_eq!(source.namespace.as_deref(), Some("prod"));
+        assert_eq!(source.name, "wiki");
+        assert_eq!(source.kind, None);
+        assert_eq!(source.backup, None);
+    }
+
     #[test]
     fn test_spec_version_top_level_ignored() {
         let yaml = r#"
@@ -316,6 +367,62 @@ version: "1.0.0"
             cond.message
         );
         assert_eq!(cond.generation, 3);
+        assert_eq!(cond.reason.as_deref(), Some("InitVersionNotFound"));
+    }
+
+    fn summary(conditions: &[ApplicationCondition]) -> Vec<&ApplicationCondition> {
+        conditions
+            .iter()
+            .filter(|c| {
+                c.condition_type == ConditionsType::Reconciling || c.condition_type == ConditionsType::Stalled
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_summarize_reconciling_while_agent_runs() {
+        let conditions = ApplicationCondition::summarize(vec![ApplicationCondition::agent_started(2)], 2);
+        let summary = summary(&conditions);
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].condition_type, ConditionsType::Reconciling);
+        assert_eq!(summary[0].reason.as_deref(), Some("Progressing"));
+        assert_eq!(summary[0].observed_generation, Some(2));
+    }
+
+    #[test]
+    fn test_summarize_stalled_on_failure() {
+        let conditions = ApplicationCondition::summarize(
+            vec![
+                ApplicationCondition::ready_ko(1),
+                ApplicationCondition::vital_ko("boom", 1),
+            ],
+            1,
+        );
+        let summary = summary(&conditions);
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].condition_type, ConditionsType::Stalled);
+        assert_eq!(summary[0].reason.as_deref(), Some("VitalAppliedFailed"));
+        assert_eq!(summary[0].message, "boom");
+    }
+
+    #[test]
+    fn test_summarize_current_when_ready() {
+        let mut old_failure = ApplicationCondition::tofu_ko("old", 1);
+        old_failure.last_transition_time = Some(Utc::now() - chrono::Duration::minutes(5));
+        let conditions = ApplicationCondition::summarize(
+            vec![
+                old_failure,
+                ApplicationCondition::ready_ok(1),
+                ApplicationCondition::new("", ConditionsStatus::True, ConditionsType::Reconciling, 1),
+            ],
+            1,
+        );
+        assert!(summary(&conditions).is_empty());
+        let conditions = ApplicationCondition::summarize(conditions, 2);
+        assert_eq!(
+            summary(&conditions)[0].condition_type,
+            ConditionsType::Reconciling
+        );
     }
 
     #[test]
@@ -333,14 +440,27 @@ version: "1.0.0"
                 category: "cat".to_string(),
                 package: "pkg".to_string(),
                 init_from: None,
+                backup: None,
                 options: None,
+                auto_install_dependencies: None,
+                self_heal: None,
             },
             status: Some(TenantInstanceStatus {
                 conditions: vec![cond.clone()],
+                observed_generation: None,
                 tag: None,
                 digest: None,
+                effective_options: None,
+                backup_policy: None,
+                upgrade_backup: None,
+                last_drill: None,
+                key_rotation: None,
                 tfstate: None,
                 rhaistate: None,
+                state: None,
+                tofu_digest: None,
+                inventory: None,
+                health: None,
                 befores: None,
                 vitals: None,
                 scalables: None,
@@ -370,9 +490,12 @@ pub fn tenant_rhai_register(engine: &mut Engine) {
             TenantInstance::rhai_get_tenant_services_names,
         )
         .register_fn("list_tenant_instance", TenantInstance::rhai_list)
-        .register_fn("options_digest", TenantInstance::get_options_digest)
+        .register_fn("options_digest", TenantInstance::rhai_get_options_digest)
         .register_fn("get_tfstate", TenantInstance::rhai_get_tfstate)
         .register_fn("get_rhaistate", TenantInstance::rhai_get_rhaistate)
+        .register_fn("lock_state", TenantInstance::rhai_lock_state)
+        .register_fn("unlock_state", TenantInstance::rhai_unlock_state)
+        .register_fn("get_children", TenantInstance::rhai_get_children)
         .register_fn("set_agent_started", TenantInstance::rhai_set_agent_started)
         .register_fn("set_missing_box", TenantInstance::rhai_set_missing_box)
         .register_fn("set_missing_package", TenantInstance::rhai_set_missing_package)
@@ -411,6 +534,7 @@ pub fn tenant_rhai_register(engine: &mut Engine) {
             TenantInstance::rhai_set_status_post_failed,
         )
         .register_fn("set_tfstate", TenantInstance::rhai_set_tfstate)
+        .register_fn("set_tofu_digest", TenantInstance::rhai_set_tofu_digest)
         .register_fn(
             "set_status_tofu_failed",
             TenantInstance::rhai_set_status_tofu_failed,
@@ -426,6 +550,9 @@ pub fn tenant_rhai_register(engine: &mut Engine) {
             "set_status_schedule_backup_failed",
             TenantInstance::rhai_set_status_schedule_backup_failed,
         )
+        .register_fn("set_backup_policy", TenantInstance::rhai_set_backup_policy)
+        .register_fn("set_last_drill", TenantInstance::rhai_set_last_drill)
+        .register_fn("set_key_rotation", TenantInstance::rhai_set_key_rotation)
         .register_fn(
             "set_status_init_failed",
             TenantInstance::rhai_set_status_init_failed,
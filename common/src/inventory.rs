@@ -0,0 +1,198 @@
+use crate::{Children, Error, Result};
+use k8s_openapi::{api::core::v1::ConfigMap, apimachinery::pkg::apis::meta::v1::OwnerReference};
+use kube::{
+    Client,
+    api::{Api, ObjectMeta, Patch, PatchParams, PostParams},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+/// Child lists kept in the inventory
+pub const LISTS: &[&str] = &["befores", "vitals", "scalables", "others", "posts", "systems"];
+
+/// A child recorded in the inventory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct InventoryItem {
+    #[serde(flatten)]
+    pub child: Children,
+    /// uid of the applied object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+    /// Hash of the applied manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Reference to the inventory of an instance, kept in its status
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct InventoryRef {
+    /// Name of the inventory ConfigMap
+    pub name: String,
+    /// Number of children per list
+    pub counts: BTreeMap<String, u32>,
+}
+
+/// Name of the inventory ConfigMap of an instance
+pub fn name(kind: &str, instance: &str) -> String {
+    format!(
+        "vynil-inventory-{}-{}",
+        kind.to_lowercase().trim_end_matches("instance"),
+        instance
+    )
+}
+
+/// Whether a list of an instance status is recorded in the inventory
+pub fn recorded(status: &Value, list: &str) -> bool {
+    status
+        .pointer(&format!("/inventory/counts/{list}"))
+        .is_some_and(|c| !c.is_null())
+}
+
+/// Legacy children list kept inline in the instance status
+pub fn inline(status: &Value, list: &str) -> Vec<InventoryItem> {
+    status
+        .get(list)
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| serde_json::from_value(v.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse(cm: &ConfigMap, list: &str) -> Result<Vec<InventoryItem>> {
+    match cm.data.as_ref().and_then(|d| d.get(list)) {
+        Some(data) => serde_json::from_str(data).map_err(Error::SerializationError),
+        None => Ok(Vec::new()),
+    }
+}
+
+async fn get(client: Client, namespace: &str, status: &Value) -> Result<Option<ConfigMap>> {
+    let Some(name) = status.pointer("/inventory/name").and_then(|n| n.as_str()) else {
+        return Ok(None);
+    };
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    api.get_opt(name).await.map_err(Error::KubeError)
+}
+
+/// Read a children list of an instance status, from the inventory or the legacy inline list
+pub async fn children(
+    client: Client,
+    namespace: &str,
+    status: &Value,
+    list: &str,
+) -> Result<Vec<InventoryItem>> {
+    if !recorded(status, list) {
+        return Ok(inline(status, list));
+    }
+    match get(client, namespace, status).await? {
+        Some(cm) => parse(&cm, list),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Read every children list of an instance status
+pub async fn all_children(
+    client: Client,
+    namespace: &str,
+    status: &Value,
+) -> Result<BTreeMap<String, Vec<InventoryItem>>> {
+    let cm = if LISTS.iter().any(|l| recorded(status, l)) {
+        get(client, namespace, status).await?
+    } else {
+        None
+    };
+    let mut ret = BTreeMap::new();
+    for list in LISTS {
+        let items = match &cm {
+            Some(cm) if recorded(status, list) => parse(cm, list)?,
+            _ => inline(status, list),
+        };
+        if !items.is_empty() {
+            ret.insert(list.to_string(), items);
+        }
+    }
+    Ok(ret)
+}
+
+/// Record a children list in the inventory of an instance, returning the status patch to apply
+pub async fn write(
+    client: Client,
+    kind: &str,
+    namespace: &str,
+    instance: &str,
+    owner: OwnerReference,
+    list: &str,
+    items: &[InventoryItem],
+) -> Result<Value> {
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let name = name(kind, instance);
+    let data = serde_json::to_string(items).map_err(Error::SerializationError)?;
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.patch(
+            &name,
+            &PatchParams::default(),
+            &Patch::Merge(json!({"data": {list: data}})),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+    } else {
+        let cm = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(name.clone()),
+                namespace: Some(namespace.to_string()),
+                labels: Some(BTreeMap::from([(
+                    "app.kubernetes.io/managed-by".to_string(),
+                    "vynil".to_string(),
+                )])),
+                owner_references: Some(vec![owner]),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([(list.to_string(), data)])),
+            ..Default::default()
+        };
+        api.create(&PostParams::default(), &cm)
+            .await
+            .map_err(Error::KubeError)?;
+    }
+    Ok(json!({
+        list: null,
+        "inventory": {"name": name, "counts": {list: items.len()}},
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_lists_are_read_inline() {
+        let status = json!({
+            "vitals": [{"kind": "PersistentVolumeClaim", "name": "data", "namespace": "ns"}],
+            "inventory": {"name": "vynil-inventory-tenant-app", "counts": {"befores": 2}},
+        });
+        assert!(!recorded(&status, "vitals"));
+        assert!(recorded(&status, "befores"));
+        let vitals = inline(&status, "vitals");
+        assert_eq!(vitals.len(), 1);
+        assert_eq!(vitals[0].child.name, "data");
+        assert_eq!(vitals[0].uid, None);
+    }
+
+    #[test]
+    fn items_keep_the_children_shape() {
+        let item: InventoryItem = serde_json::from_value(json!({
+            "apiVersion": "v1", "kind": "Secret", "name": "s", "uid": "1234", "hash": "abcd"
+        }))
+        .unwrap();
+        assert_eq!(item.child.kind, "Secret");
+        assert_eq!(item.uid.as_deref(), Some("1234"));
+        let back = serde_json::to_value(&item).unwrap();
+        assert_eq!(back["apiVersion"], "v1");
+        assert_eq!(back["hash"], "abcd");
+        assert_eq!(name("TenantInstance", "app"), "vynil-inventory-tenant-app");
+    }
+}
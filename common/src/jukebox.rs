@@ -1,5 +1,6 @@
 use crate::{
     Error, Result, RhaiRes,
+    catalog::{self, CatalogSummary},
     context::{get_client_async, get_reporter, get_short_name},
     rhai_err,
     vynilpackage::VynilPackage,
@@ -95,6 +96,9 @@ pub struct JukeBoxSpec {
     pub pull_secret: Option<String>,
     /// Actual cron-type expression that defines the interval of the updates.
     pub schedule: String,
+    /// Install missing package dependencies automatically for instances using this jukebox
+    #[serde(rename = "autoInstallDependencies")]
+    pub auto_install_dependencies: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
@@ -183,12 +187,17 @@ impl ApplicationCondition {
 }
 
 /// The status object of `JukeBox`
+///
+/// The packages themselves are stored as VynilPackage resources
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
 pub struct JukeBoxStatus {
     /// JukeBox Conditions
     pub conditions: Vec<ApplicationCondition>,
-    /// Vynil packages for this box
-    pub packages: Vec<VynilPackage>,
+    /// Summary of the catalog of this box
+    pub summary: Option<CatalogSummary>,
+    /// Incremented every time the catalog of this box is written
+    #[serde(rename = "catalogGeneration", default)]
+    pub catalog_generation: i64,
 }
 
 impl JukeBox {
@@ -257,23 +266,43 @@ impl JukeBox {
         }
     }
 
-    pub async fn set_status_updated(&mut self, packages: Vec<VynilPackage>) -> Result<Self> {
-        let count = packages.len();
-        let client = get_client_async().await;
+    fn catalog_generation(&self) -> i64 {
+        self.status.as_ref().map(|s| s.catalog_generation).unwrap_or(0)
+    }
+
+    /// Load the packages of this box from the catalog
+    pub async fn packages(&self) -> Result<Vec<VynilPackage>> {
+        catalog::load(
+            get_client_async().await,
+            &self.metadata.name.clone().unwrap_or_default(),
+        )
+        .await
+    }
+
+    /// Write the catalog then patch the status with its summary
+    async fn store_catalog(&mut self, client: Client, packages: Vec<VynilPackage>) -> Result<Self> {
         let generation = self.metadata.generation.unwrap_or(1);
+        let summary = catalog::store(client.clone(), self, packages).await?;
+        let catalog_generation = self.catalog_generation() + 1;
         let conditions: Vec<ApplicationCondition> = vec![
             ApplicationCondition::updated_ok(generation),
             ApplicationCondition::ready_ok(generation),
         ];
-        let result = self
-            .patch_status(
-                client.clone(),
-                json!({
-                    "conditions": conditions,
-                    "packages": packages,
-                }),
-            )
-            .await?;
+        self.patch_status(
+            client,
+            json!({
+                "conditions": conditions,
+                "summary": summary,
+                "catalogGeneration": catalog_generation,
+            }),
+        )
+        .await
+    }
+
+    pub async fn set_status_updated(&mut self, packages: Vec<VynilPackage>) -> Result<Self> {
+        let count = packages.len();
+        let client = get_client_async().await;
+        let result = self.store_catalog(client.clone(), packages).await?;
         self.send_event(client, Event {
             type_: EventType::Normal,
             reason: "ScanSucceed".to_string(),
@@ -298,17 +327,11 @@ impl JukeBox {
         {
             conditions.push(ApplicationCondition::ready_ko(generation));
         }
-        let existing_packages = self
-            .status
-            .as_ref()
-            .map(|s| s.packages.clone())
-            .unwrap_or_default();
         let result = self
             .patch_status(
                 client.clone(),
                 json!({
                     "conditions": conditions,
-                    "packages": existing_packages,
                 }),
             )
             .await?;
@@ -361,6 +384,13 @@ impl JukeBox {
         .map_err(rhai_err)
     }
 
+    pub fn rhai_get_packages(&mut self) -> RhaiRes<Dynamic> {
+        let lst = block_in_place(|| Handle::current().block_on(async move { self.packages().await }))
+            .map_err(rhai_err)?;
+        let v = serde_json::to_string(&lst).map_err(|e| rhai_err(Error::SerializationError(e)))?;
+        serde_json::from_str(&v).map_err(|e| rhai_err(Error::SerializationError(e)))
+    }
+
     pub fn rhai_set_status_failed(&mut self, reason: String) -> RhaiRes<JukeBox> {
         block_in_place(|| Handle::current().block_on(async move { self.set_status_failed(reason).await }))
             .map_err(rhai_err)
@@ -372,7 +402,6 @@ impl JukeBox {
         packages: Vec<VynilPackage>,
     ) -> Result<Self> {
         let client = get_client_async().await;
-        let generation = self.metadata.generation.unwrap_or(1);
 
         let (filter_category, filter_name): (String, Option<String>) = if let Some(pos) = filter.find('/') {
             (filter[..pos].to_string(), Some(filter[pos + 1..].to_string()))
@@ -380,26 +409,9 @@ impl JukeBox {
             (filter.clone(), None)
         };
 
-        let existing = self
-            .status
-            .as_ref()
-            .map(|s| s.packages.clone())
-            .unwrap_or_default();
+        let existing = self.packages().await?;
         let merged = filter_packages(existing, &filter_category, filter_name.as_deref(), packages);
-
-        let conditions = vec![
-            ApplicationCondition::updated_ok(generation),
-            ApplicationCondition::ready_ok(generation),
-        ];
-        let result = self
-            .patch_status(
-                client.clone(),
-                json!({
-                    "conditions": conditions,
-                    "packages": merged,
-                }),
-            )
-            .await?;
+        let result = self.store_catalog(client.clone(), merged).await?;
 
         self.send_event(client, Event {
             type_: EventType::Normal,
@@ -452,7 +464,8 @@ pub fn jukebox_rhai_register(engine: &mut Engine) {
         )
         .register_get("metadata", JukeBox::get_metadata)
         .register_get("spec", JukeBox::get_spec)
-        .register_get("status", JukeBox::get_status);
+        .register_get("status", JukeBox::get_status)
+        .register_get("packages", JukeBox::rhai_get_packages);
 }
 
 #[cfg(test)]
@@ -517,6 +530,8 @@ mod tests {
             recommandations: None,
             options: None,
             value_script: None,
+            migrations: None,
+            options_migrate: None,
         }
     }
 
@@ -48,6 +48,11 @@ impl FileJukeBox {
         serde_json::from_value(v).map_err(|e| rhai_err(Error::SerializationError(e)))
     }
 
+    pub fn get_packages(&mut self) -> RhaiRes<Dynamic> {
+        let packages = self.read_all_from_cache().map_err(rhai_err)?;
+        serde_json::from_value(serde_json::json!(packages)).map_err(|e| rhai_err(Error::SerializationError(e)))
+    }
+
     fn read_all_from_cache(&self) -> Result<Vec<VynilPackage>> {
         let index_path = self.cache_dir.join("index.yaml");
         if !index_path.exists() {
@@ -146,7 +151,8 @@ pub fn file_jukebox_rhai_register(engine: &mut Engine) {
             FileJukeBox::rhai_set_status_packages_merge,
         )
         .register_get("spec", FileJukeBox::get_spec)
-        .register_get("status", FileJukeBox::get_status);
+        .register_get("status", FileJukeBox::get_status)
+        .register_get("packages", FileJukeBox::get_packages);
 }
 
 #[cfg(test)]
@@ -173,6 +179,8 @@ mod tests {
             recommandations: None,
             options: None,
             value_script: None,
+            migrations: None,
+            options_migrate: None,
         }
     }
 
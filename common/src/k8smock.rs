@@ -126,6 +126,25 @@ impl K8sInstanceMock {
         Ok(String::new())
     }
 
+    pub fn lock_state(&mut self) -> RhaiRes<()> {
+        Ok(())
+    }
+
+    pub fn unlock_state(&mut self) -> RhaiRes<()> {
+        Ok(())
+    }
+
+    pub fn get_children(&mut self, list: String) -> RhaiRes<Dynamic> {
+        let status = self.get_sub("status")?;
+        if let Ok(m) = status.as_map_ref()
+            && let Some(v) = m.get(list.as_str())
+            && v.is_array()
+        {
+            return Ok(v.clone());
+        }
+        Ok(Dynamic::from_array(vec![]))
+    }
+
     pub fn get_rhaistate(&mut self) -> RhaiRes<String> {
         let status = self.get_sub("status")?;
         if let Ok(m) = status.as_map_ref()
@@ -182,6 +201,11 @@ impl K8sInstanceMock {
         Ok(self.clone())
     }
 
+    pub fn set_tofu_digest(&mut self, digest: String) -> RhaiRes<Self> {
+        self.set_status_field("tofuDigest", Dynamic::from(digest));
+        Ok(self.clone())
+    }
+
     // ── Children status setters ─────────────────────────────────────────
 
     pub fn set_status_crds(&mut self, list: Dynamic) -> RhaiRes<Self> {
@@ -247,6 +271,15 @@ impl K8sInstanceMock {
         Ok(self.clone())
     }
 
+    pub fn set_status_members(&mut self, list: Dynamic) -> RhaiRes<Self> {
+        self.set_status_field("members", list);
+        Ok(self.clone())
+    }
+
+    pub fn set_status_members_failed(&mut self, _reason: String) -> RhaiRes<Self> {
+        Ok(self.clone())
+    }
+
     pub fn set_status_init_failed(&mut self, _reason: String) -> RhaiRes<Self> {
         Ok(self.clone())
     }
@@ -255,6 +288,21 @@ impl K8sInstanceMock {
         Ok(self.clone())
     }
 
+    pub fn set_backup_policy(&mut self, policy: Dynamic) -> RhaiRes<Self> {
+        self.set_status_field("backupPolicy", policy);
+        Ok(self.clone())
+    }
+
+    pub fn set_last_drill(&mut self, drill: Dynamic) -> RhaiRes<Self> {
+        self.set_status_field("lastDrill", drill);
+        Ok(self.clone())
+    }
+
+    pub fn set_key_rotation(&mut self, rotation: Dynamic) -> RhaiRes<Self> {
+        self.set_status_field("keyRotation", rotation);
+        Ok(self.clone())
+    }
+
     // ── Services ────────────────────────────────────────────────────────
 
     pub fn set_services(&mut self, services: Dynamic) -> RhaiRes<Self> {
@@ -446,6 +494,18 @@ impl K8sJukeBoxMock {
         self.get_sub("status")
     }
 
+    /// The mocks keep the catalog in status.packages
+    pub fn get_packages(&mut self) -> RhaiRes<Dynamic> {
+        let status = self.get_sub("status")?;
+        if status.is_map() {
+            let map = status.as_map_ref().unwrap();
+            if map.contains_key("packages") {
+                return Ok(map["packages"].clone());
+            }
+        }
+        Ok(Dynamic::from_array(vec![]))
+    }
+
     pub fn set_status_updated(&mut self, packages: Dynamic) -> RhaiRes<Self> {
         self.set_status_field("packages", packages);
         Ok(self.clone())
@@ -513,6 +573,8 @@ fn register_instance_common(engine: &mut Engine) {
         .register_fn("options_digest", K8sInstanceMock::get_options_digest)
         .register_fn("get_tfstate", K8sInstanceMock::get_tfstate)
         .register_fn("get_rhaistate", K8sInstanceMock::get_rhaistate)
+        .register_fn("lock_state", K8sInstanceMock::lock_state)
+        .register_fn("unlock_state", K8sInstanceMock::unlock_state)
         .register_fn("set_agent_started", K8sInstanceMock::set_agent_started)
         .register_fn("set_missing_box", K8sInstanceMock::set_missing_box)
         .register_fn("set_missing_package", K8sInstanceMock::set_missing_package)
@@ -525,6 +587,7 @@ fn register_instance_common(engine: &mut Engine) {
             K8sInstanceMock::set_missing_init_version,
         )
         .register_fn("set_status_ready", K8sInstanceMock::set_status_ready)
+        .register_fn("get_children", K8sInstanceMock::get_children)
         .register_fn("set_tfstate", K8sInstanceMock::set_tfstate)
         .register_fn("set_status_tofu_failed", K8sInstanceMock::set_status_tofu_failed)
         .register_fn("set_rhaistate", K8sInstanceMock::set_rhaistate)
@@ -537,6 +600,7 @@ fn register_instance_common(engine: &mut Engine) {
 fn register_instance_children(engine: &mut Engine) {
     engine
         .register_fn("set_services", K8sInstanceMock::set_services)
+        .register_fn("set_tofu_digest", K8sInstanceMock::set_tofu_digest)
         .register_fn("get_services", K8sInstanceMock::get_services_string)
         .register_fn("set_status_befores", K8sInstanceMock::set_status_befores)
         .register_fn(
@@ -564,7 +628,10 @@ fn register_instance_children(engine: &mut Engine) {
         .register_fn(
             "set_status_schedule_backup_failed",
             K8sInstanceMock::set_status_schedule_backup_failed,
-        );
+        )
+        .register_fn("set_backup_policy", K8sInstanceMock::set_backup_policy)
+        .register_fn("set_last_drill", K8sInstanceMock::set_last_drill)
+        .register_fn("set_key_rotation", K8sInstanceMock::set_key_rotation);
 }
 
 pub fn k8smock_rhai_register(engine: &mut Engine, mocks: Vec<Dynamic>, created: Arc<Mutex<Vec<Dynamic>>>) {
@@ -617,6 +684,11 @@ pub fn k8smock_rhai_register(engine: &mut Engine, mocks: Vec<Dynamic>, created:
         .register_fn(
             "set_status_system_failed",
             K8sInstanceMock::set_status_system_failed,
+        )
+        .register_fn("set_status_members", K8sInstanceMock::set_status_members)
+        .register_fn(
+            "set_status_members_failed",
+            K8sInstanceMock::set_status_members_failed,
         );
     register_instance_common(engine);
 
@@ -667,5 +739,6 @@ pub fn k8smock_rhai_register(engine: &mut Engine, mocks: Vec<Dynamic>, created:
         .register_fn("set_status_failed", K8sJukeBoxMock::set_status_failed)
         .register_get("metadata", K8sJukeBoxMock::get_metadata)
         .register_get("spec", K8sJukeBoxMock::get_spec)
-        .register_get("status", K8sJukeBoxMock::get_status);
+        .register_get("status", K8sJukeBoxMock::get_status)
+        .register_get("packages", K8sJukeBoxMock::get_packages);
 }
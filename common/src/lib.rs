@@ -98,12 +98,21 @@ pub enum Error {
     PasswordSpec(String),
     #[error("INIT-VERSION-001 Init version {0} not found in registry")]
     MissingInitVersion(String),
+    #[error("INIT-BACKUP-001 No restorable VynilBackup named {0}")]
+    MissingBackup(String),
+    #[error("INIT-INSTANCE-001 Cannot clone instance {0}")]
+    InvalidInstanceRef(String),
+    #[error("DELETE-BLOCKED-001 Deletion blocked by dependent instances: {0}")]
+    DeletionBlocked(String),
 
     #[error("Error: {0}")]
     Other(String),
 
     #[error(transparent)]
     Core(#[from] vynil_core::Error),
+
+    #[error(transparent)]
+    Export(#[from] vynil_export::Error),
 }
 impl Error {
     pub fn metric_label(&self) -> String {
@@ -119,27 +128,51 @@ pub fn rhai_err(e: Error) -> Box<rhai::EvalAltResult> {
 pub fn rhai_err_str(e: String) -> Box<rhai::EvalAltResult> {
     e.into()
 }
+
+pub use vynil_export::appslug;
+
+/// `name` cut to `max` characters, a short hash of the full name keeping cut names distinct
+pub fn short_name(name: &str, max: usize) -> String {
+    if name.len() <= max {
+        return name.to_string();
+    }
+    let hash: String = sha256::digest(name).chars().take(8).collect();
+    let prefix: String = name.chars().take(max - hash.len() - 1).collect();
+    format!("{}-{hash}", prefix.trim_end_matches('-'))
+}
+pub mod catalog;
 pub mod context;
+pub mod dependents;
 pub mod handlebarshandler;
+pub mod health;
 pub mod k8smock;
 pub mod ttl_cache;
 #[macro_use]
 pub mod instance_macros;
+pub mod instanceexport;
 pub mod instanceservice;
 pub mod instancesystem;
 pub mod instancetenant;
+pub mod inventory;
 pub mod jukebox;
 pub mod jukebox_file;
+pub mod platform;
 pub mod rhaihandler;
+pub mod statestore;
+pub mod tenantprofile;
 mod tools;
+pub mod valuefrom;
+pub mod vynilbackup;
+pub mod vynildefaults;
 pub mod vynilpackage;
+pub mod vynilpolicy;
 pub mod yamlhandler;
 
 // ── Re-exports from vynil-core (preserve old import paths) ──────────────────
 
 pub use context::get_client_name;
 pub use vynil_core::{
-    Semver, chrono as chronohandler, glob as globhandler, hashes as hasheshandlers, http as httphandler,
+    Semver, backup as backuphandler, chrono as chronohandler, glob as globhandler, hashes as hasheshandlers, http as httphandler,
     http_mock as httpmock, k8s as k8sgeneric, k8s as k8sraw, k8s as k8sworkload, key as ed25519handler,
     oci as ocihandler, password as passwordhandler, register_k8s_generic, register_k8s_object,
     register_k8s_raw, s3 as s3handler, semver as semverhandler, shell as shellhandler,
@@ -0,0 +1,233 @@
+use crate::{
+    Error, Result,
+    dependents::InstalledInstance,
+    vynilpackage::{VynilPackageRequirement, VynilPackageType},
+};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+/// Annotations (or annotation prefixes) that only make sense on the cluster they were set on
+const TRANSIENT_ANNOTATIONS: [&str; 2] = [
+    "kubectl.kubernetes.io/last-applied-configuration",
+    "vynil.solidite.fr/force-",
+];
+
+/// Keeps the declarative part of an object: apiVersion, kind, name, namespace, labels,
+/// annotations (without the transient ones) and spec
+pub fn sanitize(obj: &Value) -> Value {
+    let meta = &obj["metadata"];
+    let mut metadata = Map::new();
+    for key in ["name", "namespace", "labels"] {
+        if let Some(value) = meta.get(key).filter(|v| !v.is_null()) {
+            metadata.insert(key.to_string(), value.clone());
+        }
+    }
+    let annotations: Map<String, Value> = meta["annotations"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(k, _)| !TRANSIENT_ANNOTATIONS.iter().any(|t| k.starts_with(t)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    if !annotations.is_empty() {
+        metadata.insert("annotations".to_string(), Value::Object(annotations));
+    }
+    let mut res = json!({
+        "apiVersion": obj["apiVersion"],
+        "kind": obj["kind"],
+        "metadata": metadata,
+    });
+    if let Some(spec) = obj.get("spec").filter(|v| !v.is_null()) {
+        res["spec"] = spec.clone();
+    }
+    res
+}
+
+/// Keeps the name and the labels of a namespace
+pub fn sanitize_namespace(name: &str, labels: &BTreeMap<String, String>) -> Value {
+    let labels: BTreeMap<&String, &String> = labels
+        .iter()
+        .filter(|(k, _)| k.as_str() != "kubernetes.io/metadata.name")
+        .collect();
+    json!({
+        "apiVersion": "v1",
+        "kind": "Namespace",
+        "metadata": {"name": name, "labels": labels},
+    })
+}
+
+/// Sorts the instances into tiers: every instance comes after the instances fulfilling its
+/// requirements. Instances of a dependency cycle end up together in the last tier.
+///
+/// `tenant_of` gives the tenant of a namespace; TenantPackage and TenantService requirements
+/// are only fulfilled within the same tenant.
+pub fn install_tiers<F, T>(
+    installed: &[InstalledInstance],
+    tenant_of: T,
+    requirements_of: F,
+) -> Vec<Vec<usize>>
+where
+    F: Fn(&InstalledInstance) -> Vec<VynilPackageRequirement>,
+    T: Fn(&str) -> String,
+{
+    let is_package = |i: &InstalledInstance, usage: VynilPackageType, category: &str, name: &str| {
+        i.usage == usage && i.category == category && i.package == name
+    };
+    let providers: Vec<Vec<usize>> = installed
+        .iter()
+        .enumerate()
+        .map(|(idx, inst)| {
+            let same_tenant = |o: &InstalledInstance| {
+                o.usage == VynilPackageType::Tenant && tenant_of(&o.namespace) == tenant_of(&inst.namespace)
+            };
+            let reqs = requirements_of(inst);
+            installed
+                .iter()
+                .enumerate()
+                .filter(|(other, o)| {
+                    *other != idx
+                        && reqs.iter().any(|req| match req {
+                            VynilPackageRequirement::SystemPackage { category, name } => {
+                                is_package(o, VynilPackageType::System, category, name)
+                            }
+                            VynilPackageRequirement::SystemService(svc) => {
+                                o.usage == VynilPackageType::Service && o.services.contains(svc)
+                            }
+                            VynilPackageRequirement::TenantPackage { category, name } => {
+                                same_tenant(o) && is_package(o, VynilPackageType::Tenant, category, name)
+                            }
+                            VynilPackageRequirement::TenantService(svc) => {
+                                same_tenant(o) && o.services.contains(svc)
+                            }
+                            _ => false,
+                        })
+                })
+                .map(|(other, _)| other)
+                .collect()
+        })
+        .collect();
+
+    let mut placed = vec![false; installed.len()];
+    let mut tiers: Vec<Vec<usize>> = Vec::new();
+    while placed.iter().any(|p| !p) {
+        let mut tier: Vec<usize> = (0..installed.len())
+            .filter(|&i| !placed[i] && providers[i].iter().all(|&p| placed[p]))
+            .collect();
+        if tier.is_empty() {
+            tier = (0..installed.len()).filter(|&i| !placed[i]).collect();
+        }
+        for &i in &tier {
+            placed[i] = true;
+        }
+        tiers.push(tier);
+    }
+    tiers
+}
+
+/// Writes objects as a multi-document YAML
+pub fn to_yaml_documents(objects: &[Value]) -> Result<String> {
+    objects
+        .iter()
+        .map(|o| serde_yaml::to_string(o).map_err(|e| Error::YamlError(e.to_string())))
+        .collect::<Result<Vec<_>>>()
+        .map(|docs| docs.join("---\n"))
+}
+
+/// Reads the objects of a multi-document YAML
+pub fn from_yaml_documents(content: &str) -> Result<Vec<Value>> {
+    let mut res = Vec::new();
+    for doc in serde_yaml::Deserializer::from_str(content) {
+        let value = Value::deserialize(doc).map_err(|e| Error::YamlError(e.to_string()))?;
+        if !value.is_null() {
+            res.push(value);
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inst(ns: &str, name: &str, usage: VynilPackageType, services: &[&str]) -> InstalledInstance {
+        InstalledInstance {
+            kind: match usage {
+                VynilPackageType::System => "SystemInstance",
+                VynilPackageType::Service => "ServiceInstance",
+                _ => "TenantInstance",
+            },
+            namespace: ns.to_string(),
+            name: name.to_string(),
+            jukebox: "jb".to_string(),
+            category: "cat".to_string(),
+            package: name.to_string(),
+            usage,
+            services: services.iter().map(|s| s.to_string()).collect(),
+            deleting: false,
+        }
+    }
+
+    #[test]
+    fn tiers_follow_the_requirements() {
+        let installed = vec![
+            inst("acme", "wiki", VynilPackageType::Tenant, &[]),
+            inst("acme", "sso", VynilPackageType::Tenant, &["sso"]),
+            inst("other", "sso", VynilPackageType::Tenant, &["sso"]),
+            inst("db", "pg", VynilPackageType::Service, &["postgresql"]),
+            inst("vynil-system", "cnpg", VynilPackageType::System, &[]),
+            inst("loop", "a", VynilPackageType::Tenant, &["a"]),
+            inst("loop", "b", VynilPackageType::Tenant, &["b"]),
+        ];
+        let tiers = install_tiers(
+            &installed,
+            |ns| ns.to_string(),
+            |i| match (i.namespace.as_str(), i.name.as_str()) {
+                ("acme", "wiki") => vec![
+                    VynilPackageRequirement::TenantService("sso".to_string()),
+                    VynilPackageRequirement::SystemService("postgresql".to_string()),
+                ],
+                (_, "sso") => vec![VynilPackageRequirement::SystemService("postgresql".to_string())],
+                (_, "pg") => vec![VynilPackageRequirement::SystemPackage {
+                    category: "cat".to_string(),
+                    name: "cnpg".to_string(),
+                }],
+                ("loop", "a") => vec![VynilPackageRequirement::TenantService("b".to_string())],
+                ("loop", "b") => vec![VynilPackageRequirement::TenantService("a".to_string())],
+                _ => vec![],
+            },
+        );
+        assert_eq!(tiers, vec![vec![4], vec![3], vec![1, 2], vec![0], vec![5, 6]]);
+    }
+
+    #[test]
+    fn sanitize_keeps_the_declarative_part() {
+        let obj = json!({
+            "apiVersion": "vynil.solidite.fr/v1",
+            "kind": "TenantInstance",
+            "metadata": {
+                "name": "wiki", "namespace": "acme", "uid": "1234", "resourceVersion": "42",
+                "managedFields": [{}], "finalizers": ["vynil"],
+                "annotations": {
+                    "vynil.solidite.fr/force-reinstall": "true",
+                    "kubectl.kubernetes.io/last-applied-configuration": "{}",
+                    "team": "docs"
+                }
+            },
+            "spec": {"package": "wiki"},
+            "status": {"tag": "1.0.0"}
+        });
+        assert_eq!(
+            sanitize(&obj),
+            json!({
+                "apiVersion": "vynil.solidite.fr/v1",
+                "kind": "TenantInstance",
+                "metadata": {"name": "wiki", "namespace": "acme", "annotations": {"team": "docs"}},
+                "spec": {"package": "wiki"}
+            })
+        );
+        let docs =
+            to_yaml_documents(&[sanitize(&obj), sanitize_namespace("acme", &BTreeMap::new())]).unwrap();
+        assert_eq!(from_yaml_documents(&docs).unwrap().len(), 2);
+    }
+}
@@ -1,8 +1,10 @@
 use crate::{
+    backuphandler::backup_rhai_register,
     context,
     handlebarshandler::handlebars_rhai_register,
     httphandler::http_rhai_register,
     httpmock::{HttpMockItem, httpmock_rhai_register},
+    instanceexport::instance_export_rhai_register,
     instanceservice::service_rhai_register,
     instancesystem::system_rhai_register,
     instancetenant::tenant_rhai_register,
@@ -73,6 +75,8 @@ impl Script {
         let mut script = Self::new_core(resolver_path);
         http_rhai_register(&mut script.engine);
         s3_rhai_register(&mut script.engine);
+        backup_rhai_register(&mut script.engine);
+        instance_export_rhai_register(&mut script.engine);
         service_rhai_register(&mut script.engine);
         system_rhai_register(&mut script.engine);
         tenant_rhai_register(&mut script.engine);
@@ -90,6 +94,9 @@ impl Script {
         created_objects: std::sync::Arc<std::sync::Mutex<Vec<Dynamic>>>,
     ) -> Script {
         let mut script = Self::new_core(resolver_path);
+        // Repositories on a local path need no cluster
+        backup_rhai_register(&mut script.engine);
+        instance_export_rhai_register(&mut script.engine);
         oci_mock_rhai_register(&mut script.engine);
         httpmock_rhai_register(&mut script.engine, http_mocks);
         k8smock_rhai_register(&mut script.engine, k8s_mocks, created_objects);
@@ -0,0 +1,584 @@
+use crate::{Error, Result};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use chrono::Utc;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use k8s_openapi::{
+    ByteString,
+    api::core::v1::Secret,
+    apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference},
+};
+use kube::{
+    Client,
+    api::{Api, Patch, PatchParams, PostParams},
+};
+use object_store::{
+    ObjectStore, PutMode, PutOptions, PutPayload,
+    aws::{AmazonS3Builder, S3ConditionalPut},
+    path::Path,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    io::{Read as _, Write as _},
+};
+
+/// Name of the tofu state
+pub const TFSTATE: &str = "tfstate";
+/// Name of the rhai state
+pub const RHAISTATE: &str = "rhaistate";
+/// Age after which an S3 lock is considered abandoned, it covers a whole tofu run
+const LOCK_TTL_SECS: i64 = 60 * 60;
+/// Number of 10s waits for a held S3 lock
+const LOCK_RETRIES: u32 = 30;
+/// Prefix of the encrypted states: a 12 bytes nonce, the AES-256-GCM ciphertext and its 16 bytes tag follow
+const SEALED_MAGIC: &[u8] = b"VYNILENC1";
+/// Key of the encryption key in the Secret of the operator
+pub const ENCRYPTION_SECRET_KEY: &str = "key";
+
+/// Backend holding the states of the instances
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StateBackend {
+    /// One Secret per instance, in the instance namespace
+    #[default]
+    Secret,
+    /// One object per state in an S3 bucket
+    S3,
+}
+
+/// Location of the states of an instance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct StoredState {
+    /// Backend holding the states
+    pub backend: StateBackend,
+    /// Secret name or S3 key prefix
+    pub location: String,
+    /// Names of the stored states (tfstate, rhaistate)
+    pub keys: Vec<String>,
+}
+
+impl StoredState {
+    pub fn has(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| k == key)
+    }
+}
+
+/// S3 bucket holding the states
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    pub endpoint: Option<String>,
+    pub prefix: String,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+
+/// State store configuration, read from the STATE_* environment variables
+#[derive(Clone, Debug)]
+pub struct StateConfig {
+    pub backend: StateBackend,
+    pub s3: Option<S3Config>,
+}
+
+fn env_opt(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+impl StateConfig {
+    pub fn from_env() -> Result<Self> {
+        let backend = match env_opt("STATE_BACKEND").as_deref() {
+            None | Some("secret") => StateBackend::Secret,
+            Some("s3") => StateBackend::S3,
+            Some(other) => return Err(Error::Other(format!("Unknown state backend {other}"))),
+        };
+        let s3 = env_opt("STATE_S3_BUCKET").map(|bucket| S3Config {
+            bucket,
+            region: env_opt("STATE_S3_REGION").unwrap_or("us-east-1".to_string()),
+            endpoint: env_opt("STATE_S3_ENDPOINT"),
+            prefix: env_opt("STATE_S3_PREFIX").unwrap_or_default(),
+            access_key_id: env_opt("STATE_S3_ACCESS_KEY_ID"),
+            secret_access_key: env_opt("STATE_S3_SECRET_ACCESS_KEY"),
+        });
+        if backend == StateBackend::S3 && s3.is_none() {
+            return Err(Error::Other(
+                "STATE_S3_BUCKET is required for the s3 state backend".to_string(),
+            ));
+        }
+        Ok(Self { backend, s3 })
+    }
+
+    fn s3(&self) -> Result<&S3Config> {
+        self.s3
+            .as_ref()
+            .ok_or(Error::Other("No S3 state store configured".to_string()))
+    }
+}
+
+/// The instance owning the states
+pub struct StateOwner<'a> {
+    pub kind: &'a str,
+    pub namespace: &'a str,
+    pub name: &'a str,
+    pub owner: OwnerReference,
+}
+
+impl StateOwner<'_> {
+    fn short_kind(&self) -> String {
+        self.kind.to_lowercase().trim_end_matches("instance").to_string()
+    }
+
+    fn location(&self, config: &StateConfig) -> Result<String> {
+        Ok(match config.backend {
+            StateBackend::Secret => format!("vynil-state-{}-{}", self.short_kind(), self.name),
+            StateBackend::S3 => format!(
+                "{}{}/{}/{}",
+                config.s3()?.prefix,
+                self.namespace,
+                self.short_kind(),
+                self.name
+            ),
+        })
+    }
+}
+
+fn s3_err(e: object_store::Error) -> Error {
+    Error::Other(format!("State store: {e}"))
+}
+
+fn gzip(data: &str) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data.as_bytes()).map_err(Error::Stdio)?;
+    encoder.finish().map_err(Error::Stdio)
+}
+
+fn gunzip(data: &[u8]) -> Result<String> {
+    let mut s = String::new();
+    GzDecoder::new(data)
+        .read_to_string(&mut s)
+        .map_err(Error::Stdio)?;
+    Ok(s)
+}
+
+/// Key encrypting the states, derived from STATE_ENCRYPTION_KEY
+fn encryption_key() -> Option<[u8; 32]> {
+    env_opt("STATE_ENCRYPTION_KEY").map(|k| openssl::sha::sha256(k.as_bytes()))
+}
+
+fn seal(key: Option<&[u8; 32]>, data: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(key) = key else {
+        return Ok(data);
+    };
+    let mut nonce = [0u8; 12];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let mut tag = [0u8; 16];
+    let cipher = openssl::symm::encrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        SEALED_MAGIC,
+        &data,
+        &mut tag,
+    )?;
+    Ok([SEALED_MAGIC, &nonce[..], &cipher[..], &tag[..]].concat())
+}
+
+fn unseal(key: Option<&[u8; 32]>, data: &[u8]) -> Result<Vec<u8>> {
+    // States written before the encryption are plain gzip
+    let Some(sealed) = data.strip_prefix(SEALED_MAGIC) else {
+        return Ok(data.to_vec());
+    };
+    let key = key.ok_or(Error::Other(
+        "The state is encrypted but STATE_ENCRYPTION_KEY is not set".to_string(),
+    ))?;
+    if sealed.len() < 12 + 16 {
+        return Err(Error::Other("Truncated encrypted state".to_string()));
+    }
+    let (nonce, rest) = sealed.split_at(12);
+    let (cipher, tag) = rest.split_at(rest.len() - 16);
+    openssl::symm::decrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        SEALED_MAGIC,
+        cipher,
+        tag,
+    )
+    .map_err(|_| Error::Other("Cannot decrypt the state: wrong STATE_ENCRYPTION_KEY".to_string()))
+}
+
+fn encode(value: &str) -> Result<Vec<u8>> {
+    seal(encryption_key().as_ref(), gzip(value)?)
+}
+
+fn decode(data: &[u8]) -> Result<String> {
+    gunzip(&unseal(encryption_key().as_ref(), data)?)
+}
+
+/// Create the Secret holding the key encrypting the states when it does not exist yet
+pub async fn ensure_encryption_key(client: Client, namespace: &str, name: &str) -> Result<()> {
+    let api: Api<Secret> = Api::namespaced(client, namespace);
+    if api.get_opt(name).await.map_err(Error::KubeError)?.is_some() {
+        return Ok(());
+    }
+    let mut key = [0u8; 32];
+    openssl::rand::rand_bytes(&mut key)?;
+    let hex: String = key.iter().map(|b| format!("{b:02x}")).collect();
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(BTreeMap::from([(
+                "app.kubernetes.io/managed-by".to_string(),
+                "vynil".to_string(),
+            )])),
+            ..Default::default()
+        },
+        type_: Some("Opaque".to_string()),
+        data: Some(BTreeMap::from([(
+            ENCRYPTION_SECRET_KEY.to_string(),
+            ByteString(hex.into_bytes()),
+        )])),
+        ..Default::default()
+    };
+    tracing::info!("Creating the state encryption key {namespace}/{name}");
+    api.create(&PostParams::default(), &secret)
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
+fn build_store(config: &S3Config) -> Result<Box<dyn ObjectStore>> {
+    let mut builder = AmazonS3Builder::new()
+        .with_bucket_name(&config.bucket)
+        .with_region(&config.region)
+        .with_conditional_put(S3ConditionalPut::ETagMatch);
+    if let (Some(id), Some(secret)) = (&config.access_key_id, &config.secret_access_key) {
+        builder = builder.with_access_key_id(id).with_secret_access_key(secret);
+    }
+    if let Some(endpoint) = &config.endpoint {
+        builder = builder
+            .with_endpoint(endpoint)
+            .with_allow_http(endpoint.starts_with("http://"));
+    }
+    Ok(Box::new(builder.build().map_err(s3_err)?))
+}
+
+fn state_path(location: &str, key: &str) -> Path {
+    Path::from(format!("{location}/{key}.gz"))
+}
+
+fn lock_path(location: &str) -> Path {
+    Path::from(format!("{location}/.lock"))
+}
+
+fn lock_holder() -> String {
+    std::env::var("POD_NAME").unwrap_or_else(|_| crate::context::get_client_name())
+}
+
+/// Take the lock of a state location, breaking it once abandoned
+async fn acquire_lock(store: &dyn ObjectStore, lock: &Path, retries: u32) -> Result<()> {
+    let holder = lock_holder();
+    let opts = PutOptions {
+        mode: PutMode::Create,
+        ..Default::default()
+    };
+    for attempt in 0..=retries {
+        match store
+            .put_opts(lock, PutPayload::from(holder.clone().into_bytes()), opts.clone())
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(object_store::Error::AlreadyExists { .. }) => match store.head(lock).await {
+                Ok(meta) if (Utc::now() - meta.last_modified).num_seconds() >= LOCK_TTL_SECS => {
+                    tracing::warn!("Breaking the abandoned state lock {lock}");
+                    store.delete(lock).await.map_err(s3_err)?;
+                }
+                // Released in the meantime
+                Err(object_store::Error::NotFound { .. }) => {}
+                Ok(_) if attempt < retries => {
+                    tracing::info!("State {lock} is locked, waiting");
+                    tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                }
+                Ok(_) => {}
+                Err(e) => return Err(s3_err(e)),
+            },
+            Err(e) => return Err(s3_err(e)),
+        }
+    }
+    Err(Error::Other(format!("State {lock} is still locked")))
+}
+
+/// Whether the lock of a state location is held by this agent
+async fn holds_lock(store: &dyn ObjectStore, lock: &Path) -> Result<bool> {
+    match store.get(lock).await {
+        Ok(res) => Ok(res.bytes().await.map_err(s3_err)? == lock_holder().as_bytes()),
+        Err(object_store::Error::NotFound { .. }) => Ok(false),
+        Err(e) => Err(s3_err(e)),
+    }
+}
+
+/// Release the lock of a state location, unless another agent broke and took it
+async fn release_lock(store: &dyn ObjectStore, lock: &Path) -> Result<()> {
+    if !holds_lock(store, lock).await? {
+        tracing::warn!("State lock {lock} is not held anymore");
+        return Ok(());
+    }
+    match store.delete(lock).await {
+        Ok(_) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+        Err(e) => Err(s3_err(e)),
+    }
+}
+
+async fn s3_write(store: &dyn ObjectStore, location: &str, key: &str, data: Vec<u8>) -> Result<()> {
+    let lock = lock_path(location);
+    // Already held for the whole tofu run (see `lock`)
+    if holds_lock(store, &lock).await? {
+        return store
+            .put(&state_path(location, key), PutPayload::from(data))
+            .await
+            .map(|_| ())
+            .map_err(s3_err);
+    }
+    acquire_lock(store, &lock, LOCK_RETRIES).await?;
+    let res = store
+        .put(&state_path(location, key), PutPayload::from(data))
+        .await
+        .map_err(s3_err);
+    release_lock(store, &lock).await?;
+    res.map(|_| ())
+}
+
+/// Lock the states of an instance until `unlock`, so no other agent runs tofu on them meanwhile.
+/// The writes in between keep the lock; nothing to do for the Secret backend.
+pub async fn lock(config: &StateConfig, owner: &StateOwner<'_>) -> Result<()> {
+    if config.backend != StateBackend::S3 {
+        return Ok(());
+    }
+    let store = build_store(config.s3()?)?;
+    acquire_lock(store.as_ref(), &lock_path(&owner.location(config)?), LOCK_RETRIES).await
+}
+
+/// Release the lock taken by `lock`
+pub async fn unlock(config: &StateConfig, owner: &StateOwner<'_>) -> Result<()> {
+    if config.backend != StateBackend::S3 {
+        return Ok(());
+    }
+    let store = build_store(config.s3()?)?;
+    release_lock(store.as_ref(), &lock_path(&owner.location(config)?)).await
+}
+
+/// Read a state of an instance
+pub async fn read(
+    client: Client,
+    namespace: &str,
+    stored: &StoredState,
+    key: &str,
+) -> Result<Option<String>> {
+    match stored.backend {
+        StateBackend::Secret => {
+            let api: Api<Secret> = Api::namespaced(client, namespace);
+            let secret = api.get_opt(&stored.location).await.map_err(Error::KubeError)?;
+            match secret.and_then(|s| s.data).and_then(|d| d.get(key).cloned()) {
+                Some(data) => decode(&data.0).map(Some),
+                None => Ok(None),
+            }
+        }
+        StateBackend::S3 => {
+            let store = build_store(StateConfig::from_env()?.s3()?)?;
+            match store.get(&state_path(&stored.location, key)).await {
+                Ok(res) => decode(&res.bytes().await.map_err(s3_err)?).map(Some),
+                Err(object_store::Error::NotFound { .. }) => Ok(None),
+                Err(e) => Err(s3_err(e)),
+            }
+        }
+    }
+}
+
+/// Write a state of an instance in the configured backend, returning the new location to record
+pub async fn write(
+    client: Client,
+    config: &StateConfig,
+    owner: &StateOwner<'_>,
+    previous: Option<&StoredState>,
+    key: &str,
+    value: &str,
+) -> Result<StoredState> {
+    let location = owner.location(config)?;
+    let data = encode(value)?;
+    match config.backend {
+        StateBackend::Secret => {
+            let api: Api<Secret> = Api::namespaced(client.clone(), owner.namespace);
+            if api.get_opt(&location).await.map_err(Error::KubeError)?.is_some() {
+                api.patch(
+                    &location,
+                    &PatchParams::default(),
+                    &Patch::Merge(json!({"data": {key: STANDARD.encode(&data)}})),
+                )
+                .await
+                .map_err(Error::KubeError)?;
+            } else {
+                let secret = Secret {
+                    metadata: ObjectMeta {
+                        name: Some(location.clone()),
+                        namespace: Some(owner.namespace.to_string()),
+                        labels: Some(BTreeMap::from([(
+                            "app.kubernetes.io/managed-by".to_string(),
+                            "vynil".to_string(),
+                        )])),
+                        owner_references: Some(vec![owner.owner.clone()]),
+                        ..Default::default()
+                    },
+                    type_: Some("Opaque".to_string()),
+                    data: Some(BTreeMap::from([(key.to_string(), ByteString(data))])),
+                    ..Default::default()
+                };
+                api.create(&PostParams::default(), &secret)
+                    .await
+                    .map_err(Error::KubeError)?;
+            }
+        }
+        StateBackend::S3 => {
+            let store = build_store(config.s3()?)?;
+            s3_write(store.as_ref(), &location, key, data).await?;
+        }
+    }
+    let stored = merge_keys(previous, config.backend.clone(), location, key);
+    match previous {
+        // The backend or the location changed: move the other states along
+        Some(prev) if prev.backend != stored.backend || prev.location != stored.location => {
+            let mut stored = stored;
+            for other in prev.keys.iter().filter(|k| k.as_str() != key) {
+                if let Some(value) = read(client.clone(), owner.namespace, prev, other).await? {
+                    stored =
+                        Box::pin(write(client.clone(), config, owner, Some(&stored), other, &value)).await?;
+                }
+            }
+            Ok(stored)
+        }
+        _ => Ok(stored),
+    }
+}
+
+fn merge_keys(
+    previous: Option<&StoredState>,
+    backend: StateBackend,
+    location: String,
+    key: &str,
+) -> StoredState {
+    let mut keys: Vec<String> = previous
+        .filter(|p| p.backend == backend && p.location == location)
+        .map(|p| p.keys.clone())
+        .unwrap_or_default();
+    if !keys.iter().any(|k| k == key) {
+        keys.push(key.to_string());
+    }
+    StoredState {
+        backend,
+        location,
+        keys,
+    }
+}
+
+/// Remove the states of a deleted instance (Secrets are garbage-collected with their owner)
+pub async fn purge(stored: &StoredState) -> Result<()> {
+    if stored.backend == StateBackend::S3 {
+        let store = build_store(StateConfig::from_env()?.s3()?)?;
+        for key in &stored.keys {
+            match store.delete(&state_path(&stored.location, key)).await {
+                Ok(_) | Err(object_store::Error::NotFound { .. }) => {}
+                Err(e) => return Err(s3_err(e)),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[test]
+    fn keys_are_kept_for_the_same_location() {
+        let first = merge_keys(
+            None,
+            StateBackend::Secret,
+            "vynil-state-tenant-a".to_string(),
+            TFSTATE,
+        );
+        let second = merge_keys(
+            Some(&first),
+            StateBackend::Secret,
+            "vynil-state-tenant-a".to_string(),
+            RHAISTATE,
+        );
+        assert!(second.has(TFSTATE) && second.has(RHAISTATE));
+        // A backend change starts over: the other state is still read from its old location
+        let moved = merge_keys(
+            Some(&second),
+            StateBackend::S3,
+            "ns/tenant/a".to_string(),
+            TFSTATE,
+        );
+        assert_eq!(moved.keys, vec![TFSTATE.to_string()]);
+    }
+
+    #[test]
+    fn gzip_roundtrip() {
+        let state = "{\"version\": 4, \"resources\": []}";
+        assert_eq!(gunzip(&gzip(state).unwrap()).unwrap(), state);
+    }
+
+    #[test]
+    fn sealed_roundtrip() {
+        let key = openssl::sha::sha256(b"operator-key");
+        let state = gzip("{\"version\": 4, \"resources\": []}").unwrap();
+        let sealed = seal(Some(&key), state.clone()).unwrap();
+        assert!(sealed.starts_with(SEALED_MAGIC));
+        assert!(!sealed.windows(state.len()).any(|w| w == state.as_slice()));
+        assert_eq!(unseal(Some(&key), &sealed).unwrap(), state);
+        // Wrong or missing key
+        assert!(unseal(Some(&openssl::sha::sha256(b"other")), &sealed).is_err());
+        assert!(unseal(None, &sealed).is_err());
+        // States written before the encryption are still read
+        assert_eq!(unseal(Some(&key), &state).unwrap(), state);
+    }
+
+    #[tokio::test]
+    async fn lock_is_exclusive_until_released() {
+        let store = InMemory::new();
+        let lock = lock_path("ns/tenant/a");
+        acquire_lock(&store, &lock, 0).await.unwrap();
+        assert!(acquire_lock(&store, &lock, 0).await.is_err());
+        store.delete(&lock).await.unwrap();
+        acquire_lock(&store, &lock, 0).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_keep_the_lock_held_for_a_tofu_run() {
+        let store = InMemory::new();
+        let lock = lock_path("ns/tenant/a");
+        acquire_lock(&store, &lock, 0).await.unwrap();
+        s3_write(&store, "ns/tenant/a", TFSTATE, b"state".to_vec())
+            .await
+            .unwrap();
+        assert!(holds_lock(&store, &lock).await.unwrap());
+        release_lock(&store, &lock).await.unwrap();
+        assert!(!holds_lock(&store, &lock).await.unwrap());
+        // Without a held lock, a write takes and releases it
+        s3_write(&store, "ns/tenant/a", TFSTATE, b"state".to_vec())
+            .await
+            .unwrap();
+        assert!(store.head(&lock).await.is_err());
+        // A lock taken by another agent is left alone
+        store
+            .put(&lock, PutPayload::from(b"other-agent".to_vec()))
+            .await
+            .unwrap();
+        release_lock(&store, &lock).await.unwrap();
+        assert!(store.head(&lock).await.is_ok());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{
+    Error, Result,
+    context::{get_client_async, get_reporter, get_short_name},
+};
+use chrono::{DateTime, Utc};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::{
+    Client, CustomResource, Resource,
+    api::{Api, ListParams, ObjectList, Patch, PatchParams},
+    core::{Expression, Selector, SelectorExt},
+    runtime::events::{Event, EventType, Recorder},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Label set on the TenantInstances managed by a TenantProfile
+pub const PROFILE_LABEL: &str = "vynil.solidite.fr/profile";
+
+/// TenantInstance every namespace selected by a TenantProfile must have
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantProfileInstance {
+    /// Name of the TenantInstance (handlebars template)
+    pub name: String,
+    /// The jukebox source name
+    pub jukebox: String,
+    /// The category name
+    pub category: String,
+    /// The package name
+    pub package: String,
+    /// Parameters, strings are handlebars templates rendered with the tenant context
+    pub options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Install missing package dependencies automatically (default to the JukeBox setting)
+    pub auto_install_dependencies: Option<bool>,
+}
+
+/// Describe a standard set of TenantInstances for the selected tenant namespaces
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    kind = "TenantProfile",
+    status = "TenantProfileStatus",
+    shortname = "vtp",
+    group = "vynil.solidite.fr",
+    version = "v1"
+)]
+#[kube(
+    doc = "Custom resource stamping a set of TenantInstances into every selected tenant namespace",
+    printcolumn = r#"{"name":"last_updated","type":"date",   "description":"Last update date",  "format":"date-time", "jsonPath":".status.conditions[?(@.type == 'Ready')].lastTransitionTime"}"#,
+    printcolumn = r#"{"name":"errors",      "type":"string", "description":"Errors",            "jsonPath":".status.conditions[?(@.status == 'False')].message"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantProfileSpec {
+    /// Namespaces selector (default to the namespaces carrying the tenant label)
+    pub selector: Option<LabelSelector>,
+    /// TenantInstances to create in each selected namespace
+    pub instances: Vec<TenantProfileInstance>,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
+pub enum ConditionsType {
+    #[default]
+    Ready,
+    Applied,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema, Default)]
+pub enum ConditionsStatus {
+    #[default]
+    True,
+    False,
+}
+
+/// ApplicationCondition contains details about an application condition, which is usually an error or warning
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationCondition {
+    /// LastTransitionTime is the time the condition was last observed
+    pub last_transition_time: Option<DateTime<Utc>>,
+    /// Message contains human-readable message indicating details about condition
+    pub message: String,
+    /// Type is an application condition type
+    #[serde(rename = "type")]
+    pub condition_type: ConditionsType,
+    /// Status ("True" or "False") describe if the condition is enbled
+    pub status: ConditionsStatus,
+    /// Generation for that status
+    pub generation: i64,
+}
+
+impl ApplicationCondition {
+    #[must_use]
+    pub fn new(
+        message: &str,
+        status: ConditionsStatus,
+        condition_type: ConditionsType,
+        generation: i64,
+    ) -> ApplicationCondition {
+        ApplicationCondition {
+            last_transition_time: Some(chrono::offset::Utc::now()),
+            status,
+            condition_type,
+            message: message.to_string(),
+            generation,
+        }
+    }
+
+    pub fn ready_ok(generation: i64) -> ApplicationCondition {
+        ApplicationCondition::new(
+            "Applied succesfully",
+            ConditionsStatus::True,
+            ConditionsType::Ready,
+            generation,
+        )
+    }
+
+    pub fn ready_ko(generation: i64) -> ApplicationCondition {
+        ApplicationCondition::new(
+            "No successful apply",
+            ConditionsStatus::False,
+            ConditionsType::Ready,
+            generation,
+        )
+    }
+
+    pub fn applied_ko(message: &str, generation: i64) -> ApplicationCondition {
+        ApplicationCondition::new(
+            message,
+            ConditionsStatus::False,
+            ConditionsType::Applied,
+            generation,
+        )
+    }
+
+    pub fn applied_ok(count: usize, generation: i64) -> ApplicationCondition {
+        ApplicationCondition::new(
+            &format!("{count} TenantInstances applied"),
+            ConditionsStatus::True,
+            ConditionsType::Applied,
+            generation,
+        )
+    }
+}
+
+/// The status object of `TenantProfile`
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct TenantProfileStatus {
+    /// TenantProfile Conditions
+    pub conditions: Vec<ApplicationCondition>,
+    /// Namespaces currently selected
+    pub namespaces: Vec<String>,
+    /// Managed TenantInstances (namespace/name)
+    pub instances: Vec<String>,
+}
+
+impl TenantProfile {
+    pub async fn list() -> Result<ObjectList<Self>> {
+        let api = Api::<Self>::all(get_client_async().await);
+        api.list(&ListParams::default()).await.map_err(Error::KubeError)
+    }
+
+    /// Namespace selector of this profile
+    pub fn selector(&self) -> Result<Selector> {
+        match self.spec.selector.clone() {
+            Some(sel) => Selector::try_from(sel).map_err(|e| Error::Other(format!("Invalid selector: {e}"))),
+            None => Ok(Selector::from_iter([Expression::Exists(tenant_label())])),
+        }
+    }
+
+    /// Whether a namespace with these labels is selected by this profile
+    pub fn selects(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.selector().map(|s| s.matches(labels)).unwrap_or(false)
+    }
+
+    /// Whether this profile currently manages instances in the namespace
+    pub fn manages(&self, namespace: &str) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|s| s.namespaces.iter().any(|n| n == namespace))
+    }
+
+    fn get_conditions_excluding(&self, exclude: Vec<ConditionsType>) -> Vec<ApplicationCondition> {
+        let mut ret = Vec::new();
+        if let Some(status) = self.status.clone() {
+            for c in status.conditions {
+                if !exclude.clone().into_iter().any(|exc| c.condition_type == exc) {
+                    ret.push(c);
+                }
+            }
+        }
+        ret
+    }
+
+    async fn patch_status(&mut self, client: Client, patch: serde_json::Value) -> Result<Self> {
+        let api = Api::<Self>::all(client.clone());
+        let name = self.metadata.name.clone().unwrap();
+        let new_status: Patch<serde_json::Value> = Patch::Merge(json!({
+            "apiVersion": "vynil.solidite.fr/v1",
+            "kind": "TenantProfile",
+            "status": patch
+        }));
+        let ps = PatchParams::apply(get_short_name().as_str());
+        api.patch_status(&name, &ps, &new_status)
+            .await
+            .map_err(Error::KubeError)
+    }
+
+    async fn send_event(&mut self, client: Client, ev: Event) -> Result<()> {
+        let recorder = Recorder::new(client.clone(), get_reporter());
+        let oref = self.object_ref(&());
+        match recorder.publish(&ev, &oref).await {
+            Ok(_) => Ok(()),
+            Err(e) => match e {
+                kube::Error::Api(src) => {
+                    tracing::warn!("Ignoring {:?} while sending an event", src);
+                    Ok(())
+                }
+                _ => Err(Error::KubeError(e)),
+            },
+        }
+    }
+
+    pub async fn set_status_applied(&mut self, namespaces: Vec<String>, instances: Vec<String>) -> Result<Self> {
+        let client = get_client_async().await;
+        let generation = self.metadata.generation.unwrap_or(1);
+        let count = instances.len();
+        let conditions: Vec<ApplicationCondition> = vec![
+            ApplicationCondition::applied_ok(count, generation),
+            ApplicationCondition::ready_ok(generation),
+        ];
+        let changed = self
+            .status
+            .as_ref()
+            .is_none_or(|s| s.namespaces != namespaces || s.instances != instances);
+        let result = self
+            .patch_status(
+                client.clone(),
+                json!({
+                    "conditions": conditions,
+                    "namespaces": namespaces,
+                    "instances": instances,
+                }),
+            )
+            .await?;
+        if changed {
+            self.send_event(client, Event {
+                type_: EventType::Normal,
+                reason: "ProfileApplied".to_string(),
+                note: Some(format!("{count} TenantInstances in {} namespaces", namespaces.len())),
+                action: "Apply".to_string(),
+                secondary: None,
+            })
+            .await?;
+        }
+        Ok(result)
+    }
+
+    pub async fn set_status_failed(&mut self, reason: String) -> Result<Self> {
+        let client = get_client_async().await;
+        let generation = self.metadata.generation.unwrap_or(1);
+        let mut conditions: Vec<ApplicationCondition> =
+            self.get_conditions_excluding(vec![ConditionsType::Applied]);
+        conditions.push(ApplicationCondition::applied_ko(&reason, generation));
+        if !conditions
+            .clone()
+            .into_iter()
+            .any(|c| c.condition_type == ConditionsType::Ready)
+        {
+            conditions.push(ApplicationCondition::ready_ko(generation));
+        }
+        let result = self
+            .patch_status(client.clone(), json!({ "conditions": conditions }))
+            .await?;
+        let mut note = reason;
+        note.truncate(1023);
+        self.send_event(client, Event {
+            type_: EventType::Warning,
+            reason: "ProfileApplyFailed".to_string(),
+            note: Some(note),
+            action: "Apply".to_string(),
+            secondary: None,
+        })
+        .await?;
+        Ok(result)
+    }
+}
+
+fn tenant_label() -> String {
+    std::env::var("TENANT_LABEL").unwrap_or_else(|_| "vynil.solidite.fr/tenant".to_string())
+}
+
+/// Tenant name of a namespace: its tenant label value, or the namespace name
+pub fn tenant_name(namespace: &str, labels: &BTreeMap<String, String>) -> String {
+    labels
+        .get(&tenant_label())
+        .cloned()
+        .unwrap_or_else(|| namespace.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement;
+
+    fn profile(selector: Option<LabelSelector>) -> TenantProfile {
+        TenantProfile::new("base", TenantProfileSpec {
+            selector,
+            instances: vec![],
+        })
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn default_selector_matches_tenant_label() {
+        let p = profile(None);
+        assert!(p.selects(&labels(&[("vynil.solidite.fr/tenant", "acme")])));
+        assert!(!p.selects(&labels(&[("team", "acme")])));
+    }
+
+    #[test]
+    fn explicit_selector_uses_labels_and_expressions() {
+        let p = profile(Some(LabelSelector {
+            match_labels: Some(labels(&[("tier", "premium")])),
+            match_expressions: Some(vec![LabelSelectorRequirement {
+                key: "env".to_string(),
+                operator: "In".to_string(),
+                values: Some(vec!["prod".to_string(), "staging".to_string()]),
+            }]),
+        }));
+        assert!(p.selects(&labels(&[("tier", "premium"), ("env", "prod")])));
+        assert!(!p.selects(&labels(&[("tier", "premium"), ("env", "dev")])));
+        assert!(!p.selects(&labels(&[("env", "prod")])));
+    }
+
+    #[test]
+    fn tenant_name_falls_back_to_namespace() {
+        assert_eq!(
+            tenant_name("acme-web", &labels(&[("vynil.solidite.fr/tenant", "acme")])),
+            "acme"
+        );
+        assert_eq!(tenant_name("acme-web", &labels(&[])), "acme-web");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{Error, Result};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use kube::{Client, api::Api};
+use serde_json::Value;
+use std::collections::BTreeMap;
+// Parsed in vynil-export, which lists the references of the exports
+pub use vynil_export::{ValueRef, is_reference, reference, references};
+
+/// resourceVersion of each referenced object, empty for a missing one
+pub async fn versions(client: Client, namespace: &str, refs: &[ValueRef]) -> Result<Vec<String>> {
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let configmaps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let mut versions = Vec::new();
+    for r in refs {
+        let meta = if r.kind == "Secret" {
+            secrets
+                .get_metadata_opt(&r.name)
+                .await
+                .map(|m| m.map(|m| m.metadata))
+        } else {
+            configmaps
+                .get_metadata_opt(&r.name)
+                .await
+                .map(|m| m.map(|m| m.metadata))
+        }
+        .map_err(Error::KubeError)?;
+        versions.push(meta.and_then(|m| m.resource_version).unwrap_or_default());
+    }
+    Ok(versions)
+}
+
+/// Data of the referenced objects that could be read, Secrets decoded
+async fn read_all(
+    client: Client,
+    namespace: &str,
+    refs: &[ValueRef],
+) -> BTreeMap<ValueRef, BTreeMap<String, String>> {
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let configmaps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let mut objects = BTreeMap::new();
+    for r in refs {
+        let data = if r.kind == "Secret" {
+            secrets.get(&r.name).await.map(|s| {
+                s.data
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(k, v)| (k, String::from_utf8_lossy(&v.0).into_owned()))
+                    .collect()
+            })
+        } else {
+            configmaps.get(&r.name).await.map(|c| c.data.unwrap_or_default())
+        };
+        match data {
+            Ok(data) => {
+                objects.insert(r.clone(), data);
+            }
+            Err(e) => tracing::debug!("Cannot read the {} {namespace}/{}: {e}", r.kind, r.name),
+        }
+    }
+    objects
+}
+
+fn substitute(value: &Value, objects: &BTreeMap<ValueRef, BTreeMap<String, String>>) -> Value {
+    if let Some(found) = reference(value) {
+        let from = &value["valueFrom"];
+        let key_ref = if found.kind == "Secret" {
+            &from["secretKeyRef"]
+        } else {
+            &from["configMapKeyRef"]
+        };
+        let read = key_ref["key"]
+            .as_str()
+            .and_then(|key| objects.get(&found).and_then(|data| data.get(key)));
+        return match read {
+            Some(v) => Value::String(v.clone()),
+            None if key_ref["optional"] == true => Value::Null,
+            None => value.clone(),
+        };
+    }
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), substitute(v, objects)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|v| substitute(v, objects)).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Replace the `valueFrom` of an options tree by the referenced values, as the agent does
+///
+/// Missing optional values become `null`, the references that cannot be read are kept.
+pub async fn resolve(client: Client, namespace: &str, options: &Value) -> Value {
+    let refs = references(options);
+    if refs.is_empty() {
+        return options.clone();
+    }
+    let objects = read_all(client, namespace, &refs).await;
+    substitute(options, &objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn references_are_found_anywhere_in_the_options() {
+        let options = json!({
+            "smtp": {"password": {"valueFrom": {"secretKeyRef": {"name": "smtp", "key": "password"}}}},
+            "domains": [{"valueFrom": {"configMapKeyRef": {"name": "dns", "key": "domain"}}}],
+            "user": {"valueFrom": {"secretKeyRef": {"name": "smtp", "key": "user"}}},
+            "replicas": 2
+        });
+        let refs = references(&options);
+        assert_eq!(refs.len(), 2);
+        assert_eq!((refs[0].kind, refs[0].name.as_str()), ("ConfigMap", "dns"));
+        assert_eq!((refs[1].kind, refs[1].name.as_str()), ("Secret", "smtp"));
+    }
+
+    #[test]
+    fn options_named_value_from_are_kept() {
+        let options = json!({"valueFrom": "literal", "other": {"valueFrom": {"unknown": {}}}});
+        assert!(references(&options).is_empty());
+    }
+
+    #[test]
+    fn readable_references_are_substituted() {
+        let options = json!({
+            "smtp": {"password": {"valueFrom": {"secretKeyRef": {"name": "smtp", "key": "password"}}}},
+            "domain": {"valueFrom": {"configMapKeyRef": {"name": "dns", "key": "domain"}}},
+            "relay": {"valueFrom": {"configMapKeyRef": {"name": "dns", "key": "relay", "optional": true}}}
+        });
+        let objects = BTreeMap::from([(
+            ValueRef {
+                kind: "Secret",
+                name: "smtp".to_string(),
+            },
+            BTreeMap::from([("password".to_string(), "s3cr3t".to_string())]),
+        )]);
+        let resolved = substitute(&options, &objects);
+        assert_eq!(resolved["smtp"]["password"], "s3cr3t");
+        // The ConfigMap cannot be read: the reference is kept, the optional one is dropped
+        assert_eq!(resolved["domain"], options["domain"]);
+        assert!(resolved["relay"].is_null());
+    }
+}
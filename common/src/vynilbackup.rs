@@ -0,0 +1,470 @@
+use crate::{Error, Result};
+use chrono::{DateTime, Utc};
+use kube::{
+    Client, CustomResource,
+    api::{Api, ListParams},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use vynil_export::BackupRecord;
+
+/// Label holding the name of the backed up instance
+pub use vynil_export::INSTANCE_LABEL;
+
+/// Outcome of a backup run
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, JsonSchema)]
+pub enum BackupOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// Instance a backup was taken from
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+pub struct BackupInstance {
+    /// TenantInstance or ServiceInstance
+    pub kind: String,
+    /// Instance name
+    pub name: String,
+}
+
+/// Instance whose backup initializes another instance (cloning)
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+pub struct InstanceRef {
+    /// Namespace of the source instance (default to the namespace of the instance)
+    pub namespace: Option<String>,
+    /// TenantInstance or ServiceInstance (default to the kind of the instance)
+    pub kind: Option<String>,
+    /// Name of the source instance
+    pub name: String,
+    /// `latest` (default) or the name of a VynilBackup of the source instance
+    pub backup: Option<String>,
+}
+
+impl InstanceRef {
+    /// Backup to restore among the records of the source instance, `kind` being its kind
+    pub fn pick<'a>(&self, kind: &str, records: &'a [VynilBackup]) -> Option<&'a VynilBackup> {
+        let mut restorable = records.iter().filter(|b| {
+            b.spec.instance.kind == kind
+                && b.spec.instance.name == self.name
+                && b.restorable_snapshot().is_some()
+        });
+        match self.backup.as_deref() {
+            None | Some("latest") => restorable.max_by_key(|b| b.spec.started_at),
+            Some(name) => restorable.find(|b| b.metadata.name.as_deref() == Some(name)),
+        }
+    }
+}
+
+/// Number of snapshots kept per bucket
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default, JsonSchema)]
+pub struct BackupRetention {
+    /// Last snapshots to keep
+    pub last: Option<u32>,
+    /// Hourly snapshots to keep
+    pub hourly: Option<u32>,
+    /// Daily snapshots to keep (default to RESTIC_MAX_DAYLY of the secret, or 7)
+    pub daily: Option<u32>,
+    /// Weekly snapshots to keep (default to RESTIC_MAX_WEEKLY of the secret, or 5)
+    pub weekly: Option<u32>,
+    /// Monthly snapshots to keep (default to RESTIC_MAX_MONTHLY of the secret, or 12)
+    pub monthly: Option<u32>,
+    /// Yearly snapshots to keep (default to RESTIC_MAX_YEARLY of the secret, or 4)
+    pub yearly: Option<u32>,
+}
+
+/// Backup policy of an instance
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupPolicy {
+    /// Schedule the backups (default to true)
+    pub enabled: Option<bool>,
+    /// Cron schedule of the backups (default to the namespace maintenance window)
+    pub schedule: Option<String>,
+    /// Name of the secret holding the repository settings (default to "backup-settings")
+    pub secret_name: Option<String>,
+    /// Snapshots to keep when pruning the repository
+    pub retention: Option<BackupRetention>,
+    /// Back up the instance before upgrading its package (default to true)
+    pub pre_upgrade: Option<bool>,
+    /// What to do when an upgrade fails after a pre-upgrade backup (default to Manual)
+    pub rollback: Option<RollbackMode>,
+    /// Restore the latest backup in a sandbox namespace on a schedule
+    pub drill: Option<RestoreDrill>,
+}
+
+/// Scheduled check that the backups of an instance can be restored
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default, JsonSchema)]
+pub struct RestoreDrill {
+    /// Cron schedule of the drills
+    pub schedule: String,
+    /// Minutes to wait for the sandbox instance to be ready (default to 60)
+    pub timeout: Option<u32>,
+}
+
+impl BackupPolicy {
+    /// Check the schedule and the retention, returning the reason of the first problem found
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if let Some(schedule) = &self.schedule {
+            validate_schedule(schedule)?;
+        }
+        if let Some(secret) = &self.secret_name
+            && secret.is_empty()
+        {
+            return Err("secretName cannot be empty".to_string());
+        }
+        if let Some(r) = &self.retention
+            && [r.last, r.hourly, r.daily, r.weekly, r.monthly, r.yearly]
+                .iter()
+                .all(|k| k.unwrap_or(0) == 0)
+        {
+            return Err("retention would not keep any snapshot".to_string());
+        }
+        if let Some(drill) = &self.drill {
+            validate_schedule(&drill.schedule).map_err(|e| format!("drill {e}"))?;
+            if drill.timeout == Some(0) {
+                return Err("drill timeout cannot be 0".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+const CRON_MACROS: [&str; 7] = [
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+
+/// Check a 5 fields cron schedule (as accepted by CronJobs)
+fn validate_schedule(schedule: &str) -> std::result::Result<(), String> {
+    let schedule = schedule.trim();
+    if CRON_MACROS.contains(&schedule) {
+        return Ok(());
+    }
+    let fields: Vec<&str> = schedule.split_whitespace().collect();
+    if fields.len() != 5 {
+        return Err(format!("schedule \"{schedule}\" should have 5 fields"));
+    }
+    let bounds = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 7)];
+    for (field, (min, max)) in fields.iter().zip(bounds) {
+        let valid = field.split(',').all(|part| {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (part, None),
+            };
+            let step_ok = step.is_none_or(|s| s.parse::<u32>().is_ok_and(|s| s > 0));
+            let value_ok = |v: &str| {
+                v.parse::<u32>().map_or(
+                    // month and day-of-week names (JAN, MON...)
+                    (7..=12).contains(&max) && v.len() == 3 && v.chars().all(|c| c.is_ascii_alphabetic()),
+                    |n| (min..=max).contains(&n),
+                )
+            };
+            let range_ok = range == "*"
+                || range == "?"
+                || match range.split_once('-') {
+                    Some((from, to)) => value_ok(from) && value_ok(to),
+                    None => value_ok(range),
+                };
+            step_ok && range_ok
+        });
+        if !valid {
+            return Err(format!("invalid field \"{field}\" in schedule \"{schedule}\""));
+        }
+    }
+    Ok(())
+}
+
+/// Rollback of a failed upgrade
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default, JsonSchema)]
+pub enum RollbackMode {
+    /// Wait for the vynil.solidite.fr/rollback annotation
+    #[default]
+    Manual,
+    /// Roll back as soon as the upgrade fails
+    Auto,
+}
+
+/// Annotation requesting the rollback of a failed upgrade
+pub const ROLLBACK_ANNOTATION: &str = "vynil.solidite.fr/rollback";
+
+/// Phase of an upgrade protected by a backup
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, JsonSchema)]
+pub enum UpgradePhase {
+    /// The pre-upgrade backup Job is running
+    BackingUp,
+    /// The pre-upgrade backup failed, the upgrade is blocked
+    BackupFailed,
+    /// The backup is done, the upgrade can proceed
+    BackedUp,
+    /// The upgrade failed, a rollback can be requested
+    UpgradeFailed,
+    /// The previous version is being reinstalled and restored
+    RollingBack,
+    /// The previous version was restored
+    RolledBack,
+}
+
+/// Backup taken before an upgrade
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+pub struct UpgradeBackup {
+    /// Version before the upgrade
+    pub from: String,
+    /// Version being installed
+    pub to: String,
+    /// Name of the backup Job
+    pub job: String,
+    /// Name of the VynilBackup record
+    pub backup: Option<String>,
+    /// Snapshot id
+    pub snapshot: Option<String>,
+    /// Current phase
+    pub phase: UpgradePhase,
+    /// Details on the phase
+    pub message: Option<String>,
+    /// Options of the version before the upgrade, put back on rollback
+    pub options: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Label of the sandbox namespace and instance of a restore drill, set to `<namespace>.<instance>`
+pub const DRILL_LABEL: &str = "vynil.solidite.fr/drill";
+
+/// Result of the last restore drill of an instance
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DrillResult {
+    /// Start of the drill
+    pub started_at: DateTime<Utc>,
+    /// Duration of the drill (seconds)
+    pub duration: Option<i64>,
+    /// Outcome of the drill
+    pub outcome: BackupOutcome,
+    /// Restored snapshot id
+    pub snapshot: Option<String>,
+    /// Sandbox namespace
+    pub namespace: String,
+    /// Error message of a failed drill
+    pub message: Option<String>,
+}
+
+/// Phase of the rotation of the backup repository key
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, JsonSchema)]
+pub enum KeyRotationPhase {
+    /// The rotation Job is started
+    Started,
+    /// The new key is added to the repository and verified
+    KeyAdded,
+    /// The secret uses the new key and the old one is removed
+    Rotated,
+    /// The rotation failed, annotate the instance again to resume it
+    Failed,
+}
+
+/// Rotation of the backup repository key of an instance
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyRotation {
+    /// Backup secret being rotated
+    pub secret_name: String,
+    /// Name of the rotation Job
+    pub job: Option<String>,
+    /// Start of the rotation
+    pub started_at: DateTime<Utc>,
+    /// Current phase
+    pub phase: KeyRotationPhase,
+    /// Details on the phase
+    pub message: Option<String>,
+}
+
+/// Record of a backup run
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    kind = "VynilBackup",
+    plural = "vynilbackups",
+    shortname = "vbk",
+    group = "vynil.solidite.fr",
+    version = "v1",
+    namespaced
+)]
+#[kube(
+    doc = "Custom resource recording a backup run of an instance",
+    printcolumn = r#"{"name":"instance", "type":"string",  "description":"Instance",         "jsonPath":".spec.instance.name"}"#,
+    printcolumn = r#"{"name":"version",  "type":"string",  "description":"Package version",  "jsonPath":".spec.version"}"#,
+    printcolumn = r#"{"name":"snapshot", "type":"string",  "description":"Snapshot id",      "jsonPath":".spec.snapshot"}"#,
+    printcolumn = r#"{"name":"outcome",  "type":"string",  "description":"Outcome",          "jsonPath":".spec.outcome"}"#,
+    printcolumn = r#"{"name":"size",     "type":"integer", "description":"Size (bytes)",     "jsonPath":".spec.size"}"#,
+    printcolumn = r#"{"name":"started",  "type":"date",    "description":"Start date",       "format":"date-time", "jsonPath":".spec.startedAt"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct VynilBackupSpec {
+    /// Backed up instance
+    pub instance: BackupInstance,
+    /// Package (category/package)
+    pub package: String,
+    /// Package version
+    pub version: String,
+    /// Options digest of the instance
+    pub digest: Option<String>,
+    /// Name of the secret holding the repository settings
+    pub secret_name: String,
+    /// Path of the repository within the bucket
+    pub sub_path: String,
+    /// Snapshot id (none when the backup failed)
+    pub snapshot: Option<String>,
+    /// Size of the backed up data (bytes)
+    pub size: Option<i64>,
+    /// Data added to the repository (bytes)
+    pub bytes_added: Option<i64>,
+    /// Number of backed up files
+    pub files: Option<i64>,
+    /// Start of the run
+    pub started_at: DateTime<Utc>,
+    /// Duration of the run (seconds)
+    pub duration: Option<i64>,
+    /// Outcome of the run
+    pub outcome: BackupOutcome,
+    /// Error message of a failed run
+    pub message: Option<String>,
+}
+
+impl VynilBackup {
+    /// Snapshot id when this backup can be restored
+    pub fn restorable_snapshot(&self) -> Option<&str> {
+        match self.spec.outcome {
+            BackupOutcome::Succeeded => self.spec.snapshot.as_deref(),
+            BackupOutcome::Failed => None,
+        }
+    }
+
+    /// Get a backup record
+    pub async fn get_opt(client: Client, namespace: &str, name: &str) -> Result<Option<Self>> {
+        Api::<Self>::namespaced(client, namespace)
+            .get_opt(name)
+            .await
+            .map_err(Error::KubeError)
+    }
+
+    /// Backup records of an instance, newest first
+    pub async fn list_for(client: Client, namespace: &str, instance: &str) -> Result<Vec<Self>> {
+        let lp = ListParams::default().labels(&format!("{INSTANCE_LABEL}={instance}"));
+        let mut list = Api::<Self>::namespaced(client, namespace)
+            .list(&lp)
+            .await
+            .map_err(Error::KubeError)?
+            .items;
+        list.sort_by_key(|b| std::cmp::Reverse(b.spec.started_at));
+        Ok(list)
+    }
+}
+
+impl BackupRecord for VynilBackup {
+    fn instance_kind(&self) -> &str {
+        &self.spec.instance.kind
+    }
+
+    fn started_at(&self) -> Option<DateTime<Utc>> {
+        Some(self.spec.started_at)
+    }
+
+    fn restorable_snapshot(&self) -> Option<&str> {
+        VynilBackup::restorable_snapshot(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::ResourceExt;
+    use serde_json::json;
+
+    #[test]
+    fn only_succeeded_backups_are_restorable() {
+        let spec = json!({
+            "instance": {"kind": "TenantInstance", "name": "wiki"},
+            "package": "apps/wiki",
+            "version": "1.2.0",
+            "secretName": "backup-settings",
+            "subPath": "team/wiki",
+            "snapshot": "4bd2e7f1",
+            "startedAt": "2026-10-01T02:00:00Z",
+            "outcome": "Succeeded"
+        });
+        let mut backup = VynilBackup::new("wiki-20261001020000", serde_json::from_value(spec).unwrap());
+        assert_eq!(backup.restorable_snapshot(), Some("4bd2e7f1"));
+        backup.spec.outcome = BackupOutcome::Failed;
+        assert_eq!(backup.restorable_snapshot(), None);
+    }
+
+    #[test]
+    fn instance_ref_picks_the_backup_to_clone() {
+        let record = |name: &str, started: &str, outcome: &str| {
+            let spec = json!({
+                "instance": {"kind": "TenantInstance", "name": "wiki"},
+                "package": "apps/wiki",
+                "version": "1.2.0",
+                "secretName": "backup-settings",
+                "subPath": "prod/wiki",
+                "snapshot": name,
+                "startedAt": started,
+                "outcome": outcome
+            });
+            VynilBackup::new(name, serde_json::from_value(spec).unwrap())
+        };
+        let records = vec![
+            record("wiki-1", "2026-10-01T02:00:00Z", "Succeeded"),
+            record("wiki-3", "2026-10-03T02:00:00Z", "Failed"),
+            record("wiki-2", "2026-10-02T02:00:00Z", "Succeeded"),
+        ];
+        let mut source: InstanceRef =
+            serde_json::from_value(json!({"namespace": "prod", "name": "wiki"})).unwrap();
+        let picked = source.pick("TenantInstance", &records).map(|b| b.name_any());
+        assert_eq!(picked.as_deref(), Some("wiki-2"));
+        assert!(source.pick("ServiceInstance", &records).is_none());
+        source.backup = Some("wiki-1".to_string());
+        let picked = source.pick("TenantInstance", &records).map(|b| b.name_any());
+        assert_eq!(picked.as_deref(), Some("wiki-1"));
+        source.backup = Some("wiki-3".to_string());
+        assert!(source.pick("TenantInstance", &records).is_none());
+    }
+
+    #[test]
+    fn backup_policy_validation() {
+        let policy: BackupPolicy = serde_json::from_value(json!({
+            "schedule": "15 */2 * * MON-FRI",
+            "retention": {"hourly": 24, "daily": 7, "weekly": 4}
+        }))
+        .unwrap();
+        assert_eq!(policy.validate(), Ok(()));
+        for schedule in ["@hourly", "0 1 * * *", "0,30 1-5 1 JAN *"] {
+            assert!(validate_schedule(schedule).is_ok(), "{schedule}");
+        }
+        for schedule in ["* * * *", "60 * * * *", "0 24 * * *", "*/0 * * * *"] {
+            assert!(validate_schedule(schedule).is_err(), "{schedule}");
+        }
+        let empty = BackupPolicy {
+            retention: Some(BackupRetention {
+                daily: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(empty.validate().is_err());
+        let drill: BackupPolicy = serde_json::from_value(json!({
+            "drill": {"schedule": "0 4 * * SUN", "timeout": 30}
+        }))
+        .unwrap();
+        assert_eq!(drill.validate(), Ok(()));
+        let drill = BackupPolicy {
+            drill: Some(RestoreDrill {
+                schedule: "0 4 * *".to_string(),
+                timeout: None,
+            }),
+            ..Default::default()
+        };
+        assert!(drill.validate().is_err_and(|e| e.starts_with("drill schedule")));
+    }
+}
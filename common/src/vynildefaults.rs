@@ -0,0 +1,207 @@
+use crate::{Error, Result};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::{
+    CustomResource, ResourceExt,
+    core::{Selector, SelectorExt},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Options defaults for the packages installed in the selected namespaces
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    kind = "VynilDefaults",
+    plural = "vynildefaults",
+    shortname = "vdef",
+    group = "vynil.solidite.fr",
+    version = "v1"
+)]
+#[kube(doc = "Custom resource holding options defaults for the instances of the selected namespaces")]
+#[serde(rename_all = "camelCase")]
+pub struct VynilDefaultsSpec {
+    /// Namespaces selector (default to every namespace)
+    pub namespace_selector: Option<LabelSelector>,
+    /// Options defaults for every package
+    pub options: Option<Map<String, Value>>,
+    /// Options defaults per category
+    pub categories: Option<BTreeMap<String, Map<String, Value>>>,
+    /// Options defaults per package (category/package)
+    pub packages: Option<BTreeMap<String, Map<String, Value>>>,
+}
+
+impl VynilDefaults {
+    /// Whether these defaults apply to a namespace with these labels
+    pub fn selects(&self, labels: &BTreeMap<String, String>) -> Result<bool> {
+        match self.spec.namespace_selector.clone() {
+            Some(sel) => Selector::try_from(sel)
+                .map(|s| s.matches(labels))
+                .map_err(|e| Error::Other(format!("Invalid selector: {e}"))),
+            None => Ok(true),
+        }
+    }
+
+    /// Defaults for a package, the package ones overriding the category ones overriding the global ones
+    fn for_package(&self, category: &str, package: &str) -> Map<String, Value> {
+        let mut ret = Map::new();
+        for layer in [
+            self.spec.options.as_ref(),
+            self.spec.categories.as_ref().and_then(|c| c.get(category)),
+            self.spec
+                .packages
+                .as_ref()
+                .and_then(|p| p.get(&format!("{category}/{package}"))),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            merge(&mut ret, layer);
+        }
+        ret
+    }
+}
+
+/// Deep-merge `over` into `base`, `over` winning on conflicting values
+pub fn merge(base: &mut Map<String, Value>, over: &Map<String, Value>) {
+    for (key, value) in over {
+        // A valueFrom reference replaces a value instead of being merged with it
+        let replace = crate::valuefrom::is_reference(value)
+            || base.get(key).is_some_and(crate::valuefrom::is_reference);
+        match (base.get_mut(key), value) {
+            (Some(Value::Object(current)), Value::Object(sub)) if !replace => merge(current, sub),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Options of an instance merged over the defaults selecting its namespace, in name order.
+/// Returns `None` when no defaults change the instance options.
+pub fn effective_options(
+    defaults: &[VynilDefaults],
+    namespace_labels: &BTreeMap<String, String>,
+    category: &str,
+    package: &str,
+    options: &Map<String, Value>,
+) -> Result<Option<Map<String, Value>>> {
+    let mut selected = Vec::new();
+    for d in defaults {
+        if d.selects(namespace_labels)? {
+            selected.push(d);
+        }
+    }
+    selected.sort_by_key(|d| d.name_any());
+    let mut ret = Map::new();
+    for d in selected {
+        merge(&mut ret, &d.for_package(category, package));
+    }
+    merge(&mut ret, options);
+    Ok((ret != *options).then_some(ret))
+}
+
+/// Merge patch turning the `previous` options into the `next` ones, removed keys being set to null
+pub fn merge_patch(previous: Option<&Map<String, Value>>, next: Option<&Map<String, Value>>) -> Value {
+    let (Some(previous), Some(next)) = (previous, next) else {
+        return next.map_or(Value::Null, |n| Value::Object(n.clone()));
+    };
+    let mut patch = Map::new();
+    for key in previous.keys().filter(|k| !next.contains_key(*k)) {
+        patch.insert(key.clone(), Value::Null);
+    }
+    for (key, value) in next {
+        match (previous.get(key), value) {
+            (Some(Value::Object(before)), Value::Object(after)) => {
+                patch.insert(key.clone(), merge_patch(Some(before), Some(after)));
+            }
+            _ => {
+                patch.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Value::Object(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn defaults(name: &str, spec: Value) -> VynilDefaults {
+        VynilDefaults::new(name, serde_json::from_value(spec).unwrap())
+    }
+
+    fn map(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn instance_options_win_over_package_category_and_global_defaults() {
+        let d = defaults(
+            "cluster",
+            json!({
+                "options": {"domain": "example.com", "storage": {"class": "standard", "size": "1Gi"}},
+                "categories": {"database": {"storage": {"class": "fast"}}},
+                "packages": {"database/postgresql": {"storage": {"size": "10Gi"}}},
+            }),
+        );
+        let effective = effective_options(
+            &[d],
+            &BTreeMap::new(),
+            "database",
+            "postgresql",
+            &map(json!({"domain": "db.example.org"})),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            Value::Object(effective),
+            json!({"domain": "db.example.org", "storage": {"class": "fast", "size": "10Gi"}})
+        );
+    }
+
+    #[test]
+    fn defaults_are_applied_in_name_order_and_filtered_by_namespace() {
+        let labels = BTreeMap::from([("vynil.solidite.fr/tenant".to_string(), "acme".to_string())]);
+        let list = [
+            defaults(
+                "b-tenants",
+                json!({
+                    "namespaceSelector": {"matchExpressions": [{"key": "vynil.solidite.fr/tenant", "operator": "Exists"}]},
+                    "options": {"domain": "acme.example.com"},
+                }),
+            ),
+            defaults("a-global", json!({"options": {"domain": "example.com"}})),
+        ];
+        let effective = effective_options(&list, &labels, "apps", "wiki", &Map::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(effective["domain"], "acme.example.com");
+        let effective = effective_options(&list, &BTreeMap::new(), "apps", "wiki", &Map::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(effective["domain"], "example.com");
+    }
+
+    #[test]
+    fn no_effective_options_without_matching_defaults() {
+        let d = defaults("db", json!({"categories": {"database": {"replicas": 3}}}));
+        let options = map(json!({"replicas": 1}));
+        assert_eq!(
+            effective_options(&[d], &BTreeMap::new(), "apps", "wiki", &options).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn merge_patch_removes_the_dropped_options() {
+        let previous = map(json!({"domain": "example.com", "storage": {"class": "fast", "size": "1Gi"}}));
+        let next = map(json!({"storage": {"class": "fast"}}));
+        assert_eq!(
+            merge_patch(Some(&previous), Some(&next)),
+            json!({"domain": null, "storage": {"class": "fast", "size": null}})
+        );
+        assert_eq!(merge_patch(Some(&previous), None), Value::Null);
+    }
+}
@@ -28,6 +28,7 @@ pub enum VynilPackageType {
     Tenant,
     System,
     Service,
+    Bundle,
 }
 
 /// Vynil package feature
@@ -403,6 +404,129 @@ pub enum VynilPackageRecommandation {
     TenantService(String),
 }
 
+/// Member of a bundle package, installed as a child instance of the bundle
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, JsonSchema)]
+pub struct VynilBundleMember {
+    /// Name of the member instance
+    pub name: String,
+    /// Instance kind to create (system, service or tenant)
+    #[serde(rename = "type", default = "VynilBundleMember::default_usage")]
+    pub usage: VynilPackageType,
+    /// Namespace of the member instance (default to the bundle namespace)
+    pub namespace: Option<String>,
+    /// JukeBox to install from (default to the bundle jukebox)
+    pub jukebox: Option<String>,
+    /// Package category
+    pub category: String,
+    /// Package name
+    pub package: String,
+    /// Member options, strings are handlebars templates rendered with the bundle context
+    pub options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Wait for the member to be ready before installing the next one (default to true)
+    pub wait: Option<bool>,
+}
+impl VynilBundleMember {
+    fn default_usage() -> VynilPackageType {
+        VynilPackageType::System
+    }
+}
+
+/// Option migration rule, paths are dot-separated (`storage.size`)
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VynilOptionRule {
+    /// Rename an option, keeping it under the same parent
+    Rename { path: String, name: String },
+    /// Move an option to another path
+    Move { path: String, to: String },
+    /// Replace an option by the result of a rhai script reading `value`, `()` removes it
+    Transform { path: String, script: String },
+}
+impl VynilOptionRule {
+    fn apply(&self, options: &mut serde_json::Map<String, serde_json::Value>) -> Result<()> {
+        match self {
+            VynilOptionRule::Rename { path, name } => {
+                if let Some(value) = take_option(options, path) {
+                    let to = match path.rsplit_once('.') {
+                        Some((parent, _)) => format!("{parent}.{name}"),
+                        None => name.clone(),
+                    };
+                    put_option(options, &to, value);
+                }
+            }
+            VynilOptionRule::Move { path, to } => {
+                if let Some(value) = take_option(options, path) {
+                    put_option(options, to, value);
+                }
+            }
+            VynilOptionRule::Transform { path, script } => {
+                if let Some(value) = take_option(options, path) {
+                    let mut rhai = Script::new_core(vec![]);
+                    rhai.ctx.set_value(
+                        "value",
+                        serde_json::from_value::<Dynamic>(value).map_err(Error::JsonError)?,
+                    );
+                    let result = rhai.eval(script)?;
+                    if !result.is_unit() {
+                        put_option(
+                            options,
+                            path,
+                            serde_json::to_value(&result).map_err(Error::SerializationError)?,
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove the option at a dotted path
+fn take_option(
+    options: &mut serde_json::Map<String, serde_json::Value>,
+    path: &str,
+) -> Option<serde_json::Value> {
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let last = keys.pop()?;
+    let mut current = options;
+    for key in keys {
+        current = current.get_mut(key)?.as_object_mut()?;
+    }
+    current.remove(last)
+}
+
+/// Set the option at a dotted path, creating its missing parents
+fn put_option(
+    options: &mut serde_json::Map<String, serde_json::Value>,
+    path: &str,
+    value: serde_json::Value,
+) {
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let Some(last) = keys.pop() else {
+        return;
+    };
+    let mut current = options;
+    for key in keys {
+        let entry = current
+            .entry(key)
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+        if !entry.is_object() {
+            *entry = serde_json::Value::Object(serde_json::Map::new());
+        }
+        current = entry.as_object_mut().unwrap();
+    }
+    current.insert(last.to_string(), value);
+}
+
+/// Option migrations for the instances installed with a version up to `from`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, JsonSchema)]
+pub struct VynilOptionMigration {
+    /// Last version using the previous options shape
+    pub from: String,
+    /// Rules to apply, in order
+    pub rules: Vec<VynilOptionRule>,
+}
+
 /// Vynil Package in JukeBox status
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug, JsonSchema)]
 pub struct VynilPackage {
@@ -422,8 +546,54 @@ pub struct VynilPackage {
     pub options: Option<BTreeMap<String, serde_json::Value>>,
     /// A rhai script that produce a map to be added in the package values
     pub value_script: Option<String>,
+    /// Option migrations from the previous versions
+    pub migrations: Option<Vec<VynilOptionMigration>>,
+    /// A rhai script migrating the options from a previous version
+    pub options_migrate: Option<String>,
 }
 impl VynilPackage {
+    /// Migrate the options of an instance installed with the `from` version to this version.
+    /// Returns `None` when the options are unchanged.
+    pub fn migrate_options(
+        &self,
+        from: &str,
+        options: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Option<serde_json::Map<String, serde_json::Value>>> {
+        let (Some(installed), Some(target)) = (Semver::opt_parse(from), Semver::opt_parse(&self.tag)) else {
+            return Ok(None);
+        };
+        if installed >= target {
+            return Ok(None);
+        }
+        let mut migrations: Vec<(Semver, &VynilOptionMigration)> = self
+            .migrations
+            .iter()
+            .flatten()
+            .filter_map(|m| Semver::opt_parse(&m.from).map(|v| (v, m)))
+            .filter(|(v, _)| installed <= *v && *v < target)
+            .collect();
+        migrations.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut migrated = options.clone();
+        for (_, migration) in migrations {
+            for rule in &migration.rules {
+                rule.apply(&mut migrated)?;
+            }
+        }
+        if let Some(hook) = &self.options_migrate {
+            let script = serde_json::from_str::<String>(hook).map_err(Error::JsonError)?;
+            let mut rhai = Script::new_core(vec![]);
+            rhai.ctx.set_value(
+                "options",
+                serde_json::from_value::<Dynamic>(serde_json::Value::Object(migrated))
+                    .map_err(Error::JsonError)?,
+            );
+            rhai.ctx.set_value("from", from.to_string());
+            rhai.ctx.set_value("to", self.tag.clone());
+            migrated = serde_json::from_str(&rhai.eval_map_string(&script)?).map_err(Error::JsonError)?;
+        }
+        Ok((migrated != *options).then_some(migrated))
+    }
+
     pub fn get_min_version(&self) -> Option<String> {
         for rec in &self.requirements {
             if let VynilPackageRequirement::MinimumPreviousVersion(v) = rec {
@@ -568,6 +738,10 @@ pub struct VynilPackageSource {
     pub resources: Option<BTreeMap<String, Resource>>,
     /// A rhai script that produce a map to be added in the package values
     pub value_script: Option<String>,
+    /// Bundle members, in installation order (bundle packages only)
+    pub members: Option<Vec<VynilBundleMember>>,
+    /// Option migrations from the previous versions
+    pub migrations: Option<Vec<VynilOptionMigration>>,
 }
 impl VynilPackageSource {
     pub fn get_metadata(&mut self) -> RhaiRes<Dynamic> {
@@ -648,6 +822,32 @@ impl VynilPackageSource {
         }
     }
 
+    pub fn get_members(&mut self) -> RhaiRes<Dynamic> {
+        if let Some(members) = self.members.clone() {
+            let v = serde_json::to_string(&members)
+                .map_err(Error::JsonError)
+                .map_err(rhai_err)?;
+            serde_json::from_str(&v)
+                .map_err(Error::JsonError)
+                .map_err(rhai_err)
+        } else {
+            Ok(Dynamic::from(()))
+        }
+    }
+
+    pub fn get_migrations(&mut self) -> RhaiRes<Dynamic> {
+        if let Some(migrations) = self.migrations.clone() {
+            let v = serde_json::to_string(&migrations)
+                .map_err(Error::JsonError)
+                .map_err(rhai_err)?;
+            serde_json::from_str(&v)
+                .map_err(Error::JsonError)
+                .map_err(rhai_err)
+        } else {
+            Ok(Dynamic::from(()))
+        }
+    }
+
     pub fn validate_options(&mut self) -> RhaiRes<()> {
         if let Some(options) = self.options.clone() {
             for val in options.values() {
@@ -682,7 +882,9 @@ pub fn package_rhai_register(engine: &mut Engine) {
         .register_get("options", VynilPackageSource::get_options)
         .register_get("value_script", VynilPackageSource::get_value_script)
         .register_get("images", VynilPackageSource::get_images)
-        .register_get("resources", VynilPackageSource::get_resources);
+        .register_get("resources", VynilPackageSource::get_resources)
+        .register_get("members", VynilPackageSource::get_members)
+        .register_get("migrations", VynilPackageSource::get_migrations);
 }
 
 #[cfg(test)]
@@ -741,6 +943,41 @@ requirements: []
         std::fs::remove_file(p).ok();
     }
 
+    #[test]
+    fn test_read_package_yaml_bundle_members() {
+        let yaml = MINIMAL_YAML.replace("type: tenant", "type: bundle")
+            + "\
+members:
+  - name: cert-manager
+    category: core
+    package: cert-manager
+  - name: traefik
+    category: networking
+    package: traefik
+    wait: false
+    options:
+      domain: \"{{values.domain}}\"
+  - name: minio
+    type: service
+    namespace: storage
+    category: share
+    package: minio
+";
+        let p = write_temp_yaml(&yaml, "bundle");
+        let pkg = read_package_yaml(&p).unwrap();
+        assert!(matches!(pkg.metadata.usage, VynilPackageType::Bundle));
+        let members = pkg.members.unwrap();
+        assert_eq!(members.len(), 3);
+        assert_eq!(members[0].name, "cert-manager");
+        assert_eq!(members[0].usage, VynilPackageType::System);
+        assert_eq!(members[0].wait, None);
+        assert_eq!(members[1].wait, Some(false));
+        assert_eq!(members[1].options.as_ref().unwrap()["domain"], "{{values.domain}}");
+        assert_eq!(members[2].usage, VynilPackageType::Service);
+        assert_eq!(members[2].namespace, Some("storage".to_string()));
+        std::fs::remove_file(p).ok();
+    }
+
     // ── Requirement enum variants ─────────────────────────────────────────────
 
     const REQUIREMENTS_YAML: &str = "\
@@ -975,6 +1212,8 @@ resources:
             recommandations: None,
             options: None,
             value_script: None,
+            migrations: None,
+            options_migrate: None,
         }
     }
 
@@ -1072,6 +1311,8 @@ resources:
             recommandations: None,
             options: None,
             value_script: None,
+            migrations: None,
+            options_migrate: None,
         }
     }
 
@@ -1146,4 +1387,93 @@ resources:
         ];
         assert_eq!(select_from(&chain, "2.5.0").as_deref(), Some("3.0.0"));
     }
+
+    // ── Option migrations ─────────────────────────────────────────────────────
+
+    const MIGRATIONS_YAML: &str = "\
+apiVersion: vynil.solidite.fr/v1
+kind: Package
+metadata:
+  name: test-pkg
+  category: apps
+  description: A package with option migrations
+  type: tenant
+  features: []
+requirements: []
+migrations:
+  - from: 1.0.0
+    rules:
+      - rename:
+          path: storage.size
+          name: capacity
+      - move:
+          path: domain
+          to: ingress.domain
+  - from: 2.0.0
+    rules:
+      - transform:
+          path: replicas
+          script: value * 2
+";
+
+    fn migrations() -> Vec<VynilOptionMigration> {
+        let p = write_temp_yaml(MIGRATIONS_YAML, "migrations");
+        let pkg = read_package_yaml(&p).unwrap();
+        std::fs::remove_file(p).ok();
+        pkg.migrations.unwrap()
+    }
+
+    fn options(value: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_read_package_yaml_migrations() {
+        let migrations = migrations();
+        assert_eq!(migrations.len(), 2);
+        assert_eq!(migrations[0].from, "1.0.0");
+        assert_eq!(migrations[0].rules[1], VynilOptionRule::Move {
+            path: "domain".into(),
+            to: "ingress.domain".into()
+        });
+    }
+
+    #[test]
+    fn test_migrate_options_applies_the_rules_after_the_installed_version() {
+        let mut pkg = make_pkg("3.0.0", vec![]);
+        pkg.migrations = Some(migrations());
+        let opts =
+            options(serde_json::json!({"storage": {"size": "1Gi"}, "domain": "example.com", "replicas": 2}));
+        let migrated = pkg.migrate_options("1.0.0", &opts).unwrap().unwrap();
+        assert_eq!(
+            serde_json::Value::Object(migrated),
+            serde_json::json!({"storage": {"capacity": "1Gi"}, "ingress": {"domain": "example.com"}, "replicas": 4})
+        );
+        // Installed after the first shape change: only the transform applies
+        let migrated = pkg.migrate_options("1.5.0", &opts).unwrap().unwrap();
+        assert_eq!(migrated["replicas"], 4);
+        assert_eq!(migrated["domain"], "example.com");
+    }
+
+    #[test]
+    fn test_migrate_options_none_when_unchanged() {
+        let mut pkg = make_pkg("3.0.0", vec![]);
+        pkg.migrations = Some(migrations());
+        let opts = options(serde_json::json!({"replicas": 2}));
+        assert_eq!(pkg.migrate_options("3.0.0", &opts).unwrap(), None);
+        assert_eq!(pkg.migrate_options("", &opts).unwrap(), None);
+        assert_eq!(pkg.migrate_options("2.5.0", &opts).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_options_runs_the_hook() {
+        let mut pkg = make_pkg("2.0.0", vec![]);
+        pkg.options_migrate =
+            Some(serde_json::to_string("options.upgraded = `${from}->${to}`; options").unwrap());
+        let migrated = pkg
+            .migrate_options("1.0.0", &options(serde_json::json!({})))
+            .unwrap()
+            .unwrap();
+        assert_eq!(migrated["upgraded"], "1.0.0->2.0.0");
+    }
 }
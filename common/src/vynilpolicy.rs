@@ -0,0 +1,364 @@
+use crate::{Error, Result, context::get_client_async};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::{
+    CustomResource, ResourceExt,
+    api::{Api, ListParams, ObjectList},
+    core::{Selector, SelectorExt},
+};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use wildmatch::WildMatch;
+
+/// Allow/deny lists of glob patterns (deny wins, no allow list means everything is allowed)
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct PolicyList {
+    /// Allowed patterns
+    pub allow: Option<Vec<String>>,
+    /// Denied patterns
+    pub deny: Option<Vec<String>>,
+}
+
+/// Maximum number of instances of the matching packages per namespace
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct PolicyLimit {
+    /// Package pattern (category/package)
+    pub package: String,
+    /// Maximum number of instances
+    pub max: u32,
+}
+
+/// JSON-schema fragment the options of the matching packages must satisfy
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct PolicyOptions {
+    /// Package pattern (category/package)
+    pub package: String,
+    /// Schema applied to the instance options object
+    pub schema: serde_json::Map<String, Value>,
+}
+
+/// Restrict the packages and options instances can use
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    kind = "VynilPolicy",
+    shortname = "vpol",
+    group = "vynil.solidite.fr",
+    version = "v1"
+)]
+#[kube(doc = "Custom resource restricting the packages and options allowed in the selected namespaces")]
+#[serde(rename_all = "camelCase")]
+pub struct VynilPolicySpec {
+    /// Namespaces selector (default to every namespace)
+    pub namespace_selector: Option<LabelSelector>,
+    /// JukeBox names
+    pub jukeboxes: Option<PolicyList>,
+    /// Category names
+    pub categories: Option<PolicyList>,
+    /// Packages (category/package)
+    pub packages: Option<PolicyList>,
+    /// Instances count limits per namespace
+    pub limits: Option<Vec<PolicyLimit>>,
+    /// Options constraints
+    pub options: Option<Vec<PolicyOptions>>,
+}
+
+/// The instance a policy is evaluated for
+pub struct PolicySubject<'a> {
+    pub jukebox: &'a str,
+    pub category: &'a str,
+    pub package: &'a str,
+    pub options: Value,
+    /// Number of other instances of the same package in the namespace
+    pub others: usize,
+}
+
+impl PolicyList {
+    fn allows(&self, value: &str) -> bool {
+        let matches = |l: &Vec<String>| l.iter().any(|p| WildMatch::new(p).matches(value));
+        !self.deny.as_ref().is_some_and(matches) && self.allow.as_ref().is_none_or(matches)
+    }
+}
+
+impl VynilPolicy {
+    pub async fn list() -> Result<ObjectList<Self>> {
+        let api = Api::<Self>::all(get_client_async().await);
+        api.list(&ListParams::default()).await.map_err(Error::KubeError)
+    }
+
+    /// Whether this policy applies to a namespace with these labels
+    pub fn selects(&self, labels: &BTreeMap<String, String>) -> Result<bool> {
+        match self.spec.namespace_selector.clone() {
+            Some(sel) => Selector::try_from(sel)
+                .map(|s| s.matches(labels))
+                .map_err(|e| Error::Other(format!("Invalid selector: {e}"))),
+            None => Ok(true),
+        }
+    }
+
+    /// List the violations of this policy by an instance
+    pub fn violations(&self, subject: &PolicySubject) -> Vec<String> {
+        let mut ret = Vec::new();
+        let name = self.name_any();
+        let full = format!("{}/{}", subject.category, subject.package);
+        if let Some(l) = &self.spec.jukeboxes
+            && !l.allows(subject.jukebox)
+        {
+            ret.push(format!("{name}: JukeBox {} is not allowed", subject.jukebox));
+        }
+        if let Some(l) = &self.spec.categories
+            && !l.allows(subject.category)
+        {
+            ret.push(format!("{name}: category {} is not allowed", subject.category));
+        }
+        if let Some(l) = &self.spec.packages
+            && !l.allows(&full)
+        {
+            ret.push(format!("{name}: package {full} is not allowed"));
+        }
+        for limit in self.spec.limits.iter().flatten() {
+            if WildMatch::new(&limit.package).matches(&full) && subject.others >= limit.max as usize {
+                ret.push(format!(
+                    "{name}: at most {} instances of {} per namespace",
+                    limit.max, limit.package
+                ));
+            }
+        }
+        for opt in self.spec.options.iter().flatten() {
+            if WildMatch::new(&opt.package).matches(&full) {
+                let mut errors = Vec::new();
+                check_schema("options", &Value::Object(opt.schema.clone()), &subject.options, &mut errors);
+                ret.extend(errors.into_iter().map(|e| format!("{name}: {e}")));
+            }
+        }
+        ret
+    }
+}
+
+/// List the violations of every policy selecting the namespace
+pub fn evaluate(
+    policies: &[VynilPolicy],
+    namespace_labels: &BTreeMap<String, String>,
+    subject: &PolicySubject,
+) -> Result<Vec<String>> {
+    let mut ret = Vec::new();
+    for policy in policies {
+        if policy.selects(namespace_labels)? {
+            ret.extend(policy.violations(subject));
+        }
+    }
+    Ok(ret)
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Check a value against a JSON-schema fragment
+///
+/// Supported keywords: type, enum, const, minimum, maximum, minLength, maxLength, pattern,
+/// properties, required, additionalProperties (boolean), items, minItems and maxItems.
+/// The `valueFrom` references are expected to be resolved beforehand: a constrained value still
+/// read from a reference could not be checked and is denied.
+pub fn check_schema(path: &str, schema: &Value, value: &Value, errors: &mut Vec<String>) {
+    if crate::valuefrom::is_reference(value) {
+        if schema.as_object().is_none_or(|s| !s.is_empty()) {
+            errors.push(format!("{path} is read from an object that cannot be resolved"));
+        }
+        return;
+    }
+    if let Some(t) = schema.get("type") {
+        let ok = match t {
+            Value::String(s) => type_matches(s, value),
+            Value::Array(a) => a.iter().any(|s| s.as_str().is_some_and(|s| type_matches(s, value))),
+            _ => true,
+        };
+        if !ok {
+            errors.push(format!("{path} should be of type {t}"));
+            return;
+        }
+    }
+    if let Some(Value::Array(vals)) = schema.get("enum")
+        && !vals.contains(value)
+    {
+        errors.push(format!("{path} should be one of {}", Value::Array(vals.clone())));
+    }
+    if let Some(c) = schema.get("const")
+        && c != value
+    {
+        errors.push(format!("{path} should be {c}"));
+    }
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64)
+            && n < min
+        {
+            errors.push(format!("{path} should be >= {min}"));
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64)
+            && n > max
+        {
+            errors.push(format!("{path} should be <= {max}"));
+        }
+    }
+    if let Some(s) = value.as_str() {
+        let len = s.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64)
+            && len < min
+        {
+            errors.push(format!("{path} should be at least {min} characters long"));
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64)
+            && len > max
+        {
+            errors.push(format!("{path} should be at most {max} characters long"));
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            match Regex::new(pattern) {
+                Ok(re) if !re.is_match(s) => errors.push(format!("{path} should match {pattern}")),
+                Ok(_) => {}
+                Err(e) => errors.push(format!("{path}: invalid pattern {pattern}: {e}")),
+            }
+        }
+    }
+    if let Some(obj) = value.as_object() {
+        for key in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !obj.contains_key(key) {
+                errors.push(format!("{path}.{key} is required"));
+            }
+        }
+        let props = schema.get("properties").and_then(Value::as_object);
+        for (key, val) in obj {
+            match props.and_then(|p| p.get(key)) {
+                Some(sub) => check_schema(&format!("{path}.{key}"), sub, val, errors),
+                None => {
+                    if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                        errors.push(format!("{path}.{key} is not allowed"));
+                    }
+                }
+            }
+        }
+    }
+    if let Some(items) = value.as_array() {
+        let len = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64)
+            && len < min
+        {
+            errors.push(format!("{path} should have at least {min} items"));
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64)
+            && len > max
+        {
+            errors.push(format!("{path} should have at most {max} items"));
+        }
+        if let Some(sub) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                check_schema(&format!("{path}[{i}]"), sub, item, errors);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(spec: Value) -> VynilPolicy {
+        VynilPolicy::new("restrict", serde_json::from_value(spec).unwrap())
+    }
+
+    fn subject(options: Value, others: usize) -> PolicySubject<'static> {
+        PolicySubject {
+            jukebox: "vynil",
+            category: "apps",
+            package: "nextcloud",
+            options,
+            others,
+        }
+    }
+
+    #[test]
+    fn lists_deny_wins_over_allow() {
+        let p = policy(json!({
+            "jukeboxes": {"allow": ["vynil", "home-*"]},
+            "packages": {"allow": ["apps/*"], "deny": ["apps/nextcloud"]},
+        }));
+        let v = p.violations(&subject(json!({}), 0));
+        assert_eq!(v, vec!["restrict: package apps/nextcloud is not allowed"]);
+        let p = policy(json!({"jukeboxes": {"allow": ["home-*"]}}));
+        assert_eq!(p.violations(&subject(json!({}), 0)).len(), 1);
+    }
+
+    #[test]
+    fn limits_count_other_instances() {
+        let p = policy(json!({"limits": [{"package": "apps/*", "max": 2}]}));
+        assert!(p.violations(&subject(json!({}), 1)).is_empty());
+        assert_eq!(p.violations(&subject(json!({}), 2)).len(), 1);
+    }
+
+    #[test]
+    fn options_are_checked_against_the_schema() {
+        let p = policy(json!({"options": [{"package": "apps/nextcloud", "schema": {
+            "properties": {
+                "replicas": {"type": "integer", "maximum": 3},
+                "storage": {"properties": {"class": {"enum": ["standard"]}}},
+                "domain": {"type": "string", "pattern": "\\.example\\.com$"},
+            },
+        }}]}));
+        assert!(
+            p.violations(&subject(
+                json!({"replicas": 2, "storage": {"class": "standard"}, "domain": "a.example.com"}),
+                0
+            ))
+            .is_empty()
+        );
+        let v = p.violations(&subject(
+            json!({"replicas": 5, "storage": {"class": "fast"}, "domain": "evil.org"}),
+            0,
+        ));
+        assert_eq!(v.len(), 3);
+        // The properties are walked in the order of the schema map, which depends on serde_json features
+        for path in ["options.replicas", "options.storage.class", "options.domain"] {
+            assert!(v.iter().any(|e| e.contains(path)), "{path} not in {v:?}");
+        }
+    }
+
+    #[test]
+    fn unresolved_references_are_denied_when_constrained() {
+        let p = policy(json!({"options": [{"package": "apps/nextcloud", "schema": {
+            "properties": {"domain": {"type": "string", "pattern": "\\.example\\.com$"}, "smtp": {}},
+        }}]}));
+        let domain = json!({"valueFrom": {"secretKeyRef": {"name": "nextcloud", "key": "domain"}}});
+        let v = p.violations(&subject(json!({ "domain": domain }), 0));
+        assert_eq!(v.len(), 1);
+        assert!(v[0].contains("options.domain"));
+        // Unconstrained references and resolved values pass
+        let smtp = json!({"valueFrom": {"secretKeyRef": {"name": "nextcloud", "key": "smtp"}}});
+        assert!(p.violations(&subject(json!({ "smtp": smtp }), 0)).is_empty());
+        assert!(p.violations(&subject(json!({"domain": "cloud.example.com"}), 0)).is_empty());
+    }
+
+    #[test]
+    fn namespace_selector_defaults_to_all() {
+        let labels: BTreeMap<String, String> = [("tier".to_string(), "free".to_string())].into();
+        assert!(policy(json!({})).selects(&labels).unwrap());
+        let p = policy(json!({"namespaceSelector": {"matchLabels": {"tier": "premium"}}}));
+        assert!(!p.selects(&labels).unwrap());
+    }
+}
@@ -0,0 +1,700 @@
+use crate::{Error, Result, RhaiRes, rhai_err};
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use futures::{StreamExt, TryStreamExt};
+use object_store::{ClientOptions, ObjectStore, local::LocalFileSystem, path::Path};
+use rhai::{Dynamic, Engine, Map};
+use rustic_core::{
+    BackupOptions, CheckOptions, ConfigOptions, ErrorKind, FileType, Id, KeepOptions, KeyOptions,
+    LocalDestination, LsOptions, NoProgressBars, OpenStatus, PathList, PruneOptions, ReadBackend, Repository,
+    RepositoryBackends, RepositoryOptions, RestoreOptions, RusticError, RusticResult, SnapshotGroupCriterion,
+    SnapshotOptions, WriteBackend,
+    repofile::{KeyFile, KeyId},
+};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{runtime::Handle, task::block_in_place};
+
+/// restic considers a lock stale once it has not been refreshed for 30 minutes
+const STALE_LOCK_MINUTES: i64 = 30;
+
+type OpenRepo = Repository<NoProgressBars, OpenStatus>;
+
+fn backup_err(e: impl std::fmt::Display) -> Error {
+    Error::Backup(e.to_string())
+}
+
+/// A restic-compatible repository
+#[derive(Clone, Debug)]
+pub struct BackupRepo {
+    url: String,
+    password: String,
+    options: HashMap<String, String>,
+}
+
+impl BackupRepo {
+    /// Open the repository at `url`, creating it first when `create` is set
+    pub fn open(url: &str, password: &str, options: HashMap<String, String>, create: bool) -> Result<Self> {
+        let repo = BackupRepo {
+            url: url.to_string(),
+            password: password.to_string(),
+            options,
+        };
+        let raw = repo.raw()?;
+        if raw.config_id().map_err(backup_err)?.is_none() {
+            if !create {
+                return Err(Error::Backup(format!("No repository at {url}")));
+            }
+            tracing::info!("Creating the backup repository {url}");
+            raw.init(&KeyOptions::default(), &ConfigOptions::default())
+                .map_err(backup_err)?;
+        } else {
+            raw.open().map_err(backup_err)?;
+        }
+        Ok(repo)
+    }
+
+    fn backend(&self) -> Result<Arc<StoreBackend>> {
+        let (store, prefix): (Arc<dyn ObjectStore>, String) =
+            if let Some((endpoint, bucket, prefix)) = parse_s3_url(&self.url) {
+                (self.s3_store(&endpoint, &bucket)?, prefix)
+            } else if let Some(path) = local_path(&self.url) {
+                (
+                    Arc::new(LocalFileSystem::new()),
+                    path.trim_start_matches('/').to_string(),
+                )
+            } else {
+                return Err(Error::Backup(format!(
+                    "Unsupported repository {}, only local and s3 ones are",
+                    self.url
+                )));
+            };
+        Ok(Arc::new(StoreBackend {
+            store,
+            prefix,
+            location: self.url.clone(),
+            handle: Handle::try_current().map_err(backup_err)?,
+        }))
+    }
+
+    fn raw(&self) -> Result<Repository<NoProgressBars, ()>> {
+        let backends = RepositoryBackends::new(self.backend()?, None);
+        let repo_opts = RepositoryOptions::default().password(self.password.clone());
+        Repository::new(&repo_opts, &backends).map_err(backup_err)
+    }
+
+    fn repo(&self) -> Result<OpenRepo> {
+        self.raw()?.open().map_err(backup_err)
+    }
+
+    /// Remove the locks left by crashed restic processes (every lock when `all` is set)
+    pub async fn unlock(&self, all: bool) -> Result<i64> {
+        let limit = Utc::now() - Duration::minutes(STALE_LOCK_MINUTES);
+        if let Some(s3) = parse_s3_url(&self.url) {
+            self.unlock_s3(s3, all, limit).await
+        } else if let Some(path) = local_path(&self.url) {
+            unlock_local(path, all, limit)
+        } else {
+            Err(Error::Backup(format!(
+                "Removing the locks of {} is not supported, only local and s3 repositories are",
+                self.url
+            )))
+        }
+    }
+
+    /// Bucket of a S3 repository, the settings missing from the options coming from the environment
+    fn s3_store(&self, endpoint: &str, bucket: &str) -> Result<Arc<dyn ObjectStore>> {
+        let setting = |key: &str, env: &str| {
+            self.options
+                .get(key)
+                .cloned()
+                .or_else(|| std::env::var(env).ok())
+                .unwrap_or_default()
+        };
+        let store = crate::s3::build_store(
+            bucket,
+            &setting("region", "AWS_DEFAULT_REGION"),
+            endpoint,
+            &setting("access_key_id", "AWS_ACCESS_KEY_ID"),
+            &setting("secret_access_key", "AWS_SECRET_ACCESS_KEY"),
+            client_options(&self.options),
+        )?;
+        Ok(Arc::from(store))
+    }
+
+    async fn unlock_s3(
+        &self,
+        (endpoint, bucket, prefix): (String, String, String),
+        all: bool,
+        limit: DateTime<Utc>,
+    ) -> Result<i64> {
+        let store = self.s3_store(&endpoint, &bucket)?;
+        let locks = Path::from(format!("{prefix}/locks").trim_start_matches('/'));
+        let mut stale = Vec::new();
+        let mut list = store.list(Some(&locks));
+        while let Some(meta) = list.next().await {
+            let meta = meta.map_err(|e| Error::Other(e.to_string()))?;
+            if all || meta.last_modified < limit {
+                stale.push(meta.location);
+            }
+        }
+        for location in &stale {
+            tracing::warn!("Removing the stale lock {location}");
+            store
+                .delete(location)
+                .await
+                .map_err(|e| Error::Other(e.to_string()))?;
+        }
+        Ok(stale.len() as i64)
+    }
+
+    /// Back `path` up, returning the snapshot id and its statistics
+    pub fn snapshot(&self, path: &str, tags: &str, host: &str) -> Result<Map> {
+        let repo = self.repo()?.to_indexed_ids().map_err(backup_err)?;
+        let mut opts = SnapshotOptions::default();
+        if !tags.is_empty() {
+            opts = opts.add_tags(tags).map_err(backup_err)?;
+        }
+        if !host.is_empty() {
+            opts = opts.host(host.to_string());
+        }
+        let snap = opts.to_snapshot().map_err(backup_err)?;
+        let source = PathList::from_string(path)
+            .map_err(backup_err)?
+            .sanitize()
+            .map_err(backup_err)?;
+        let snap = repo
+            .backup(&BackupOptions::default(), &source, snap)
+            .map_err(backup_err)?;
+        let mut ret = Map::new();
+        ret.insert("id".into(), snap.id.to_string().into());
+        if let Some(summary) = snap.summary {
+            ret.insert("bytes_added".into(), (summary.data_added as i64).into());
+            ret.insert("bytes".into(), (summary.total_bytes_processed as i64).into());
+            ret.insert("files".into(), (summary.total_files_processed as i64).into());
+            ret.insert("files_new".into(), (summary.files_new as i64).into());
+            ret.insert("files_changed".into(), (summary.files_changed as i64).into());
+            ret.insert(
+                "files_unmodified".into(),
+                (summary.files_unmodified as i64).into(),
+            );
+        }
+        Ok(ret)
+    }
+
+    /// Forget the snapshots outside of the retention policy, returning their ids
+    pub fn forget(&self, keep: Map) -> Result<Vec<Dynamic>> {
+        let count = |key: &str| keep.get(key).and_then(|v| v.as_int().ok()).map(|v| v as i32);
+        let mut opts = KeepOptions::default();
+        if let Some(n) = count("last") {
+            opts = opts.keep_last(n);
+        }
+        if let Some(n) = count("hourly") {
+            opts = opts.keep_hourly(n);
+        }
+        if let Some(n) = count("daily") {
+            opts = opts.keep_daily(n);
+        }
+        if let Some(n) = count("weekly") {
+            opts = opts.keep_weekly(n);
+        }
+        if let Some(n) = count("monthly") {
+            opts = opts.keep_monthly(n);
+        }
+        if let Some(n) = count("yearly") {
+            opts = opts.keep_yearly(n);
+        }
+        let repo = self.repo()?;
+        let ids = repo
+            .get_forget_snapshots(&opts, SnapshotGroupCriterion::default(), |_| true)
+            .map_err(backup_err)?
+            .into_forget_ids();
+        repo.delete_snapshots(&ids).map_err(backup_err)?;
+        Ok(ids.iter().map(|id| id.to_string().into()).collect())
+    }
+
+    /// Ids of the snapshots of the repository
+    pub fn snapshots(&self) -> Result<Vec<Dynamic>> {
+        Ok(self
+            .repo()?
+            .get_all_snapshots()
+            .map_err(backup_err)?
+            .into_iter()
+            .map(|snap| snap.id.to_string().into())
+            .collect())
+    }
+
+    /// Remove the data no snapshot uses anymore
+    pub fn prune(&self) -> Result<Map> {
+        let repo = self.repo()?;
+        let opts = PruneOptions::default();
+        let plan = repo.prune_plan(&opts).map_err(backup_err)?;
+        let mut ret = Map::new();
+        ret.insert(
+            "packs_removed".into(),
+            (plan.stats.packs_to_delete.remove as i64).into(),
+        );
+        ret.insert(
+            "bytes_removed".into(),
+            (plan.stats.size_to_delete.remove as i64).into(),
+        );
+        repo.prune(&opts, plan).map_err(backup_err)?;
+        Ok(ret)
+    }
+
+    /// Check the repository structure
+    pub fn check(&self) -> Result<()> {
+        self.repo()?.check(CheckOptions::default()).map_err(backup_err)
+    }
+
+    /// Add a key opening the repository with `password`, returning its id
+    pub fn add_key(&self, password: &str) -> Result<String> {
+        let id = self
+            .repo()?
+            .add_key(password, &KeyOptions::default())
+            .map_err(backup_err)?;
+        Ok(id.to_hex().to_string())
+    }
+
+    /// Id of the key the repository is opened with
+    pub fn key_id(&self) -> Result<String> {
+        let backend = self.backend()?;
+        for id in backend.list(FileType::Key).map_err(backup_err)? {
+            let data = backend.read_full(FileType::Key, &id).map_err(backup_err)?;
+            let key: KeyFile = serde_json::from_slice(&data)?;
+            if key.key_from_password(&self.password).is_ok() {
+                return Ok(id.to_hex().to_string());
+            }
+        }
+        Err(Error::Backup(format!(
+            "No key of {} opens with the password",
+            self.url
+        )))
+    }
+
+    /// Ids of the keys of the repository
+    pub fn keys(&self) -> Result<Vec<Dynamic>> {
+        Ok(self
+            .repo()?
+            .list::<KeyId>()
+            .map_err(backup_err)?
+            .map(|id| id.to_hex().to_string().into())
+            .collect())
+    }
+
+    /// Remove a key of the repository
+    pub fn remove_key(&self, id: &str) -> Result<()> {
+        if id == self.key_id()? {
+            return Err(Error::Backup(format!(
+                "key {id} is the one the repository was opened with"
+            )));
+        }
+        let id: Id = id.parse().map_err(backup_err)?;
+        let backend = self.backend()?;
+        if !backend.list(FileType::Key).map_err(backup_err)?.contains(&id) {
+            return Err(Error::Backup(format!("No key {id} in {}", self.url)));
+        }
+        backend.remove(FileType::Key, &id, false).map_err(backup_err)
+    }
+
+    /// Restore a snapshot (`latest` for the last one) into `target`
+    pub fn restore(&self, snapshot: &str, target: &str) -> Result<Map> {
+        let repo = self.repo()?.to_indexed().map_err(backup_err)?;
+        let node = repo
+            .node_from_snapshot_path(&format!("{snapshot}:"), |_| true)
+            .map_err(backup_err)?;
+        let ls = repo.ls(&node, &LsOptions::default()).map_err(backup_err)?;
+        let dest = LocalDestination::new(target, true, !node.is_dir()).map_err(backup_err)?;
+        let opts = RestoreOptions::default();
+        let plan = repo
+            .prepare_restore(&opts, ls.clone(), &dest, false)
+            .map_err(backup_err)?;
+        let mut ret = Map::new();
+        ret.insert("files".into(), (plan.stats.files.restore as i64).into());
+        ret.insert("bytes".into(), (plan.restore_size as i64).into());
+        repo.restore(plan, &opts, ls, &dest).map_err(backup_err)?;
+        Ok(ret)
+    }
+}
+
+/// Remove the locks of a repository on the local filesystem
+fn unlock_local(path: &str, all: bool, limit: DateTime<Utc>) -> Result<i64> {
+    let locks = std::path::Path::new(path).join("locks");
+    if !locks.is_dir() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in std::fs::read_dir(&locks).map_err(backup_err)? {
+        let entry = entry.map_err(backup_err)?;
+        let modified: DateTime<Utc> = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .map_err(backup_err)?
+            .into();
+        if all || modified < limit {
+            tracing::warn!("Removing the stale lock {}", entry.path().display());
+            std::fs::remove_file(entry.path()).map_err(backup_err)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Path of a repository on the local filesystem (`/path` or `local:/path`)
+fn local_path(url: &str) -> Option<&str> {
+    let path = url.strip_prefix("local:").unwrap_or(url);
+    path.starts_with('/').then_some(path)
+}
+
+/// Endpoint, bucket and prefix of a `s3:https://host/bucket/prefix` repository
+fn parse_s3_url(url: &str) -> Option<(String, String, String)> {
+    let parsed = url::Url::parse(url.strip_prefix("s3:")?).ok()?;
+    let mut segments = parsed.path().trim_matches('/').splitn(2, '/');
+    let bucket = segments.next().filter(|b| !b.is_empty())?.to_string();
+    let prefix = segments.next().unwrap_or_default().to_string();
+    let endpoint = format!(
+        "{}://{}",
+        parsed.scheme(),
+        &parsed[url::Position::BeforeHost..url::Position::AfterPort]
+    );
+    Some((endpoint, bucket, prefix))
+}
+
+/// HTTP client settings of a S3 repository: `insecure_tls` accepts the invalid certificates of the
+/// endpoint (self-signed ones)
+fn client_options(options: &HashMap<String, String>) -> ClientOptions {
+    ClientOptions::new()
+        .with_allow_invalid_certificates(options.get("insecure_tls").is_some_and(|v| v == "true"))
+}
+
+fn store_err(path: &Path, e: object_store::Error) -> Box<RusticError> {
+    RusticError::with_source(ErrorKind::Backend, "Request on `{path}` failed", e)
+        .attach_context("path", path.to_string())
+}
+
+/// restic repository below `prefix` in an object store, a S3 bucket or the local filesystem
+#[derive(Debug)]
+struct StoreBackend {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    location: String,
+    handle: Handle,
+}
+
+impl StoreBackend {
+    fn dir(&self, tpe: FileType) -> Path {
+        Path::from(format!("{}/{}", self.prefix, tpe.dirname()).trim_start_matches('/'))
+    }
+
+    fn path(&self, tpe: FileType, id: &Id) -> Path {
+        let hex = id.to_hex();
+        let hex = hex.as_str();
+        let key = match tpe {
+            FileType::Config => format!("{}/config", self.prefix),
+            FileType::Pack => format!("{}/data/{}/{hex}", self.prefix, &hex[0..2]),
+            _ => format!("{}/{}/{hex}", self.prefix, tpe.dirname()),
+        };
+        Path::from(key.trim_start_matches('/'))
+    }
+
+    /// rustic calls the backend synchronously, from runtime threads or from its own ones
+    fn block<T>(&self, op: impl Future<Output = T>) -> T {
+        match Handle::try_current() {
+            Ok(_) => block_in_place(|| self.handle.block_on(op)),
+            Err(_) => self.handle.block_on(op),
+        }
+    }
+}
+
+impl ReadBackend for StoreBackend {
+    fn location(&self) -> String {
+        self.location.clone()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        if tpe == FileType::Config {
+            let path = self.path(tpe, &Id::default());
+            return match self.block(self.store.head(&path)) {
+                Ok(meta) => Ok(vec![(Id::default(), meta.size as u32)]),
+                Err(object_store::Error::NotFound { .. }) => Ok(Vec::new()),
+                Err(e) => Err(store_err(&path, e)),
+            };
+        }
+        let dir = self.dir(tpe);
+        let objects: Vec<_> = self
+            .block(self.store.list(Some(&dir)).try_collect())
+            .map_err(|e| store_err(&dir, e))?;
+        Ok(objects
+            .into_iter()
+            .filter_map(|meta| {
+                let id = meta.location.filename()?.parse().ok()?;
+                Some((id, meta.size as u32))
+            })
+            .collect())
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        let path = self.path(tpe, id);
+        self.block(async { self.store.get(&path).await?.bytes().await })
+            .map_err(|e| store_err(&path, e))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        let path = self.path(tpe, id);
+        let range = offset as usize..(offset + length) as usize;
+        self.block(self.store.get_range(&path, range))
+            .map_err(|e| store_err(&path, e))
+    }
+}
+
+impl WriteBackend for StoreBackend {
+    fn write_bytes(&self, tpe: FileType, id: &Id, _cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        let path = self.path(tpe, id);
+        self.block(self.store.put(&path, buf.into()))
+            .map(|_| ())
+            .map_err(|e| store_err(&path, e))
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+        let path = self.path(tpe, id);
+        self.block(self.store.delete(&path))
+            .map_err(|e| store_err(&path, e))
+    }
+}
+
+fn to_options(options: Map) -> HashMap<String, String> {
+    options
+        .into_iter()
+        .filter(|(k, _)| k.as_str() != "create")
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Run a blocking repository operation, stale locks being removed before the write ones
+fn run<T>(repo: &BackupRepo, write: bool, op: impl FnOnce(&BackupRepo) -> Result<T>) -> RhaiRes<T> {
+    block_in_place(|| {
+        if write {
+            Handle::current().block_on(repo.unlock(false))?;
+        }
+        op(repo)
+    })
+    .map_err(rhai_err)
+}
+
+pub fn backup_repo_open(url: String, password: String, options: Map) -> RhaiRes<BackupRepo> {
+    let create = options
+        .get("create")
+        .and_then(|v| v.as_bool().ok())
+        .unwrap_or(false);
+    block_in_place(|| BackupRepo::open(&url, &password, to_options(options), create)).map_err(rhai_err)
+}
+
+pub fn backup_rhai_register(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<BackupRepo>("BackupRepo")
+        .register_fn("backup_repo_open", backup_repo_open)
+        .register_fn("backup_repo_open", |url: String, password: String| {
+            backup_repo_open(url, password, Map::new())
+        })
+        .register_fn(
+            "backup_repo_snapshot",
+            |repo: &mut BackupRepo, path: String, tags: String, host: String| {
+                run(repo, true, |r| r.snapshot(&path, &tags, &host))
+            },
+        )
+        .register_fn("backup_repo_forget", |repo: &mut BackupRepo, keep: Map| {
+            run(repo, true, |r| r.forget(keep))
+        })
+        .register_fn("backup_repo_snapshots", |repo: &mut BackupRepo| {
+            run(repo, false, |r| r.snapshots())
+        })
+        .register_fn("backup_repo_prune", |repo: &mut BackupRepo| {
+            run(repo, true, |r| r.prune())
+        })
+        .register_fn("backup_repo_check", |repo: &mut BackupRepo| {
+            run(repo, false, |r| r.check())
+        })
+        .register_fn(
+            "backup_repo_restore",
+            |repo: &mut BackupRepo, snapshot: String, target: String| {
+                run(repo, false, |r| r.restore(&snapshot, &target))
+            },
+        )
+        .register_fn(
+            "backup_repo_add_key",
+            |repo: &mut BackupRepo, password: String| run(repo, false, |r| r.add_key(&password)),
+        )
+        .register_fn("backup_repo_key_id", |repo: &mut BackupRepo| {
+            run(repo, false, |r| r.key_id())
+        })
+        .register_fn("backup_repo_keys", |repo: &mut BackupRepo| {
+            run(repo, false, |r| r.keys())
+        })
+        .register_fn("backup_repo_remove_key", |repo: &mut BackupRepo, id: String| {
+            run(repo, false, |r| r.remove_key(&id))
+        })
+        .register_fn("backup_repo_unlock", |repo: &mut BackupRepo| {
+            block_in_place(|| Handle::current().block_on(repo.unlock(true))).map_err(rhai_err)
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::ClientConfigKey;
+    use std::fs;
+
+    #[test]
+    fn s3_urls_are_split_in_endpoint_bucket_and_prefix() {
+        assert_eq!(
+            parse_s3_url("s3:https://minio.local:9000/backups/ns/app"),
+            Some((
+                "https://minio.local:9000".to_string(),
+                "backups".to_string(),
+                "ns/app".to_string()
+            ))
+        );
+        assert_eq!(parse_s3_url("/var/lib/backups"), None);
+        assert_eq!(parse_s3_url("s3:https://minio.local/"), None);
+    }
+
+    #[test]
+    fn s3_repositories_accept_invalid_certificates_on_demand() {
+        let insecure = HashMap::from([("insecure_tls".to_string(), "true".to_string())]);
+        assert_eq!(
+            client_options(&insecure)
+                .get_config_value(&ClientConfigKey::AllowInvalidCertificates)
+                .as_deref(),
+            Some("true")
+        );
+        assert_ne!(
+            client_options(&HashMap::new())
+                .get_config_value(&ClientConfigKey::AllowInvalidCertificates)
+                .as_deref(),
+            Some("true")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn repositories_are_created_in_s3_buckets() {
+        let store = Arc::new(object_store::memory::InMemory::new());
+        let backend = Arc::new(StoreBackend {
+            store: store.clone(),
+            prefix: "ns/app".to_string(),
+            location: "s3:https://minio.local/backups/ns/app".to_string(),
+            handle: Handle::current(),
+        });
+        block_in_place(|| {
+            let backends = RepositoryBackends::new(backend.clone(), None);
+            let opts = RepositoryOptions::default().password("secret");
+            let repo = Repository::new(&opts, &backends).unwrap();
+            assert!(repo.config_id().unwrap().is_none());
+            repo.init(&KeyOptions::default(), &ConfigOptions::default())
+                .unwrap();
+            Repository::new(&opts, &backends).unwrap().open().unwrap();
+            assert_eq!(backend.list(FileType::Config).unwrap().len(), 1);
+            assert_eq!(backend.list(FileType::Key).unwrap().len(), 1);
+
+            let id = Id::random();
+            backend
+                .write_bytes(FileType::Pack, &id, false, Bytes::from_static(b"some data"))
+                .unwrap();
+            assert_eq!(backend.list_with_size(FileType::Pack).unwrap(), vec![(id, 9)]);
+            assert_eq!(
+                backend.read_partial(FileType::Pack, &id, false, 5, 4).unwrap(),
+                Bytes::from_static(b"data")
+            );
+            backend.remove(FileType::Pack, &id, false).unwrap();
+            assert!(backend.list(FileType::Pack).unwrap().is_empty());
+        });
+        let hex = Id::random().to_hex();
+        assert_eq!(
+            backend.path(FileType::Pack, &hex.parse().unwrap()).to_string(),
+            format!("ns/app/data/{}/{}", &hex[0..2], hex.as_str())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn snapshot_forget_and_restore_a_local_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("data.txt"), "some data").unwrap();
+        let url = dir.path().join("repo").to_string_lossy().to_string();
+
+        assert!(BackupRepo::open(&url, "secret", HashMap::new(), false).is_err());
+        let repo = BackupRepo::open(&url, "secret", HashMap::new(), true).unwrap();
+        let first = repo
+            .snapshot(&source.to_string_lossy(), "namespace:test", "app")
+            .unwrap();
+        assert_eq!(first["files_new"].as_int().unwrap(), 1);
+        assert!(first["bytes_added"].as_int().unwrap() > 0);
+        let second = repo
+            .snapshot(&source.to_string_lossy(), "namespace:test", "app")
+            .unwrap();
+        assert_eq!(second["files_unmodified"].as_int().unwrap(), 1);
+
+        let mut keep = Map::new();
+        keep.insert("last".into(), Dynamic::from(1_i64));
+        let forgotten = repo.forget(keep).unwrap();
+        assert_eq!(forgotten.len(), 1);
+        assert_eq!(forgotten[0].to_string(), first["id"].to_string());
+        let snapshots = repo.snapshots().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].to_string(), second["id"].to_string());
+        repo.prune().unwrap();
+        repo.check().unwrap();
+
+        let target = dir.path().join("target");
+        let restored = repo.restore("latest", &target.to_string_lossy()).unwrap();
+        assert_eq!(restored["files"].as_int().unwrap(), 1);
+        let restored_file = target.join(source.strip_prefix("/").unwrap()).join("data.txt");
+        assert_eq!(fs::read_to_string(restored_file).unwrap(), "some data");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn repository_keys_are_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("repo").to_string_lossy().to_string();
+        let old = BackupRepo::open(&url, "old-secret", HashMap::new(), true).unwrap();
+        let old_id = old.key_id().unwrap();
+        let new_id = old.add_key("new-secret").unwrap();
+        assert_ne!(old_id, new_id);
+
+        let new = BackupRepo::open(&url, "new-secret", HashMap::new(), false).unwrap();
+        assert_eq!(new.key_id().unwrap(), new_id);
+        assert_eq!(new.keys().unwrap().len(), 2);
+        assert!(new.remove_key(&new_id).is_err());
+        new.remove_key(&old_id).unwrap();
+        assert_eq!(new.keys().unwrap().len(), 1);
+        assert!(BackupRepo::open(&url, "old-secret", HashMap::new(), false).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn locks_are_removed_from_local_repositories_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("repo").to_string_lossy().to_string();
+        let repo = BackupRepo::open(&url, "secret", HashMap::new(), true).unwrap();
+        let locks = dir.path().join("repo").join("locks");
+        fs::create_dir_all(&locks).unwrap();
+        fs::write(locks.join("0123456789abcdef"), "lock").unwrap();
+        // A fresh lock is not stale
+        assert_eq!(repo.unlock(false).await.unwrap(), 0);
+        assert_eq!(repo.unlock(true).await.unwrap(), 1);
+        assert_eq!(fs::read_dir(&locks).unwrap().count(), 0);
+
+        assert_eq!(local_path("local:/var/backups"), Some("/var/backups"));
+        let rest = BackupRepo {
+            url: "rest:https://backups.local/app".to_string(),
+            password: "secret".to_string(),
+            options: HashMap::new(),
+        };
+        assert!(rest.unlock(true).await.is_err());
+    }
+}
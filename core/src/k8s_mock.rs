@@ -293,6 +293,33 @@ fn upsert_in_list(list: &mut Vec<Dynamic>, kind: &str, obj: &Dynamic) {
     list.push(obj.clone());
 }
 
+/// kind, name and namespace of a mock object
+fn identity(obj: &Dynamic) -> Option<(String, Option<String>, Option<String>)> {
+    let map = obj.as_map_ref().ok()?;
+    let meta = map
+        .get("metadata")
+        .and_then(|m| m.as_map_ref().ok().map(|m| m.clone()));
+    let field = |key: &str| {
+        meta.as_ref()
+            .and_then(|m| m.get(key))
+            .and_then(|v| v.clone().into_string().ok())
+    };
+    Some((
+        map.get("kind")?.clone().into_string().ok()?,
+        field("name"),
+        field("namespace"),
+    ))
+}
+
+/// Store `obj` in place of the object of the same name, as a replace does
+fn replace_in_list(list: &mut Vec<Dynamic>, obj: &Dynamic) {
+    let key = identity(obj);
+    match list.iter_mut().find(|e| key.is_some() && identity(e) == key) {
+        Some(entry) => *entry = obj.clone(),
+        None => list.push(obj.clone()),
+    }
+}
+
 // ── K8sGenericMock ──────────────────────────────────────────────────────────
 
 #[derive(Clone, Debug)]
@@ -542,7 +569,7 @@ impl K8sGenericMock {
         }
         let merged = merge_with_existing(&self.mocks.lock().unwrap(), &self.kind, &obj);
         upsert_in_list(&mut self.created.lock().unwrap(), &self.kind, &merged);
-        upsert_in_list(&mut self.mocks.lock().unwrap(), &self.kind, &obj);
+        replace_in_list(&mut self.mocks.lock().unwrap(), &obj);
         Ok(obj)
     }
 
@@ -76,6 +76,10 @@ pub enum Error {
     #[error("OCI parse error {0}")]
     OCIParseError(#[from] oci_client::ParseError),
 
+    #[cfg(feature = "backup")]
+    #[error("Backup error: {0}")]
+    Backup(String),
+
     #[cfg(feature = "k8s")]
     #[error("K8s error: {0}")]
     KubeError(#[from] kube::Error),
@@ -122,6 +126,8 @@ pub mod yaml;
 
 #[cfg(feature = "s3")] pub mod s3;
 
+#[cfg(feature = "backup")] pub mod backup;
+
 #[cfg(feature = "k8s")] pub mod k8s;
 #[cfg(feature = "k8s")] pub mod k8s_mock;
 
@@ -1,20 +1,46 @@
-use crate::{Error, RhaiRes, rhai_err};
-use futures::StreamExt;
-use object_store::{ObjectStore, path::Path};
-use rhai::{Dynamic, Engine};
-use tokio::{runtime::Handle, task::block_in_place};
+use crate::{Error, Result, RhaiRes, rhai_err};
+use futures::{StreamExt, TryStreamExt, stream};
+use object_store::{ClientOptions, ObjectMeta, ObjectStore, PutPayload, WriteMultipart, path::Path};
+use openssl::sha::Sha256;
+use rhai::{Array, Dynamic, Engine, Map};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path as FsPath,
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    runtime::Handle,
+    task::block_in_place,
+};
 
-fn build_store(
+/// File listing the mirrored objects with their size and checksum
+pub const MANIFEST: &str = ".s3-manifest.json";
+/// Objects transferred at once when no concurrency is given
+const DEFAULT_CONCURRENCY: usize = 8;
+/// Objects fetched per listing request
+const PAGE_SIZE: usize = 1000;
+/// Objects larger than this are uploaded in parts
+const MULTIPART_THRESHOLD: u64 = 8 * 1024 * 1024;
+/// Parts of an object uploaded at once
+const PART_CONCURRENCY: usize = 4;
+/// Bytes read from a file at once when uploading it in parts
+const READ_BUFFER: usize = 256 * 1024;
+
+pub(crate) fn build_store(
     bucket: &str,
     region: &str,
     endpoint: &str,
     access_key: &str,
     secret_key: &str,
+    client: ClientOptions,
 ) -> crate::Result<Box<dyn ObjectStore>> {
     use object_store::aws::AmazonS3Builder;
     let mut builder = AmazonS3Builder::new()
         .with_bucket_name(bucket)
-        .with_region(region);
+        .with_region(region)
+        .with_client_options(client);
     if !access_key.is_empty() {
         builder = builder
             .with_access_key_id(access_key)
@@ -38,7 +64,14 @@ pub fn s3_get_yaml(
 ) -> RhaiRes<Dynamic> {
     block_in_place(|| {
         Handle::current().block_on(async move {
-            let store = build_store(&bucket, &region, &endpoint, &access_key, &secret_key)?;
+            let store = build_store(
+                &bucket,
+                &region,
+                &endpoint,
+                &access_key,
+                &secret_key,
+                ClientOptions::default(),
+            )?;
             let full_key = format!("{}{}", prefix, key);
             let path = Path::from(full_key.as_str());
             let result = store.get(&path).await.map_err(|e| Error::Other(e.to_string()))?;
@@ -63,7 +96,14 @@ pub fn s3_list_keys(
 ) -> RhaiRes<Vec<String>> {
     block_in_place(|| {
         Handle::current().block_on(async move {
-            let store = build_store(&bucket, &region, &endpoint, &access_key, &secret_key)?;
+            let store = build_store(
+                &bucket,
+                &region,
+                &endpoint,
+                &access_key,
+                &secret_key,
+                ClientOptions::default(),
+            )?;
             let prefix_path = Path::from(prefix.as_str());
             let mut list = store.list(Some(&prefix_path));
             let mut keys = vec![];
@@ -77,10 +117,422 @@ pub fn s3_list_keys(
     .map_err(rhai_err)
 }
 
+fn s3_err(e: object_store::Error) -> Error {
+    Error::Other(e.to_string())
+}
+
+/// Size and sha256 of a mirrored object
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct ManifestEntry {
+    size: u64,
+    sha256: String,
+}
+
+/// Objects of a mirrored prefix, by key relative to the prefix
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct Manifest {
+    prefix: String,
+    objects: BTreeMap<String, ManifestEntry>,
+}
+
+/// A bucket of an S3 compatible object store
+#[derive(Clone, Debug)]
+pub struct S3Bucket {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl S3Bucket {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+
+    /// Connect to a bucket, the endpoint and the credentials being optional
+    pub fn open(
+        bucket: &str,
+        region: &str,
+        endpoint: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self> {
+        let store = build_store(
+            bucket,
+            region,
+            endpoint,
+            access_key,
+            secret_key,
+            ClientOptions::default(),
+        )?;
+        Ok(Self::new(Arc::from(store)))
+    }
+
+    /// Write an object
+    pub async fn put(&self, key: &str, content: Vec<u8>) -> Result<()> {
+        let size = content.len() as u64;
+        self.write(&Path::from(key), content.as_slice(), size, |_| Ok(()))
+            .await?;
+        Ok(())
+    }
+
+    /// Write the `size` bytes of `reader` to `location`, in parts for the large objects
+    ///
+    /// Returns the sha256 of the content; it is only stored when `check` accepts that sha256.
+    async fn write(
+        &self,
+        location: &Path,
+        mut reader: impl AsyncRead + Unpin,
+        size: u64,
+        check: impl FnOnce(&str) -> Result<()>,
+    ) -> Result<String> {
+        let mut hasher = Sha256::new();
+        if size <= MULTIPART_THRESHOLD {
+            let mut content = Vec::with_capacity(size as usize);
+            reader.read_to_end(&mut content).await?;
+            hasher.update(&content);
+            let sha256 = hex(&hasher.finish());
+            check(&sha256)?;
+            self.store
+                .put(location, PutPayload::from(content))
+                .await
+                .map_err(s3_err)?;
+            return Ok(sha256);
+        }
+        let mut upload = WriteMultipart::new(self.store.put_multipart(location).await.map_err(s3_err)?);
+        let mut buffer = vec![0; READ_BUFFER];
+        loop {
+            let read = match reader.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => {
+                    upload.abort().await.map_err(s3_err)?;
+                    return Err(e.into());
+                }
+            };
+            hasher.update(&buffer[..read]);
+            upload.wait_for_capacity(PART_CONCURRENCY).await.map_err(s3_err)?;
+            upload.write(&buffer[..read]);
+        }
+        let sha256 = hex(&hasher.finish());
+        if let Err(e) = check(&sha256) {
+            upload.abort().await.map_err(s3_err)?;
+            return Err(e);
+        }
+        upload.finish().await.map_err(s3_err)?;
+        Ok(sha256)
+    }
+
+    /// Delete an object
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        self.store.delete(&Path::from(key)).await.map_err(s3_err)
+    }
+
+    /// List up to `limit` objects of `prefix` with a key after `after`, in key order
+    ///
+    /// Returns the key to list the next page from, none on the last page.
+    pub async fn list_page(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<ObjectMeta>, Option<String>)> {
+        let prefix = Path::from(prefix);
+        let mut list = match after {
+            Some(after) => self.store.list_with_offset(Some(&prefix), &Path::from(after)),
+            None => self.store.list(Some(&prefix)),
+        };
+        let mut page = Vec::new();
+        let mut more = false;
+        while let Some(meta) = list.next().await {
+            if page.len() == limit.max(1) {
+                more = true;
+                break;
+            }
+            page.push(meta.map_err(s3_err)?);
+        }
+        let next = if more {
+            page.last().map(|m| m.location.to_string())
+        } else {
+            None
+        };
+        Ok((page, next))
+    }
+
+    /// List every object of `prefix`, page by page
+    pub async fn list(&self, prefix: &str) -> Result<Vec<ObjectMeta>> {
+        let mut all = Vec::new();
+        let mut after = None;
+        loop {
+            let (page, next) = self.list_page(prefix, after.as_deref(), PAGE_SIZE).await?;
+            all.extend(page);
+            match next {
+                Some(n) => after = Some(n),
+                None => return Ok(all),
+            }
+        }
+    }
+
+    /// Copy the objects of `prefix` into `dir`, along with a manifest of their checksums
+    pub async fn download(&self, prefix: &str, dir: &FsPath, concurrency: usize) -> Result<Map> {
+        let base = Path::from(prefix);
+        let objects = self.list(prefix).await?;
+        let entries: Vec<(String, ManifestEntry)> = stream::iter(objects)
+            .map(|meta| {
+                let base = &base;
+                async move {
+                    let key = relative_key(base, &meta.location)?;
+                    let target = dir.join(&key);
+                    if let Some(parent) = target.parent() {
+                        tokio::fs::create_dir_all(parent).await?;
+                    }
+                    let mut body = self
+                        .store
+                        .get(&meta.location)
+                        .await
+                        .map_err(s3_err)?
+                        .into_stream();
+                    let mut file = tokio::fs::File::create(&target).await?;
+                    let mut hasher = Sha256::new();
+                    let mut size = 0;
+                    while let Some(chunk) = body.next().await {
+                        let chunk = chunk.map_err(s3_err)?;
+                        hasher.update(&chunk);
+                        size += chunk.len();
+                        file.write_all(&chunk).await?;
+                    }
+                    file.flush().await?;
+                    if size != meta.size {
+                        return Err(Error::Other(format!(
+                            "{} is {} bytes long, {size} were read",
+                            meta.location, meta.size
+                        )));
+                    }
+                    let entry = ManifestEntry {
+                        size: size as u64,
+                        sha256: hex(&hasher.finish()),
+                    };
+                    Ok((key, entry))
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .try_collect()
+            .await?;
+        let manifest = Manifest {
+            prefix: prefix.to_string(),
+            objects: entries.into_iter().collect(),
+        };
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(dir.join(MANIFEST), serde_json::to_vec(&manifest)?).await?;
+        Ok(stats(&manifest.objects, 0))
+    }
+
+    /// Copy the files of `dir` below `prefix`, checking them against the manifest of the download
+    ///
+    /// With `delete_extra`, the objects of `prefix` that are not in `dir` are removed.
+    pub async fn upload(
+        &self,
+        dir: &FsPath,
+        prefix: &str,
+        concurrency: usize,
+        delete_extra: bool,
+    ) -> Result<Map> {
+        let manifest: Option<Manifest> = match tokio::fs::read(dir.join(MANIFEST)).await {
+            Ok(content) => Some(serde_json::from_slice(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let files = list_files(dir)?;
+        if let Some(missing) = manifest
+            .iter()
+            .flat_map(|m| m.objects.keys())
+            .find(|k| !files.contains(*k))
+        {
+            return Err(Error::Other(format!(
+                "{missing} is missing from {}",
+                dir.display()
+            )));
+        }
+        let base = Path::from(prefix);
+        let uploaded: Vec<(String, ManifestEntry)> = stream::iter(files.iter())
+            .map(|key| {
+                let (base, manifest) = (&base, &manifest);
+                async move {
+                    let file = tokio::fs::File::open(dir.join(key)).await?;
+                    let size = file.metadata().await?.len();
+                    let expected = manifest.as_ref().and_then(|m| m.objects.get(key));
+                    let location: Path = base.parts().chain(Path::from(key.as_str()).parts()).collect();
+                    let sha256 = self
+                        .write(&location, file, size, |sha256| match expected {
+                            Some(e) if e.size != size || e.sha256 != sha256 => {
+                                Err(Error::Other(format!("checksum mismatch for {key}")))
+                            }
+                            _ => Ok(()),
+                        })
+                        .await?;
+                    let entry = ManifestEntry { size, sha256 };
+                    let head = self.store.head(&location).await.map_err(s3_err)?;
+                    if head.size as u64 != entry.size {
+                        return Err(Error::Other(format!(
+                            "{location} is {} bytes long after upload, {} were sent",
+                            head.size, entry.size
+                        )));
+                    }
+                    Ok((key.clone(), entry))
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .try_collect()
+            .await?;
+        let mut deleted = 0;
+        if delete_extra {
+            for meta in self.list(prefix).await? {
+                if !files.contains(&relative_key(&base, &meta.location)?) {
+                    self.store.delete(&meta.location).await.map_err(s3_err)?;
+                    deleted += 1;
+                }
+            }
+        }
+        let uploaded: BTreeMap<String, ManifestEntry> = uploaded.into_iter().collect();
+        Ok(stats(&uploaded, deleted))
+    }
+}
+
+/// Lowercase hexadecimal form of a digest
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Key of `location` relative to `base`, usable as a relative file path
+fn relative_key(base: &Path, location: &Path) -> Result<String> {
+    let parts: Vec<String> = location
+        .prefix_match(base)
+        .ok_or_else(|| Error::Other(format!("{location} is not below {base}")))?
+        .map(|p| p.as_ref().to_string())
+        .collect();
+    if parts.is_empty() || parts.iter().any(|p| p == ".." || p == ".") {
+        return Err(Error::Other(format!("{location} cannot be mirrored to a file")));
+    }
+    Ok(parts.join("/"))
+}
+
+/// Files of `dir` (but the manifest), relative to `dir`
+fn list_files(dir: &FsPath) -> Result<BTreeSet<String>> {
+    fn walk(dir: &FsPath, rel: &str, files: &mut BTreeSet<String>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let key = if rel.is_empty() {
+                name
+            } else {
+                format!("{rel}/{name}")
+            };
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &key, files)?;
+            } else if key != MANIFEST {
+                files.insert(key);
+            }
+        }
+        Ok(())
+    }
+    let mut files = BTreeSet::new();
+    walk(dir, "", &mut files)?;
+    Ok(files)
+}
+
+fn stats(objects: &BTreeMap<String, ManifestEntry>, deleted: i64) -> Map {
+    let mut ret = Map::new();
+    ret.insert("objects".into(), (objects.len() as i64).into());
+    ret.insert(
+        "bytes".into(),
+        (objects.values().map(|e| e.size).sum::<u64>() as i64).into(),
+    );
+    ret.insert("deleted".into(), deleted.into());
+    ret
+}
+
+fn meta_to_map(meta: ObjectMeta) -> Dynamic {
+    let mut ret = Map::new();
+    ret.insert("key".into(), meta.location.to_string().into());
+    ret.insert("size".into(), (meta.size as i64).into());
+    ret.insert("last_modified".into(), meta.last_modified.to_rfc3339().into());
+    if let Some(etag) = meta.e_tag {
+        ret.insert("etag".into(), etag.into());
+    }
+    ret.into()
+}
+
+fn concurrency(options: &Map) -> usize {
+    options
+        .get("concurrency")
+        .and_then(|v| v.as_int().ok())
+        .map_or(DEFAULT_CONCURRENCY, |v| v.max(1) as usize)
+}
+
+/// Run an async bucket operation from rhai
+fn block<T>(op: impl Future<Output = Result<T>>) -> RhaiRes<T> {
+    block_in_place(|| Handle::current().block_on(op)).map_err(rhai_err)
+}
+
+/// Open a bucket from a map of bucket, region, endpoint, access_key and secret_key
+pub fn s3_bucket(settings: Map) -> RhaiRes<S3Bucket> {
+    let get = |key: &str| {
+        settings
+            .get(key)
+            .filter(|v| !v.is_unit())
+            .map(|v| v.to_string())
+            .unwrap_or_default()
+    };
+    S3Bucket::open(
+        &get("bucket"),
+        &get("region"),
+        &get("endpoint"),
+        &get("access_key"),
+        &get("secret_key"),
+    )
+    .map_err(rhai_err)
+}
+
 pub fn s3_rhai_register(engine: &mut Engine) {
     engine
         .register_fn("s3_get_yaml", s3_get_yaml)
-        .register_fn("s3_list_keys", s3_list_keys);
+        .register_fn("s3_list_keys", s3_list_keys)
+        .register_type_with_name::<S3Bucket>("S3Bucket")
+        .register_fn("s3_bucket", s3_bucket)
+        .register_fn("put", |b: &mut S3Bucket, key: String, content: String| {
+            block(b.put(&key, content.into_bytes()))
+        })
+        .register_fn("delete", |b: &mut S3Bucket, key: String| block(b.delete(&key)))
+        .register_fn("list", |b: &mut S3Bucket, prefix: String| -> RhaiRes<Array> {
+            Ok(block(b.list(&prefix))?.into_iter().map(meta_to_map).collect())
+        })
+        .register_fn(
+            "list_page",
+            |b: &mut S3Bucket, prefix: String, after: Dynamic, limit: i64| -> RhaiRes<Map> {
+                let after = after.into_string().ok();
+                let (page, next) = block(b.list_page(&prefix, after.as_deref(), limit.max(1) as usize))?;
+                let mut ret = Map::new();
+                ret.insert(
+                    "objects".into(),
+                    page.into_iter().map(meta_to_map).collect::<Array>().into(),
+                );
+                ret.insert("next".into(), next.map_or(Dynamic::UNIT, Dynamic::from));
+                Ok(ret)
+            },
+        )
+        .register_fn(
+            "download",
+            |b: &mut S3Bucket, prefix: String, dir: String, options: Map| {
+                block(b.download(&prefix, FsPath::new(&dir), concurrency(&options)))
+            },
+        )
+        .register_fn(
+            "upload",
+            |b: &mut S3Bucket, dir: String, prefix: String, options: Map| {
+                let delete_extra = options
+                    .get("delete_extra")
+                    .and_then(|v| v.as_bool().ok())
+                    .unwrap_or(false);
+                block(b.upload(FsPath::new(&dir), &prefix, concurrency(&options), delete_extra))
+            },
+        );
 }
 
 #[cfg(test)]
@@ -137,4 +589,89 @@ mod tests {
         keys.sort();
         assert_eq!(keys, vec!["prefix/a.yaml", "prefix/b.yaml", "prefix/c.yaml"]);
     }
+
+    fn keys(objects: &[ObjectMeta]) -> Vec<String> {
+        objects.iter().map(|m| m.location.to_string()).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bucket_put_list_pages_and_delete() {
+        let bucket = S3Bucket::new(Arc::new(InMemory::new()));
+        for key in ["data/a", "data/b", "data/c", "data/sub/d", "other/e"] {
+            bucket.put(key, key.as_bytes().to_vec()).await.unwrap();
+        }
+        let (page, next) = bucket.list_page("data", None, 2).await.unwrap();
+        assert_eq!(keys(&page), vec!["data/a", "data/b"]);
+        assert_eq!(next.as_deref(), Some("data/b"));
+        let (page, next) = bucket.list_page("data", next.as_deref(), 2).await.unwrap();
+        assert_eq!(keys(&page), vec!["data/c", "data/sub/d"]);
+        assert_eq!(next, None);
+
+        bucket.delete("data/b").await.unwrap();
+        assert_eq!(
+            keys(&bucket.list("data").await.unwrap()),
+            vec!["data/a", "data/c", "data/sub/d"]
+        );
+        assert_eq!(bucket.list("").await.unwrap().len(), 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bucket_mirror_restores_to_another_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = S3Bucket::new(Arc::new(InMemory::new()));
+        for (key, content) in [
+            ("app/media/logo.png", "png"),
+            ("app/index.html", "<html/>"),
+            ("skip", "-"),
+        ] {
+            source.put(key, content.as_bytes().to_vec()).await.unwrap();
+        }
+        let saved = source.download("app", dir.path(), 2).await.unwrap();
+        assert_eq!(saved["objects"].as_int().unwrap(), 2);
+        assert_eq!(saved["bytes"].as_int().unwrap(), 10);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("media/logo.png")).unwrap(),
+            "png"
+        );
+        assert!(dir.path().join(MANIFEST).is_file());
+
+        let target = S3Bucket::new(Arc::new(InMemory::new()));
+        target.put("restored/stale", b"old".to_vec()).await.unwrap();
+        let restored = target.upload(dir.path(), "restored", 4, true).await.unwrap();
+        assert_eq!(restored["objects"].as_int().unwrap(), 2);
+        assert_eq!(restored["deleted"].as_int().unwrap(), 1);
+        assert_eq!(
+            keys(&target.list("restored").await.unwrap()),
+            vec!["restored/index.html", "restored/media/logo.png"]
+        );
+
+        std::fs::write(dir.path().join("index.html"), "<html>changed</html>").unwrap();
+        let err = target.upload(dir.path(), "restored", 4, false).await.unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch for index.html"));
+        std::fs::remove_file(dir.path().join("index.html")).unwrap();
+        let err = target.upload(dir.path(), "restored", 4, false).await.unwrap_err();
+        assert!(err.to_string().contains("index.html is missing"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bucket_mirror_streams_large_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let content: Vec<u8> = (0..MULTIPART_THRESHOLD + 1000).map(|i| (i % 251) as u8).collect();
+        let source = S3Bucket::new(Arc::new(InMemory::new()));
+        source.put("app/big.bin", content.clone()).await.unwrap();
+        let saved = source.download("app", dir.path(), 2).await.unwrap();
+        assert_eq!(saved["bytes"].as_int().unwrap(), content.len() as i64);
+        assert_eq!(std::fs::read(dir.path().join("big.bin")).unwrap(), content);
+
+        let target = S3Bucket::new(Arc::new(InMemory::new()));
+        target.upload(dir.path(), "restored", 2, false).await.unwrap();
+        let copy = target.store.get(&Path::from("restored/big.bin")).await.unwrap();
+        assert_eq!(copy.bytes().await.unwrap().as_ref(), content.as_slice());
+
+        // A changed large file is not kept
+        std::fs::write(dir.path().join("big.bin"), &content[1..]).unwrap();
+        let err = target.upload(dir.path(), "other", 2, false).await.unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch for big.bin"));
+        assert!(target.list("other").await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,442 @@
+//! Portable export of an instance (`InstanceExport`), restorable on another cluster.
+//!
+//! The archive holds the instance spec, the Secrets and ConfigMaps its options read, the package
+//! reference, the backup to restore and the published services. Shared by the operator (pre-upgrade backups), the agent
+//! `export`/`import` actions and kubectl-vynil, hence only using kube and serde_json.
+use chrono::{DateTime, Utc};
+use k8s_openapi::{
+    api::{
+        batch::v1::{CronJob, Job},
+        core::v1::{ConfigMap, Secret},
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+};
+use kube::{
+    Client,
+    api::{Api, ApiResource, DeleteParams, DynamicObject, ListParams, PostParams},
+    runtime::wait::{await_condition, conditions},
+};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("K8s error: {0}")]
+    KubeError(#[from] kube::Error),
+
+    #[error("K8s wait error: {0}")]
+    KubeWaitError(#[from] kube::runtime::wait::Error),
+
+    #[error("Elapsed wait error: {0}")]
+    Elapsed(#[from] tokio::time::error::Elapsed),
+
+    #[error("SerializationError: {0}")]
+    SerializationError(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    Other(String),
+}
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Label of the backup records holding the name of their instance
+pub const INSTANCE_LABEL: &str = "vynil.solidite.fr/instance";
+
+/// A backup record (`VynilBackup`), typed or not
+pub trait BackupRecord {
+    /// Kind of the backed up instance
+    fn instance_kind(&self) -> &str;
+    /// Start date of the backup
+    fn started_at(&self) -> Option<DateTime<Utc>>;
+    /// Snapshot to restore, None when the backup failed
+    fn restorable_snapshot(&self) -> Option<&str>;
+}
+
+impl BackupRecord for Value {
+    fn instance_kind(&self) -> &str {
+        self["spec"]["instance"]["kind"].as_str().unwrap_or_default()
+    }
+
+    fn started_at(&self) -> Option<DateTime<Utc>> {
+        self["spec"]["startedAt"].as_str().and_then(|d| d.parse().ok())
+    }
+
+    fn restorable_snapshot(&self) -> Option<&str> {
+        if self["spec"]["outcome"] == "Succeeded" {
+            self["spec"]["snapshot"].as_str()
+        } else {
+            None
+        }
+    }
+}
+
+/// A `valueFrom` reference found in the options
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ValueRef {
+    /// Secret or ConfigMap
+    pub kind: &'static str,
+    /// Name of the referenced object
+    pub name: String,
+}
+
+/// The object read by a `valueFrom` value, None for any other value
+pub fn reference(value: &Value) -> Option<ValueRef> {
+    let from = value.as_object().filter(|o| o.len() == 1)?.get("valueFrom")?;
+    let (kind, key_ref) = if let Some(key_ref) = from.get("secretKeyRef") {
+        ("Secret", key_ref)
+    } else {
+        ("ConfigMap", from.get("configMapKeyRef")?)
+    };
+    Some(ValueRef {
+        kind,
+        name: key_ref.get("name")?.as_str()?.to_string(),
+    })
+}
+
+/// Whether a value is read from a Secret or a ConfigMap
+pub fn is_reference(value: &Value) -> bool {
+    reference(value).is_some()
+}
+
+fn collect(value: &Value, refs: &mut Vec<ValueRef>) {
+    if let Some(found) = reference(value) {
+        refs.push(found);
+        return;
+    }
+    match value {
+        Value::Object(map) => map.values().for_each(|v| collect(v, refs)),
+        Value::Array(items) => items.iter().for_each(|v| collect(v, refs)),
+        _ => {}
+    }
+}
+
+/// Objects referenced by the `valueFrom` of an options tree
+pub fn references(options: &Value) -> Vec<ValueRef> {
+    let mut refs = Vec::new();
+    collect(options, &mut refs);
+    refs.sort();
+    refs.dedup();
+    refs
+}
+
+/// Application slug of the `inst` instance of the `pkg` package, prefixing its objects
+pub fn appslug(pkg: &str, inst: &str) -> String {
+    if pkg == inst {
+        inst.to_string()
+    } else {
+        format!("{inst}-{pkg}").chars().take(28).collect()
+    }
+}
+
+/// Completion state of a Job: `Some(true)` when complete, `Some(false)` when failed
+pub fn job_outcome(job: &Job) -> Option<bool> {
+    let conditions = job.status.as_ref()?.conditions.as_ref()?;
+    conditions
+        .iter()
+        .filter(|c| c.status == "True")
+        .find_map(|c| match c.type_.as_str() {
+            "Complete" => Some(true),
+            "Failed" => Some(false),
+            _ => None,
+        })
+}
+
+/// Creation date of a Job
+pub fn job_created_at(job: &Job) -> Option<DateTime<Utc>> {
+    let created = job.metadata.creation_timestamp.as_ref()?;
+    serde_json::to_value(created)
+        .ok()
+        .and_then(|v| serde_json::from_value(v).ok())
+}
+
+/// Newest restorable backup record of a `kind` instance, started at `since` or later
+pub fn latest_backup<'a, R: BackupRecord>(
+    records: &'a [R],
+    kind: &str,
+    since: Option<DateTime<Utc>>,
+) -> Option<&'a R> {
+    records
+        .iter()
+        .filter(|b| {
+            b.instance_kind() == kind
+                && b.restorable_snapshot().is_some()
+                && since.is_none_or(|s| b.started_at().is_some_and(|d| d >= s))
+        })
+        .max_by_key(|b| b.started_at())
+}
+
+/// Backup records of an instance, as plain objects
+pub async fn list_backups(client: Client, namespace: &str, instance: &str) -> Result<Vec<Value>> {
+    let resource = ApiResource {
+        group: "vynil.solidite.fr".to_string(),
+        version: "v1".to_string(),
+        api_version: "vynil.solidite.fr/v1".to_string(),
+        kind: "VynilBackup".to_string(),
+        plural: "vynilbackups".to_string(),
+    };
+    let lp = ListParams::default().labels(&format!("{INSTANCE_LABEL}={instance}"));
+    Api::<DynamicObject>::namespaced_with(client, namespace, &resource)
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .map(|b| serde_json::to_value(b).map_err(Error::SerializationError))
+        .collect()
+}
+
+/// Runs the `<slug>-backups` CronJob of an instance once as the `job` Job, replacing a previous
+/// run. Returns the created Job, None when the backups are not scheduled.
+pub async fn start_backup_job(client: Client, namespace: &str, slug: &str, job: &str) -> Result<Option<Job>> {
+    let cron = Api::<CronJob>::namespaced(client.clone(), namespace)
+        .get_opt(&format!("{slug}-backups"))
+        .await?;
+    let Some(template) = cron.and_then(|c| c.spec).map(|s| s.job_template) else {
+        return Ok(None);
+    };
+    let api: Api<Job> = Api::namespaced(client, namespace);
+    if let Some(previous) = api.get_opt(job).await? {
+        api.delete(job, &DeleteParams::background()).await?;
+        let uid = previous.metadata.uid.unwrap_or_default();
+        tokio::time::timeout(
+            Duration::from_secs(60),
+            await_condition(api.clone(), job, conditions::is_deleted(&uid)),
+        )
+        .await??;
+    }
+    let definition = Job {
+        metadata: ObjectMeta {
+            name: Some(job.to_string()),
+            namespace: Some(namespace.to_string()),
+            labels: template.metadata.and_then(|m| m.labels),
+            annotations: Some(BTreeMap::from([(
+                "cronjob.kubernetes.io/instantiate".to_string(),
+                "manual".to_string(),
+            )])),
+            ..Default::default()
+        },
+        spec: template.spec,
+        ..Default::default()
+    };
+    Ok(Some(api.create(&PostParams::default(), &definition).await?))
+}
+
+/// Backs up an instance now, waiting at most `timeout` for the `<slug>-export` Job, and returns
+/// the record of the new backup
+pub async fn fresh_backup(
+    client: Client,
+    kind: &str,
+    namespace: &str,
+    instance: &str,
+    package: &str,
+    timeout: Duration,
+) -> Result<Value> {
+    let deadline = Instant::now() + timeout;
+    let slug = appslug(package, instance);
+    let name = format!("{slug}-export");
+    let Some(job) = start_backup_job(client.clone(), namespace, &slug, &name).await? else {
+        return Err(Error::Other(format!(
+            "{kind} {namespace}/{instance} has no backup CronJob, are backups enabled?"
+        )));
+    };
+    let since = job_created_at(&job);
+    let api: Api<Job> = Api::namespaced(client.clone(), namespace);
+    loop {
+        let job = api.get(&name).await?;
+        match job_outcome(&job) {
+            Some(true) => break,
+            Some(false) => return Err(Error::Other(format!("backup Job {namespace}/{name} failed"))),
+            None if Instant::now() >= deadline => {
+                return Err(Error::Other(format!(
+                    "backup Job {namespace}/{name} not done after {}s",
+                    timeout.as_secs()
+                )));
+            }
+            None => tokio::time::sleep(Duration::from_secs(5)).await,
+        }
+    }
+    let records = list_backups(client, namespace, instance).await?;
+    latest_backup(&records, kind, since)
+        .cloned()
+        .ok_or_else(|| Error::Other(format!("no backup recorded by the Job {namespace}/{name}")))
+}
+
+/// Builds the export of a `kind` instance, `record` being the VynilBackup to restore
+pub fn archive(kind: &str, instance: &Value, record: &Value, exported_at: &str) -> Value {
+    let mut spec = instance["spec"].clone();
+    if let Some(spec) = spec.as_object_mut() {
+        spec.remove("initFrom");
+    }
+    let status = &instance["status"];
+    // The options keep their valueFrom: the referenced objects are listed, not exported
+    let refs: Vec<Value> = references(&spec["options"])
+        .into_iter()
+        .map(|r| json!({"kind": r.kind, "name": r.name}))
+        .collect();
+    json!({
+        "apiVersion": "vynil.solidite.fr/v1",
+        "kind": "InstanceExport",
+        "exportedAt": exported_at,
+        "source": {
+            "kind": kind,
+            "namespace": instance["metadata"]["namespace"],
+            "name": instance["metadata"]["name"],
+        },
+        "package": {
+            "jukebox": spec["jukebox"],
+            "category": spec["category"],
+            "name": spec["package"],
+            "tag": status["tag"],
+        },
+        "spec": spec,
+        "references": refs,
+        "backup": {
+            "name": record["metadata"]["name"],
+            "secretName": record["spec"]["secretName"],
+            "subPath": record["spec"]["subPath"],
+            "snapshot": record["spec"]["snapshot"],
+            "version": record["spec"]["version"],
+            "startedAt": record["spec"]["startedAt"],
+        },
+        "services": status["services"].as_array().cloned().unwrap_or_default(),
+    })
+}
+
+/// Builds the instance to create from an export, restoring its backup. The namespace and the name
+/// default to the ones of the exported instance.
+pub fn from_archive(
+    archive: &Value,
+    namespace: Option<&str>,
+    name: Option<&str>,
+    jukebox: Option<&str>,
+    secret_name: Option<&str>,
+) -> Result<Value> {
+    if archive["kind"] != "InstanceExport" || !archive["spec"].is_object() {
+        return Err(Error::Other("not an InstanceExport".to_string()));
+    }
+    let source = &archive["source"];
+    let backup = &archive["backup"];
+    let mut spec = archive["spec"].clone();
+    if let Some(jukebox) = jukebox {
+        spec["jukebox"] = jukebox.into();
+    }
+    spec["initFrom"] = json!({
+        "secretName": secret_name.map(Value::from).unwrap_or_else(|| backup["secretName"].clone()),
+        "subPath": backup["subPath"],
+        "snapshot": backup["snapshot"],
+        "version": backup["version"],
+    });
+    let source_ns = source["namespace"].as_str().unwrap_or_default();
+    let source_name = source["name"].as_str().unwrap_or_default();
+    Ok(json!({
+        "apiVersion": "vynil.solidite.fr/v1",
+        "kind": source["kind"],
+        "metadata": {
+            "name": name.unwrap_or(source_name),
+            "namespace": namespace.unwrap_or(source_ns),
+            "annotations": {"vynil.solidite.fr/imported-from": format!("{source_ns}/{source_name}")},
+        },
+        "spec": spec,
+    }))
+}
+
+/// Secrets and ConfigMaps read by the options of an export that are missing from `namespace`,
+/// as `Kind/name`
+pub async fn missing_references(client: Client, namespace: &str, archive: &Value) -> Result<Vec<String>> {
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let configmaps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let mut missing = Vec::new();
+    for r in archive["references"].as_array().into_iter().flatten() {
+        let kind = r["kind"].as_str().unwrap_or_default();
+        let name = r["name"].as_str().unwrap_or_default();
+        let found = if kind == "Secret" {
+            secrets.get_metadata_opt(name).await?.is_some()
+        } else {
+            configmaps.get_metadata_opt(name).await?.is_some()
+        };
+        if !found {
+            missing.push(format!("{kind}/{name}"));
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, started: &str, outcome: &str) -> Value {
+        json!({
+            "apiVersion": "vynil.solidite.fr/v1",
+            "kind": "VynilBackup",
+            "metadata": {"name": name},
+            "spec": {
+                "instance": {"kind": "TenantInstance", "name": "wiki"},
+                "package": "apps/wiki",
+                "version": "1.2.0",
+                "secretName": "backup-settings",
+                "subPath": "prod/wiki",
+                "snapshot": format!("snap-{name}"),
+                "startedAt": started,
+                "outcome": outcome
+            }
+        })
+    }
+
+    #[test]
+    fn export_then_import_restores_the_backup() {
+        let records = vec![
+            record("wiki-1", "2026-10-17T02:00:00Z", "Succeeded"),
+            record("wiki-3", "2026-10-18T03:00:00Z", "Failed"),
+            record("wiki-2", "2026-10-18T02:00:00Z", "Succeeded"),
+        ];
+        let picked = latest_backup(&records, "TenantInstance", None).unwrap();
+        assert_eq!(picked["metadata"]["name"], "wiki-2");
+        let since = "2026-10-18T02:30:00Z".parse().ok();
+        assert!(latest_backup(&records, "TenantInstance", since).is_none());
+
+        let instance = json!({
+            "metadata": {"namespace": "prod", "name": "wiki"},
+            "spec": {
+                "jukebox": "home-alpha", "category": "apps", "package": "wiki",
+                "options": {
+                    "domain": "wiki.example.com",
+                    "smtp": {"password": {"valueFrom": {"secretKeyRef": {"name": "wiki-smtp", "key": "password"}}}}
+                },
+                "initFrom": {"snapshot": "old"}
+            },
+            "status": {
+                "tag": "1.2.0",
+                "effectiveOptions": {"domain": "wiki.example.com", "smtp": {"password": "s3cr3t"}},
+                "services": [{"key": "wiki"}]
+            }
+        });
+        let archive = archive("TenantInstance", &instance, picked, "2026-10-18T04:00:00Z");
+        assert_eq!(archive["package"]["tag"], "1.2.0");
+        assert!(archive["spec"].get("initFrom").is_none());
+        assert_eq!(archive["backup"]["snapshot"], "snap-wiki-2");
+        assert_eq!(archive["services"][0]["key"], "wiki");
+        assert_eq!(
+            archive["references"],
+            json!([{"kind": "Secret", "name": "wiki-smtp"}])
+        );
+
+        let obj = from_archive(&archive, Some("staging"), None, Some("home-beta"), None).unwrap();
+        assert_eq!(obj["kind"], "TenantInstance");
+        assert_eq!(obj["metadata"]["namespace"], "staging");
+        assert_eq!(obj["metadata"]["name"], "wiki");
+        assert_eq!(
+            obj["metadata"]["annotations"]["vynil.solidite.fr/imported-from"],
+            "prod/wiki"
+        );
+        assert_eq!(obj["spec"]["jukebox"], "home-beta");
+        // The resolved secret never leaves the cluster, the reference is restored as is
+        assert_eq!(obj["spec"]["options"], instance["spec"]["options"]);
+        assert_eq!(obj["spec"]["initFrom"]["snapshot"], "snap-wiki-2");
+        assert_eq!(obj["spec"]["initFrom"]["secretName"], "backup-settings");
+        assert_eq!(obj["spec"]["initFrom"]["version"], "1.2.0");
+        assert!(from_archive(&json!({"kind": "Other"}), None, None, None, None).is_err());
+    }
+}
@@ -15,15 +15,16 @@ use anyhow::{Context, Result, bail};
 use k8s_openapi::api::{batch::v1::Job, core::v1::Pod};
 use kube::{
     Client,
-    api::{Api, DynamicObject, ListParams, Patch, PatchParams},
+    api::{Api, DynamicObject, ListParams, Patch, PatchParams, PostParams},
     discovery::ApiResource,
 };
+use vynil_export::{archive, fresh_backup, from_archive, latest_backup, list_backups, missing_references};
 
 use crate::{
     bundle::build_bundle,
     cli::{
-        DiagnosticArgs, InstanceArgs, InstanceKindInfo, InstanceScanArgs, InstanceTarget, JukeboxArgs,
-        JukeboxVerb, TransportArgs, UpgradeArgs,
+        DiagnosticArgs, ExportArgs, ImportArgs, InstanceArgs, InstanceKindInfo, InstanceScanArgs,
+        InstanceTarget, JukeboxArgs, JukeboxVerb, TransportArgs, UpgradeArgs,
     },
     items::resolve_items,
     transport::{TransportMode, get_item, read_sa_token},
@@ -425,6 +426,127 @@ pub struct JukeboxScanArgsRef<'a> {
     pub timeout: u64,
 }
 
+/// `kubectl-vynil vti -n <ns> <inst> export`.
+pub async fn run_export(
+    info: &InstanceKindInfo,
+    namespace: &str,
+    name: &str,
+    args: &ExportArgs,
+) -> Result<()> {
+    if info.kind != "TenantInstance" {
+        bail!("EXP-ERR-01: only TenantInstances can be exported");
+    }
+    let client = Client::try_default()
+        .await
+        .context("EXP-ERR-02: failed to create kube client")?;
+    let inst_api: Api<DynamicObject> = Api::namespaced_with(
+        client.clone(),
+        namespace,
+        &vynil_api_resource(info.kind, info.plural),
+    );
+    let instance = serde_json::to_value(
+        inst_api
+            .get(name)
+            .await
+            .context("EXP-ERR-03: failed to read instance")?,
+    )?;
+    let record = if args.no_backup {
+        let records = list_backups(client, namespace, name)
+            .await
+            .context("EXP-ERR-05: failed to list the backups")?;
+        latest_backup(&records, info.kind, None)
+            .cloned()
+            .context("EXP-ERR-05: no restorable backup")?
+    } else {
+        let package = instance["spec"]["package"]
+            .as_str()
+            .context("EXP-ERR-03: instance spec has no .spec.package")?;
+        eprintln!("waiting for the backup of {}/{} ...", namespace, name);
+        fresh_backup(
+            client,
+            info.kind,
+            namespace,
+            name,
+            package,
+            Duration::from_secs(args.timeout),
+        )
+        .await
+        .context("EXP-ERR-04: backup failed")?
+    };
+
+    let export = archive(info.kind, &instance, &record, &chrono::Utc::now().to_rfc3339());
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| format!("{}-export.yaml", name));
+    std::fs::write(&output, serde_yaml::to_string(&export)?)
+        .with_context(|| format!("EXP-ERR-06: failed to write {}", output))?;
+    println!(
+        "exported {}/{} with backup {} to {}",
+        namespace,
+        name,
+        record["metadata"]["name"].as_str().unwrap_or_default(),
+        output
+    );
+    Ok(())
+}
+
+/// `kubectl-vynil vti -n <ns> <inst> import -f <file>`.
+pub async fn run_import(
+    info: &InstanceKindInfo,
+    namespace: &str,
+    name: &str,
+    args: &ImportArgs,
+) -> Result<()> {
+    let content = std::fs::read_to_string(&args.file)
+        .with_context(|| format!("IMP-ERR-01: failed to read {}", args.file))?;
+    let export: serde_json::Value = serde_yaml::from_str(&content).context("IMP-ERR-02: invalid export")?;
+    if export["source"]["kind"] != info.kind {
+        bail!("IMP-ERR-03: the export is not a {}", info.kind);
+    }
+    let obj = from_archive(
+        &export,
+        Some(namespace),
+        Some(name),
+        args.jukebox.as_deref(),
+        args.secret_name.as_deref(),
+    )
+    .context("IMP-ERR-02: invalid export")?;
+    let client = Client::try_default()
+        .await
+        .context("IMP-ERR-04: failed to create kube client")?;
+    let missing = missing_references(client.clone(), namespace, &export)
+        .await
+        .context("IMP-ERR-06: failed to check the objects read by the options")?;
+    if !missing.is_empty() {
+        bail!(
+            "IMP-ERR-07: the options read objects missing from {}, create them first: {}",
+            namespace,
+            missing.join(", ")
+        );
+    }
+    let inst_api: Api<DynamicObject> =
+        Api::namespaced_with(client, namespace, &vynil_api_resource(info.kind, info.plural));
+    inst_api
+        .create(&PostParams::default(), &serde_json::from_value(obj)?)
+        .await
+        .context("IMP-ERR-05: failed to create the instance")?;
+    println!(
+        "created {} {}/{}, restoring snapshot {}",
+        info.kind,
+        namespace,
+        name,
+        export["backup"]["snapshot"].as_str().unwrap_or_default()
+    );
+    for service in export["services"].as_array().into_iter().flatten() {
+        println!(
+            "published service to recreate consumers for: {}",
+            serde_json::to_string(service)?
+        );
+    }
+    Ok(())
+}
+
 /// Top-level dispatch for `kubectl-vynil jukebox …`.
 pub async fn run_jukebox(args: &JukeboxArgs) -> Result<()> {
     match &args.verb {
@@ -458,6 +580,8 @@ pub async fn run_instance(info: &InstanceKindInfo, args: &InstanceArgs) -> Resul
         Upgrade(a) => run_upgrade(info, &namespace, &args.name, a).await,
         Scan(a) => run_instance_scan(info, &namespace, &args.name, a).await,
         Diagnostic(a) => run_diagnostic(info, &namespace, &args.name, a).await,
+        Export(a) => run_export(info, &namespace, &args.name, a).await,
+        Import(a) => run_import(info, &namespace, &args.name, a).await,
         _ => unreachable!("item verbs handled above"),
     }
 }
@@ -288,6 +288,30 @@ fn build_summary(
         }
     }
 
+    // The children lists live in the inventory ConfigMap (see `instance/children`)
+    if let Some((_, state_result)) = items.iter().find(|(name, _)| *name == "state")
+        && state_result.status == 200
+        && let Ok(state_yaml) = String::from_utf8(state_result.body.clone())
+        && let Ok(doc) = serde_yaml::from_str::<serde_yaml::Value>(&state_yaml)
+        && let Some(status) = doc.get("status")
+        && let Some(inventory) = status.get("inventory")
+        && let Some(counts) = inventory.get("counts").and_then(|c| c.as_mapping())
+    {
+        md.push_str("\n## Instance Inventory\n\n");
+        if let Some(name) = inventory.get("name").and_then(|n| n.as_str()) {
+            md.push_str(&format!("- **ConfigMap**: `{}`\n\n", name));
+        }
+        md.push_str("| List | Children |\n");
+        md.push_str("|------|----------|\n");
+        for (list, count) in counts {
+            md.push_str(&format!(
+                "| {} | {} |\n",
+                list.as_str().unwrap_or("—"),
+                count.as_u64().unwrap_or_default()
+            ));
+        }
+    }
+
     md
 }
 
@@ -108,6 +108,14 @@ pub enum InstanceVerb {
     Diagnostic(DiagnosticArgs),
     /// Print the children diagnostic item to stdout.
     Children(ItemArgs),
+    /// Print the instances depending on this one (they block its deletion).
+    Dependents(ItemArgs),
+    /// Print the backups recorded for this instance, newest first.
+    Backups(ItemArgs),
+    /// Back up the instance and write a portable export of it (TenantInstance only).
+    Export(ExportArgs),
+    /// Create this instance from an export, restoring its backup (TenantInstance only).
+    Import(ImportArgs),
     /// Print the agent log diagnostic item to stdout.
     Agentlog(ItemArgs),
     /// Print the child logs diagnostic item to stdout.
@@ -121,6 +129,8 @@ impl InstanceVerb {
     pub fn as_item(&self) -> Option<(&'static str, &TransportArgs)> {
         match self {
             InstanceVerb::Children(a) => Some(("children", &a.transport)),
+            InstanceVerb::Dependents(a) => Some(("dependents", &a.transport)),
+            InstanceVerb::Backups(a) => Some(("backups", &a.transport)),
             InstanceVerb::Agentlog(a) => Some(("agentlog", &a.transport)),
             InstanceVerb::Childlogs(a) => Some(("childlogs", &a.transport)),
             InstanceVerb::Operatorlog(a) => Some(("operatorlog", &a.transport)),
@@ -152,6 +162,32 @@ pub struct InstanceScanArgs {
     pub timeout: u64,
 }
 
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Output file path. Defaults to `<name>-export.yaml`.
+    #[arg(short = 'o', long)]
+    pub output: Option<String>,
+    /// Reference the latest recorded backup instead of running a fresh one.
+    #[arg(long)]
+    pub no_backup: bool,
+    /// Maximum seconds to wait for the backup job to finish.
+    #[arg(long, default_value_t = 3600)]
+    pub timeout: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Export file written by `export`.
+    #[arg(short = 'f', long)]
+    pub file: String,
+    /// JukeBox to install the package from, when it differs on this cluster.
+    #[arg(long)]
+    pub jukebox: Option<String>,
+    /// Secret holding the backup repository settings, when it differs on this cluster.
+    #[arg(long)]
+    pub secret_name: Option<String>,
+}
+
 /// Transport flags shared by the bundle and single-item diagnostic verbs.
 #[derive(Args, Debug, Clone)]
 pub struct TransportArgs {
@@ -270,6 +306,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn dependents_verb_maps_to_item_name() {
+        let cli = Cli::try_parse_from(["kubectl-vynil", "vsvc", "-n", "ns", "pg", "dependents"]).unwrap();
+        match cli.command {
+            Commands::Vsvc(a) => assert_eq!(a.verb.as_item().unwrap().0, "dependents"),
+            _ => panic!("expected vsvc"),
+        }
+    }
+
+    #[test]
+    fn parses_instance_export_and_import() {
+        let cli = Cli::try_parse_from([
+            "kubectl-vynil",
+            "vti",
+            "-n",
+            "ns",
+            "wiki",
+            "export",
+            "--no-backup",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Vti(a) => match a.verb {
+                InstanceVerb::Export(e) => {
+                    assert!(e.no_backup);
+                    assert_eq!(e.timeout, 3600);
+                    assert!(e.output.is_none());
+                }
+                _ => panic!("expected export"),
+            },
+            _ => panic!("expected vti"),
+        }
+        let cli = Cli::try_parse_from([
+            "kubectl-vynil",
+            "vti",
+            "-n",
+            "ns",
+            "wiki",
+            "import",
+            "-f",
+            "wiki.yaml",
+            "--jukebox",
+            "home",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Vti(a) => match a.verb {
+                InstanceVerb::Import(i) => {
+                    assert_eq!(i.file, "wiki.yaml");
+                    assert_eq!(i.jukebox.as_deref(), Some("home"));
+                    assert!(i.secret_name.is_none());
+                }
+                _ => panic!("expected import"),
+            },
+            _ => panic!("expected vti"),
+        }
+        assert!(Cli::try_parse_from(["kubectl-vynil", "vti", "-n", "ns", "wiki", "import"]).is_err());
+    }
+
     #[test]
     fn cluster_and_state_verbs_are_no_longer_exposed() {
         // These remain bundle items but must not be standalone instance verbs anymore.
@@ -281,7 +376,7 @@ mod tests {
             );
         }
         // The interactive log/children verbs stay.
-        for kept in ["children", "agentlog", "childlogs", "operatorlog"] {
+        for kept in ["children", "dependents", "backups", "agentlog", "childlogs", "operatorlog"] {
             assert!(
                 Cli::try_parse_from(["kubectl-vynil", "vti", "-n", "ns", "x", kept]).is_ok(),
                 "verb {} should still parse",
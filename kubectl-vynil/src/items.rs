@@ -7,6 +7,8 @@ pub const ALL_ITEMS: &[&str] = &[
     "packages",
     "state",
     "children",
+    "dependents",
+    "backups",
     "agentlog",
     "childlogs",
     "operatorlog",
@@ -36,6 +38,8 @@ pub fn item_path(item: &str) -> &'static str {
         "packages" => "packages/packages",
         "state" => "instance/state",
         "children" => "instance/children",
+        "dependents" => "instance/dependents",
+        "backups" => "instance/backups",
         "agentlog" => "logs/agentlog",
         "childlogs" => "logs/childlogs",
         "operatorlog" => "logs/operatorlog",
@@ -96,6 +100,8 @@ mod tests {
         assert_eq!(item_path("packages"), "packages/packages");
         assert_eq!(item_path("state"), "instance/state");
         assert_eq!(item_path("children"), "instance/children");
+        assert_eq!(item_path("dependents"), "instance/dependents");
+        assert_eq!(item_path("backups"), "instance/backups");
         assert_eq!(item_path("agentlog"), "logs/agentlog");
         assert_eq!(item_path("childlogs"), "logs/childlogs");
         assert_eq!(item_path("operatorlog"), "logs/operatorlog");
@@ -0,0 +1,225 @@
+use crate::{Error, Result, ServiceInstance, TenantInstance, instance_common::InstanceKind};
+use common::{dependents, vynilbackup::VynilBackup, vynilpackage::VynilPackage};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{Client, Resource, ResourceExt, api::Api};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+/// Schema keyword flagging the options a backup can only be restored with
+pub const RESTORE_KEYWORD: &str = "x-restore";
+/// Annotation (or label) of an instance listing the other tenants allowed to clone it
+pub const ALLOW_CLONE_KEY: &str = "vynil.solidite.fr/allow-clone";
+
+/// Options of the clone: its own options, completed with the options of the source flagged
+/// `x-restore` in the package schema
+fn restore_options(
+    schema: Option<&BTreeMap<String, Value>>,
+    source: &Map<String, Value>,
+    target: &Map<String, Value>,
+) -> Map<String, Value> {
+    let mut options = target.clone();
+    for (key, def) in schema.into_iter().flatten() {
+        if def[RESTORE_KEYWORD].as_bool() == Some(true)
+            && !options.contains_key(key)
+            && let Some(value) = source.get(key)
+        {
+            options.insert(key.clone(), value.clone());
+        }
+    }
+    options
+}
+
+/// Tells if the annotations or the labels of the source instance allow `tenant` to clone it
+fn allows_clone(meta: &ObjectMeta, tenant: &str) -> bool {
+    [meta.annotations.as_ref(), meta.labels.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m.get(ALLOW_CLONE_KEY))
+        .any(|tenants| tenants.split(',').any(|t| t.trim() == tenant))
+}
+
+/// Checks that an instance of `namespace` may clone the `kind` instance `source_ns/source`.
+///
+/// The source must live in a namespace of the same tenant, or allow the tenant with the
+/// `vynil.solidite.fr/allow-clone` annotation or label.
+pub async fn authorize(
+    client: Client,
+    namespace: &str,
+    kind: &str,
+    source_ns: &str,
+    source: &str,
+) -> Result<()> {
+    let namespaces = dependents::tenant_namespaces(client.clone(), namespace).await?;
+    if namespaces.iter().any(|ns| ns == source_ns) {
+        return Ok(());
+    }
+    let tenant = dependents::tenant_name(client.clone(), namespace).await?;
+    let meta = if kind == TenantInstance::kind(&()) {
+        Api::<TenantInstance>::namespaced(client, source_ns)
+            .get_metadata_opt(source)
+            .await
+            .map(|m| m.map(|m| m.metadata))
+    } else {
+        Api::<ServiceInstance>::namespaced(client, source_ns)
+            .get_metadata_opt(source)
+            .await
+            .map(|m| m.map(|m| m.metadata))
+    }
+    .map_err(Error::KubeError)?;
+    if meta.is_some_and(|m| allows_clone(&m, &tenant)) {
+        Ok(())
+    } else {
+        Err(Error::InvalidInstanceRef(format!(
+            "{kind} {source_ns}/{source}: {source_ns} is not a namespace of the tenant {tenant} and the instance does not allow it with {ALLOW_CLONE_KEY}"
+        )))
+    }
+}
+
+/// Options of the source instance, None when it no longer exists
+async fn source_options(
+    client: Client,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+) -> Result<Option<Map<String, Value>>> {
+    let options = if kind == TenantInstance::kind(&()) {
+        Api::<TenantInstance>::namespaced(client, namespace)
+            .get_opt(name)
+            .await
+            .map_err(Error::KubeError)?
+            .and_then(|i| i.spec.options)
+    } else {
+        Api::<ServiceInstance>::namespaced(client, namespace)
+            .get_opt(name)
+            .await
+            .map_err(Error::KubeError)?
+            .and_then(|i| i.spec.options)
+    };
+    Ok(options)
+}
+
+/// Resolves `initFrom.instanceRef` into the repository, the snapshot and the version of a backup of
+/// the source instance, and copies the options needed to restore it.
+///
+/// The version is pinned to the one of the backup when it is still in the catalog. Returns whether
+/// the spec was updated.
+pub async fn resolve<T: InstanceKind>(
+    inst: &T,
+    pck: &VynilPackage,
+    cached_packages: &[VynilPackage],
+    client: Client,
+) -> Result<bool> {
+    // Already installed: nothing to clone
+    if !inst.current_tag().is_empty() {
+        return Ok(false);
+    }
+    let Some(source) = inst.init_from_instance() else {
+        return Ok(false);
+    };
+    let kind = source.kind.clone().unwrap_or_else(|| T::kind(&()).to_string());
+    let namespace = source
+        .namespace
+        .clone()
+        .unwrap_or_else(|| inst.namespace().unwrap_or_default());
+    let name = format!("{kind} {namespace}/{}", source.name);
+    if kind != TenantInstance::kind(&()) && kind != ServiceInstance::kind(&()) {
+        return Err(Error::InvalidInstanceRef(format!("{name}: unsupported kind")));
+    }
+    authorize(
+        client.clone(),
+        &inst.namespace().unwrap_or_default(),
+        &kind,
+        &namespace,
+        &source.name,
+    )
+    .await?;
+    let records = VynilBackup::list_for(client.clone(), &namespace, &source.name).await?;
+    let record = source.pick(&kind, &records).ok_or_else(|| {
+        Error::InvalidInstanceRef(format!(
+            "{name}: no restorable backup {}",
+            source.backup.as_deref().unwrap_or("latest")
+        ))
+    })?;
+    let package = format!("{}/{}", inst.spec_category(), inst.spec_package());
+    if record.spec.package != package {
+        return Err(Error::InvalidInstanceRef(format!(
+            "{name}: {} is a backup of {}, not {package}",
+            record.name_any(),
+            record.spec.package
+        )));
+    }
+
+    let in_catalog = cached_packages.iter().any(|p| {
+        p.metadata.name == inst.spec_package()
+            && p.metadata.category == inst.spec_category()
+            && T::accepts_package_type(&p.metadata.usage)
+            && p.tag == record.spec.version
+    });
+    let version = inst
+        .init_from_version()
+        .map(str::to_string)
+        .or_else(|| in_catalog.then(|| record.spec.version.clone()));
+    let mut init_from = json!({
+        "secretName": record.spec.secret_name,
+        "subPath": record.spec.sub_path,
+        "snapshot": record.restorable_snapshot(),
+        "instanceRef": {
+            "namespace": namespace,
+            "kind": kind,
+            "name": source.name,
+            "backup": record.name_any(),
+        },
+    });
+    if let Some(version) = version {
+        init_from["version"] = version.into();
+    }
+
+    let source_options = source_options(client, &kind, &namespace, &source.name)
+        .await?
+        .unwrap_or_default();
+    let options = restore_options(pck.options.as_ref(), &source_options, &inst.spec_options());
+    inst.clone()
+        .set_init_from_instance(init_from, options, name)
+        .await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clone_is_allowed_by_the_annotations_or_the_labels() {
+        let meta: ObjectMeta = serde_json::from_value(json!({
+            "annotations": {ALLOW_CLONE_KEY: "staging, qa"},
+            "labels": {ALLOW_CLONE_KEY: "preprod"}
+        }))
+        .unwrap();
+        assert!(allows_clone(&meta, "qa"));
+        assert!(allows_clone(&meta, "preprod"));
+        assert!(!allows_clone(&meta, "other"));
+        assert!(!allows_clone(&ObjectMeta::default(), "qa"));
+    }
+
+    #[test]
+    fn clone_copies_the_restore_options() {
+        let schema: BTreeMap<String, Value> = serde_json::from_value(json!({
+            "db_name": {"type": "string", "x-restore": true},
+            "storage": {"type": "object", "x-restore": true},
+            "domain": {"type": "string"}
+        }))
+        .unwrap();
+        let source =
+            json!({"db_name": "wiki_prod", "storage": {"size": "10Gi"}, "domain": "wiki.example.com"});
+        let target = json!({"storage": {"size": "1Gi"}, "domain": "wiki.staging.example.com"});
+        let options = restore_options(
+            Some(&schema),
+            source.as_object().unwrap(),
+            target.as_object().unwrap(),
+        );
+        assert_eq!(
+            Value::Object(options),
+            json!({"db_name": "wiki_prod", "storage": {"size": "1Gi"}, "domain": "wiki.staging.example.com"})
+        );
+    }
+}
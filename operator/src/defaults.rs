@@ -0,0 +1,45 @@
+use crate::{Error, Result, instance_common::InstanceKind};
+use common::vynildefaults::VynilDefaults;
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{
+    Client, ResourceExt,
+    api::{Api, ListParams},
+};
+use serde_json::{Map, Value};
+
+/// Options of a package merged over the VynilDefaults selecting the namespace
+///
+/// Returns `None` when no defaults change the options.
+pub async fn effective_options(
+    client: Client,
+    namespace: &str,
+    category: &str,
+    package: &str,
+    options: &Map<String, Value>,
+) -> Result<Option<Map<String, Value>>> {
+    let defaults = Api::<VynilDefaults>::all(client.clone())
+        .list(&ListParams::default())
+        .await
+        .map_err(Error::KubeError)?
+        .items;
+    if defaults.is_empty() {
+        return Ok(None);
+    }
+    let ns = Api::<Namespace>::all(client)
+        .get(namespace)
+        .await
+        .map_err(Error::KubeError)?;
+    common::vynildefaults::effective_options(&defaults, ns.labels(), category, package, options)
+}
+
+/// Effective options of an instance
+pub async fn for_instance<T: InstanceKind>(inst: &T, client: Client) -> Result<Option<Map<String, Value>>> {
+    effective_options(
+        client,
+        &inst.namespace().unwrap_or_default(),
+        inst.spec_category(),
+        inst.spec_package(),
+        &inst.spec_options(),
+    )
+    .await
+}
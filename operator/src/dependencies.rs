@@ -0,0 +1,558 @@
+use crate::{
+    Error, JukeBox, Result, SystemInstance, TenantInstance,
+    instance_common::InstanceKind,
+    manager::{Context, JukeCacheItem},
+};
+use common::{
+    dependents::{InstalledInstance, list_installed},
+    instancesystem::SystemInstanceSpec,
+    instancetenant::TenantInstanceSpec,
+    vynilpackage::{VynilPackageRequirement, VynilPackageType},
+};
+use kube::{
+    Client, ResourceExt,
+    api::{Api, DeleteParams, ListParams, Patch, PatchParams, PostParams},
+    runtime::controller::Action,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+use tokio::time::Duration;
+
+/// Label set on auto-installed dependencies, holding the name of the instance that required it
+pub const DEPENDENCY_OF_LABEL: &str = "vynil.solidite.fr/dependency-of";
+/// Label set on auto-installed dependencies, holding the namespace of the instance that required it
+pub const DEPENDENCY_OF_NS_LABEL: &str = "vynil.solidite.fr/dependency-of-namespace";
+
+/// A package requirement that can be satisfied by installing another vynil package
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependency {
+    pub usage: VynilPackageType,
+    pub category: String,
+    pub name: String,
+}
+
+impl Dependency {
+    pub fn kind(&self) -> &'static str {
+        match self.usage {
+            VynilPackageType::Tenant => "TenantInstance",
+            VynilPackageType::Service => "ServiceInstance",
+            VynilPackageType::System | VynilPackageType::Bundle => "SystemInstance",
+        }
+    }
+}
+
+/// Extracts the package requirements of `reqs` that are meaningful for an instance of type `usage`.
+/// SystemPackage always applies, TenantPackage only for tenant instances.
+pub fn package_dependencies(reqs: &[VynilPackageRequirement], usage: &VynilPackageType) -> Vec<Dependency> {
+    reqs.iter()
+        .filter_map(|req| match req {
+            VynilPackageRequirement::SystemPackage { category, name } => Some(Dependency {
+                usage: VynilPackageType::System,
+                category: category.clone(),
+                name: name.clone(),
+            }),
+            VynilPackageRequirement::TenantPackage { category, name } if *usage == VynilPackageType::Tenant => {
+                Some(Dependency {
+                    usage: VynilPackageType::Tenant,
+                    category: category.clone(),
+                    name: name.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds the jukebox providing a dependency, preferring the jukebox of the dependent instance
+pub fn find_dependency_jukebox(
+    packages: &BTreeMap<String, JukeCacheItem>,
+    preferred: &str,
+    dep: &Dependency,
+) -> Option<String> {
+    let provides = |item: &JukeCacheItem| {
+        item.packages.iter().any(|p| {
+            p.metadata.category == dep.category && p.metadata.name == dep.name && p.metadata.usage == dep.usage
+        })
+    };
+    if packages.get(preferred).is_some_and(provides) {
+        return Some(preferred.to_string());
+    }
+    packages
+        .iter()
+        .find(|(_, item)| provides(item))
+        .map(|(name, _)| name.clone())
+}
+
+/// Returns the requirements of the package an instance is using, from the packages cache
+pub fn installed_requirements(
+    packages: &BTreeMap<String, JukeCacheItem>,
+    inst: &InstalledInstance,
+) -> Vec<VynilPackageRequirement> {
+    packages
+        .get(&inst.jukebox)
+        .and_then(|item| {
+            item.packages.iter().find(|p| {
+                p.metadata.category == inst.category
+                    && p.metadata.name == inst.package
+                    && p.metadata.usage == inst.usage
+            })
+        })
+        .map(|p| p.requirements.clone())
+        .unwrap_or_default()
+}
+
+/// Tells if auto-installation of dependencies is enabled for an instance.
+/// The instance setting wins over the one of its jukebox; disabled by default.
+pub async fn auto_install_enabled<T: InstanceKind>(inst: &T, client: Client) -> Result<bool> {
+    if let Some(enabled) = inst.spec_auto_install_dependencies() {
+        return Ok(enabled);
+    }
+    let api: Api<JukeBox> = Api::all(client);
+    Ok(api
+        .get_opt(inst.spec_jukebox())
+        .await
+        .map_err(Error::KubeError)?
+        .and_then(|jb| jb.spec.auto_install_dependencies)
+        .unwrap_or(false))
+}
+
+/// Label selector of the dependencies auto-installed for the instance `namespace`/`name`
+fn dependency_selector(namespace: &str, name: &str) -> String {
+    format!("{DEPENDENCY_OF_LABEL}={name},{DEPENDENCY_OF_NS_LABEL}={namespace}")
+}
+
+fn requester_labels(namespace: &str, name: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (DEPENDENCY_OF_LABEL.to_string(), name.to_string()),
+        (DEPENDENCY_OF_NS_LABEL.to_string(), namespace.to_string()),
+    ])
+}
+
+fn dependency_labels<T: InstanceKind>(inst: &T) -> BTreeMap<String, String> {
+    requester_labels(&inst.namespace().unwrap_or_default(), &inst.name_any())
+}
+
+/// Finds an instance of `installed` still requiring `dep`, limited to the `scope` namespaces when set
+fn remaining_requester<'a, F>(
+    installed: &'a [InstalledInstance],
+    scope: Option<&[String]>,
+    dep: &Dependency,
+    requirements_of: F,
+) -> Option<&'a InstalledInstance>
+where
+    F: Fn(&InstalledInstance) -> Vec<VynilPackageRequirement>,
+{
+    installed.iter().find(|i| {
+        scope.is_none_or(|nss| nss.contains(&i.namespace))
+            && package_dependencies(&requirements_of(i), &i.usage).contains(dep)
+    })
+}
+
+/// Creates the missing package dependencies of an instance and waits for them to be ready.
+///
+/// Returns `None` when every dependency is installed and ready, or `Some(Action)` (a requeue)
+/// after recording which dependency is awaited in the resource status.
+pub async fn ensure_dependencies<T: InstanceKind>(
+    inst: &T,
+    reqs: &[VynilPackageRequirement],
+    ctx: &Context,
+) -> Result<Option<Action>> {
+    let client = ctx.client.clone();
+    let my_ns = ctx.client.default_namespace().to_string();
+    let ns = inst.namespace().unwrap_or_default();
+    for dep in package_dependencies(reqs, &T::package_type()) {
+        let (found, ready, target_ns) = match dep.usage {
+            VynilPackageType::System => {
+                let api: Api<SystemInstance> = Api::all(client.clone());
+                let lst: Vec<SystemInstance> = api
+                    .list(&ListParams::default())
+                    .await
+                    .map_err(Error::KubeError)?
+                    .items
+                    .into_iter()
+                    .filter(|i| i.spec.category == dep.category && i.spec.package == dep.name)
+                    .collect();
+                (!lst.is_empty(), lst.iter().any(|i| i.is_ready()), my_ns.clone())
+            }
+            _ => {
+                let allowed = inst.tenant_namespaces().await?;
+                let api: Api<TenantInstance> = Api::all(client.clone());
+                let lst: Vec<TenantInstance> = api
+                    .list(&ListParams::default())
+                    .await
+                    .map_err(Error::KubeError)?
+                    .items
+                    .into_iter()
+                    .filter(|i| {
+                        i.spec.category == dep.category
+                            && i.spec.package == dep.name
+                            && allowed.contains(&i.namespace().unwrap_or_default())
+                    })
+                    .collect();
+                (!lst.is_empty(), lst.iter().any(|i| i.is_ready()), ns.clone())
+            }
+        };
+        if ready {
+            continue;
+        }
+        if !found {
+            let jukebox = {
+                let packages = ctx.packages.read().await;
+                find_dependency_jukebox(&packages, inst.spec_jukebox(), &dep)
+            };
+            let Some(jukebox) = jukebox else {
+                inst.clone()
+                    .set_missing_requirement(format!(
+                        "Cannot auto-install {}/{}: no jukebox provides it",
+                        dep.category, dep.name
+                    ))
+                    .await?;
+                return Ok(Some(Action::requeue(Duration::from_secs(15 * 60))));
+            };
+            tracing::info!(
+                "Auto-installing {} {}/{} from {jukebox} for {}/{}",
+                dep.kind(),
+                target_ns,
+                dep.name,
+                ns,
+                inst.name_any()
+            );
+            let created = match dep.usage {
+                VynilPackageType::System => {
+                    let mut obj = SystemInstance::new(&dep.name, SystemInstanceSpec {
+                        jukebox,
+                        category: dep.category.clone(),
+                        package: dep.name.clone(),
+                        options: None,
+                        auto_install_dependencies: Some(true),
+                        self_heal: None,
+                    });
+                    obj.metadata.namespace = Some(target_ns.clone());
+                    obj.metadata.labels = Some(dependency_labels(inst));
+                    let api: Api<SystemInstance> = Api::namespaced(client.clone(), &target_ns);
+                    api.create(&PostParams::default(), &obj).await.map(|_| ())
+                }
+                _ => {
+                    let mut obj = TenantInstance::new(&dep.name, TenantInstanceSpec {
+                        jukebox,
+                        category: dep.category.clone(),
+                        package: dep.name.clone(),
+                        init_from: None,
+                        backup: None,
+                        options: None,
+                        auto_install_dependencies: Some(true),
+                        self_heal: None,
+                    });
+                    obj.metadata.namespace = Some(target_ns.clone());
+                    obj.metadata.labels = Some(dependency_labels(inst));
+                    let api: Api<TenantInstance> = Api::namespaced(client.clone(), &target_ns);
+                    api.create(&PostParams::default(), &obj).await.map(|_| ())
+                }
+            };
+            match created {
+                Ok(()) => {}
+                Err(kube::Error::Api(e)) if e.code == 409 => {
+                    inst.clone()
+                        .set_missing_requirement(format!(
+                            "Cannot auto-install {}/{}: {} {}/{} already exists with another package",
+                            dep.category,
+                            dep.name,
+                            dep.kind(),
+                            target_ns,
+                            dep.name
+                        ))
+                        .await?;
+                    return Ok(Some(Action::requeue(Duration::from_secs(15 * 60))));
+                }
+                Err(e) => return Err(Error::KubeError(e)),
+            }
+        }
+        inst.clone()
+            .set_missing_requirement(format!(
+                "Waiting for dependency {}/{} to be ready",
+                dep.category, dep.name
+            ))
+            .await?;
+        return Ok(Some(Action::requeue(Duration::from_secs(60))));
+    }
+    Ok(None)
+}
+
+/// Deletes the auto-installed dependencies of an instance being deleted, once no other
+/// instance requires them anymore. A dependency still required is handed over to one of its
+/// remaining requesters (through its labels), so it is collected when that one goes away.
+pub async fn collect_dependencies<T: InstanceKind>(
+    inst: &T,
+    reqs: &[VynilPackageRequirement],
+    ctx: &Context,
+) -> Result<()> {
+    let deps = package_dependencies(reqs, &T::package_type());
+    if deps.is_empty() {
+        return Ok(());
+    }
+    let client = ctx.client.clone();
+    let ns = inst.namespace().unwrap_or_default();
+    let installed: Vec<InstalledInstance> = list_installed(client.clone())
+        .await?
+        .into_iter()
+        .filter(|i| !(i.deleting || (i.kind == T::kind(&()) && i.namespace == ns && i.name == inst.name_any())))
+        .collect();
+    // Only the dependencies installed for this instance, not the ones of other instances
+    let lp = ListParams::default().labels(&dependency_selector(&ns, &inst.name_any()));
+    for dep in deps {
+        // (namespace, name, namespaces where dependents can live)
+        let candidates: Vec<(String, String, Option<Vec<String>>)> = match dep.usage {
+            VynilPackageType::System => {
+                let api: Api<SystemInstance> = Api::all(client.clone());
+                api.list(&lp)
+                    .await
+                    .map_err(Error::KubeError)?
+                    .items
+                    .into_iter()
+                    .filter(|i| i.spec.category == dep.category && i.spec.package == dep.name)
+                    .map(|i| (i.namespace().unwrap_or_default(), i.name_any(), None))
+                    .collect()
+            }
+            _ => {
+                // A handed over dependency can live in another namespace of the tenant
+                let api: Api<TenantInstance> = Api::all(client.clone());
+                let mut res = Vec::new();
+                for i in api.list(&lp).await.map_err(Error::KubeError)?.items {
+                    if i.spec.category == dep.category && i.spec.package == dep.name {
+                        let nss = i.get_tenant_namespaces().await?;
+                        res.push((i.namespace().unwrap_or_default(), i.name_any(), Some(nss)));
+                    }
+                }
+                res
+            }
+        };
+        for (cand_ns, cand_name, scope) in candidates {
+            let requester = {
+                let packages = ctx.packages.read().await;
+                remaining_requester(&installed, scope.as_deref(), &dep, |i| {
+                    installed_requirements(&packages, i)
+                })
+                .cloned()
+            };
+            if let Some(requester) = requester {
+                tracing::debug!(
+                    "Keeping dependency {} {cand_ns}/{cand_name}: still required by {} {}/{}",
+                    dep.kind(),
+                    requester.kind,
+                    requester.namespace,
+                    requester.name
+                );
+                let patch = Patch::Merge(json!({
+                    "metadata": {"labels": requester_labels(&requester.namespace, &requester.name)}
+                }));
+                let res = match dep.usage {
+                    VynilPackageType::System => Api::<SystemInstance>::namespaced(client.clone(), &cand_ns)
+                        .patch(&cand_name, &PatchParams::default(), &patch)
+                        .await
+                        .map(|_| ()),
+                    _ => Api::<TenantInstance>::namespaced(client.clone(), &cand_ns)
+                        .patch(&cand_name, &PatchParams::default(), &patch)
+                        .await
+                        .map(|_| ()),
+                };
+                if let Err(e) = res {
+                    tracing::warn!("Handing over {} {cand_ns}/{cand_name} failed with: {e}", dep.kind());
+                }
+                continue;
+            }
+            tracing::info!("Deleting unused dependency {} {cand_ns}/{cand_name}", dep.kind());
+            let res = match dep.usage {
+                VynilPackageType::System => Api::<SystemInstance>::namespaced(client.clone(), &cand_ns)
+                    .delete(&cand_name, &DeleteParams::default())
+                    .await
+                    .map(|_| ()),
+                _ => Api::<TenantInstance>::namespaced(client.clone(), &cand_ns)
+                    .delete(&cand_name, &DeleteParams::default())
+                    .await
+                    .map(|_| ()),
+            };
+            if let Err(e) = res {
+                tracing::warn!("Deleting {} {cand_ns}/{cand_name} failed with: {e}", dep.kind());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::vynilpackage::{VynilPackage, VynilPackageMeta};
+
+    fn make_pkg(category: &str, name: &str, usage: VynilPackageType) -> VynilPackage {
+        VynilPackage {
+            registry: String::new(),
+            image: String::new(),
+            tag: String::new(),
+            metadata: VynilPackageMeta {
+                name: name.to_string(),
+                category: category.to_string(),
+                description: String::new(),
+                app_version: None,
+                usage,
+                features: vec![],
+                backup_affinity: None,
+            },
+            requirements: vec![],
+            recommandations: None,
+            options: None,
+            value_script: None,
+            migrations: None,
+            options_migrate: None,
+        }
+    }
+
+    fn reqs() -> Vec<VynilPackageRequirement> {
+        vec![
+            VynilPackageRequirement::SystemPackage {
+                category: "core".to_string(),
+                name: "cert-manager".to_string(),
+            },
+            VynilPackageRequirement::TenantPackage {
+                category: "dbo".to_string(),
+                name: "pg".to_string(),
+            },
+            VynilPackageRequirement::CustomResourceDefinition("foo.bar".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_package_dependencies_for_tenant() {
+        let deps = package_dependencies(&reqs(), &VynilPackageType::Tenant);
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[0].kind(), "SystemInstance");
+        assert_eq!(deps[1].kind(), "TenantInstance");
+        assert_eq!(deps[1].name, "pg");
+    }
+
+    #[test]
+    fn test_dependency_selector_matches_the_labels_values() {
+        assert_eq!(
+            dependency_selector("team", "wiki"),
+            "vynil.solidite.fr/dependency-of=wiki,vynil.solidite.fr/dependency-of-namespace=team"
+        );
+    }
+
+    #[test]
+    fn test_remaining_requester_after_the_first_one_is_deleted() {
+        let dep = Dependency {
+            usage: VynilPackageType::Tenant,
+            category: "dbo".to_string(),
+            name: "pg".to_string(),
+        };
+        let app = |ns: &str, name: &str, deleting: bool| InstalledInstance {
+            kind: "TenantInstance",
+            namespace: ns.to_string(),
+            name: name.to_string(),
+            jukebox: "jb".to_string(),
+            category: "app".to_string(),
+            package: "web".to_string(),
+            usage: VynilPackageType::Tenant,
+            services: vec![],
+            deleting,
+        };
+        // wiki installed pg and is being deleted, blog (same tenant) requires it too
+        let installed: Vec<InstalledInstance> =
+            vec![app("team", "wiki", true), app("team-apps", "blog", false), app("other", "web", false)]
+                .into_iter()
+                .filter(|i| !i.deleting)
+                .collect();
+        let scope = vec!["team".to_string(), "team-apps".to_string()];
+        let requester = remaining_requester(&installed, Some(&scope), &dep, |_| reqs()).unwrap();
+        assert_eq!((requester.namespace.as_str(), requester.name.as_str()), ("team-apps", "blog"));
+        assert_eq!(
+            requester_labels(&requester.namespace, &requester.name),
+            BTreeMap::from([
+                (DEPENDENCY_OF_LABEL.to_string(), "blog".to_string()),
+                (DEPENDENCY_OF_NS_LABEL.to_string(), "team-apps".to_string()),
+            ])
+        );
+        let outside = vec!["elsewhere".to_string()];
+        assert!(remaining_requester(&installed, Some(&outside), &dep, |_| reqs()).is_none());
+    }
+
+    #[test]
+    fn test_package_dependencies_ignore_tenant_for_system() {
+        let deps = package_dependencies(&reqs(), &VynilPackageType::System);
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].name, "cert-manager");
+    }
+
+    #[test]
+    fn test_find_dependency_jukebox_prefers_own_jukebox() {
+        let dep = Dependency {
+            usage: VynilPackageType::System,
+            category: "core".to_string(),
+            name: "cert-manager".to_string(),
+        };
+        let mut packages = BTreeMap::new();
+        packages.insert("aaa".to_string(), JukeCacheItem {
+            pull_secret: None,
+            generation: 0,
+            packages: vec![make_pkg("core", "cert-manager", VynilPackageType::System)],
+        });
+        packages.insert("mine".to_string(), JukeCacheItem {
+            pull_secret: None,
+            generation: 0,
+            packages: vec![make_pkg("core", "cert-manager", VynilPackageType::System)],
+        });
+        assert_eq!(
+            find_dependency_jukebox(&packages, "mine", &dep),
+            Some("mine".to_string())
+        );
+        assert_eq!(
+            find_dependency_jukebox(&packages, "other", &dep),
+            Some("aaa".to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_dependency_jukebox_checks_usage() {
+        let dep = Dependency {
+            usage: VynilPackageType::System,
+            category: "core".to_string(),
+            name: "cert-manager".to_string(),
+        };
+        let mut packages = BTreeMap::new();
+        packages.insert("jb".to_string(), JukeCacheItem {
+            pull_secret: None,
+            generation: 0,
+            packages: vec![make_pkg("core", "cert-manager", VynilPackageType::Tenant)],
+        });
+        assert_eq!(find_dependency_jukebox(&packages, "jb", &dep), None);
+    }
+
+    #[test]
+    fn test_installed_requirements_from_cache() {
+        let mut pkg = make_pkg("app", "web", VynilPackageType::Tenant);
+        pkg.requirements = reqs();
+        let mut packages = BTreeMap::new();
+        packages.insert("jb".to_string(), JukeCacheItem {
+            pull_secret: None,
+            generation: 0,
+            packages: vec![pkg],
+        });
+        let inst = InstalledInstance {
+            kind: "TenantInstance",
+            namespace: "ns".to_string(),
+            name: "web".to_string(),
+            jukebox: "jb".to_string(),
+            category: "app".to_string(),
+            package: "web".to_string(),
+            usage: VynilPackageType::Tenant,
+            services: vec![],
+            deleting: false,
+        };
+        assert_eq!(installed_requirements(&packages, &inst).len(), 3);
+        let unknown = InstalledInstance {
+            jukebox: "missing".to_string(),
+            ..inst
+        };
+        assert!(installed_requirements(&packages, &unknown).is_empty());
+    }
+}
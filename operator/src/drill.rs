@@ -0,0 +1,148 @@
+use crate::{Error, Result, get_client_name, instance_common::InstanceKind};
+use common::{handlebarshandler::HandleBars, vynilpackage::VynilPackageFeature};
+use k8s_openapi::api::batch::v1::CronJob;
+use kube::{
+    Client, ResourceExt,
+    api::{Api, DeleteParams, Patch, PatchParams},
+};
+use serde_json::{Value, json};
+
+/// Name of the restore drill CronJob of an instance (CronJob names are limited to 52 characters)
+fn cron_name<T: InstanceKind>(inst: &T) -> String {
+    common::short_name(
+        &format!(
+            "drill--{}--{}",
+            inst.namespace().unwrap_or_default(),
+            inst.name_any()
+        ),
+        52,
+    )
+}
+
+/// Wraps the agent Job rendered for the `drill` action into a CronJob
+fn cron_from_job(job: &Value, name: &str, schedule: &str) -> Value {
+    let mut spec = job["spec"].clone();
+    // A failed drill is reported, not retried
+    spec["backoffLimit"] = 0.into();
+    let labels = job["metadata"]["labels"].clone();
+    json!({
+        "apiVersion": "batch/v1",
+        "kind": "CronJob",
+        "metadata": {"name": name, "labels": labels},
+        "spec": {
+            "schedule": schedule,
+            "concurrencyPolicy": "Forbid",
+            "successfulJobsHistoryLimit": 1,
+            "failedJobsHistoryLimit": 1,
+            "jobTemplate": {"metadata": {"labels": labels}, "spec": spec}
+        }
+    })
+}
+
+/// Maintains the restore drill CronJob of an instance in the vynil namespace.
+///
+/// The CronJob runs the agent `drill` action with the context of the install Job, it is removed
+/// when the drills or the backups are disabled.
+pub async fn schedule<T: InstanceKind>(
+    inst: &T,
+    features: &[VynilPackageFeature],
+    hbs: &mut HandleBars<'static>,
+    context: &Value,
+    client: Client,
+    my_ns: &str,
+) -> Result<()> {
+    let policy = inst.spec_backup();
+    let drill = policy
+        .filter(|p| p.enabled.unwrap_or(true))
+        .and_then(|p| p.drill.as_ref())
+        .filter(|_| features.contains(&VynilPackageFeature::Backup));
+    let Some(drill) = drill else {
+        return unschedule(inst, client, my_ns).await;
+    };
+    let name = cron_name(inst);
+    let mut context = context.clone();
+    {
+        let obj = context.as_object_mut().unwrap();
+        obj.insert("package_action".to_string(), "drill".into());
+        obj.insert("job_name".to_string(), name.clone().into());
+    }
+    let job_def_str = hbs.render("{{> package.yaml }}", &context)?;
+    let job_def: Value = common::yamlhandler::yaml_str_to_json(&job_def_str)?;
+    let cron_api: Api<CronJob> = Api::namespaced(client, my_ns);
+    cron_api
+        .patch(
+            &name,
+            &PatchParams::apply(&get_client_name()).force(),
+            &Patch::Apply(cron_from_job(&job_def, &name, &drill.schedule)),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
+/// Removes the restore drill CronJob of an instance
+pub async fn unschedule<T: InstanceKind>(inst: &T, client: Client, my_ns: &str) -> Result<()> {
+    let name = cron_name(inst);
+    let cron_api: Api<CronJob> = Api::namespaced(client, my_ns);
+    if cron_api
+        .get_metadata_opt(&name)
+        .await
+        .map_err(Error::KubeError)?
+        .is_some()
+    {
+        match cron_api.delete(&name, &DeleteParams::foreground()).await {
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Deleting CronJob {} failed with: {e}", &name),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drill_cron_wraps_the_agent_job() {
+        let job = json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {"name": "drill--team--wiki", "labels": {"vynil.solidite.fr/action": "drill"}},
+            "spec": {"backoffLimit": 3, "template": {"spec": {"containers": [{"name": "drill", "args": ["tenant", "drill"]}]}}}
+        });
+        let cron = cron_from_job(&job, "drill--team--wiki", "0 4 * * SUN");
+        assert_eq!(cron["kind"], "CronJob");
+        assert_eq!(cron["spec"]["schedule"], "0 4 * * SUN");
+        assert_eq!(cron["spec"]["jobTemplate"]["spec"]["backoffLimit"], 0);
+        assert_eq!(
+            cron["spec"]["jobTemplate"]["spec"]["template"]["spec"]["containers"][0]["args"][1],
+            "drill"
+        );
+        assert_eq!(
+            cron["spec"]["jobTemplate"]["metadata"]["labels"]["vynil.solidite.fr/action"],
+            "drill"
+        );
+    }
+
+    #[test]
+    fn drill_cron_names_of_long_instances_stay_distinct() {
+        let inst = |name: &str| -> crate::TenantInstance {
+            serde_json::from_value(json!({
+                "apiVersion": "vynil.solidite.fr/v1",
+                "kind": "TenantInstance",
+                "metadata": {"name": name, "namespace": "a-rather-long-tenant-namespace"},
+                "spec": {"jukebox": "home", "category": "apps", "package": "wiki"}
+            }))
+            .unwrap()
+        };
+        let first = cron_name(&inst("wiki-for-the-documentation-team"));
+        let second = cron_name(&inst("wiki-for-the-documentation-group"));
+        assert!(first.len() <= 52 && second.len() <= 52);
+        assert_ne!(first, second);
+        assert!(first.starts_with("drill--a-rather-long-tenant-namespace--wiki"));
+        assert_eq!(
+            cron_name(&inst("wiki")),
+            "drill--a-rather-long-tenant-namespace--wiki"
+        );
+    }
+}
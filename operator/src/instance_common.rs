@@ -1,11 +1,12 @@
 use crate::{
-    Error, Reconciler, Result, get_client_name, manager::Context, metrics::ReconcileMeasurerInstance,
-    telemetry,
+    Error, Reconciler, Result, clone, defaults, dependencies, drill, get_client_name, manager::Context,
+    metrics::ReconcileMeasurerInstance, policy, rotation, telemetry, upgrade,
 };
 use async_trait::async_trait;
 use chrono::Utc;
 use common::{
     rhaihandler::Script,
+    vynilbackup::{BackupPolicy, InstanceRef, KeyRotation, UpgradeBackup, VynilBackup},
     vynilpackage::{VynilPackage, VynilPackageRecommandation, VynilPackageRequirement, VynilPackageType},
 };
 use k8s_openapi::{
@@ -14,7 +15,7 @@ use k8s_openapi::{
 };
 use kube::{
     Client, ResourceExt,
-    api::{Api, DeleteParams, Patch, PatchParams, PostParams},
+    api::{Api, DeleteParams, ListParams, Patch, PatchParams, PostParams},
     runtime::{
         conditions,
         controller::Action,
@@ -65,6 +66,20 @@ pub trait InstanceKind:
     fn package_type() -> VynilPackageType
     where
         Self: Sized;
+    /// Package types this instance kind can install
+    fn accepts_package_type(usage: &VynilPackageType) -> bool
+    where
+        Self: Sized,
+    {
+        *usage == Self::package_type()
+    }
+    /// Whether the agent can reconfigure this instance kind without a full install
+    fn supports_reconfigure() -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
 
     // ── Spec accessors ────────────────────────────────────────────────────
     fn spec_jukebox(&self) -> &str;
@@ -72,18 +87,67 @@ pub trait InstanceKind:
     fn spec_package(&self) -> &str;
     /// Returns the currently installed tag from the status, or an empty string.
     fn current_tag(&self) -> String;
+    /// Returns the options digest of the last successful install, if any.
+    fn installed_digest(&self) -> Option<String>;
     /// Returns the version requested for initial restore, or None if absent.
     /// Default implementation returns None (SystemInstance, or no initFrom.version).
     fn init_from_version(&self) -> Option<&str> {
         None
     }
+    /// Returns the VynilBackup requested for initial restore, or None if absent.
+    fn init_from_backup(&self) -> Option<&str> {
+        None
+    }
+    /// Returns the instance to clone while `initFrom.instanceRef` is not resolved, or None.
+    fn init_from_instance(&self) -> Option<&InstanceRef> {
+        None
+    }
+    /// Returns the backup policy of the instance, or None if absent.
+    fn spec_backup(&self) -> Option<&BackupPolicy> {
+        None
+    }
+    /// Returns the backup taken before the running upgrade, if any.
+    fn upgrade_backup(&self) -> Option<&UpgradeBackup> {
+        None
+    }
     fn have_child(&self) -> bool;
-    fn get_options_digest(&mut self) -> String;
+    fn get_options_digest(&mut self) -> Result<String>;
+    /// Returns the instance options, empty when unset.
+    fn spec_options(&self) -> serde_json::Map<String, Value>;
+    /// Returns the instance `autoInstallDependencies` setting, if set.
+    fn spec_auto_install_dependencies(&self) -> Option<bool>;
+    /// Returns whether deleted children should be re-applied (`selfHeal`, default to true).
+    fn spec_self_heal(&self) -> bool;
+    /// Namespaces where the TenantPackage dependencies of this instance may live.
+    /// Default implementation returns the instance namespace only.
+    async fn tenant_namespaces(&self) -> Result<Vec<String>> {
+        Ok(vec![self.namespace().unwrap_or_default()])
+    }
 
     // ── Status update methods ─────────────────────────────────────────────
     async fn set_missing_box(self, jukebox: String) -> Result<Self>;
     async fn set_missing_package(self, category: String, package: String) -> Result<Self>;
     async fn set_missing_requirement(self, reason: String) -> Result<Self>;
+    async fn set_deletion_blocked(self, dependents: Vec<String>) -> Result<Self>;
+    async fn set_policy_violations(self, violations: Vec<String>) -> Result<Self>;
+    async fn set_policy_compliant(self) -> Result<Self>;
+    async fn set_observed_generation(self) -> Result<Self>;
+    /// Writes options migrated from the `from` version back to the spec.
+    async fn set_migrated_options(
+        self,
+        options: serde_json::Map<String, Value>,
+        from: String,
+        to: String,
+    ) -> Result<Self>;
+    /// Records the options merged over the VynilDefaults in the status.
+    async fn set_effective_options(self, options: Option<serde_json::Map<String, Value>>) -> Result<Self>;
+    /// Removes the states kept in the state store once the delete job is done.
+    async fn purge_state(&self) -> Result<()>;
+    /// Checks the health of the monitored children, recording it in the status.
+    /// Returns `None` when the instance is not monitored (not installed yet, or agent running).
+    async fn check_health(&self) -> Result<Option<bool>>;
+    /// Looks for deleted children, returns whether the install job should run again.
+    async fn self_heal(&self) -> Result<bool>;
     /// Records that the requested init version was not found.
     /// Default no-op for instance types that don't support initFrom (e.g. SystemInstance).
     async fn set_missing_init_version(self, _version: String) -> Result<Self>
@@ -92,6 +156,43 @@ pub trait InstanceKind:
     {
         Ok(self)
     }
+    /// Writes the `initFrom` resolved from `initFrom.instanceRef` and the copied options to the spec.
+    /// Default no-op for instance types that don't support initFrom (e.g. SystemInstance).
+    async fn set_init_from_instance(
+        self,
+        _init_from: Value,
+        _options: serde_json::Map<String, Value>,
+        _source: String,
+    ) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(self)
+    }
+    /// Records that the backup policy cannot be applied.
+    /// Default no-op for instance types without backups (e.g. SystemInstance).
+    async fn set_invalid_backup_policy(self, _reason: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(self)
+    }
+    /// Records the backup taken before an upgrade.
+    /// Default no-op for instance types without backups (e.g. SystemInstance).
+    async fn set_upgrade_backup(self, _upgrade: Option<UpgradeBackup>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(self)
+    }
+    /// Records the rotation of the backup repository key.
+    /// Default no-op for instance types without backups (e.g. SystemInstance).
+    async fn set_key_rotation(self, _rotation: KeyRotation) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(self)
+    }
 
     // ── Type-specific behaviors ───────────────────────────────────────────
 
@@ -120,6 +221,8 @@ pub trait InstanceKind:
     // ── Metrics ───────────────────────────────────────────────────────────
     fn count_and_measure_metrics(&self, ctx: &Context, trace_id: &TraceId) -> ReconcileMeasurerInstance;
     fn record_reconcile_failure(&self, ctx: &Context, error: &Error);
+    fn record_health(&self, ctx: &Context, healthy: bool);
+    fn forget_health(&self, ctx: &Context);
 }
 
 // ── Namespace helper ──────────────────────────────────────────────────────────
@@ -178,9 +281,10 @@ pub async fn build_base_recommendations(
 
 /// Resolves the package version to use for initial restoration.
 ///
-/// Returns `Ok(Some(version))` if a valid `initFrom.version` is found,
-/// `Ok(None)` if no version was requested or the instance is already installed,
-/// or `Err(Error::MissingInitVersion)` if the requested version doesn't exist.
+/// Returns `Ok(Some(version))` if a valid `initFrom.version` (or version of the
+/// `initFrom.backup` record) is found, `Ok(None)` if no version was requested or the
+/// instance is already installed, or `Err(Error::MissingInitVersion)` if the requested
+/// version doesn't exist.
 pub async fn resolve_init_version<T: InstanceKind>(
     inst: &T,
     pck: &VynilPackage,
@@ -189,20 +293,29 @@ pub async fn resolve_init_version<T: InstanceKind>(
     client: Client,
     vynil_ns: &str,
 ) -> Result<Option<String>> {
-    let requested = match inst.init_from_version() {
-        Some(v) => v,
-        None => return Ok(None),
-    };
     // Already installed: ignore the init version override
     if !inst.current_tag().is_empty() {
         return Ok(None);
     }
+    let from_backup;
+    let requested = match (inst.init_from_version(), inst.init_from_backup()) {
+        (Some(v), _) => v,
+        (None, Some(name)) => {
+            let backup = VynilBackup::get_opt(client.clone(), &ns(inst), name)
+                .await?
+                .filter(|b| b.restorable_snapshot().is_some())
+                .ok_or_else(|| Error::MissingBackup(name.to_string()))?;
+            from_backup = backup.spec.version;
+            &from_backup
+        }
+        (None, None) => return Ok(None),
+    };
 
     // 1. Check local cache first (no network call)
     let in_cache = cached_packages.iter().any(|p| {
         p.metadata.name == inst.spec_package()
             && p.metadata.category == inst.spec_category()
-            && p.metadata.usage == T::package_type()
+            && T::accepts_package_type(&p.metadata.usage)
             && p.tag == requested
     });
     if in_cache {
@@ -325,12 +438,7 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
     ctx.diagnostics.write().await.last_event = Utc::now();
 
     // ── Suspend annotation ────────────────────────────────────────────────
-    if inst
-        .annotations()
-        .get("vynil.solidite.fr/suspend")
-        .map(|v| v == "true")
-        .unwrap_or(false)
-    {
+    if is_suspended(inst) {
         tracing::info!(
             "{}Instance {}/{} is suspended, skipping reconciliation",
             T::type_name(),
@@ -340,6 +448,14 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
         return Ok(Action::requeue(Duration::from_secs(15 * 60)));
     }
 
+    // ── Health monitoring ─────────────────────────────────────────────────
+    check_health(inst, &ctx).await;
+
+    // ── Option defaults ───────────────────────────────────────────────────
+    let effective = defaults::for_instance(inst, ctx.client.clone()).await?;
+    let updated = inst.clone().set_effective_options(effective).await?;
+    let inst = &updated;
+
     let mut hbs = ctx.renderer.clone();
     let client = ctx.client.clone();
     let my_ns = ctx.client.default_namespace();
@@ -355,10 +471,24 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
         obj.insert("package_type".to_string(), T::type_name().into());
         obj.insert("package_action".to_string(), "install".into());
         obj.insert("job_name".to_string(), job_name.clone().into());
-        obj.insert("digest".to_string(), inst.clone().get_options_digest().into());
+        obj.insert("digest".to_string(), inst.clone().get_options_digest()?.into());
         obj.insert("oci_mount".to_string(), false.into());
     }
 
+    // ── Policies ──────────────────────────────────────────────────────────
+    let violations = policy::check(inst, client.clone()).await?;
+    if !violations.is_empty() {
+        inst.clone().set_policy_violations(violations).await?;
+        return Ok(Action::requeue(Duration::from_secs(15 * 60)));
+    }
+    inst.clone().set_policy_compliant().await?;
+
+    // ── Backup policy ─────────────────────────────────────────────────────
+    if let Some(Err(reason)) = inst.spec_backup().map(|b| b.validate()) {
+        inst.clone().set_invalid_backup_policy(reason).await?;
+        return Ok(Action::requeue(Duration::from_secs(15 * 60)));
+    }
+
     // ── Package lookup ────────────────────────────────────────────────────
     let (pck, pull_secret, cached_packages) = {
         let packages = ctx.packages.read().await;
@@ -376,7 +506,7 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
             .find(|p| {
                 p.metadata.name == inst.spec_package()
                     && p.metadata.category == inst.spec_category()
-                    && p.metadata.usage == T::package_type()
+                    && T::accepts_package_type(&p.metadata.usage)
                     && p.is_min_version_ok(current_version.clone())
                     && p.is_vynil_version_ok()
             })
@@ -409,6 +539,12 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
             .insert("use_secret".to_string(), false.into());
     }
 
+    // ── Cloning ───────────────────────────────────────────────────────────
+    if clone::resolve(inst, &pck, &cached_packages, client.clone()).await? {
+        // The spec change triggers a new reconciliation with the resolved initFrom
+        return Ok(Action::await_change());
+    }
+
     // ── initFrom version resolution ───────────────────────────────────────
     let effective_tag =
         match resolve_init_version(inst, &pck, &cached_packages, &pull_secret, client.clone(), my_ns).await {
@@ -416,14 +552,42 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
             Ok(None) => pck.tag.clone(),
             Err(e) => return Err(e),
         };
+
+    // ── Pre-upgrade backup ────────────────────────────────────────────────
+    let job_api: Api<Job> = Api::namespaced(client.clone(), my_ns);
+    let (effective_tag, mut rerun) =
+        match upgrade::guard(inst, &pck, effective_tag, &job_api, &job_name, client.clone()).await? {
+            upgrade::Step::Proceed(tag) => (tag, false),
+            upgrade::Step::Reinstall(tag) => (tag, true),
+            upgrade::Step::Wait(action) => return Ok(action),
+        };
+
+    // ── Option migrations ─────────────────────────────────────────────────
+    // Only once the guard let the new version in: the pre-upgrade backup keeps the previous
+    // options and a rollback reinstalls the previous version with them
+    if !rerun
+        && effective_tag == pck.tag
+        && let Some(migrated) = pending_migration(inst, &pck, &current_version)?
+    {
+        inst.clone()
+            .set_migrated_options(migrated, current_version.clone(), pck.tag.clone())
+            .await?;
+        // The spec change triggers a new reconciliation with the migrated options
+        return Ok(Action::await_change());
+    }
     {
         let obj = context.as_object_mut().unwrap();
-        obj.insert("tag".to_string(), effective_tag.into());
+        obj.insert("tag".to_string(), effective_tag.clone().into());
         obj.insert("image".to_string(), pck.image.clone().into());
         obj.insert("registry".to_string(), pck.registry.clone().into());
     }
 
     // ── Requirements ──────────────────────────────────────────────────────
+    if dependencies::auto_install_enabled(inst, client.clone()).await?
+        && let Some(action) = dependencies::ensure_dependencies(inst, &pck.requirements, &ctx).await?
+    {
+        return Ok(action);
+    }
     if let Some(action) = inst.check_requirements(pck.requirements, client.clone()).await? {
         return Ok(action);
     }
@@ -463,7 +627,6 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
     }
 
     // ── Force-reinstall annotation ────────────────────────────────────────
-    let job_api: Api<Job> = Api::namespaced(client.clone(), my_ns);
     if inst
         .annotations()
         .contains_key("vynil.solidite.fr/force-reinstall")
@@ -478,6 +641,32 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
         api.patch(&inst.name_any(), &PatchParams::default(), &patch)
             .await
             .map_err(Error::KubeError)?;
+        rerun = true;
+    }
+
+    // ── Self-heal ─────────────────────────────────────────────────────────
+    // The job definition is unchanged when only children were deleted, so run it again
+    if !rerun && inst.spec_self_heal() {
+        match inst.self_heal().await {
+            Ok(missing) => rerun = missing,
+            Err(e) => tracing::warn!(
+                "Looking for deleted children of {}Instance {}/{} failed: {e}",
+                T::type_name(),
+                ns,
+                inst.name_any()
+            ),
+        }
+    }
+    let action = if rerun {
+        "install"
+    } else {
+        select_action(inst, &effective_tag, &job_api, &job_name).await?
+    };
+    context
+        .as_object_mut()
+        .unwrap()
+        .insert("package_action".to_string(), action.into());
+    if rerun {
         let job = job_api.get_metadata_opt(&job_name).await;
         if matches!(job, Ok(Some(_))) {
             delete_job_and_wait(&job_api, &job_name).await?;
@@ -489,13 +678,178 @@ pub async fn do_reconcile<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Resul
     let job_def: Value = common::yamlhandler::yaml_str_to_json(&job_def_str)?;
     upsert_job(&job_api, &job_name, job_def).await?;
 
+    // ── Restore drills ────────────────────────────────────────────────────
+    drill::schedule(
+        inst,
+        &pck.metadata.features,
+        &mut hbs,
+        &context,
+        client.clone(),
+        my_ns,
+    )
+    .await?;
+
+    // ── Backup key rotation ───────────────────────────────────────────────
+    rotation::start(
+        inst,
+        &pck.metadata.features,
+        &mut hbs,
+        &context,
+        client.clone(),
+        my_ns,
+    )
+    .await?;
+
+    inst.clone().set_observed_generation().await?;
+
     Ok(Action::requeue(Duration::from_secs(15 * 60)))
 }
 
+/// Chooses the agent action: `reconfigure` when only the options changed since the last install.
+///
+/// An up-to-date instance keeps the action of its existing Job so the Job definition stays stable.
+async fn select_action<T: InstanceKind>(
+    inst: &T,
+    tag: &str,
+    job_api: &Api<Job>,
+    job_name: &str,
+) -> Result<&'static str> {
+    if !T::supports_reconfigure() || inst.current_tag() != tag {
+        return Ok("install");
+    }
+    let Some(installed) = inst.installed_digest() else {
+        return Ok("install");
+    };
+    if installed != inst.clone().get_options_digest()? {
+        return Ok("reconfigure");
+    }
+    let job = job_api.get_metadata_opt(job_name).await.ok().flatten();
+    let reconfiguring = job
+        .as_ref()
+        .and_then(|j| j.labels().get("vynil.solidite.fr/action"))
+        .is_some_and(|action| action == "reconfigure");
+    Ok(if reconfiguring { "reconfigure" } else { "install" })
+}
+
+/// Delay between two health checks of an installed instance (`HEALTH_CHECK_INTERVAL` seconds)
+fn health_interval() -> Duration {
+    let secs = std::env::var("HEALTH_CHECK_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(5 * 60);
+    Duration::from_secs(secs.clamp(30, 15 * 60))
+}
+
+/// Migrates the options installed with the `from` version to the package version.
+///
+/// Returns `None` when the options are unchanged or when the `options-migrated` annotation
+/// already records this migration, so a migration only runs once per version change.
+fn pending_migration<T: InstanceKind>(
+    inst: &T,
+    pck: &VynilPackage,
+    from: &str,
+) -> Result<Option<serde_json::Map<String, Value>>> {
+    let migration = format!("{from} -> {}", pck.tag);
+    if inst.annotations().get("vynil.solidite.fr/options-migrated") == Some(&migration) {
+        return Ok(None);
+    }
+    pck.migrate_options(from, &inst.spec_options())
+}
+
+fn is_suspended<T: InstanceKind>(inst: &T) -> bool {
+    inst.annotations()
+        .get("vynil.solidite.fr/suspend")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// Checks the health of an instance, recording it in its status and metrics
+async fn check_health<T: InstanceKind>(inst: &T, ctx: &Context) {
+    match inst.check_health().await {
+        Ok(Some(healthy)) => inst.record_health(ctx, healthy),
+        Ok(None) => {}
+        Err(e) => tracing::warn!(
+            "Checking the health of {}Instance {}/{} failed: {e}",
+            T::type_name(),
+            ns(inst),
+            inst.name_any()
+        ),
+    }
+}
+
+/// Checks the health of every instance of a kind at the health interval.
+///
+/// Runs apart from the reconciliation so the health checks do not trigger the package lookup,
+/// the template render and the Job diff of a full reconcile.
+pub async fn monitor_health<T: InstanceKind>(ctx: Arc<Context>) {
+    let api: Api<T> = Api::all(ctx.client.clone());
+    loop {
+        tokio::time::sleep(health_interval()).await;
+        match api.list(&ListParams::default()).await {
+            Ok(list) => {
+                for inst in list
+                    .items
+                    .iter()
+                    .filter(|i| i.meta().deletion_timestamp.is_none() && !is_suspended(*i))
+                {
+                    check_health(inst, &ctx).await;
+                }
+            }
+            Err(e) => tracing::warn!("Listing the {}Instances to check failed: {e}", T::type_name()),
+        }
+    }
+}
+
 // ── Generic cleanup (Cleanup / finalizer deletion) ────────────────────────────
 
+/// Lists the instances that would lose a requirement if `inst` was deleted.
+async fn find_dependents<T: InstanceKind>(inst: &T, ctx: &Context) -> Result<Vec<String>> {
+    let installed = common::dependents::list_installed(ctx.client.clone()).await?;
+    let Some(target) = installed
+        .iter()
+        .find(|i| i.is(&T::kind(&()), &ns(inst), &inst.name_any()))
+        .cloned()
+    else {
+        return Ok(Vec::new());
+    };
+    let tenant_namespaces = inst.tenant_namespaces().await?;
+    let packages = ctx.packages.read().await;
+    Ok(
+        common::dependents::find_dependents(&target, &tenant_namespaces, &installed, |i| {
+            dependencies::installed_requirements(&packages, i)
+        })
+        .iter()
+        .map(|d| d.to_string())
+        .collect(),
+    )
+}
+
 pub async fn do_cleanup<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Result<Action> {
     ctx.diagnostics.write().await.last_event = Utc::now();
+    inst.forget_health(&ctx);
+
+    // ── Dependents protection ─────────────────────────────────────────────
+    let dependents = find_dependents(inst, &ctx).await?;
+    if !dependents.is_empty() {
+        if inst
+            .annotations()
+            .get("vynil.solidite.fr/force-delete")
+            .map(|v| v == "true")
+            .unwrap_or(false)
+        {
+            tracing::warn!(
+                "Force deleting {}Instance {}/{} still required by: {}",
+                T::type_name(),
+                ns(inst),
+                inst.name_any(),
+                dependents.join(", ")
+            );
+        } else {
+            inst.clone().set_deletion_blocked(dependents.clone()).await?;
+            return Err(Error::DeletionBlocked(dependents.join(", ")));
+        }
+    }
+
     let mut hbs = ctx.renderer.clone();
     let client = ctx.client.clone();
     let my_ns = ctx.client.default_namespace();
@@ -511,7 +865,7 @@ pub async fn do_cleanup<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Result<
         obj.insert("package_type".to_string(), T::type_name().into());
         obj.insert("package_action".to_string(), "delete".into());
         obj.insert("job_name".to_string(), job_name.clone().into());
-        obj.insert("digest".to_string(), inst.clone().get_options_digest().into());
+        obj.insert("digest".to_string(), inst.clone().get_options_digest()?.into());
         obj.insert("oci_mount".to_string(), false.into());
     }
 
@@ -528,7 +882,7 @@ pub async fn do_cleanup<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Result<
             .find(|p| {
                 p.metadata.name == inst.spec_package()
                     && p.metadata.category == inst.spec_category()
-                    && p.metadata.usage == T::package_type()
+                    && T::accepts_package_type(&p.metadata.usage)
                     && p.is_min_version_ok(current_version.clone())
                     && p.is_vynil_version_ok()
             })
@@ -590,6 +944,9 @@ pub async fn do_cleanup<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Result<
             .insert("ctrl_values".to_string(), "\"{}\"".into());
     }
 
+    // ── Delete the restore drills ─────────────────────────────────────────
+    drill::unschedule(inst, client.clone(), my_ns).await?;
+
     // ── Delete the install job ────────────────────────────────────────────
     let job_api: Api<Job> = Api::namespaced(client.clone(), my_ns);
     let job = job_api.get_metadata_opt(&job_name).await;
@@ -621,6 +978,12 @@ pub async fn do_cleanup<T: InstanceKind>(inst: &T, ctx: Arc<Context>) -> Result<
         Ok(_) => {}
         Err(e) => tracing::warn!("Deleting Job {} failed with: {e}", &job_name),
     }
+
+    // ── Remove the states left in the state store ─────────────────────────
+    inst.purge_state().await?;
+
+    // ── Garbage-collect auto-installed dependencies ───────────────────────
+    dependencies::collect_dependencies(inst, &pck.requirements, &ctx).await?;
     Ok(Action::await_change())
 }
 
@@ -633,7 +996,9 @@ mod tests {
     use common::{
         instancesystem::SystemInstanceSpec,
         instancetenant::{InitFrom, TenantInstanceSpec, TenantInstanceStatus},
-        vynilpackage::{VynilPackage, VynilPackageMeta, VynilPackageType},
+        vynilpackage::{
+            VynilOptionMigration, VynilOptionRule, VynilPackage, VynilPackageMeta, VynilPackageType,
+        },
     };
 
     fn make_tenant(version: Option<&str>, installed_tag: Option<&str>) -> TenantInstance {
@@ -650,17 +1015,32 @@ mod tests {
                 init_from: version.map(|v| InitFrom {
                     secret_name: None,
                     sub_path: None,
-                    snapshot: "snap1".to_string(),
+                    snapshot: Some("snap1".to_string()),
                     version: Some(v.to_string()),
+                    backup: None,
+                    instance_ref: None,
                 }),
+                backup: None,
                 options: None,
+                auto_install_dependencies: None,
+                self_heal: None,
             },
             status: installed_tag.map(|t| TenantInstanceStatus {
                 tag: Some(t.to_string()),
                 conditions: vec![],
+                observed_generation: None,
                 digest: None,
+                effective_options: None,
+                backup_policy: None,
+                upgrade_backup: None,
+                last_drill: None,
+                key_rotation: None,
                 tfstate: None,
                 rhaistate: None,
+                state: None,
+                tofu_digest: None,
+                inventory: None,
+                health: None,
                 befores: None,
                 vitals: None,
                 scalables: None,
@@ -689,6 +1069,8 @@ mod tests {
             recommandations: None,
             options: None,
             value_script: None,
+            migrations: None,
+            options_migrate: None,
         }
     }
 
@@ -723,6 +1105,8 @@ mod tests {
                 category: "cat".to_string(),
                 package: "pkg".to_string(),
                 options: None,
+                auto_install_dependencies: None,
+                self_heal: None,
             },
             status: None,
         };
@@ -804,4 +1188,36 @@ mod tests {
         // Ok(None) → do_reconcile uses pck.tag ("2.0.0") for normal upgrade
         assert!(matches!(result, Ok(None)));
     }
+
+    // ── Tests pending_migration() ────────────────────────────────────────
+
+    /// A migration recorded in the annotation is not applied again on the next reconcile
+    #[test]
+    fn test_pending_migration_runs_once() {
+        let mut inst = make_tenant(None, Some("1.0.0"));
+        inst.spec.options = serde_json::from_value(serde_json::json!({"replicas": 1})).unwrap();
+        let mut pck = make_package("pkg", "cat", "2.0.0", VynilPackageType::Tenant);
+        pck.migrations = Some(vec![VynilOptionMigration {
+            from: "1.0.0".to_string(),
+            rules: vec![VynilOptionRule::Transform {
+                path: "replicas".to_string(),
+                script: "value + 1".to_string(),
+            }],
+        }]);
+        let migrated = pending_migration(&inst, &pck, "1.0.0")
+            .unwrap()
+            .expect("a migration");
+        assert_eq!(migrated["replicas"], 2);
+
+        // What set_migrated_options writes back, status.tag is still the installed version
+        inst.spec.options = Some(migrated);
+        inst.metadata.annotations = Some(
+            [(
+                "vynil.solidite.fr/options-migrated".to_string(),
+                "1.0.0 -> 2.0.0".to_string(),
+            )]
+            .into(),
+        );
+        assert!(pending_migration(&inst, &pck, "1.0.0").unwrap().is_none());
+    }
 }
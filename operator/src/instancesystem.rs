@@ -11,7 +11,7 @@ use common::{
     rhaihandler::Script,
     vynilpackage::{VynilPackageRecommandation, VynilPackageRequirement, VynilPackageType},
 };
-use kube::{Client, runtime::controller::Action};
+use kube::{Client, ResourceExt, runtime::controller::Action};
 use opentelemetry::trace::TraceId;
 use std::sync::Arc;
 use tokio::time::Duration;
@@ -33,6 +33,10 @@ impl InstanceKind for SystemInstance {
         VynilPackageType::System
     }
 
+    fn accepts_package_type(usage: &VynilPackageType) -> bool {
+        matches!(usage, VynilPackageType::System | VynilPackageType::Bundle)
+    }
+
     fn spec_jukebox(&self) -> &str {
         &self.spec.jukebox
     }
@@ -52,14 +56,30 @@ impl InstanceKind for SystemInstance {
             .unwrap_or_default()
     }
 
+    fn installed_digest(&self) -> Option<String> {
+        self.status.as_ref().and_then(|s| s.digest.clone())
+    }
+
     fn have_child(&self) -> bool {
         self.have_child()
     }
 
-    fn get_options_digest(&mut self) -> String {
+    fn get_options_digest(&mut self) -> Result<String> {
         self.get_options_digest()
     }
 
+    fn spec_options(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.spec.options.clone().unwrap_or_default()
+    }
+
+    fn spec_auto_install_dependencies(&self) -> Option<bool> {
+        self.spec.auto_install_dependencies
+    }
+
+    fn spec_self_heal(&self) -> bool {
+        self.spec.self_heal.unwrap_or(true)
+    }
+
     async fn set_missing_box(mut self, jukebox: String) -> Result<Self> {
         SystemInstance::set_missing_box(&mut self, jukebox).await
     }
@@ -72,6 +92,54 @@ impl InstanceKind for SystemInstance {
         SystemInstance::set_missing_requirement(&mut self, reason).await
     }
 
+    async fn set_deletion_blocked(mut self, dependents: Vec<String>) -> Result<Self> {
+        SystemInstance::set_deletion_blocked(&mut self, dependents).await
+    }
+
+    async fn set_policy_violations(mut self, violations: Vec<String>) -> Result<Self> {
+        SystemInstance::set_policy_violations(&mut self, violations).await
+    }
+
+    async fn set_policy_compliant(mut self) -> Result<Self> {
+        SystemInstance::set_policy_compliant(&mut self).await
+    }
+
+    async fn set_observed_generation(mut self) -> Result<Self> {
+        SystemInstance::set_observed_generation(&mut self).await
+    }
+
+    async fn set_migrated_options(
+        mut self,
+        options: serde_json::Map<String, serde_json::Value>,
+        from: String,
+        to: String,
+    ) -> Result<Self> {
+        SystemInstance::set_migrated_options(&mut self, options, from, to).await
+    }
+
+    async fn set_effective_options(
+        mut self,
+        options: Option<serde_json::Map<String, serde_json::Value>>,
+    ) -> Result<Self> {
+        SystemInstance::set_effective_options(&mut self, options).await
+    }
+
+    async fn purge_state(&self) -> Result<()> {
+        // The delete job may have recorded new states since this object was read
+        SystemInstance::get(self.namespace().unwrap_or_default(), self.name_any())
+            .await?
+            .purge_state()
+            .await
+    }
+
+    async fn check_health(&self) -> Result<Option<bool>> {
+        SystemInstance::check_health(&mut self.clone(), &["systems"]).await
+    }
+
+    async fn self_heal(&self) -> Result<bool> {
+        SystemInstance::self_heal(&mut self.clone(), &["systems"]).await
+    }
+
     async fn check_requirements(
         &self,
         reqs: Vec<VynilPackageRequirement>,
@@ -111,6 +179,14 @@ impl InstanceKind for SystemInstance {
     fn record_reconcile_failure(&self, ctx: &Context, error: &Error) {
         ctx.metrics.system_instance.reconcile_failure(self, error);
     }
+
+    fn record_health(&self, ctx: &Context, healthy: bool) {
+        ctx.metrics.system_instance.set_health(self, healthy);
+    }
+
+    fn forget_health(&self, ctx: &Context) {
+        ctx.metrics.system_instance.remove_health(self);
+    }
 }
 
 // ── Reconciler implementation ─────────────────────────────────────────────────
@@ -9,9 +9,10 @@ use crate::{
 use async_trait::async_trait;
 use common::{
     rhaihandler::Script,
+    vynilbackup::{BackupPolicy, InstanceRef, KeyRotation, UpgradeBackup},
     vynilpackage::{VynilPackageRecommandation, VynilPackageRequirement, VynilPackageType},
 };
-use kube::{Client, runtime::controller::Action};
+use kube::{Client, ResourceExt, runtime::controller::Action};
 use opentelemetry::trace::TraceId;
 use std::sync::Arc;
 use tokio::time::Duration;
@@ -33,6 +34,10 @@ impl InstanceKind for TenantInstance {
         VynilPackageType::Tenant
     }
 
+    fn supports_reconfigure() -> bool {
+        true
+    }
+
     fn spec_jukebox(&self) -> &str {
         &self.spec.jukebox
     }
@@ -52,18 +57,58 @@ impl InstanceKind for TenantInstance {
             .unwrap_or_default()
     }
 
+    fn installed_digest(&self) -> Option<String> {
+        self.status.as_ref().and_then(|s| s.digest.clone())
+    }
+
     fn init_from_version(&self) -> Option<&str> {
         self.spec.init_from.as_ref()?.version.as_deref()
     }
 
+    fn init_from_backup(&self) -> Option<&str> {
+        self.spec.init_from.as_ref()?.backup.as_deref()
+    }
+
+    fn init_from_instance(&self) -> Option<&InstanceRef> {
+        let init_from = self.spec.init_from.as_ref()?;
+        init_from
+            .instance_ref
+            .as_ref()
+            .filter(|_| init_from.snapshot.is_none())
+    }
+
+    fn spec_backup(&self) -> Option<&BackupPolicy> {
+        self.spec.backup.as_ref()
+    }
+
+    fn upgrade_backup(&self) -> Option<&UpgradeBackup> {
+        self.status.as_ref()?.upgrade_backup.as_ref()
+    }
+
     fn have_child(&self) -> bool {
         self.have_child()
     }
 
-    fn get_options_digest(&mut self) -> String {
+    fn get_options_digest(&mut self) -> Result<String> {
         self.get_options_digest()
     }
 
+    fn spec_options(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.spec.options.clone().unwrap_or_default()
+    }
+
+    fn spec_auto_install_dependencies(&self) -> Option<bool> {
+        self.spec.auto_install_dependencies
+    }
+
+    fn spec_self_heal(&self) -> bool {
+        self.spec.self_heal.unwrap_or(true)
+    }
+
+    async fn tenant_namespaces(&self) -> Result<Vec<String>> {
+        self.get_tenant_namespaces().await
+    }
+
     async fn set_missing_box(mut self, jukebox: String) -> Result<Self> {
         TenantInstance::set_missing_box(&mut self, jukebox).await
     }
@@ -76,10 +121,79 @@ impl InstanceKind for TenantInstance {
         TenantInstance::set_missing_requirement(&mut self, reason).await
     }
 
+    async fn set_deletion_blocked(mut self, dependents: Vec<String>) -> Result<Self> {
+        TenantInstance::set_deletion_blocked(&mut self, dependents).await
+    }
+
+    async fn set_policy_violations(mut self, violations: Vec<String>) -> Result<Self> {
+        TenantInstance::set_policy_violations(&mut self, violations).await
+    }
+
+    async fn set_policy_compliant(mut self) -> Result<Self> {
+        TenantInstance::set_policy_compliant(&mut self).await
+    }
+
+    async fn set_observed_generation(mut self) -> Result<Self> {
+        TenantInstance::set_observed_generation(&mut self).await
+    }
+
+    async fn set_migrated_options(
+        mut self,
+        options: serde_json::Map<String, serde_json::Value>,
+        from: String,
+        to: String,
+    ) -> Result<Self> {
+        TenantInstance::set_migrated_options(&mut self, options, from, to).await
+    }
+
+    async fn set_effective_options(
+        mut self,
+        options: Option<serde_json::Map<String, serde_json::Value>>,
+    ) -> Result<Self> {
+        TenantInstance::set_effective_options(&mut self, options).await
+    }
+
+    async fn purge_state(&self) -> Result<()> {
+        // The delete job may have recorded new states since this object was read
+        TenantInstance::get(self.namespace().unwrap_or_default(), self.name_any())
+            .await?
+            .purge_state()
+            .await
+    }
+
+    async fn check_health(&self) -> Result<Option<bool>> {
+        TenantInstance::check_health(&mut self.clone(), &["vitals", "scalables"]).await
+    }
+
+    async fn self_heal(&self) -> Result<bool> {
+        TenantInstance::self_heal(&mut self.clone(), &["vitals", "scalables", "others"]).await
+    }
+
     async fn set_missing_init_version(mut self, version: String) -> Result<Self> {
         TenantInstance::set_missing_init_version(&mut self, version).await
     }
 
+    async fn set_init_from_instance(
+        mut self,
+        init_from: serde_json::Value,
+        options: serde_json::Map<String, serde_json::Value>,
+        source: String,
+    ) -> Result<Self> {
+        TenantInstance::set_init_from_instance(&mut self, init_from, options, source).await
+    }
+
+    async fn set_invalid_backup_policy(mut self, reason: String) -> Result<Self> {
+        TenantInstance::set_invalid_backup_policy(&mut self, reason).await
+    }
+
+    async fn set_upgrade_backup(mut self, upgrade: Option<UpgradeBackup>) -> Result<Self> {
+        TenantInstance::set_upgrade_backup(&mut self, upgrade).await
+    }
+
+    async fn set_key_rotation(mut self, rotation: KeyRotation) -> Result<Self> {
+        TenantInstance::set_key_rotation(&mut self, rotation).await
+    }
+
     async fn check_requirements(
         &self,
         reqs: Vec<VynilPackageRequirement>,
@@ -140,6 +254,14 @@ impl InstanceKind for TenantInstance {
     fn record_reconcile_failure(&self, ctx: &Context, error: &Error) {
         ctx.metrics.tenant_instance.reconcile_failure(self, error);
     }
+
+    fn record_health(&self, ctx: &Context, healthy: bool) {
+        ctx.metrics.tenant_instance.set_health(self, healthy);
+    }
+
+    fn forget_health(&self, ctx: &Context) {
+        ctx.metrics.tenant_instance.remove_health(self);
+    }
 }
 
 // ── Reconciler implementation ─────────────────────────────────────────────────
@@ -1,6 +1,7 @@
 use crate::{Error, JukeBox, Reconciler, Result, get_client_name, manager::Context, telemetry};
 use async_trait::async_trait;
 use chrono::Utc;
+use common::jukebox::{ConditionsStatus, ConditionsType};
 use k8s_openapi::api::batch::v1::{CronJob, Job};
 use kube::{
     api::{Api, DeleteParams, Patch, PatchParams, PostParams, ResourceExt},
@@ -52,11 +53,11 @@ impl Reconciler for JukeBox {
         let job_api: Api<Job> = Api::namespaced(client.clone(), ns);
         let force_scan = self.annotations().get("vynil.solidite.fr/force-scan").cloned();
 
-        // Upsert cache from current status before any guard — this ensures the cache
-        // is updated on every status change regardless of Job state (fixes latency and
-        // cron scan cases).
+        // Reload the cache from the catalog before any guard — this ensures the cache
+        // is updated on every catalog generation change regardless of Job state (fixes
+        // latency and cron scan cases).
         if ctx.cache_needs_update(self).await {
-            ctx.upsert_jukebox_cache(self).await;
+            ctx.upsert_jukebox_cache(self).await?;
         }
 
         // Guard: if scan job exists and is still running, requeue.
@@ -109,7 +110,7 @@ impl Reconciler for JukeBox {
         //  - force-scan annotation is present (explicit user request).
         // A terminal job without force-scan is left untouched; periodic rescans are
         // the CronJob's responsibility.
-        if should_create_scan_job(job_is_terminal, &force_scan) {
+        if should_create_scan_job(job_is_terminal, &force_scan) || needs_catalog_migration(self) {
             // force-scan: delete the known terminal job and inject the package filter.
             // Annotation is removed ONLY after successful job creation so that a creation
             // failure retries on the next reconcile.
@@ -218,6 +219,18 @@ fn should_create_scan_job(job_is_terminal: bool, force_scan: &Option<String>) ->
     !job_is_terminal || force_scan.is_some()
 }
 
+/// A JukeBox last scanned successfully before the catalog moved out of its status has no
+/// VynilPackage yet: rescan it instead of waiting for the next scheduled scan
+fn needs_catalog_migration(jukebox: &JukeBox) -> bool {
+    jukebox.status.as_ref().is_some_and(|s| {
+        s.summary.is_none()
+            && s.catalog_generation == 0
+            && s.conditions
+                .iter()
+                .any(|c| c.condition_type == ConditionsType::Updated && c.status == ConditionsStatus::True)
+    })
+}
+
 fn inject_package_filter(context: &mut Value, filter_value: &str) {
     if filter_value != "true" && !filter_value.is_empty() {
         context
@@ -254,7 +267,7 @@ mod tests {
     use crate::manager::{Context, Diagnostics, JukeCacheItem};
     use common::{
         handlebarshandler::HandleBars,
-        jukebox::{JukeBoxSpec, JukeBoxStatus},
+        jukebox::{ApplicationCondition, JukeBoxSpec, JukeBoxStatus},
         vynilpackage::{VynilPackage, VynilPackageMeta, VynilPackageType},
     };
     use http::{Request, Response, StatusCode};
@@ -281,10 +294,12 @@ mod tests {
             recommandations: None,
             options: None,
             value_script: None,
+            migrations: None,
+            options_migrate: None,
         }
     }
 
-    fn make_jukebox(name: &str, packages: Vec<VynilPackage>) -> JukeBox {
+    fn make_jukebox(name: &str, generation: i64) -> JukeBox {
         JukeBox {
             metadata: ObjectMeta {
                 name: Some(name.to_string()),
@@ -295,10 +310,12 @@ mod tests {
                 pull_secret: None,
                 source: None,
                 maturity: None,
+                auto_install_dependencies: None,
             },
             status: Some(JukeBoxStatus {
                 conditions: vec![],
-                packages,
+                summary: None,
+                catalog_generation: generation,
             }),
         }
     }
@@ -324,6 +341,21 @@ mod tests {
         .unwrap()
     }
 
+    fn catalog_list_body(jukebox: &str, packages: Vec<VynilPackage>) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "apiVersion": "vynil.solidite.fr/v1",
+            "kind": "VynilPackageList",
+            "metadata": {},
+            "items": [{
+                "apiVersion": "vynil.solidite.fr/v1",
+                "kind": "VynilPackage",
+                "metadata": { "name": format!("{jukebox}.db.pg") },
+                "spec": { "jukebox": jukebox, "category": "db", "name": "pg", "versions": packages },
+            }],
+        }))
+        .unwrap()
+    }
+
     fn not_found_body(resource: &str, name: &str) -> Vec<u8> {
         serde_json::to_vec(&json!({
             "kind": "Status",
@@ -337,12 +369,22 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn cache_updated_from_status_while_job_running() {
+    async fn cache_updated_from_catalog_while_job_running() {
         let pkg = make_pkg("db", "pg");
-        let jb = make_jukebox("box-a", vec![pkg.clone()]);
+        let jb = make_jukebox("box-a", 1);
+        let catalog = catalog_list_body("box-a", vec![pkg.clone()]);
         let (mock_svc, handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
         let spawned = tokio::spawn(async move {
             let mut h = pin!(handle);
+            let (req, send) = h.next_request().await.expect("expected catalog LIST request");
+            assert!(req.uri().path().ends_with("/vynilpackages"));
+            send.send_response(
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(catalog))
+                    .unwrap(),
+            );
             let (_req, send) = h.next_request().await.expect("expected job GET request");
             send.send_response(
                 Response::builder()
@@ -362,6 +404,7 @@ mod tests {
             "cache must be updated despite running job"
         );
         assert_eq!(cache["box-a"].packages, vec![pkg]);
+        assert_eq!(cache["box-a"].generation, 1);
         drop(cache);
         spawned.await.unwrap();
     }
@@ -369,10 +412,11 @@ mod tests {
     #[tokio::test]
     async fn cleanup_removes_cache_entry() {
         let pkg = make_pkg("db", "pg");
-        let jb = make_jukebox("box-a", vec![pkg.clone()]);
+        let jb = make_jukebox("box-a", 1);
         let mut initial_cache = BTreeMap::new();
         initial_cache.insert("box-a".to_string(), JukeCacheItem {
             pull_secret: None,
+            generation: 1,
             packages: vec![pkg],
         });
         let (mock_svc, handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
@@ -409,7 +453,17 @@ mod tests {
         spawned.await.unwrap();
     }
 
-    use super::{inject_package_filter, should_create_scan_job};
+    use super::{inject_package_filter, needs_catalog_migration, should_create_scan_job};
+
+    #[test]
+    fn legacy_status_needs_catalog_migration() {
+        let mut jb = make_jukebox("box-a", 0);
+        assert!(!needs_catalog_migration(&jb));
+        jb.status.as_mut().unwrap().conditions = vec![ApplicationCondition::updated_ok(1)];
+        assert!(needs_catalog_migration(&jb));
+        jb.status.as_mut().unwrap().catalog_generation = 1;
+        assert!(!needs_catalog_migration(&jb));
+    }
 
     #[test]
     fn no_job_always_creates_scan_job() {
@@ -2,15 +2,23 @@ use async_trait::async_trait;
 use kube::runtime::controller::Action;
 use manager::Context;
 use std::sync::Arc;
+pub mod clone;
+pub mod defaults;
+pub mod dependencies;
+pub mod drill;
 pub mod instance_common;
 pub mod instanceservice;
 pub mod instancesystem;
 pub mod instancetenant;
 pub mod jukebox;
+pub mod policy;
+pub mod rotation;
+pub mod tenantprofile;
+pub mod upgrade;
 
 pub use common::{
     Error, Result, instanceservice::ServiceInstance, instancesystem::SystemInstance,
-    instancetenant::TenantInstance, jukebox::JukeBox,
+    instancetenant::TenantInstance, jukebox::JukeBox, tenantprofile::TenantProfile,
 };
 
 #[async_trait]
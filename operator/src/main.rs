@@ -3,9 +3,10 @@ pub use controller::*;
 use tracing_subscriber::{EnvFilter, Registry, prelude::*};
 
 use actix_web::{
-    App, HttpRequest, HttpResponse, HttpServer, Responder, get, middleware,
+    App, HttpRequest, HttpResponse, HttpServer, Responder, get, middleware, post,
     web::{self, Data},
 };
+use std::{fs::File, io::BufReader};
 
 #[get("/metrics")]
 async fn metrics(c: Data<Manager>, _req: HttpRequest) -> impl Responder {
@@ -20,6 +21,34 @@ async fn health(_: HttpRequest) -> impl Responder {
     HttpResponse::Ok().json("healthy")
 }
 
+#[post("/validate")]
+async fn validate(review: web::Json<serde_json::Value>) -> impl Responder {
+    let client = common::context::get_client_async().await;
+    HttpResponse::Ok().json(controller::policy::review(client, review.into_inner()).await)
+}
+
+/// TLS configuration of the admission webhook listener, when a certificate is provided
+fn webhook_tls() -> Option<rustls::ServerConfig> {
+    let cert = std::env::var("WEBHOOK_TLS_CERT").ok()?;
+    let key = std::env::var("WEBHOOK_TLS_KEY").ok()?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(&cert).expect("Can not open the webhook certificate"),
+    ))
+    .collect::<std::result::Result<Vec<_>, _>>()
+    .expect("Invalid webhook certificate");
+    let key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(&key).expect("Can not open the webhook key"),
+    ))
+    .expect("Invalid webhook key")
+    .expect("No private key found in the webhook key");
+    Some(
+        rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .expect("Invalid webhook certificate"),
+    )
+}
+
 #[get("/")]
 async fn index(c: Data<Manager>, _req: HttpRequest) -> impl Responder {
     let d = c.diagnostics().await;
@@ -60,7 +89,8 @@ async fn async_main() -> Result<()> {
 
     common::context::init_k8s();
     // Start kubernetes controller
-    let (manager, controller_jbs, controller_tnts, controller_stms, controller_svcs) = Manager::new().await;
+    let (manager, controller_jbs, controller_tnts, controller_stms, controller_svcs, controller_prfs) =
+        Manager::new().await;
 
     // Start web server
     let server = HttpServer::new(move || {
@@ -75,12 +105,29 @@ async fn async_main() -> Result<()> {
     .expect("Can not bind to 0.0.0.0:9000")
     .shutdown_timeout(5);
 
+    // The admission webhook is only served over TLS
+    let webhook = webhook_tls().map(|tls| {
+        HttpServer::new(|| App::new().wrap(middleware::Logger::default()).service(validate))
+            .bind_rustls_0_23("0.0.0.0:9443", tls)
+            .expect("Can not bind to 0.0.0.0:9443")
+            .shutdown_timeout(5)
+            .run()
+    });
+    let webhook = async {
+        match webhook {
+            Some(webhook) => webhook.await,
+            None => std::future::pending().await,
+        }
+    };
+
     tokio::select! {
         _ = controller_jbs => tracing::warn!("JukeBox controller exited"),
         _ = controller_tnts => tracing::warn!("TenantInstance controller exited"),
         _ = controller_stms => tracing::warn!("SystemInstance controller exited"),
         _ = controller_svcs => tracing::warn!("ServiceInstance controller exited"),
+        _ = controller_prfs => tracing::warn!("TenantProfile controller exited"),
         _ = server.run() => tracing::info!("actix exited"),
+        _ = webhook => tracing::info!("admission webhook exited"),
     }
     Ok(())
 }
@@ -1,23 +1,30 @@
 use crate::{
-    JukeBox, Metrics, ServiceInstance, SystemInstance, TenantInstance, instanceservice, instancesystem,
-    instancetenant, jukebox,
+    JukeBox, Metrics, Result, ServiceInstance, SystemInstance, TenantInstance, TenantProfile,
+    instance_common::monitor_health, instanceservice, instancesystem, instancetenant, jukebox, tenantprofile,
 };
 use chrono::{DateTime, Utc};
-use common::{handlebarshandler::HandleBars, vynilpackage::VynilPackage};
+use common::{catalog, handlebarshandler::HandleBars, vynilpackage::VynilPackage};
 use futures::{FutureExt, StreamExt, future::BoxFuture};
+use k8s_openapi::api::{
+    apps::v1::{DaemonSet, Deployment, StatefulSet},
+    core::v1::{ConfigMap, Namespace, PersistentVolumeClaim, Secret, Service},
+    networking::v1::Ingress,
+};
 use kube::{
-    ResourceExt,
+    Resource, ResourceExt,
     api::{Api, ListParams, ObjectList},
     client::Client,
-    runtime::{controller::Controller, events::Reporter, watcher::Config},
+    runtime::{controller::Controller, events::Reporter, reflector::ObjectRef, watcher::Config},
 };
-use serde::Serialize;
+use serde::{Serialize, de::DeserializeOwned};
 use serde_json::{Value, json};
 use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 use tokio::sync::RwLock;
 
 pub struct JukeCacheItem {
     pub pull_secret: Option<String>,
+    /// Catalog generation of the JukeBox status these packages were loaded for
+    pub generation: i64,
     pub packages: Vec<VynilPackage>,
 }
 
@@ -37,28 +44,86 @@ pub struct Context {
     /// Packages cache
     pub packages: Arc<RwLock<BTreeMap<String, JukeCacheItem>>>,
 }
+/// Instance owning a child, from the labels the agent set on it
+pub(crate) fn owner_of<K: Resource<DynamicType = ()>>(child: &impl ResourceExt) -> Option<ObjectRef<K>> {
+    let labels = child.labels();
+    let name = labels.get("app.kubernetes.io/instance")?;
+    let namespace = labels.get("vynil.solidite.fr/owner-namespace")?;
+    Some(ObjectRef::new(name).within(namespace))
+}
+
+fn watch_kind<K, C>(ctrl: Controller<K>, client: &Client, owner_type: &str) -> Controller<K>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static,
+    C: Resource<DynamicType = ()> + Clone + DeserializeOwned + std::fmt::Debug + Send + 'static,
+{
+    ctrl.watches(
+        Api::<C>::all(client.clone()),
+        Config::default().labels(&format!(
+            "app.kubernetes.io/managed-by=vynil,vynil.solidite.fr/owner-type={owner_type}"
+        )),
+        |child: C| owner_of::<K>(&child),
+    )
+}
+
+/// Reconcile an instance when one of its usual children changes or is deleted
+fn watch_children<K>(ctrl: Controller<K>, client: &Client, owner_type: &str) -> Controller<K>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static,
+{
+    let ctrl = watch_kind::<K, Deployment>(ctrl, client, owner_type);
+    let ctrl = watch_kind::<K, StatefulSet>(ctrl, client, owner_type);
+    let ctrl = watch_kind::<K, DaemonSet>(ctrl, client, owner_type);
+    let ctrl = watch_kind::<K, Service>(ctrl, client, owner_type);
+    let ctrl = watch_kind::<K, Ingress>(ctrl, client, owner_type);
+    let ctrl = watch_kind::<K, ConfigMap>(ctrl, client, owner_type);
+    let ctrl = watch_kind::<K, Secret>(ctrl, client, owner_type);
+    watch_kind::<K, PersistentVolumeClaim>(ctrl, client, owner_type)
+}
+
 pub(crate) fn cache_entry_differs(cache: &BTreeMap<String, JukeCacheItem>, jukebox: &JukeBox) -> bool {
     let Some(status) = &jukebox.status else {
         return false;
     };
     match cache.get(&jukebox.name_any()) {
-        Some(entry) => entry.packages != status.packages || entry.pull_secret != jukebox.spec.pull_secret,
+        Some(entry) => {
+            entry.generation != status.catalog_generation || entry.pull_secret != jukebox.spec.pull_secret
+        }
         None => true,
     }
 }
 
-pub(crate) fn upsert_cache_entry(cache: &mut BTreeMap<String, JukeCacheItem>, jukebox: &JukeBox) {
+pub(crate) fn upsert_cache_entry(
+    cache: &mut BTreeMap<String, JukeCacheItem>,
+    jukebox: &JukeBox,
+    packages: Vec<VynilPackage>,
+) {
     let Some(status) = &jukebox.status else { return };
     cache.insert(jukebox.name_any(), JukeCacheItem {
         pull_secret: jukebox.spec.pull_secret.clone(),
-        packages: status.packages.clone(),
+        generation: status.catalog_generation,
+        packages,
     });
 }
 
+/// Build the packages cache of the listed JukeBoxes from the catalog
+async fn load_package_cache(client: Client, jukeboxes: &[JukeBox]) -> Result<BTreeMap<String, JukeCacheItem>> {
+    let mut all = catalog::load_all(client).await?;
+    let mut cache = BTreeMap::new();
+    for juke in jukeboxes {
+        let packages = all.remove(&juke.name_any()).unwrap_or_default();
+        upsert_cache_entry(&mut cache, juke, packages);
+    }
+    Ok(cache)
+}
+
 impl Context {
-    pub async fn upsert_jukebox_cache(&self, jukebox: &JukeBox) {
+    /// Reload the packages of a JukeBox from the catalog
+    pub async fn upsert_jukebox_cache(&self, jukebox: &JukeBox) -> Result<()> {
+        let packages = catalog::load(self.client.clone(), &jukebox.name_any()).await?;
         let mut cache = self.packages.write().await;
-        upsert_cache_entry(&mut cache, jukebox);
+        upsert_cache_entry(&mut cache, jukebox, packages);
+        Ok(())
     }
 
     pub async fn remove_jukebox_cache(&self, name: &str) {
@@ -71,15 +136,13 @@ impl Context {
     }
 
     pub async fn set_package_cache(&self, list: &ObjectList<JukeBox>) {
-        let mut cache = BTreeMap::new();
-        for juke in list.items.clone() {
-            if let Some(status) = juke.status.clone() {
-                cache.insert(juke.name_any(), JukeCacheItem {
-                    pull_secret: juke.spec.pull_secret.clone(),
-                    packages: status.packages,
-                });
+        let cache = match load_package_cache(self.client.clone(), &list.items).await {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!("While loading the packages catalog: {:?}", e);
+                return;
             }
-        }
+        };
         let jukes = list
             .items
             .clone()
@@ -128,7 +191,7 @@ pub struct Manager {
     metrics: Arc<Metrics>,
 }
 
-/// Manager that owns a Controller for JukeBox, SystemInstance, and TenantInstance
+/// Manager that owns a Controller for JukeBox, SystemInstance, TenantInstance and TenantProfile
 impl Manager {
     pub async fn new() -> (
         Self,
@@ -136,6 +199,7 @@ impl Manager {
         BoxFuture<'static, ()>,
         BoxFuture<'static, ()>,
         BoxFuture<'static, ()>,
+        BoxFuture<'static, ()>,
     ) {
         let client = Client::try_default().await.expect("create client");
         let manager = Manager::default();
@@ -147,34 +211,45 @@ impl Manager {
         }
         let packages: Arc<RwLock<BTreeMap<String, JukeCacheItem>>> = Arc::default();
         match JukeBox::list().await {
-            Ok(list) => {
-                let mut cache = BTreeMap::new();
-                for juke in list.items.clone() {
-                    if let Some(status) = juke.status.clone() {
-                        cache.insert(juke.name_any(), JukeCacheItem {
-                            pull_secret: juke.spec.pull_secret.clone(),
-                            packages: status.packages,
-                        });
-                    }
+            Ok(list) => match load_package_cache(client.clone(), &list.items).await {
+                Ok(cache) => {
+                    tracing::debug!("Initialize packages cache");
+                    *packages.write().await = cache;
+                    tracing::debug!("Initialize packages cache done");
                 }
-                tracing::debug!("Initialize packages cache");
-                *packages.write().await = cache;
-                tracing::debug!("Initialize packages cache done");
-            }
+                Err(e) => tracing::warn!("While loading the packages catalog: {:?}", e),
+            },
             Err(e) => tracing::warn!("While listing jukebox: {:?}", e),
         };
 
+        let vynil_ns = std::env::var("VYNIL_NAMESPACE").unwrap_or_else(|_| "vynil-system".to_string());
+        let state_key =
+            std::env::var("STATE_ENCRYPTION_SECRET").unwrap_or_else(|_| "vynil-state-key".to_string());
+        let ensured = common::statestore::ensure_encryption_key(client.clone(), &vynil_ns, &state_key);
+        if let Err(e) = ensured.await {
+            tracing::warn!("While creating the state encryption key: {:?}", e);
+        }
+
         let context = Arc::new(Context {
             client: client.clone(),
             metrics: manager.metrics.clone(),
             diagnostics: manager.diagnostics.clone(),
             renderer: hbs,
             base_context: json!({
-                "vynil_namespace": std::env::var("VYNIL_NAMESPACE").unwrap_or_else(|_| "vynil-system".to_string()),
+                "vynil_namespace": vynil_ns,
                 "agent_image": std::env::var("AGENT_IMAGE").unwrap_or_else(|_| common::DEFAULT_AGENT_IMAGE.to_string()),
                 "service_account": std::env::var("AGENT_ACCOUNT").unwrap_or_else(|_| "vynil-agent".to_string()),
                 "log_level": std::env::var("AGENT_LOG_LEVEL").unwrap_or_else(|_| "info".to_string()),
                 "label_key": std::env::var("TENANT_LABEL").unwrap_or_else(|_| "vynil.solidite.fr/tenant".to_string()),
+                "state": {
+                    "backend": std::env::var("STATE_BACKEND").unwrap_or_else(|_| "secret".to_string()),
+                    "s3_bucket": std::env::var("STATE_S3_BUCKET").unwrap_or_default(),
+                    "s3_region": std::env::var("STATE_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                    "s3_endpoint": std::env::var("STATE_S3_ENDPOINT").unwrap_or_default(),
+                    "s3_prefix": std::env::var("STATE_S3_PREFIX").unwrap_or_default(),
+                    "s3_secret": std::env::var("STATE_S3_SECRET").unwrap_or_default(),
+                    "encryption_secret": state_key,
+                },
             }),
             packages,
         });
@@ -182,7 +257,9 @@ impl Manager {
         let jbs = Api::<JukeBox>::all(client.clone());
         let tnts = Api::<TenantInstance>::all(client.clone());
         let svcs = Api::<ServiceInstance>::all(client.clone());
-        let stms = Api::<SystemInstance>::all(client);
+        let stms = Api::<SystemInstance>::all(client.clone());
+        let prfs = Api::<TenantProfile>::all(client.clone());
+        let nss = Api::<Namespace>::all(client);
         // Ensure CRD is installed before loop-watching
         let _r = jbs
             .list(&ListParams::default().limit(1))
@@ -200,6 +277,15 @@ impl Manager {
             .list(&ListParams::default().limit(1))
             .await
             .expect("is the crd installed?");
+        let _r = prfs
+            .list(&ListParams::default().limit(1))
+            .await
+            .expect("is the crd installed?");
+
+        // Health checks run apart from the reconciliations
+        tokio::spawn(monitor_health::<TenantInstance>(context.clone()));
+        tokio::spawn(monitor_health::<ServiceInstance>(context.clone()));
+        tokio::spawn(monitor_health::<SystemInstance>(context.clone()));
 
         // All good. Start controller and return its future.
         let controller_jbs = Controller::new(jbs, Config::default().any_semantic())
@@ -207,7 +293,12 @@ impl Manager {
             .filter_map(|x| async move { std::result::Result::ok(x) })
             .for_each(|_| futures::future::ready(()))
             .boxed();
-        let controller_tnts = Controller::new(tnts, Config::default().any_semantic())
+        let tnts_ctrl = watch_children(
+            Controller::new(tnts, Config::default().any_semantic()),
+            &context.client,
+            "tenant",
+        );
+        let controller_tnts = tnts_ctrl
             .run(
                 instancetenant::reconcile,
                 instancetenant::error_policy,
@@ -216,7 +307,12 @@ impl Manager {
             .filter_map(|x| async move { std::result::Result::ok(x) })
             .for_each(|_| futures::future::ready(()))
             .boxed();
-        let controller_stms = Controller::new(stms, Config::default().any_semantic())
+        let stms_ctrl = watch_children(
+            Controller::new(stms, Config::default().any_semantic()),
+            &context.client,
+            "system",
+        );
+        let controller_stms = stms_ctrl
             .run(
                 instancesystem::reconcile,
                 instancesystem::error_policy,
@@ -225,7 +321,12 @@ impl Manager {
             .filter_map(|x| async move { std::result::Result::ok(x) })
             .for_each(|_| futures::future::ready(()))
             .boxed();
-        let controller_svcs = Controller::new(svcs, Config::default().any_semantic())
+        let svcs_ctrl = watch_children(
+            Controller::new(svcs, Config::default().any_semantic()),
+            &context.client,
+            "service",
+        );
+        let controller_svcs = svcs_ctrl
             .run(
                 instanceservice::reconcile,
                 instanceservice::error_policy,
@@ -234,12 +335,40 @@ impl Manager {
             .filter_map(|x| async move { std::result::Result::ok(x) })
             .for_each(|_| futures::future::ready(()))
             .boxed();
+        // Profiles are reconciled when a namespace they select (or used to select) changes,
+        // and when one of the TenantInstances they own changes.
+        let profile_ctrl = Controller::new(prfs, Config::default().any_semantic());
+        let profiles = profile_ctrl.store();
+        let controller_prfs = profile_ctrl
+            .owns(
+                Api::<TenantInstance>::all(context.client.clone()),
+                Config::default().labels(common::tenantprofile::PROFILE_LABEL),
+            )
+            .watches(nss, Config::default(), move |ns: Namespace| {
+                let name = ns.name_any();
+                let labels = ns.labels().clone();
+                profiles
+                    .state()
+                    .into_iter()
+                    .filter(|p| p.selects(&labels) || p.manages(&name))
+                    .map(|p| ObjectRef::from_obj(&*p))
+                    .collect::<Vec<_>>()
+            })
+            .run(
+                tenantprofile::reconcile,
+                tenantprofile::error_policy,
+                context.clone(),
+            )
+            .filter_map(|x| async move { std::result::Result::ok(x) })
+            .for_each(|_| futures::future::ready(()))
+            .boxed();
         (
             manager,
             controller_jbs,
             controller_tnts,
             controller_stms,
             controller_svcs,
+            controller_prfs,
         )
     }
 
@@ -250,6 +379,7 @@ impl Manager {
         prometheus_client::encoding::text::encode_registry(&mut buffer, &self.metrics.reg_sys).unwrap();
         prometheus_client::encoding::text::encode_registry(&mut buffer, &self.metrics.reg_svc).unwrap();
         prometheus_client::encoding::text::encode_registry(&mut buffer, &self.metrics.reg_tnt).unwrap();
+        prometheus_client::encoding::text::encode_registry(&mut buffer, &self.metrics.reg_prf).unwrap();
         prometheus_client::encoding::text::encode_eof(&mut buffer).unwrap();
         buffer
     }
@@ -287,10 +417,12 @@ mod tests {
             recommandations: None,
             options: None,
             value_script: None,
+            migrations: None,
+            options_migrate: None,
         }
     }
 
-    fn make_jukebox(name: &str, packages: Vec<VynilPackage>, pull_secret: Option<String>) -> JukeBox {
+    fn make_jukebox(name: &str, generation: i64, pull_secret: Option<String>) -> JukeBox {
         JukeBox {
             metadata: ObjectMeta {
                 name: Some(name.to_string()),
@@ -301,10 +433,12 @@ mod tests {
                 pull_secret,
                 source: None,
                 maturity: None,
+                auto_install_dependencies: None,
             },
             status: Some(JukeBoxStatus {
                 conditions: vec![],
-                packages,
+                summary: None,
+                catalog_generation: generation,
             }),
         }
     }
@@ -312,49 +446,50 @@ mod tests {
     #[test]
     fn cache_entry_differs_when_not_in_cache() {
         let cache = BTreeMap::new();
-        let jb = make_jukebox("box-a", vec![make_pkg("db", "pg")], None);
+        let jb = make_jukebox("box-a", 1, None);
         assert!(cache_entry_differs(&cache, &jb));
     }
 
     #[test]
-    fn cache_entry_differs_when_packages_changed() {
+    fn cache_entry_differs_when_catalog_generation_changed() {
         let mut cache = BTreeMap::new();
         cache.insert("box-a".to_string(), JukeCacheItem {
             pull_secret: None,
+            generation: 1,
             packages: vec![make_pkg("db", "old")],
         });
-        let jb = make_jukebox("box-a", vec![make_pkg("db", "pg")], None);
+        let jb = make_jukebox("box-a", 2, None);
         assert!(cache_entry_differs(&cache, &jb));
     }
 
     #[test]
     fn cache_entry_idempotent_when_status_unchanged() {
-        let pkg = make_pkg("db", "pg");
         let mut cache = BTreeMap::new();
         cache.insert("box-a".to_string(), JukeCacheItem {
             pull_secret: None,
-            packages: vec![pkg.clone()],
+            generation: 1,
+            packages: vec![make_pkg("db", "pg")],
         });
-        let jb = make_jukebox("box-a", vec![pkg], None);
+        let jb = make_jukebox("box-a", 1, None);
         assert!(!cache_entry_differs(&cache, &jb));
     }
 
     #[test]
     fn cache_entry_differs_when_pull_secret_changed() {
-        let pkg = make_pkg("db", "pg");
         let mut cache = BTreeMap::new();
         cache.insert("box-a".to_string(), JukeCacheItem {
             pull_secret: None,
-            packages: vec![pkg.clone()],
+            generation: 1,
+            packages: vec![make_pkg("db", "pg")],
         });
-        let jb = make_jukebox("box-a", vec![pkg], Some("new-secret".to_string()));
+        let jb = make_jukebox("box-a", 1, Some("new-secret".to_string()));
         assert!(cache_entry_differs(&cache, &jb));
     }
 
     #[test]
     fn cache_entry_does_not_differ_when_no_status() {
         let cache = BTreeMap::new();
-        let mut jb = make_jukebox("box-a", vec![], None);
+        let mut jb = make_jukebox("box-a", 0, None);
         jb.status = None;
         assert!(!cache_entry_differs(&cache, &jb));
     }
@@ -362,10 +497,11 @@ mod tests {
     #[test]
     fn upsert_cache_entry_inserts_new() {
         let mut cache = BTreeMap::new();
-        let jb = make_jukebox("box-a", vec![make_pkg("db", "pg")], None);
-        upsert_cache_entry(&mut cache, &jb);
+        let jb = make_jukebox("box-a", 3, None);
+        upsert_cache_entry(&mut cache, &jb, vec![make_pkg("db", "pg")]);
         assert!(cache.contains_key("box-a"));
         assert_eq!(cache["box-a"].packages.len(), 1);
+        assert_eq!(cache["box-a"].generation, 3);
     }
 
     #[test]
@@ -373,11 +509,13 @@ mod tests {
         let mut cache = BTreeMap::new();
         cache.insert("box-a".to_string(), JukeCacheItem {
             pull_secret: None,
+            generation: 1,
             packages: vec![make_pkg("db", "old")],
         });
-        let jb = make_jukebox("box-a", vec![make_pkg("db", "new")], None);
-        upsert_cache_entry(&mut cache, &jb);
+        let jb = make_jukebox("box-a", 2, None);
+        upsert_cache_entry(&mut cache, &jb, vec![make_pkg("db", "new")]);
         assert_eq!(cache["box-a"].packages[0].metadata.name, "new");
+        assert!(!cache_entry_differs(&cache, &jb));
     }
 
     #[test]
@@ -385,10 +523,11 @@ mod tests {
         let mut cache = BTreeMap::new();
         cache.insert("box-b".to_string(), JukeCacheItem {
             pull_secret: None,
+            generation: 1,
             packages: vec![make_pkg("monitoring", "prom")],
         });
-        let jb = make_jukebox("box-a", vec![make_pkg("db", "pg")], None);
-        upsert_cache_entry(&mut cache, &jb);
+        let jb = make_jukebox("box-a", 1, None);
+        upsert_cache_entry(&mut cache, &jb, vec![make_pkg("db", "pg")]);
         assert!(cache.contains_key("box-a"));
         assert!(cache.contains_key("box-b"));
     }
@@ -396,9 +535,23 @@ mod tests {
     #[test]
     fn upsert_no_op_when_no_status() {
         let mut cache = BTreeMap::new();
-        let mut jb = make_jukebox("box-a", vec![], None);
+        let mut jb = make_jukebox("box-a", 0, None);
         jb.status = None;
-        upsert_cache_entry(&mut cache, &jb);
+        upsert_cache_entry(&mut cache, &jb, vec![]);
         assert!(!cache.contains_key("box-a"));
     }
+
+    #[test]
+    fn children_map_to_their_owner_instance() {
+        let mut svc = Service::default();
+        svc.metadata.labels = Some(BTreeMap::from([
+            ("app.kubernetes.io/instance".to_string(), "web".to_string()),
+            ("vynil.solidite.fr/owner-namespace".to_string(), "team-a".to_string()),
+        ]));
+        let owner = owner_of::<TenantInstance>(&svc).unwrap();
+        assert_eq!(owner.name, "web");
+        assert_eq!(owner.namespace.as_deref(), Some("team-a"));
+        svc.metadata.labels = None;
+        assert!(owner_of::<TenantInstance>(&svc).is_none());
+    }
 }
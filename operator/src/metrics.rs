@@ -1,9 +1,9 @@
-use crate::{Error, JukeBox, ServiceInstance, SystemInstance, TenantInstance};
+use crate::{Error, JukeBox, ServiceInstance, SystemInstance, TenantInstance, TenantProfile};
 use kube::ResourceExt;
 use opentelemetry::trace::TraceId;
 use prometheus_client::{
     encoding::EncodeLabelSet,
-    metrics::{counter::Counter, exemplar::HistogramWithExemplars, family::Family},
+    metrics::{counter::Counter, exemplar::HistogramWithExemplars, family::Family, gauge::Gauge},
     registry::{Registry, Unit},
 };
 use std::sync::Arc;
@@ -15,10 +15,12 @@ pub struct Metrics {
     pub system_instance: ReconcileMetricsSystemInstance,
     pub service_instance: ReconcileMetricsServiceInstance,
     pub tenant_instance: ReconcileMetricsTenantInstance,
+    pub tenant_profile: ReconcileMetricsTenantProfile,
     pub reg_box: Arc<Registry>,
     pub reg_sys: Arc<Registry>,
     pub reg_svc: Arc<Registry>,
     pub reg_tnt: Arc<Registry>,
+    pub reg_prf: Arc<Registry>,
 }
 
 impl Default for Metrics {
@@ -27,19 +29,23 @@ impl Default for Metrics {
         let mut reg_sys = Registry::with_prefix("system_instance_reconcile");
         let mut reg_svc = Registry::with_prefix("service_instance_reconcile");
         let mut reg_tnt = Registry::with_prefix("tenant_instance_reconcile");
+        let mut reg_prf = Registry::with_prefix("tenant_profile_reconcile");
         let jukebox = ReconcileMetricsJukebox::default().register(&mut reg_box);
         let system_instance = ReconcileMetricsSystemInstance::default().register(&mut reg_sys);
         let service_instance = ReconcileMetricsServiceInstance::default().register(&mut reg_svc);
         let tenant_instance = ReconcileMetricsTenantInstance::default().register(&mut reg_tnt);
+        let tenant_profile = ReconcileMetricsTenantProfile::default().register(&mut reg_prf);
         Self {
             reg_box: Arc::new(reg_box),
             reg_sys: Arc::new(reg_sys),
             reg_svc: Arc::new(reg_svc),
             reg_tnt: Arc::new(reg_tnt),
+            reg_prf: Arc::new(reg_prf),
             jukebox,
             system_instance,
             service_instance,
             tenant_instance,
+            tenant_profile,
         }
     }
 }
@@ -191,6 +197,7 @@ pub struct ReconcileMetricsSystemInstance {
     pub runs: Family<LabelInstance, Counter>,
     pub failures: Family<ErrorLabelsInstance, Counter>,
     pub duration: Family<LabelInstance, HistogramWithExemplars<TraceLabel>>,
+    pub healthy: Family<LabelInstance, Gauge>,
 }
 
 impl Default for ReconcileMetricsSystemInstance {
@@ -201,6 +208,7 @@ impl Default for ReconcileMetricsSystemInstance {
             duration: Family::new_with_constructor(|| {
                 HistogramWithExemplars::new([0.01, 0.1, 0.5, 1., 5., 15., 60., 120., 300.].into_iter())
             }),
+            healthy: Family::<LabelInstance, Gauge>::default(),
         }
     }
 }
@@ -215,6 +223,11 @@ impl ReconcileMetricsSystemInstance {
             self.duration.clone(),
         );
         r.register("failures", "reconciliation errors", self.failures.clone());
+        r.register(
+            "healthy",
+            "1 when every monitored child is healthy",
+            self.healthy.clone(),
+        );
         r.register("runs", "reconciliations", self.runs.clone());
         self
     }
@@ -232,6 +245,29 @@ impl ReconcileMetricsSystemInstance {
             .inc();
     }
 
+    pub fn set_health(&self, doc: &SystemInstance, healthy: bool) {
+        self.healthy
+            .get_or_create(&LabelInstance {
+                name: doc.name_any(),
+                namespace: doc.namespace(),
+                jukebox: doc.spec.jukebox.clone(),
+                category: doc.spec.category.clone(),
+                package: doc.spec.package.clone(),
+            })
+            .set(i64::from(healthy));
+    }
+
+    /// Drop the health series of a deleted instance
+    pub fn remove_health(&self, doc: &SystemInstance) {
+        self.healthy.remove(&LabelInstance {
+            name: doc.name_any(),
+            namespace: doc.namespace(),
+            jukebox: doc.spec.jukebox.clone(),
+            category: doc.spec.category.clone(),
+            package: doc.spec.package.clone(),
+        });
+    }
+
     pub fn count_and_measure(&self, doc: &SystemInstance, trace_id: &TraceId) -> ReconcileMeasurerInstance {
         let labels = LabelInstance {
             name: doc.name_any(),
@@ -255,6 +291,7 @@ pub struct ReconcileMetricsTenantInstance {
     pub runs: Family<LabelInstance, Counter>,
     pub failures: Family<ErrorLabelsInstance, Counter>,
     pub duration: Family<LabelInstance, HistogramWithExemplars<TraceLabel>>,
+    pub healthy: Family<LabelInstance, Gauge>,
 }
 
 impl Default for ReconcileMetricsTenantInstance {
@@ -265,6 +302,7 @@ impl Default for ReconcileMetricsTenantInstance {
             duration: Family::new_with_constructor(|| {
                 HistogramWithExemplars::new([0.01, 0.1, 0.5, 1., 5., 15., 60., 120., 300.].into_iter())
             }),
+            healthy: Family::<LabelInstance, Gauge>::default(),
         }
     }
 }
@@ -279,6 +317,11 @@ impl ReconcileMetricsTenantInstance {
             self.duration.clone(),
         );
         r.register("failures", "reconciliation errors", self.failures.clone());
+        r.register(
+            "healthy",
+            "1 when every monitored child is healthy",
+            self.healthy.clone(),
+        );
         r.register("runs", "reconciliations", self.runs.clone());
         self
     }
@@ -296,6 +339,29 @@ impl ReconcileMetricsTenantInstance {
             .inc();
     }
 
+    pub fn set_health(&self, doc: &TenantInstance, healthy: bool) {
+        self.healthy
+            .get_or_create(&LabelInstance {
+                name: doc.name_any(),
+                namespace: doc.namespace(),
+                jukebox: doc.spec.jukebox.clone(),
+                category: doc.spec.category.clone(),
+                package: doc.spec.package.clone(),
+            })
+            .set(i64::from(healthy));
+    }
+
+    /// Drop the health series of a deleted instance
+    pub fn remove_health(&self, doc: &TenantInstance) {
+        self.healthy.remove(&LabelInstance {
+            name: doc.name_any(),
+            namespace: doc.namespace(),
+            jukebox: doc.spec.jukebox.clone(),
+            category: doc.spec.category.clone(),
+            package: doc.spec.package.clone(),
+        });
+    }
+
     pub fn count_and_measure(&self, doc: &TenantInstance, trace_id: &TraceId) -> ReconcileMeasurerInstance {
         let labels = LabelInstance {
             name: doc.name_any(),
@@ -319,6 +385,7 @@ pub struct ReconcileMetricsServiceInstance {
     pub runs: Family<LabelInstance, Counter>,
     pub failures: Family<ErrorLabelsInstance, Counter>,
     pub duration: Family<LabelInstance, HistogramWithExemplars<TraceLabel>>,
+    pub healthy: Family<LabelInstance, Gauge>,
 }
 
 impl Default for ReconcileMetricsServiceInstance {
@@ -329,6 +396,7 @@ impl Default for ReconcileMetricsServiceInstance {
             duration: Family::new_with_constructor(|| {
                 HistogramWithExemplars::new([0.01, 0.1, 0.5, 1., 5., 15., 60., 120., 300.].into_iter())
             }),
+            healthy: Family::<LabelInstance, Gauge>::default(),
         }
     }
 }
@@ -343,6 +411,11 @@ impl ReconcileMetricsServiceInstance {
             self.duration.clone(),
         );
         r.register("failures", "reconciliation errors", self.failures.clone());
+        r.register(
+            "healthy",
+            "1 when every monitored child is healthy",
+            self.healthy.clone(),
+        );
         r.register("runs", "reconciliations", self.runs.clone());
         self
     }
@@ -360,6 +433,29 @@ impl ReconcileMetricsServiceInstance {
             .inc();
     }
 
+    pub fn set_health(&self, doc: &ServiceInstance, healthy: bool) {
+        self.healthy
+            .get_or_create(&LabelInstance {
+                name: doc.name_any(),
+                namespace: doc.namespace(),
+                jukebox: doc.spec.jukebox.clone(),
+                category: doc.spec.category.clone(),
+                package: doc.spec.package.clone(),
+            })
+            .set(i64::from(healthy));
+    }
+
+    /// Drop the health series of a deleted instance
+    pub fn remove_health(&self, doc: &ServiceInstance) {
+        self.healthy.remove(&LabelInstance {
+            name: doc.name_any(),
+            namespace: doc.namespace(),
+            jukebox: doc.spec.jukebox.clone(),
+            category: doc.spec.category.clone(),
+            package: doc.spec.package.clone(),
+        });
+    }
+
     pub fn count_and_measure(&self, doc: &ServiceInstance, trace_id: &TraceId) -> ReconcileMeasurerInstance {
         let labels = LabelInstance {
             name: doc.name_any(),
@@ -377,3 +473,57 @@ impl ReconcileMetricsServiceInstance {
         }
     }
 }
+
+#[derive(Clone)]
+pub struct ReconcileMetricsTenantProfile {
+    pub runs: Family<LabelBox, Counter>,
+    pub failures: Family<ErrorLabels, Counter>,
+    pub duration: Family<LabelBox, HistogramWithExemplars<TraceLabel>>,
+}
+
+impl Default for ReconcileMetricsTenantProfile {
+    fn default() -> Self {
+        Self {
+            runs: Family::<LabelBox, Counter>::default(),
+            failures: Family::<ErrorLabels, Counter>::default(),
+            duration: Family::new_with_constructor(|| {
+                HistogramWithExemplars::new([0.01, 0.1, 0.5, 1., 5., 15., 60., 120., 300.].into_iter())
+            }),
+        }
+    }
+}
+
+impl ReconcileMetricsTenantProfile {
+    /// Register API metrics to start tracking them.
+    pub fn register(self, r: &mut Registry) -> Self {
+        r.register_with_unit(
+            "duration",
+            "reconcile duration",
+            Unit::Seconds,
+            self.duration.clone(),
+        );
+        r.register("failures", "reconciliation errors", self.failures.clone());
+        r.register("runs", "reconciliations", self.runs.clone());
+        self
+    }
+
+    pub fn reconcile_failure(&self, doc: &TenantProfile, e: &Error) {
+        self.failures
+            .get_or_create(&ErrorLabels {
+                instance: doc.name_any(),
+                error: e.metric_label(),
+            })
+            .inc();
+    }
+
+    pub fn count_and_measure(&self, doc: &TenantProfile, trace_id: &TraceId) -> ReconcileMeasurerBox {
+        let labels = LabelBox { name: doc.name_any() };
+        self.runs.get_or_create(&labels).inc();
+        ReconcileMeasurerBox {
+            start: Instant::now(),
+            labels,
+            trace: trace_id.try_into().ok(),
+            metric: self.duration.clone(),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{
+    Error, Result, ServiceInstance, SystemInstance, TenantInstance, clone, defaults,
+    instance_common::InstanceKind,
+};
+use common::{
+    valuefrom,
+    vynilpolicy::{PolicySubject, VynilPolicy, evaluate},
+};
+use k8s_openapi::{api::core::v1::Namespace, apimachinery::pkg::apis::meta::v1::Time};
+use kube::{
+    Client, ResourceExt,
+    api::{Api, ListParams},
+};
+use serde_json::{Value, json};
+
+/// Count the instances of a package in a namespace taking precedence over `name`
+///
+/// Older instances (then lower names) come first, so a limit never blocks an instance
+/// that was already counted in. Without a creation timestamp (admission of a new
+/// instance), every other instance counts.
+async fn count_others<T: InstanceKind>(
+    client: Client,
+    namespace: &str,
+    name: &str,
+    category: &str,
+    package: &str,
+    created: Option<Time>,
+) -> Result<usize> {
+    let api = Api::<T>::namespaced(client, namespace);
+    let list = api.list(&ListParams::default()).await.map_err(Error::KubeError)?;
+    Ok(list
+        .items
+        .iter()
+        .filter(|i| i.spec_category() == category && i.spec_package() == package)
+        .filter(|i| precedes(&i.meta().creation_timestamp, &i.name_any(), &created, name))
+        .count())
+}
+
+/// Tells if the instance `other_name` is counted before the instance `name`, itself excluded
+fn precedes(other_created: &Option<Time>, other_name: &str, created: &Option<Time>, name: &str) -> bool {
+    other_name != name && (created.is_none() || (other_created, other_name) < (created, name))
+}
+
+/// Creation timestamp of the instance of an admission request: set on UPDATE so the instance
+/// keeps its place in the count, `None` for a new instance
+fn request_created(request: &Value) -> Option<Time> {
+    if request["operation"] != "UPDATE" {
+        return None;
+    }
+    serde_json::from_value(request["oldObject"]["metadata"]["creationTimestamp"].clone()).ok()
+}
+
+/// Evaluate every policy selecting the namespace
+async fn violations(
+    client: Client,
+    namespace: &str,
+    jukebox: &str,
+    category: &str,
+    package: &str,
+    options: Value,
+    others: usize,
+) -> Result<Vec<String>> {
+    let policies = Api::<VynilPolicy>::all(client.clone())
+        .list(&ListParams::default())
+        .await
+        .map_err(Error::KubeError)?
+        .items;
+    if policies.is_empty() {
+        return Ok(vec![]);
+    }
+    let ns = Api::<Namespace>::all(client.clone())
+        .get(namespace)
+        .await
+        .map_err(Error::KubeError)?;
+    // Check the values the agent will read, not their references
+    let options = valuefrom::resolve(client, namespace, &options).await;
+    evaluate(&policies, ns.labels(), &PolicySubject {
+        jukebox,
+        category,
+        package,
+        options,
+        others,
+    })
+}
+
+/// List the policy violations of an instance
+pub async fn check<T: InstanceKind>(inst: &T, client: Client) -> Result<Vec<String>> {
+    let namespace = inst.namespace().unwrap_or_default();
+    let others = count_others::<T>(
+        client.clone(),
+        &namespace,
+        &inst.name_any(),
+        inst.spec_category(),
+        inst.spec_package(),
+        inst.meta().creation_timestamp.clone(),
+    )
+    .await?;
+    let instance = serde_json::to_value(inst).map_err(Error::JsonError)?;
+    let options = if instance["status"]["effectiveOptions"].is_null() {
+        instance["spec"]["options"].clone()
+    } else {
+        instance["status"]["effectiveOptions"].clone()
+    };
+    violations(
+        client,
+        &namespace,
+        inst.spec_jukebox(),
+        inst.spec_category(),
+        inst.spec_package(),
+        if options.is_null() { json!({}) } else { options },
+        others,
+    )
+    .await
+}
+
+/// Violations of the instance carried by an admission request, `None` when it is not concerned
+async fn review_request(client: Client, request: &Value) -> Result<Option<Vec<String>>> {
+    let object = &request["object"];
+    if !object["metadata"]["deletionTimestamp"].is_null() {
+        return Ok(None);
+    }
+    // Metadata-only updates (finalizers, annotations) are never blocked
+    if request["operation"] == "UPDATE" && request["oldObject"]["spec"] == object["spec"] {
+        return Ok(None);
+    }
+    let spec = &object["spec"];
+    let namespace = request["namespace"].as_str().unwrap_or_default();
+    let name = object["metadata"]["name"].as_str().unwrap_or_default();
+    let jukebox = spec["jukebox"].as_str().unwrap_or_default();
+    let category = spec["category"].as_str().unwrap_or_default();
+    let package = spec["package"].as_str().unwrap_or_default();
+    let created = request_created(request);
+    let others = match request["kind"]["kind"].as_str() {
+        Some("TenantInstance") => {
+            count_others::<TenantInstance>(client.clone(), namespace, name, category, package, created).await?
+        }
+        Some("ServiceInstance") => {
+            count_others::<ServiceInstance>(client.clone(), namespace, name, category, package, created).await?
+        }
+        Some("SystemInstance") => {
+            count_others::<SystemInstance>(client.clone(), namespace, name, category, package, created).await?
+        }
+        _ => return Ok(None),
+    };
+    let options = spec["options"].as_object().cloned().unwrap_or_default();
+    let options = defaults::effective_options(client.clone(), namespace, category, package, &options)
+        .await?
+        .unwrap_or(options);
+    let options = Value::Object(options);
+    violations(client, namespace, jukebox, category, package, options, others)
+        .await
+        .map(Some)
+}
+
+/// Reason to refuse the `initFrom.instanceRef` of an admission request, `None` when the instance
+/// may clone its source
+async fn clone_denial(client: Client, request: &Value) -> Result<Option<String>> {
+    let object = &request["object"];
+    let source = &object["spec"]["initFrom"]["instanceRef"];
+    if source.is_null()
+        || !object["metadata"]["deletionTimestamp"].is_null()
+        || request["oldObject"]["spec"]["initFrom"]["instanceRef"] == *source
+    {
+        return Ok(None);
+    }
+    let kind = match request["kind"]["kind"].as_str() {
+        Some(kind @ ("TenantInstance" | "ServiceInstance")) => kind,
+        _ => return Ok(None),
+    };
+    let namespace = request["namespace"].as_str().unwrap_or_default();
+    let source_kind = source["kind"].as_str().unwrap_or(kind);
+    let source_ns = source["namespace"].as_str().unwrap_or(namespace);
+    let name = source["name"].as_str().unwrap_or_default();
+    match clone::authorize(client, namespace, source_kind, source_ns, name).await {
+        Err(Error::InvalidInstanceRef(reason)) => Ok(Some(reason)),
+        other => other.map(|()| None),
+    }
+}
+
+/// Answer an AdmissionReview for the instance kinds
+pub async fn review(client: Client, review: Value) -> Value {
+    let request = &review["request"];
+    if let Ok(Some(reason)) = clone_denial(client.clone(), request).await {
+        return json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "response": {
+                "uid": request["uid"],
+                "allowed": false,
+                "status": {"code": 403, "message": format!("Cannot clone: {reason}")},
+            },
+        });
+    }
+    let response = match review_request(client, request).await {
+        Ok(Some(v)) if !v.is_empty() => json!({
+            "uid": request["uid"],
+            "allowed": false,
+            "status": {"code": 403, "message": format!("Policy violations: {}", v.join("; "))},
+        }),
+        Ok(_) => json!({"uid": request["uid"], "allowed": true}),
+        Err(e) => {
+            // The reconciliation enforces the policies anyway, do not block the API on errors
+            tracing::warn!("While reviewing {}: {e:?}", request["uid"]);
+            json!({"uid": request["uid"], "allowed": true})
+        }
+    };
+    json!({
+        "apiVersion": "admission.k8s.io/v1",
+        "kind": "AdmissionReview",
+        "response": response,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(ts: &str) -> Option<Time> {
+        serde_json::from_value(json!(ts)).ok()
+    }
+
+    #[test]
+    fn updated_instances_keep_their_place_in_the_count() {
+        let update = json!({
+            "operation": "UPDATE",
+            "oldObject": {"metadata": {"name": "wiki", "creationTimestamp": "2026-10-01T00:00:00Z"}},
+            "object": {"metadata": {"name": "wiki", "creationTimestamp": "2026-10-01T00:00:00Z"}}
+        });
+        let created = request_created(&update);
+        assert!(created.is_some());
+        assert!(request_created(&json!({"operation": "CREATE", "object": {}})).is_none());
+
+        // The instance itself never counts
+        assert!(!precedes(&created, "wiki", &created, "wiki"));
+        assert!(!precedes(&created, "wiki", &None, "wiki"));
+        // Older instances count, newer ones do not
+        assert!(precedes(&time("2026-09-01T00:00:00Z"), "docs", &created, "wiki"));
+        assert!(!precedes(&time("2026-10-02T00:00:00Z"), "docs", &created, "wiki"));
+        // A new instance counts every other one
+        assert!(precedes(&time("2026-10-02T00:00:00Z"), "docs", &None, "wiki"));
+    }
+}
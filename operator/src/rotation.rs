@@ -0,0 +1,138 @@
+use crate::{
+    Error, Result,
+    instance_common::{InstanceKind, delete_job_and_wait},
+};
+use chrono::Utc;
+use common::{
+    handlebarshandler::HandleBars,
+    vynilbackup::{KeyRotation, KeyRotationPhase},
+    vynilpackage::VynilPackageFeature,
+};
+use k8s_openapi::api::batch::v1::Job;
+use kube::{
+    Client, ResourceExt,
+    api::{Api, Patch, PatchParams, PostParams},
+};
+use serde_json::{Value, json};
+
+pub const ROTATE_ANNOTATION: &str = "vynil.solidite.fr/rotate-backup-key";
+
+/// Name of the key rotation Job of an instance
+fn job_name<T: InstanceKind>(inst: &T) -> String {
+    common::short_name(
+        &format!(
+            "rotate--{}--{}",
+            inst.namespace().unwrap_or_default(),
+            inst.name_any()
+        ),
+        63,
+    )
+}
+
+/// A failed rotation is resumed by setting the annotation again, not retried
+fn once(mut job: Value) -> Value {
+    job["spec"]["backoffLimit"] = 0.into();
+    job
+}
+
+/// Starts the rotation of the backup repository key when the instance is annotated.
+///
+/// The Job runs the agent `rotate-key` action with the context of the install Job: it rotates the
+/// key of every instance using the same backup secret and records the outcome of each in its
+/// `status.keyRotation`. The annotation is removed once the Job is created.
+pub async fn start<T: InstanceKind>(
+    inst: &T,
+    features: &[VynilPackageFeature],
+    hbs: &mut HandleBars<'static>,
+    context: &Value,
+    client: Client,
+    my_ns: &str,
+) -> Result<()> {
+    if !inst.annotations().contains_key(ROTATE_ANNOTATION) {
+        return Ok(());
+    }
+    let ns = inst.namespace().unwrap_or_default();
+    if T::type_name() == "system" || !features.contains(&VynilPackageFeature::Backup) {
+        tracing::warn!(
+            "{}Instance {}/{} has no backup, ignoring {ROTATE_ANNOTATION}",
+            T::type_name(),
+            ns,
+            inst.name_any()
+        );
+    } else {
+        let name = job_name(inst);
+        let mut context = context.clone();
+        {
+            let obj = context.as_object_mut().unwrap();
+            obj.insert("package_action".to_string(), "rotate-key".into());
+            obj.insert("job_name".to_string(), name.clone().into());
+        }
+        let job_def_str = hbs.render("{{> package.yaml }}", &context)?;
+        let job_def: Value = common::yamlhandler::yaml_str_to_json(&job_def_str)?;
+        let job_api: Api<Job> = Api::namespaced(client.clone(), my_ns);
+        if job_api
+            .get_metadata_opt(&name)
+            .await
+            .map_err(Error::KubeError)?
+            .is_some()
+        {
+            delete_job_and_wait(&job_api, &name).await?;
+        }
+        let mut rotation = KeyRotation {
+            secret_name: inst
+                .spec_backup()
+                .and_then(|b| b.secret_name.clone())
+                .unwrap_or_else(|| "backup-settings".to_string()),
+            job: Some(name.clone()),
+            started_at: Utc::now(),
+            phase: KeyRotationPhase::Started,
+            message: None,
+        };
+        if let Err(e) = job_api
+            .create(
+                &PostParams::default(),
+                &serde_json::from_value(once(job_def)).map_err(Error::SerializationError)?,
+            )
+            .await
+        {
+            rotation.phase = KeyRotationPhase::Failed;
+            rotation.message = Some(format!("Creating the rotation Job failed: {e}"));
+            inst.clone().set_key_rotation(rotation).await?;
+            return Err(Error::KubeError(e));
+        }
+        inst.clone().set_key_rotation(rotation).await?;
+        tracing::info!("Started the backup key rotation Job {my_ns}/{name}");
+    }
+    let api = Api::<T>::namespaced(client, &ns);
+    let patch = Patch::Json::<()>(
+        serde_json::from_value(json!([
+            {"op": "remove", "path": "/metadata/annotations/vynil.solidite.fr~1rotate-backup-key"}
+        ]))
+        .unwrap(),
+    );
+    api.patch(&inst.name_any(), &PatchParams::default(), &patch)
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_job_runs_once() {
+        let job = json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {"name": "rotate--team--wiki"},
+            "spec": {"backoffLimit": 3, "template": {"spec": {"containers": [{"args": ["tenant", "rotate-key"]}]}}}
+        });
+        let job = once(job);
+        assert_eq!(job["spec"]["backoffLimit"], 0);
+        assert_eq!(
+            job["spec"]["template"]["spec"]["containers"][0]["args"][1],
+            "rotate-key"
+        );
+    }
+}
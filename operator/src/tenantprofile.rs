@@ -0,0 +1,226 @@
+use crate::{Error, Result, TenantInstance, get_client_name, manager::Context, telemetry};
+use chrono::Utc;
+use common::{
+    handlebarshandler::HandleBars,
+    tenantprofile::{PROFILE_LABEL, TenantProfile, TenantProfileInstance, tenant_name},
+    vynilbackup::DRILL_LABEL,
+};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{
+    Resource, ResourceExt,
+    api::{Api, DeleteParams, ListParams, Patch, PatchParams},
+    runtime::controller::Action,
+};
+use serde_json::{Value, json};
+use std::{collections::BTreeSet, sync::Arc};
+use tokio::time::Duration;
+use tracing::{Span, field, instrument};
+
+/// Render every string of an options tree as a handlebars template
+fn render_value(hbs: &mut HandleBars<'static>, value: &Value, context: &Value) -> Result<Value> {
+    Ok(match value {
+        Value::String(s) => Value::String(hbs.render(s, context)?),
+        Value::Array(a) => Value::Array(
+            a.iter()
+                .map(|v| render_value(hbs, v, context))
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        Value::Object(o) => {
+            let mut r = serde_json::Map::new();
+            for (k, v) in o {
+                r.insert(k.clone(), render_value(hbs, v, context)?);
+            }
+            Value::Object(r)
+        }
+        v => v.clone(),
+    })
+}
+
+/// Build the TenantInstance a profile entry requires in a namespace
+fn render_instance(
+    hbs: &mut HandleBars<'static>,
+    profile: &TenantProfile,
+    item: &TenantProfileInstance,
+    namespace: &str,
+    context: &Value,
+) -> Result<Value> {
+    let name = hbs.render(&item.name, context)?;
+    let options = match &item.options {
+        Some(opts) => render_value(hbs, &Value::Object(opts.clone()), context)?,
+        None => json!({}),
+    };
+    let owner = profile
+        .controller_owner_ref(&())
+        .ok_or(Error::Other("TenantProfile without uid".to_string()))?;
+    let mut spec = json!({
+        "jukebox": item.jukebox,
+        "category": item.category,
+        "package": item.package,
+        "options": options,
+    });
+    if let Some(auto) = item.auto_install_dependencies {
+        spec["autoInstallDependencies"] = auto.into();
+    }
+    let mut labels = serde_json::Map::new();
+    labels.insert(PROFILE_LABEL.to_string(), profile.name_any().into());
+    Ok(json!({
+        "apiVersion": "vynil.solidite.fr/v1",
+        "kind": "TenantInstance",
+        "metadata": {
+            "name": name,
+            "namespace": namespace,
+            "labels": labels,
+            "ownerReferences": [owner],
+        },
+        "spec": spec,
+    }))
+}
+
+async fn apply(profile: &TenantProfile, ctx: Arc<Context>) -> Result<(Vec<String>, Vec<String>)> {
+    let client = ctx.client.clone();
+    let mut hbs = ctx.renderer.clone();
+    let selector = profile.selector()?;
+    let ns_api: Api<Namespace> = Api::all(client.clone());
+    let namespaces: Vec<Namespace> = ns_api
+        .list(&ListParams::default().labels(&selector.to_string()))
+        .await
+        .map_err(Error::KubeError)?
+        .items
+        .into_iter()
+        // Restore drill sandboxes carry the labels of their source namespace
+        .filter(|ns| ns.metadata.deletion_timestamp.is_none() && !ns.labels().contains_key(DRILL_LABEL))
+        .collect();
+    let mut applied = BTreeSet::new();
+    for ns in &namespaces {
+        let namespace = ns.name_any();
+        let labels = ns.labels().clone();
+        let context = json!({
+            "tenant": tenant_name(&namespace, &labels),
+            "namespace": namespace,
+            "labels": labels,
+            "profile": profile.name_any(),
+        });
+        let api: Api<TenantInstance> = Api::namespaced(client.clone(), &namespace);
+        for item in &profile.spec.instances {
+            let obj = render_instance(&mut hbs, profile, item, &namespace, &context)?;
+            let name = obj["metadata"]["name"].as_str().unwrap_or_default().to_string();
+            api.patch(
+                &name,
+                &PatchParams::apply(&get_client_name()).force(),
+                &Patch::Apply(obj),
+            )
+            .await
+            .map_err(Error::KubeError)?;
+            applied.insert(format!("{namespace}/{name}"));
+        }
+    }
+    // Prune the instances no longer required (namespace unselected or entry removed)
+    let all: Api<TenantInstance> = Api::all(client.clone());
+    let lp = ListParams::default().labels(&format!("{PROFILE_LABEL}={}", profile.name_any()));
+    for inst in all.list(&lp).await.map_err(Error::KubeError)?.items {
+        let ns = inst.namespace().unwrap_or_default();
+        let key = format!("{ns}/{}", inst.name_any());
+        if !applied.contains(&key) && inst.metadata.deletion_timestamp.is_none() {
+            tracing::info!("TenantProfile {} removes TenantInstance {key}", profile.name_any());
+            let api: Api<TenantInstance> = Api::namespaced(client.clone(), &ns);
+            match api.delete(&inst.name_any(), &DeleteParams::default()).await {
+                Ok(_) => {}
+                Err(kube::Error::Api(e)) if e.code == 404 => {}
+                Err(e) => return Err(Error::KubeError(e)),
+            }
+        }
+    }
+    Ok((
+        namespaces.iter().map(|n| n.name_any()).collect(),
+        applied.into_iter().collect(),
+    ))
+}
+
+#[instrument(skip(ctx, profile), fields(trace_id))]
+pub async fn reconcile(profile: Arc<TenantProfile>, ctx: Arc<Context>) -> Result<Action> {
+    let trace_id = telemetry::get_trace_id();
+    if trace_id != opentelemetry::trace::TraceId::INVALID {
+        Span::current().record("trace_id", field::display(&trace_id));
+    }
+    let _mes = ctx.metrics.tenant_profile.count_and_measure(&profile, &trace_id);
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    let mut profile = (*profile).clone();
+    match apply(&profile, ctx.clone()).await {
+        Ok((namespaces, instances)) => {
+            profile.set_status_applied(namespaces, instances).await?;
+            Ok(Action::requeue(Duration::from_secs(15 * 60)))
+        }
+        Err(e) => {
+            profile.set_status_failed(e.to_string()).await?;
+            Err(e)
+        }
+    }
+}
+
+#[must_use]
+pub fn error_policy(profile: Arc<TenantProfile>, error: &Error, ctx: Arc<Context>) -> Action {
+    tracing::warn!(
+        "reconcile failed for TenantProfile {:?}: {:?}",
+        profile.metadata.name,
+        error
+    );
+    ctx.metrics.tenant_profile.reconcile_failure(&profile, error);
+    Action::requeue(Duration::from_secs(5 * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::tenantprofile::TenantProfileSpec;
+
+    fn make_profile() -> TenantProfile {
+        let mut p = TenantProfile::new("base", TenantProfileSpec {
+            selector: None,
+            instances: vec![],
+        });
+        p.metadata.uid = Some("1234".to_string());
+        p
+    }
+
+    fn make_item(options: Value) -> TenantProfileInstance {
+        TenantProfileInstance {
+            name: "{{tenant}}-auth".to_string(),
+            jukebox: "vynil".to_string(),
+            category: "auth".to_string(),
+            package: "authentik".to_string(),
+            options: options.as_object().cloned(),
+            auto_install_dependencies: Some(true),
+        }
+    }
+
+    #[test]
+    fn render_instance_templates_name_and_options() {
+        let mut hbs = HandleBars::new();
+        let ctx = json!({"tenant": "acme", "namespace": "acme-auth", "labels": {}, "profile": "base"});
+        let item = make_item(json!({
+            "domain": "{{tenant}}.example.com",
+            "replicas": 2,
+            "hosts": ["{{namespace}}.svc"],
+        }));
+        let obj = render_instance(&mut hbs, &make_profile(), &item, "acme-auth", &ctx).unwrap();
+        assert_eq!(obj["metadata"]["name"], "acme-auth");
+        assert_eq!(obj["metadata"]["namespace"], "acme-auth");
+        assert_eq!(obj["metadata"]["labels"][PROFILE_LABEL], "base");
+        assert_eq!(obj["metadata"]["ownerReferences"][0]["kind"], "TenantProfile");
+        assert_eq!(obj["spec"]["options"]["domain"], "acme.example.com");
+        assert_eq!(obj["spec"]["options"]["replicas"], 2);
+        assert_eq!(obj["spec"]["options"]["hosts"][0], "acme-auth.svc");
+        assert_eq!(obj["spec"]["autoInstallDependencies"], true);
+    }
+
+    #[test]
+    fn render_instance_without_options() {
+        let mut hbs = HandleBars::new();
+        let ctx = json!({"tenant": "acme"});
+        let mut item = make_item(Value::Null);
+        item.auto_install_dependencies = None;
+        let obj = render_instance(&mut hbs, &make_profile(), &item, "acme", &ctx).unwrap();
+        assert_eq!(obj["spec"]["options"], json!({}));
+        assert!(obj["spec"].get("autoInstallDependencies").is_none());
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{Error, Result, instance_common::InstanceKind};
+use common::{
+    appslug,
+    instanceexport::{job_created_at, job_outcome, latest_backup, start_backup_job},
+    vynilbackup::{ROLLBACK_ANNOTATION, RollbackMode, UpgradeBackup, UpgradePhase, VynilBackup},
+    vynilpackage::{VynilPackage, VynilPackageFeature},
+};
+use k8s_openapi::api::batch::v1::Job;
+use kube::{
+    Client, ResourceExt,
+    api::{Api, Patch, PatchParams},
+    runtime::controller::Action,
+};
+use tokio::time::Duration;
+
+/// What the install job should do once the upgrade guard ran
+#[derive(Debug)]
+pub enum Step {
+    /// Install this tag
+    Proceed(String),
+    /// Install this tag again from scratch (rollback)
+    Reinstall(String),
+    /// Do not touch the install job yet
+    Wait(Action),
+}
+
+/// Tag installed by an install job
+fn job_tag(job: &Job) -> Option<&str> {
+    job.spec
+        .as_ref()?
+        .template
+        .spec
+        .as_ref()?
+        .containers
+        .iter()
+        .flat_map(|c| c.env.iter().flatten())
+        .find(|e| e.name == "TAG")?
+        .value
+        .as_deref()
+}
+
+/// Whether the upgrade of `inst` to `tag` should be protected by a backup
+fn wants_backup<T: InstanceKind>(inst: &T, pck: &VynilPackage, tag: &str) -> bool {
+    let current = inst.current_tag();
+    let policy = inst.spec_backup();
+    !current.is_empty()
+        && current != tag
+        && pck.metadata.features.contains(&VynilPackageFeature::Backup)
+        && policy.and_then(|p| p.enabled).unwrap_or(true)
+        && policy.and_then(|p| p.pre_upgrade).unwrap_or(true)
+}
+
+/// Whether a failed upgrade should be rolled back now
+fn rollback_requested<T: InstanceKind>(inst: &T) -> bool {
+    inst.spec_backup().and_then(|p| p.rollback) == Some(RollbackMode::Auto)
+        || inst.annotations().contains_key(ROLLBACK_ANNOTATION)
+}
+
+/// Backs up the instance before upgrading it to `tag`, and rolls the upgrade back when it failed.
+///
+/// The backup Job is created from the jobTemplate of the instance backup CronJob, its outcome is
+/// recorded in `status.upgradeBackup`.
+pub async fn guard<T: InstanceKind>(
+    inst: &T,
+    pck: &VynilPackage,
+    tag: String,
+    job_api: &Api<Job>,
+    job_name: &str,
+    client: Client,
+) -> Result<Step> {
+    let ns = inst.namespace().unwrap_or_default();
+    let current = inst.current_tag();
+    let backup_job = format!("{}-pre-upgrade", appslug(inst.spec_package(), &inst.name_any()));
+    let backup_api: Api<Job> = Api::namespaced(client.clone(), &ns);
+
+    if let Some(mut up) = inst.upgrade_backup().filter(|u| u.to == tag).cloned() {
+        match up.phase {
+            UpgradePhase::RolledBack => return Ok(Step::Proceed(up.from)),
+            UpgradePhase::RollingBack => {
+                let job = job_api.get_opt(job_name).await.map_err(Error::KubeError)?;
+                if let Some(job) = job
+                    && job_outcome(&job) == Some(true)
+                    && job_tag(&job) == Some(up.from.as_str())
+                {
+                    up.phase = UpgradePhase::RolledBack;
+                    up.message = None;
+                    inst.clone().set_upgrade_backup(Some(up.clone())).await?;
+                }
+                return Ok(Step::Proceed(up.from));
+            }
+            UpgradePhase::BackingUp => {
+                let Some(job) = backup_api.get_opt(&backup_job).await.map_err(Error::KubeError)? else {
+                    // Deleted while running, start over
+                    return start_backup(inst, &current, tag, &backup_job, client).await;
+                };
+                return match job_outcome(&job) {
+                    None => Ok(Step::Wait(Action::requeue(Duration::from_secs(30)))),
+                    Some(false) => {
+                        up.phase = UpgradePhase::BackupFailed;
+                        up.message = Some(format!("Job {backup_job} failed"));
+                        inst.clone().set_upgrade_backup(Some(up)).await?;
+                        Ok(Step::Wait(Action::requeue(Duration::from_secs(15 * 60))))
+                    }
+                    Some(true) => {
+                        let records = VynilBackup::list_for(client.clone(), &ns, &inst.name_any()).await?;
+                        let record = latest_backup(&records, &T::kind(&()), job_created_at(&job));
+                        up.phase = UpgradePhase::BackedUp;
+                        up.message = record.is_none().then(|| "no backup record found".to_string());
+                        up.snapshot = record.and_then(|b| b.spec.snapshot.clone());
+                        up.backup = record.map(|b| b.name_any());
+                        inst.clone().set_upgrade_backup(Some(up)).await?;
+                        Ok(Step::Proceed(tag))
+                    }
+                };
+            }
+            UpgradePhase::BackupFailed => {
+                // Deleting the failed Job retries the backup, disabling preUpgrade skips it
+                if !wants_backup(inst, pck, &tag) {
+                    inst.clone().set_upgrade_backup(None).await?;
+                    return Ok(Step::Proceed(tag));
+                }
+                if backup_api
+                    .get_metadata_opt(&backup_job)
+                    .await
+                    .map_err(Error::KubeError)?
+                    .is_none()
+                {
+                    return start_backup(inst, &current, tag, &backup_job, client).await;
+                }
+                return Ok(Step::Wait(Action::requeue(Duration::from_secs(15 * 60))));
+            }
+            UpgradePhase::BackedUp | UpgradePhase::UpgradeFailed => {
+                if current != up.from {
+                    return Ok(Step::Proceed(tag));
+                }
+                let job = job_api.get_opt(job_name).await.map_err(Error::KubeError)?;
+                let failed = job
+                    .as_ref()
+                    .is_some_and(|j| job_outcome(j) == Some(false) && job_tag(j) == Some(tag.as_str()));
+                if !failed {
+                    return Ok(Step::Proceed(tag));
+                }
+                if rollback_requested(inst) {
+                    if let Some(options) = up.options.take()
+                        && options != inst.spec_options()
+                    {
+                        // Reinstall the previous version with its own options: put them back
+                        // first, the spec change triggers the rollback again
+                        restore_options(inst, options, client).await?;
+                        inst.clone().set_upgrade_backup(Some(up)).await?;
+                        return Ok(Step::Wait(Action::await_change()));
+                    }
+                    if inst.annotations().contains_key(ROLLBACK_ANNOTATION) {
+                        let api = Api::<T>::namespaced(client.clone(), &ns);
+                        let patch = Patch::Json::<()>(
+                            serde_json::from_value(serde_json::json!([
+                                {"op": "remove", "path": "/metadata/annotations/vynil.solidite.fr~1rollback"}
+                            ]))
+                            .unwrap(),
+                        );
+                        api.patch(&inst.name_any(), &PatchParams::default(), &patch)
+                            .await
+                            .map_err(Error::KubeError)?;
+                    }
+                    up.phase = UpgradePhase::RollingBack;
+                    up.message = None;
+                    inst.clone().set_upgrade_backup(Some(up.clone())).await?;
+                    return Ok(Step::Reinstall(up.from));
+                }
+                if up.phase == UpgradePhase::BackedUp {
+                    up.phase = UpgradePhase::UpgradeFailed;
+                    up.message = Some(format!(
+                        "annotate with {ROLLBACK_ANNOTATION}=true to restore {}",
+                        up.from
+                    ));
+                    inst.clone().set_upgrade_backup(Some(up)).await?;
+                }
+                return Ok(Step::Proceed(tag));
+            }
+        }
+    }
+
+    if !wants_backup(inst, pck, &tag) {
+        return Ok(Step::Proceed(tag));
+    }
+    start_backup(inst, &current, tag, &backup_job, client).await
+}
+
+/// JSON patch putting back the options saved before an upgrade, forgetting their migration
+fn restore_options_patch<T: InstanceKind>(
+    inst: &T,
+    options: serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Value {
+    let mut ops = vec![serde_json::json!({"op": "add", "path": "/spec/options", "value": options})];
+    if inst
+        .annotations()
+        .contains_key("vynil.solidite.fr/options-migrated")
+    {
+        ops.push(serde_json::json!(
+            {"op": "remove", "path": "/metadata/annotations/vynil.solidite.fr~1options-migrated"}
+        ));
+    }
+    serde_json::Value::Array(ops)
+}
+
+/// Puts back the options saved before an upgrade
+async fn restore_options<T: InstanceKind>(
+    inst: &T,
+    options: serde_json::Map<String, serde_json::Value>,
+    client: Client,
+) -> Result<()> {
+    let api = Api::<T>::namespaced(client, &inst.namespace().unwrap_or_default());
+    let patch = Patch::Json::<()>(serde_json::from_value(restore_options_patch(inst, options)).unwrap());
+    api.patch(&inst.name_any(), &PatchParams::default(), &patch)
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
+/// Starts the pre-upgrade backup Job from the backup CronJob of the instance
+async fn start_backup<T: InstanceKind>(
+    inst: &T,
+    current: &str,
+    tag: String,
+    backup_job: &str,
+    client: Client,
+) -> Result<Step> {
+    let ns = inst.namespace().unwrap_or_default();
+    let slug = appslug(inst.spec_package(), &inst.name_any());
+    if start_backup_job(client, &ns, &slug, backup_job).await?.is_none() {
+        // Backups are not scheduled (yet), nothing to restore from
+        return Ok(Step::Proceed(tag));
+    }
+    inst.clone()
+        .set_upgrade_backup(Some(UpgradeBackup {
+            from: current.to_string(),
+            to: tag,
+            job: backup_job.to_string(),
+            backup: None,
+            snapshot: None,
+            phase: UpgradePhase::BackingUp,
+            message: None,
+            options: Some(inst.spec_options()),
+        }))
+        .await?;
+    Ok(Step::Wait(Action::requeue(Duration::from_secs(30))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TenantInstance;
+    use serde_json::json;
+
+    #[test]
+    fn install_job_outcome_and_tag() {
+        let mut job: Job = serde_json::from_value(json!({
+            "metadata": {"name": "tenant--team--wiki"},
+            "spec": {"template": {"spec": {"containers": [{
+                "name": "agent",
+                "env": [{"name": "LOG_LEVEL", "value": "info"}, {"name": "TAG", "value": "1.3.0"}]
+            }]}}},
+            "status": {"conditions": [{"type": "Failed", "status": "True"}]}
+        }))
+        .unwrap();
+        assert_eq!(job_tag(&job), Some("1.3.0"));
+        assert_eq!(job_outcome(&job), Some(false));
+        job.status = None;
+        assert_eq!(job_outcome(&job), None);
+        assert_eq!(appslug("wiki", "wiki"), "wiki");
+        assert_eq!(
+            appslug("postgresql-cluster", "team-database"),
+            "team-database-postgresql-clu"
+        );
+    }
+
+    #[test]
+    fn rollback_puts_back_the_options_before_their_migration() {
+        let inst: TenantInstance = serde_json::from_value(json!({
+            "metadata": {
+                "name": "wiki",
+                "namespace": "team",
+                "annotations": {"vynil.solidite.fr/options-migrated": "1.0.0 -> 2.0.0"}
+            },
+            "spec": {"jukebox": "jb", "category": "apps", "package": "wiki", "options": {"replicas": 2}}
+        }))
+        .unwrap();
+        let before = json!({"replicas": 1}).as_object().unwrap().clone();
+        assert_eq!(
+            restore_options_patch(&inst, before.clone()),
+            json!([
+                {"op": "add", "path": "/spec/options", "value": {"replicas": 1}},
+                {"op": "remove", "path": "/metadata/annotations/vynil.solidite.fr~1options-migrated"}
+            ])
+        );
+        let mut unmigrated = inst.clone();
+        unmigrated.metadata.annotations = None;
+        assert_eq!(
+            restore_options_patch(&unmigrated, before),
+            json!([{"op": "add", "path": "/spec/options", "value": {"replicas": 1}}])
+        );
+    }
+
+    #[test]
+    fn job_creation_date() {
+        let job: Job = serde_json::from_value(json!({
+            "metadata": {"name": "wiki-pre-upgrade", "creationTimestamp": "2026-10-01T02:00:00Z"}
+        }))
+        .unwrap();
+        assert_eq!(
+            job_created_at(&job).map(|d| d.to_rfc3339()).as_deref(),
+            Some("2026-10-01T02:00:00+00:00")
+        );
+    }
+}
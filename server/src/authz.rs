@@ -4,7 +4,7 @@ use kube::{Api, Client};
 
 /// Check if the identity has permission to access the instance resource
 ///
-/// For instance-scoped items (state, children, agentlog, childlogs, operatorlog),
+/// For instance-scoped items (state, children, dependents, backups, agentlog, childlogs, operatorlog),
 /// we need to verify the caller can read the instance via SubjectAccessReview.
 ///
 /// For generic items (clusterinfo, vynilconfig, packages), no SAR is needed.
@@ -54,7 +54,7 @@ pub async fn check_instance_access(
 pub fn is_instance_scoped_item(item: &str) -> bool {
     matches!(
         item,
-        "state" | "children" | "agentlog" | "childlogs" | "operatorlog"
+        "state" | "children" | "dependents" | "backups" | "agentlog" | "childlogs" | "operatorlog"
     )
 }
 
@@ -94,6 +94,8 @@ mod tests {
     fn test_is_instance_scoped_item() {
         assert!(is_instance_scoped_item("state"));
         assert!(is_instance_scoped_item("children"));
+        assert!(is_instance_scoped_item("dependents"));
+        assert!(is_instance_scoped_item("backups"));
         assert!(is_instance_scoped_item("agentlog"));
         assert!(is_instance_scoped_item("childlogs"));
         assert!(is_instance_scoped_item("operatorlog"));
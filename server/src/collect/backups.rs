@@ -0,0 +1,34 @@
+use crate::{
+    dto::{BackupState, BackupsState},
+    error::DiagError,
+};
+use common::{dependents::kind_from_plural, vynilbackup::VynilBackup};
+use kube::{Client, ResourceExt};
+
+/// Get the backup records of an instance, newest first.
+pub async fn get_backups(
+    client: &Client,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+) -> Result<BackupsState, DiagError> {
+    let kind = kind_from_plural(kind).ok_or(DiagError::UnknownKind)?;
+    let items = VynilBackup::list_for(client.clone(), namespace, name)
+        .await
+        .map_err(|e| DiagError::InternalError(e.to_string()))?
+        .into_iter()
+        .filter(|b| b.spec.instance.kind == kind)
+        .map(|b| BackupState {
+            name: b.name_any(),
+            version: b.spec.version,
+            digest: b.spec.digest,
+            snapshot: b.spec.snapshot,
+            size: b.spec.size,
+            started_at: b.spec.started_at.to_rfc3339(),
+            duration: b.spec.duration,
+            outcome: format!("{:?}", b.spec.outcome),
+            message: b.spec.message,
+        })
+        .collect();
+    Ok(BackupsState { items })
+}
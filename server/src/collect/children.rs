@@ -5,7 +5,7 @@ use crate::{
 };
 use common::{
     Children, instanceservice::ServiceInstance, instancesystem::SystemInstance,
-    instancetenant::TenantInstance,
+    instancetenant::TenantInstance, inventory,
 };
 use kube::{Api, Client};
 use serde_json;
@@ -50,7 +50,7 @@ async fn get_tenant_children(
     let api: Api<TenantInstance> = Api::namespaced(client.clone(), namespace);
     let instance = api.get(name).await.map_err(DiagError::KubeError)?;
 
-    extract_children_from_instance(&instance)
+    all_children(client, &serde_json::to_value(&instance)?).await
 }
 
 /// Extract children from a ServiceInstance
@@ -62,7 +62,7 @@ async fn get_service_children(
     let api: Api<ServiceInstance> = Api::namespaced(client.clone(), namespace);
     let instance = api.get(name).await.map_err(DiagError::KubeError)?;
 
-    extract_children_from_instance(&instance)
+    all_children(client, &serde_json::to_value(&instance)?).await
 }
 
 /// Extract children from a SystemInstance
@@ -74,43 +74,41 @@ async fn get_system_children(
     let api: Api<SystemInstance> = Api::namespaced(client.clone(), namespace);
     let instance = api.get(name).await.map_err(DiagError::KubeError)?;
 
-    extract_children_from_instance(&instance)
+    all_children(client, &serde_json::to_value(&instance)?).await
 }
 
-/// Status categories that hold `Children` lists across all instance kinds.
-/// `systems` is SystemInstance-specific — omitting it dropped all SystemInstance children.
-const CHILD_CATEGORIES: &[&str] = &[
-    "befores",
-    "vitals",
-    "scalables",
-    "others",
-    "posts",
-    "systems",
-    "services",
-];
-
-/// Extract every child from an instance's `status`, across all categories, from its JSON form.
-pub fn all_children_from_status(instance_json: &serde_json::Value) -> Vec<Children> {
-    let Some(status) = instance_json.get("status").and_then(|s| s.as_object()) else {
-        return Vec::new();
+/// Status categories still holding `Children` lists inline; the other lists (including the
+/// SystemInstance-specific `systems`) are read from the instance inventory.
+const STATUS_CATEGORIES: &[&str] = &["members", "services"];
+
+/// Extract every child of an instance from its JSON form: the inventory lists (or their legacy
+/// inline copies) first, then the lists kept in the status.
+pub async fn all_children(
+    client: &Client,
+    instance_json: &serde_json::Value,
+) -> Result<Vec<Children>, DiagError> {
+    let Some(status) = instance_json.get("status").filter(|s| s.is_object()) else {
+        return Ok(Vec::new());
     };
+    let namespace = instance_json
+        .pointer("/metadata/namespace")
+        .and_then(|n| n.as_str())
+        .unwrap_or_default();
+    let mut lists = inventory::all_children(client.clone(), namespace, status)
+        .await
+        .map_err(|e| DiagError::InternalError(e.to_string()))?;
     let mut children = Vec::new();
-    for cat in CHILD_CATEGORIES {
+    for list in inventory::LISTS {
+        if let Some(items) = lists.remove(*list) {
+            children.extend(items.into_iter().map(|i| i.child));
+        }
+    }
+    for cat in STATUS_CATEGORIES {
         if let Some(arr) = status.get(*cat).and_then(|v| v.as_array()) {
             children.extend(extract_children_from_json(arr));
         }
     }
-    children
-}
-
-/// Extract children from an instance using serde_json to handle the different status shapes.
-fn extract_children_from_instance(
-    instance: &(impl serde::Serialize + std::fmt::Debug),
-) -> Result<Vec<Children>, DiagError> {
-    match serde_json::to_value(instance) {
-        Ok(json) => Ok(all_children_from_status(&json)),
-        Err(_) => Ok(Vec::new()),
-    }
+    Ok(children)
 }
 
 /// Extract Children from a JSON array (entries that don't shape as Children are skipped).
@@ -0,0 +1,32 @@
+use crate::{auth::Identity, authz::check_instance_access, dto::DependentsState, error::DiagError};
+use common::dependents::kind_from_plural;
+use kube::Client;
+
+/// Get the instances that would break if this instance was deleted.
+///
+/// SECURITY: dependents may live in other tenants' namespaces. Only the dependents the caller
+/// can read themselves are listed; the others are only counted in `hidden`.
+pub async fn get_dependents(
+    client: &Client,
+    identity: &Identity,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+) -> Result<DependentsState, DiagError> {
+    let kind = kind_from_plural(kind).ok_or(DiagError::UnknownKind)?;
+    let all = common::dependents::get_dependents(client.clone(), kind, namespace, name)
+        .await
+        .map_err(|e| DiagError::InternalError(e.to_string()))?;
+
+    let mut items = Vec::new();
+    let mut hidden = 0;
+    for dep in all {
+        let plural = format!("{}s", dep.kind.to_lowercase());
+        if check_instance_access(client, identity, &plural, &dep.namespace, &dep.name).await? {
+            items.push(dep);
+        } else {
+            hidden += 1;
+        }
+    }
+    Ok(DependentsState { items, hidden })
+}
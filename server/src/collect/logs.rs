@@ -201,9 +201,10 @@ pub async fn get_child_logs(
     Ok((scrubbed, stats))
 }
 
-/// Get all children of an instance, across every status category (befores/vitals/scalables/
-/// others/posts for tenant & service ; systems for system). Generic JSON walk so a per-kind
-/// schema difference (SystemInstance uses `systems`) does not silently drop children.
+/// Get all children of an instance, across every category (befores/vitals/scalables/
+/// others/posts for tenant & service ; systems for system), read from the instance inventory.
+/// Generic JSON walk so a per-kind schema difference (SystemInstance uses `systems`) does not
+/// silently drop children.
 async fn get_instance_children(
     client: &Client,
     kind: &str,
@@ -227,7 +228,7 @@ async fn get_instance_children(
     }
     .map_err(DiagError::SerializationError)?;
 
-    Ok(crate::collect::children::all_children_from_status(&json))
+    crate::collect::children::all_children(client, &json).await
 }
 
 /// Resolve the pods of a child workload via its label selector (matchLabels), instead of
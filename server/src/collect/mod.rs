@@ -1,5 +1,7 @@
+pub mod backups;
 pub mod children;
 pub mod clusterinfo;
+pub mod dependents;
 pub mod logs;
 pub mod packages;
 pub mod state;
@@ -1,4 +1,4 @@
-use common::Children;
+use common::{Children, dependents::Dependent};
 use serde::{Deserialize, Serialize};
 
 /// Cluster information DTO
@@ -48,6 +48,34 @@ pub struct PackageState {
     pub ready: bool,
 }
 
+/// Reverse dependencies DTO
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DependentsState {
+    pub items: Vec<Dependent>,
+    /// Number of dependents the caller isn't allowed to read
+    pub hidden: usize,
+}
+
+/// Backup records DTO
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupsState {
+    pub items: Vec<BackupState>,
+}
+
+/// Individual backup record
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupState {
+    pub name: String,
+    pub version: String,
+    pub digest: Option<String>,
+    pub snapshot: Option<String>,
+    pub size: Option<i64>,
+    pub started_at: String,
+    pub duration: Option<i64>,
+    pub outcome: String,
+    pub message: Option<String>,
+}
+
 /// Child information with current state
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChildWithState {
@@ -15,8 +15,10 @@ use crate::{
     auth::extract_identity,
     authz::check_item_access,
     collect::{
+        backups::get_backups,
         children::get_children,
         clusterinfo::get_cluster_info,
+        dependents::get_dependents,
         logs::{get_agent_log, get_child_logs, get_operator_log},
         packages::get_packages,
         state::get_instance_state,
@@ -54,12 +56,14 @@ const INSTANCE_PATH: &str = "/apis/diag.vynil.solidite.fr/v1/namespaces/:ns/:kin
 const VALID_KINDS: [&str; 3] = ["tenantinstances", "serviceinstances", "systeminstances"];
 
 /// Valid items for the diagnostic API
-const VALID_ITEMS: [&str; 8] = [
+const VALID_ITEMS: [&str; 10] = [
     "clusterinfo",
     "vynilconfig",
     "packages",
     "state",
     "children",
+    "dependents",
+    "backups",
     "agentlog",
     "childlogs",
     "operatorlog",
@@ -301,6 +305,14 @@ async fn instance_handler(
             add_scrub_header(response.headers_mut(), &stats);
             Ok(response)
         }
+        "dependents" => {
+            let dependents = get_dependents(&state.client, &identity, &kind, &ns, &name).await?;
+            Ok((StatusCode::OK, Json(dependents)).into_response())
+        }
+        "backups" => {
+            let backups = get_backups(&state.client, &kind, &ns, &name).await?;
+            Ok((StatusCode::OK, Json(backups)).into_response())
+        }
         "agentlog" => {
             let (logs, stats) =
                 get_agent_log(&state.client, &kind, &ns, &name, &state.config.vynil_namespace).await?;